@@ -0,0 +1,48 @@
+//! Compares parsing the same selection paths for every use with getting them
+//! from a shared `JsonPathCache`.
+//!
+//! ```
+//! cargo bench -p apollo-router-benchmarks --bench path_cache
+//! ```
+
+use apollo_router::graphql::JsonPath;
+use apollo_router::graphql::JsonPathCache;
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+const PATHS: [&str; 4] = [
+    "topProducts/@/name",
+    "topProducts/@/reviews/@/author/name",
+    "me/reviews/@/product/upc",
+    "topProducts/@/... on Book/isbn",
+];
+
+const SELECTIONS: usize = 1000;
+
+fn path_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("path_selection");
+
+    group.bench_function("parse_every_time", |b| {
+        b.iter(|| {
+            for i in 0..SELECTIONS {
+                black_box(JsonPath::from(black_box(PATHS[i % PATHS.len()])));
+            }
+        })
+    });
+
+    let cache = JsonPathCache::new();
+    group.bench_function("path_cache", |b| {
+        b.iter(|| {
+            for i in 0..SELECTIONS {
+                black_box(cache.get(black_box(PATHS[i % PATHS.len()])));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, path_cache);
+criterion_main!(benches);
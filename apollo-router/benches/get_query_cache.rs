@@ -0,0 +1,56 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use apollo_router::services::router;
+use apollo_router::TestHarness;
+use tower::ServiceExt;
+
+const REQUESTS: u32 = 10_000;
+
+// query TopProducts($first: Int) { topProducts(first: $first) { upc name reviews { id product { name } author { id name } } } }
+// with the variables {"first": 2}
+const QUERY_STRING: &str = "query=query%20TopProducts%28%24first%3A%20Int%29%20%7B%20topProducts%28first%3A%20%24first%29%20%7B%20upc%20name%20reviews%20%7B%20id%20product%20%7B%20name%20%7D%20author%20%7B%20id%20name%20%7D%20%7D%20%7D%20%7D&operationName=TopProducts&variables=%7B%22first%22%3A2%7D";
+
+#[tokio::main]
+async fn main() {
+    println!("Columns:");
+    println!("* Size of the GET query cache");
+    println!("* Mean time of {REQUESTS} identical GET requests");
+    println!();
+    for (display, size) in [("none", None), (" 100", Some(100))] {
+        print!("{display} ");
+        let mean = identical_get_requests(size).await;
+        println!("{:>6} µs", mean.as_micros());
+    }
+}
+
+async fn identical_get_requests(get_query_cache_size: Option<usize>) -> Duration {
+    let router = TestHarness::builder()
+        .configuration_json(serde_json::json!({
+            "supergraph": { "get_query_cache_size": get_query_cache_size }
+        }))
+        .unwrap()
+        .build_router()
+        .await
+        .unwrap();
+
+    let request = || -> router::Request {
+        http::Request::get(format!("http://127.0.0.1/?{QUERY_STRING}"))
+            .header("accept", "application/json")
+            .header("apollo-require-preflight", "true")
+            .body(hyper::Body::empty())
+            .unwrap()
+            .into()
+    };
+
+    // Warm up the query planner cache
+    let response = router.clone().oneshot(request()).await.unwrap();
+    assert!(response.response.status().is_success());
+
+    let start_time = Instant::now();
+    for _ in 0..REQUESTS {
+        let response = router.clone().oneshot(request()).await.unwrap();
+        assert!(response.response.status().is_success());
+    }
+    start_time.elapsed() / REQUESTS
+}
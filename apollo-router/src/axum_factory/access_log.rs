@@ -0,0 +1,423 @@
+//! Access log records for the requests received on the supergraph listener
+
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Instant;
+
+use axum::body::BoxBody;
+use axum::extract::ConnectInfo;
+use axum::extract::OriginalUri;
+use axum::extract::State;
+use axum::middleware::Next;
+use axum::response::Response;
+use bytes::Bytes;
+use http::HeaderMap;
+use http::Method;
+use http::Request;
+use http::StatusCode;
+use http_body::Body as _;
+use hyper::Body;
+use itertools::Itertools;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::configuration::ip_net::IpNet;
+use crate::configuration::AccessLog;
+use crate::configuration::AccessLogField;
+use crate::configuration::AccessLogFormat;
+
+/// The target of the access log events, to filter them in or out of the logs
+pub(crate) const ACCESS_LOG_TARGET: &str = "apollo_router::access_log";
+
+/// The operation name of a GraphQL request, added to the response extensions
+/// for the access log
+#[derive(Clone, Debug)]
+pub(crate) struct OperationName(pub(crate) String);
+
+/// Logs a record once the response body has been sent, or dropped if the client disconnected.
+///
+/// The state holds the configuration of the access log, and the trusted proxies.
+pub(super) async fn access_log(
+    State((config, trusted_proxies)): State<(Arc<AccessLog>, Arc<Vec<IpNet>>)>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let start = Instant::now();
+    let method = request.method().clone();
+    // nested routers, like with a path prefix, only see the end of the path
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.0.path())
+        .unwrap_or_else(|| request.uri().path())
+        .to_string();
+    let client_ip = client_ip(
+        request.headers(),
+        request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0),
+        &trusted_proxies,
+    );
+
+    let response = next.run(request).await;
+
+    let record = Record {
+        config,
+        start,
+        method,
+        path,
+        status: response.status(),
+        client_ip,
+        operation_name: response
+            .extensions()
+            .get::<OperationName>()
+            .map(|name| name.0.clone()),
+    };
+    response.map(|body| {
+        axum::body::boxed(LoggedBody {
+            inner: body,
+            record: Some(record),
+            bytes: 0,
+        })
+    })
+}
+
+/// The address of the client, found by walking the `X-Forwarded-For` entries from the
+/// right, from the peer address: each trusted proxy gives the address it received the request
+/// from, and the first address that is not a trusted proxy is the client. Only the proxies
+/// can be trusted to append the right entries, any client can set the entries on the left.
+///
+/// Every address is trusted when no proxy is configured, and the leftmost entry is used.
+/// Clients connected through a Unix socket, without a peer address, are trusted as well.
+/// The walk stops at an entry that is not an IP address, and the standard `Forwarded` header
+/// is ignored.
+fn client_ip(
+    headers: &HeaderMap,
+    peer_addr: Option<SocketAddr>,
+    trusted_proxies: &[IpNet],
+) -> Option<String> {
+    let is_trusted = |ip: IpAddr| {
+        trusted_proxies.is_empty() || trusted_proxies.iter().any(|net| net.contains(ip))
+    };
+
+    let mut client = peer_addr.map(|addr| addr.ip());
+    if client.map_or(true, is_trusted) {
+        let forwarded_for = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        for entry in forwarded_for.into_iter().rev() {
+            let ip = match entry.parse::<IpAddr>() {
+                Ok(ip) => ip,
+                // the entries of some proxies have a port
+                Err(_) => match entry.parse::<SocketAddr>() {
+                    Ok(addr) => addr.ip(),
+                    Err(_) => break,
+                },
+            };
+            client = Some(ip);
+            if !is_trusted(ip) {
+                break;
+            }
+        }
+    }
+    client.map(|ip| ip.to_string())
+}
+
+struct Record {
+    config: Arc<AccessLog>,
+    start: Instant,
+    method: Method,
+    path: String,
+    status: StatusCode,
+    client_ip: Option<String>,
+    operation_name: Option<String>,
+}
+
+impl Record {
+    fn format(&self, bytes: usize) -> String {
+        let mut fields = Map::new();
+        for field in &self.config.fields {
+            let (key, value) = match field {
+                AccessLogField::Method => ("method", json!(self.method.as_str())),
+                AccessLogField::Path => ("path", json!(self.path)),
+                AccessLogField::Status => ("status", json!(self.status.as_u16())),
+                AccessLogField::Duration => ("duration", json!(self.start.elapsed().as_secs_f64())),
+                AccessLogField::Bytes => ("bytes", json!(bytes)),
+                AccessLogField::ClientIp => ("client_ip", json!(self.client_ip)),
+                AccessLogField::OperationName => ("operation_name", json!(self.operation_name)),
+            };
+            fields.insert(key.to_string(), value);
+        }
+
+        match self.config.format {
+            AccessLogFormat::Json => Value::Object(fields).to_string(),
+            AccessLogFormat::Text => fields
+                .iter()
+                .map(|(key, value)| format!("{key}={}", text_value(value)))
+                .join(" "),
+        }
+    }
+}
+
+/// Values are quoted only when needed to split the record on spaces
+fn text_value(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s)
+            if !s.is_empty()
+                && !s.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') =>
+        {
+            s.clone()
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Counts the bytes of the response body, and logs the record when it is dropped
+struct LoggedBody {
+    inner: BoxBody,
+    record: Option<Record>,
+    bytes: usize,
+}
+
+impl http_body::Body for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(Some(Ok(data))) = &poll {
+            self.bytes += data.len();
+        }
+        poll
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        if let Some(record) = self.record.take() {
+            tracing::info!(target: ACCESS_LOG_TARGET, "{}", record.format(self.bytes));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use axum::middleware;
+    use axum::response::IntoResponse;
+    use axum::routing::post;
+    use axum::Router;
+    use parking_lot::Mutex;
+    use tower::ServiceExt;
+    use tracing::field::Field;
+    use tracing::field::Visit;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Layer;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct CaptureRecords(Arc<Mutex<Vec<String>>>);
+
+    impl CaptureRecords {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut self.0.lock())
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for CaptureRecords {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            struct Message<'a>(&'a mut Vec<String>);
+            impl Visit for Message<'_> {
+                fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                    if field.name() == "message" {
+                        self.0.push(format!("{value:?}"));
+                    }
+                }
+            }
+
+            if event.metadata().target() == ACCESS_LOG_TARGET {
+                event.record(&mut Message(&mut self.0.lock()));
+            }
+        }
+    }
+
+    async fn send(config: AccessLog, trusted_proxies: &[&str]) -> Vec<String> {
+        let trusted_proxies = Arc::new(
+            trusted_proxies
+                .iter()
+                .map(|net| net.parse().unwrap())
+                .collect::<Vec<IpNet>>(),
+        );
+        let records = CaptureRecords::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(records.clone()));
+
+        let app = Router::new()
+            .route(
+                "/graphql",
+                post(|| async {
+                    let mut response = (StatusCode::OK, "{\"data\":{}}").into_response();
+                    response
+                        .extensions_mut()
+                        .insert(OperationName("GetMe".to_string()));
+                    response
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                (Arc::new(config), trusted_proxies),
+                access_log,
+            ));
+        let mut request = Request::post("/graphql?extra=1")
+            .header("x-forwarded-for", "192.0.2.1, 10.0.0.1")
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))));
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        // the record is only logged once the body was sent
+        assert!(records.0.lock().is_empty());
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        records.take()
+    }
+
+    #[tokio::test]
+    async fn it_logs_a_json_record_per_request() {
+        let records = send(
+            AccessLog {
+                enabled: true,
+                ..Default::default()
+            },
+            &[],
+        )
+        .await;
+        assert_eq!(records.len(), 1);
+
+        let mut record: Value = serde_json::from_str(&records[0]).unwrap();
+        assert!(record["duration"].as_f64().unwrap() >= 0.0);
+        record.as_object_mut().unwrap().remove("duration");
+        assert_eq!(
+            record,
+            json!({
+                "method": "POST",
+                "path": "/graphql",
+                "status": 200,
+                "bytes": 11,
+                "client_ip": "192.0.2.1",
+                "operation_name": "GetMe"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_logs_the_configured_fields_as_text() {
+        let records = send(
+            AccessLog {
+                enabled: true,
+                format: AccessLogFormat::Text,
+                fields: vec![
+                    AccessLogField::Status,
+                    AccessLogField::Method,
+                    AccessLogField::OperationName,
+                ],
+            },
+            &[],
+        )
+        .await;
+        assert_eq!(records, vec!["status=200 method=POST operation_name=GetMe"]);
+    }
+
+    #[test]
+    fn client_ip_falls_back_to_the_peer_address() {
+        let peer_addr = Some(SocketAddr::from(([10, 0, 0, 1], 4000)));
+        assert_eq!(
+            client_ip(&HeaderMap::new(), peer_addr, &[]).as_deref(),
+            Some("10.0.0.1")
+        );
+        assert_eq!(client_ip(&HeaderMap::new(), None, &[]), None);
+    }
+
+    #[test]
+    fn client_ip_is_the_first_untrusted_address_from_the_right() {
+        let trusted_proxies = ["10.0.0.0/8".parse().unwrap()];
+        let peer_addr = Some(SocketAddr::from(([10, 0, 0, 1], 4000)));
+        let logged_ip = |forwarded_for: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", forwarded_for.parse().unwrap());
+            client_ip(&headers, peer_addr, &trusted_proxies)
+        };
+
+        // the leftmost entry was set by the client, not by a trusted proxy
+        assert_eq!(
+            logged_ip("203.0.113.7, 192.0.2.1, 10.0.0.2").as_deref(),
+            Some("192.0.2.1")
+        );
+        assert_eq!(
+            logged_ip("203.0.113.7, 192.0.2.1:5432").as_deref(),
+            Some("192.0.2.1")
+        );
+        // a request going only through trusted proxies
+        assert_eq!(logged_ip("10.0.0.3, 10.0.0.2").as_deref(), Some("10.0.0.3"));
+        // the walk stops at an invalid entry
+        assert_eq!(
+            logged_ip("192.0.2.1, unknown, 10.0.0.2").as_deref(),
+            Some("10.0.0.2")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_only_uses_forwarded_for_from_trusted_proxies() {
+        let config = AccessLog {
+            enabled: true,
+            format: AccessLogFormat::Text,
+            fields: vec![AccessLogField::ClientIp],
+        };
+        // the client connection comes from 10.0.0.1
+        assert_eq!(
+            send(config.clone(), &["10.0.0.0/8"]).await,
+            vec!["client_ip=192.0.2.1"]
+        );
+        assert_eq!(
+            send(config, &["172.16.0.0/12"]).await,
+            vec!["client_ip=10.0.0.1"]
+        );
+    }
+}
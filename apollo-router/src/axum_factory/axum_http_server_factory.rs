@@ -1,10 +1,13 @@
 // With regards to ELv2 licensing, this entire file is license key functionality
 
 //! Axum http server factory. Axum provides routing capability on top of Hyper HTTP.
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 use std::time::Instant;
 
 use axum::extract::Extension;
@@ -15,17 +18,22 @@ use axum::middleware::Next;
 use axum::response::*;
 use axum::routing::get;
 use axum::Router;
+use bytes::Bytes;
 use futures::channel::oneshot;
 use futures::future::join;
 use futures::future::join_all;
 use futures::prelude::*;
 use http::header::ACCEPT_ENCODING;
 use http::header::CONTENT_ENCODING;
+use http::header::CONTENT_TYPE;
+use http::header::WWW_AUTHENTICATE;
 use http::HeaderValue;
+use http::Method;
 use http::Request;
-use http_body::combinators::UnsyncBoxBody;
+use http::Uri;
 use hyper::Body;
 use itertools::Itertools;
+use mime::APPLICATION_JSON;
 use multimap::MultiMap;
 use serde::Serialize;
 #[cfg(unix)]
@@ -34,30 +42,50 @@ use tokio::sync::mpsc;
 use tokio_rustls::TlsAcceptor;
 use tower::service_fn;
 use tower::BoxError;
+use tower::Service;
 use tower::ServiceExt;
 use tower_http::trace::TraceLayer;
 
+use super::access_log::access_log;
+use super::access_log::OperationName;
+use super::health::SubgraphHealth;
+use super::health::SubgraphStatus;
 use super::listeners::ensure_endpoints_consistency;
 use super::listeners::ensure_listenaddrs_consistency;
 use super::listeners::extra_endpoints;
 use super::listeners::ListenersAndRouters;
+use super::utils::answer_options_requests;
 use super::utils::decompress_request_body;
+use super::utils::graphql_error_response;
+use super::utils::limit_request_body;
+use super::utils::mark_untrusted_peer;
+use super::utils::reject_forbidden_preflight;
+use super::utils::reject_upgrade_requests;
+use super::utils::DisconnectTracked;
 use super::utils::PropagatingMakeSpan;
 use super::ListenAddrAndRouter;
 use crate::axum_factory::compression::Compressor;
 use crate::axum_factory::listeners::get_extra_listeners;
 use crate::axum_factory::listeners::serve_router_on_listen_addr;
+use crate::axum_factory::listeners::ConnectionLimits;
 use crate::configuration::Configuration;
 use crate::configuration::ListenAddr;
+use crate::configuration::RouteGroup;
+use crate::error::FetchError;
+use crate::http_ext::is_authorized;
+#[cfg(unix)]
+use crate::http_server_factory::bind_error;
 use crate::http_server_factory::HttpServerFactory;
 use crate::http_server_factory::HttpServerHandle;
 use crate::http_server_factory::Listener;
+use crate::plugin::HealthProbe;
 use crate::plugins::traffic_shaping::Elapsed;
 use crate::plugins::traffic_shaping::RateLimited;
 use crate::router::ApolloRouterError;
 use crate::router_factory::Endpoint;
 use crate::router_factory::RouterFactory;
 use crate::services::router;
+use crate::services::router_service::OPERATION_NAME;
 use crate::uplink::entitlement::EntitlementState;
 use crate::uplink::entitlement::ENTITLEMENT_EXPIRED_SHORT_MESSAGE;
 
@@ -74,7 +102,6 @@ impl AxumHttpServerFactory {
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
-#[allow(dead_code)]
 enum HealthStatus {
     Up,
     Down,
@@ -83,6 +110,51 @@ enum HealthStatus {
 #[derive(Debug, Serialize)]
 struct Health {
     status: HealthStatus,
+    /// Only reported when the detailed health check is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<&'static str>,
+    /// Only reported when the detailed health check is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_hash: Option<String>,
+    /// Only reported when the subgraphs are probed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subgraphs: Option<BTreeMap<String, SubgraphStatus>>,
+}
+
+/// Runs the health probes of the plugins, a probe that does not complete in time fails
+async fn run_health_probes(probes: &[HealthProbe], timeout: Duration) -> bool {
+    match tokio::time::timeout(timeout, join_all(probes.iter().map(HealthProbe::check))).await {
+        Ok(results) => results.into_iter().all(|passed| passed),
+        Err(_) => {
+            tracing::warn!("health probes did not complete within {timeout:?}");
+            false
+        }
+    }
+}
+
+/// The subgraph URLs from the schema, with the ones set by the `override_subgraph_url` plugin
+fn subgraph_urls<RF>(service_factory: &RF, configuration: &Configuration) -> HashMap<String, Uri>
+where
+    RF: RouterFactory,
+{
+    let mut urls = service_factory.subgraph_urls();
+    let overrides = configuration
+        .apollo_plugins
+        .plugins
+        .get("override_subgraph_url")
+        .and_then(|overrides| {
+            serde_json::from_value::<HashMap<String, String>>(overrides.clone()).ok()
+        })
+        .unwrap_or_default();
+    for (name, url) in overrides {
+        match url.parse() {
+            Ok(url) => {
+                urls.insert(name, url);
+            }
+            Err(_) => tracing::error!("invalid URL '{url}' for subgraph '{name}'"),
+        }
+    }
+    urls
 }
 
 pub(crate) fn make_axum_router<RF>(
@@ -94,6 +166,50 @@ pub(crate) fn make_axum_router<RF>(
 where
     RF: RouterFactory,
 {
+    if configuration.admin.enabled {
+        tracing::info!(
+            "Configuration endpoint exposed at {}/admin/config",
+            configuration.admin.listen
+        );
+        // the configuration does not change for the lifetime of the server
+        let redacted_configuration = Bytes::from(
+            serde_json::to_vec(&configuration.redacted())
+                .map_err(|e| ApolloRouterError::ServiceCreationError(e.into()))?,
+        );
+        let token = configuration.admin.token.clone();
+        endpoints.insert(
+            configuration.admin.listen.clone(),
+            Endpoint::from_router_service(
+                "/admin/config".to_string(),
+                service_fn(move |req: router::Request| {
+                    tracing::info!(
+                        monotonic_counter.apollo_router_http_route_requests_total = 1u64,
+                        route = "admin_config"
+                    );
+                    let response = if is_authorized(req.router_request.headers(), token.as_deref())
+                    {
+                        http::Response::builder()
+                            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                            .body::<hyper::Body>(redacted_configuration.clone().into())
+                    } else {
+                        http::Response::builder()
+                            .status(StatusCode::UNAUTHORIZED)
+                            .header(WWW_AUTHENTICATE, "Bearer")
+                            .body(hyper::Body::empty())
+                    };
+                    async move {
+                        Ok(router::Response {
+                            response: response?,
+                            context: req.context,
+                        })
+                    }
+                })
+                .boxed(),
+            )
+            .in_group(RouteGroup::Admin),
+        );
+    }
+
     ensure_listenaddrs_consistency(configuration, &endpoints)?;
 
     if configuration.health_check.enabled {
@@ -101,31 +217,81 @@ where
             "Health check endpoint exposed at {}/health",
             configuration.health_check.listen
         );
+        let detailed = configuration.health_check.detailed;
+        let schema_hash = service_factory.schema_id();
+        let subgraph_health = configuration
+            .health_check
+            .subgraphs
+            .as_ref()
+            .map(|config| {
+                SubgraphHealth::spawn(subgraph_urls(&service_factory, configuration), config)
+                    .map_err(|e| ApolloRouterError::ServiceCreationError(e.into()))
+            })
+            .transpose()?;
+        let health_probes = Arc::new(service_factory.health_probes());
+        let probe_timeout = configuration.health_check.probe_timeout;
         endpoints.insert(
             configuration.health_check.listen.clone(),
             Endpoint::from_router_service(
                 "/health".to_string(),
                 service_fn(move |req: router::Request| {
-                    let health = Health {
-                        status: HealthStatus::Up,
+                    tracing::info!(
+                        monotonic_counter.apollo_router_http_route_requests_total = 1u64,
+                        route = "health"
+                    );
+                    let (subgraphs_healthy, subgraphs) = match &subgraph_health {
+                        Some(subgraph_health) => {
+                            let (healthy, subgraphs) = subgraph_health.report();
+                            (healthy, Some(subgraphs))
+                        }
+                        None => (true, None),
                     };
-                    tracing::trace!(?health, request = ?req.router_request, "health check");
+                    let schema_hash = schema_hash.clone().filter(|_| detailed);
+                    let health_probes = health_probes.clone();
                     async move {
+                        let healthy = subgraphs_healthy
+                            && run_health_probes(&health_probes, probe_timeout).await;
+                        let health = Health {
+                            status: if healthy {
+                                HealthStatus::Up
+                            } else {
+                                HealthStatus::Down
+                            },
+                            version: detailed.then_some(env!("CARGO_PKG_VERSION")),
+                            schema_hash,
+                            subgraphs,
+                        };
+                        tracing::trace!(?health, request = ?req.router_request, "health check");
                         Ok(router::Response {
-                            response: http::Response::builder().body::<hyper::Body>(
-                                serde_json::to_vec(&health).map_err(BoxError::from)?.into(),
-                            )?,
+                            response: http::Response::builder()
+                                .status(if healthy {
+                                    StatusCode::OK
+                                } else {
+                                    StatusCode::SERVICE_UNAVAILABLE
+                                })
+                                .body::<hyper::Body>(
+                                    serde_json::to_vec(&health).map_err(BoxError::from)?.into(),
+                                )?,
                             context: req.context,
                         })
                     }
                 })
                 .boxed(),
-            ),
+            )
+            .in_group(RouteGroup::Health),
         );
     }
 
     ensure_endpoints_consistency(configuration, &endpoints)?;
 
+    for (_, endpoints) in endpoints.iter_all_mut() {
+        for endpoint in endpoints {
+            if let Some(group) = endpoint.group {
+                endpoint.timeout = configuration.supergraph.route_timeouts.for_group(group);
+            }
+        }
+    }
+
     let mut main_endpoint = main_endpoint(
         service_factory,
         configuration,
@@ -216,10 +382,11 @@ impl HttpServerFactory for AxumHttpServerFactory {
                         })
                     }) {
                         Some(listener) => listener,
-                        None => Listener::Unix(
-                            UnixListener::bind(path)
-                                .map_err(ApolloRouterError::ServerCreationError)?,
-                        ),
+                        None => {
+                            Listener::Unix(UnixListener::bind(&path).map_err(|source| {
+                                bind_error(ListenAddr::UnixSocket(path), source)
+                            })?)
+                        }
                     }
                 }
             };
@@ -231,6 +398,7 @@ impl HttpServerFactory for AxumHttpServerFactory {
                 main_listener,
                 actual_main_listen_address.clone(),
                 all_routers.main.1,
+                ConnectionLimits::from(&configuration.supergraph),
                 all_connections_stopped_sender.clone(),
             );
 
@@ -243,7 +411,8 @@ impl HttpServerFactory for AxumHttpServerFactory {
             // serve extra routers
 
             let listeners_and_routers =
-                get_extra_listeners(previous_listeners, all_routers.extra).await?;
+                get_extra_listeners(previous_listeners, all_routers.extra, &configuration.tls)
+                    .await?;
 
             let actual_extra_listen_adresses = listeners_and_routers
                 .iter()
@@ -269,6 +438,7 @@ impl HttpServerFactory for AxumHttpServerFactory {
                             listener,
                             listen_addr.clone(),
                             router,
+                            ConnectionLimits::from(&configuration.supergraph),
                             all_connections_stopped_sender.clone(),
                         );
                         (
@@ -321,8 +491,16 @@ where
         ApolloRouterError::ServiceCreationError(format!("CORS configuration error: {e}").into())
     })?;
 
-    let main_route = main_router::<RF>(configuration)
+    let mut main_route = main_router::<RF>(configuration);
+    if let Some(timeout) = configuration.supergraph.route_timeouts.graphql {
+        main_route = main_route.layer(middleware::from_fn_with_state(timeout, route_timeout));
+    }
+    let main_route = main_route
         .layer(middleware::from_fn(decompress_request_body))
+        .layer(middleware::from_fn_with_state(
+            configuration.supergraph.max_request_bytes,
+            limit_request_body,
+        ))
         .layer(middleware::from_fn_with_state(
             (entitlement, Instant::now(), Arc::new(AtomicU64::new(0))),
             entitlement_handler,
@@ -330,15 +508,71 @@ where
         .layer(TraceLayer::new_for_http().make_span_with(PropagatingMakeSpan { entitlement }))
         .layer(Extension(service_factory))
         .layer(cors);
-
-    let route = endpoints_on_main_listener
+    // runs before the CORS layer, which answers preflight requests itself
+    let main_route = match configuration.cors.rejected_preflight_origins() {
+        Some(allowed_origins) => main_route.layer(middleware::from_fn_with_state(
+            allowed_origins,
+            reject_forbidden_preflight,
+        )),
+        None => main_route,
+    };
+    // the other OPTIONS requests are answered before the CORS layer as well, only on the
+    // GraphQL routes
+    let main_route = main_route
+        .route_layer(middleware::from_fn(answer_options_requests))
+        .route_layer(middleware::from_fn(reject_upgrade_requests));
+
+    let mut route = endpoints_on_main_listener
         .into_iter()
         .fold(main_route, |acc, r| acc.merge(r.into_router()));
 
+    if configuration.supergraph.access_log.enabled {
+        route = route.layer(middleware::from_fn_with_state(
+            (
+                Arc::new(configuration.supergraph.access_log.clone()),
+                Arc::new(configuration.supergraph.trusted_proxies.clone()),
+            ),
+            access_log,
+        ));
+    }
+    // marks the requests whose client identification headers are ignored
+    if !configuration.supergraph.trusted_proxies.is_empty() {
+        route = route.layer(middleware::from_fn_with_state(
+            Arc::new(configuration.supergraph.trusted_proxies.clone()),
+            mark_untrusted_peer,
+        ));
+    }
+
+    if let Some(path_prefix) = &configuration.supergraph.path_prefix {
+        let path_prefix = path_prefix.trim_end_matches('/');
+        if !path_prefix.starts_with('/') || path_prefix.contains(['*', ':']) {
+            return Err(ApolloRouterError::ServiceCreationError(
+                format!(
+                    "invalid path prefix '{path_prefix}': it must start with '/' and cannot contain wildcards or parameters"
+                )
+                .into(),
+            ));
+        }
+        // axum strips the prefix from the nested requests
+        route = Router::new().nest(path_prefix, route.clone()).merge(route);
+    }
+
     let listener = configuration.supergraph.listen.clone();
     Ok(ListenAddrAndRouter(listener, route))
 }
 
+/// Answers with a HTTP 504 Gateway Timeout if the response is not ready in time
+async fn route_timeout<B>(
+    State(timeout): State<Duration>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => Elapsed::new().into_response(),
+    }
+}
+
 async fn entitlement_handler<B>(
     State((entitlement, start, delta)): State<(EntitlementState, Instant, Arc<AtomicU64>)>,
     request: Request<B>,
@@ -375,10 +609,11 @@ async fn entitlement_handler<B>(
     }
 
     if matches!(entitlement, EntitlementState::EntitledHalt) {
-        http::Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
-            .body(UnsyncBoxBody::default())
-            .expect("canned response must be valid")
+        graphql_error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ENTITLEMENT_EXPIRED_SHORT_MESSAGE,
+            "ENTITLEMENT_EXPIRED",
+        )
     } else {
         next.run(request).await
     }
@@ -388,16 +623,17 @@ pub(super) fn main_router<RF>(configuration: &Configuration) -> axum::Router
 where
     RF: RouterFactory,
 {
+    let slow_request_threshold = configuration.supergraph.slow_request_threshold;
     let mut router = Router::new().route(
         &configuration.supergraph.sanitized_path(),
         get({
             move |Extension(service): Extension<RF>, request: Request<Body>| {
-                handle_graphql(service.create().boxed(), request)
+                handle_graphql(service.create().boxed(), request, slow_request_threshold)
             }
         })
         .post({
             move |Extension(service): Extension<RF>, request: Request<Body>| {
-                handle_graphql(service.create().boxed(), request)
+                handle_graphql(service.create().boxed(), request, slow_request_threshold)
             }
         }),
     );
@@ -407,12 +643,12 @@ where
             "/",
             get({
                 move |Extension(service): Extension<RF>, request: Request<Body>| {
-                    handle_graphql(service.create().boxed(), request)
+                    handle_graphql(service.create().boxed(), request, slow_request_threshold)
                 }
             })
             .post({
                 move |Extension(service): Extension<RF>, request: Request<Body>| {
-                    handle_graphql(service.create().boxed(), request)
+                    handle_graphql(service.create().boxed(), request, slow_request_threshold)
                 }
             }),
         );
@@ -424,6 +660,7 @@ where
 async fn handle_graphql(
     service: router::BoxService,
     http_request: Request<Body>,
+    slow_request_threshold: Option<Duration>,
 ) -> impl IntoResponse {
     tracing::info!(counter.apollo_router_session_count_active = 1,);
 
@@ -434,8 +671,38 @@ async fn handle_graphql(
         .headers()
         .get(ACCEPT_ENCODING)
         .cloned();
+    let method = request.router_request.method().clone();
 
-    let res = service.oneshot(request).await;
+    // the time spent waiting for the service to be ready, like when a concurrency limit is
+    // reached, is recorded apart from the time spent handling the request
+    let received = Instant::now();
+    let res = match service.ready_oneshot().await {
+        Ok(mut service) => {
+            tracing::info!(
+                histogram.apollo_router_queue_time_seconds = received.elapsed().as_secs_f64(),
+            );
+            let called = Instant::now();
+            let res = service.call(request).await;
+            tracing::info!(
+                histogram.apollo_router_service_time_seconds = called.elapsed().as_secs_f64(),
+            );
+            res
+        }
+        Err(e) => Err(e),
+    };
+    // the time until the response is ready, the time spent sending its body is not counted
+    if let Some(threshold) = slow_request_threshold {
+        let elapsed = received.elapsed();
+        if elapsed > threshold {
+            let operation_name = context.get::<_, String>(OPERATION_NAME).ok().flatten();
+            tracing::warn!(
+                operation_name = operation_name.as_deref().unwrap_or_default(),
+                duration = ?elapsed,
+                "slow request: answered in {elapsed:?}, over the {threshold:?} threshold"
+            );
+            tracing::info!(monotonic_counter.apollo_router_slow_requests_total = 1u64);
+        }
+    }
     let dur = context.busy_time();
     let processing_seconds = dur.as_secs_f64();
 
@@ -458,16 +725,27 @@ async fn handle_graphql(
             if e.is::<Elapsed>() {
                 return Elapsed::new().into_response();
             }
+            // the request failed at the transport level, like a subgraph request
+            if let Some(fetch_error) = e
+                .downcast_ref::<FetchError>()
+                .or_else(|| e.source().and_then(|e| e.downcast_ref::<FetchError>()))
+            {
+                return (fetch_error.http_status(), Json(fetch_error.to_response()))
+                    .into_response();
+            }
 
-            (
+            graphql_error_response(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "router service call failed",
+                "INTERNAL_SERVER_ERROR",
             )
-                .into_response()
         }
         Ok(response) => {
             tracing::info!(counter.apollo_router_session_count_active = -1,);
             let (mut parts, body) = response.response.into_parts();
+            if let Ok(Some(operation_name)) = context.get::<_, String>(OPERATION_NAME) {
+                parts.extensions.insert(OperationName(operation_name));
+            }
 
             let opt_compressor = accept_encoding
                 .as_ref()
@@ -484,7 +762,12 @@ async fn handle_graphql(
                 }
             };
 
-            http::Response::from_parts(parts, body).into_response()
+            if method == Method::HEAD {
+                // the body of responses to HEAD requests is never sent
+                http::Response::from_parts(parts, body).into_response()
+            } else {
+                http::Response::from_parts(parts, DisconnectTracked::new(body)).into_response()
+            }
         }
     }
 }
@@ -0,0 +1,205 @@
+// With regards to ELv2 licensing, this entire file is license key functionality
+//! Subgraph reachability reported by the health check
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::join_all;
+use http::header::CONTENT_TYPE;
+use http::Uri;
+use mime::APPLICATION_JSON;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+
+use crate::configuration::SubgraphHealthCheck;
+
+/// The query sent to probe a subgraph, answered by any GraphQL server
+const PROBE_QUERY: &str = r#"{"query":"query HealthCheck { __typename }"}"#;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum SubgraphStatus {
+    Up,
+    Down,
+    /// No probe result more recent than `stale_after`, or the last probe timed out
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Probe {
+    /// `None` when the subgraph did not answer within the probe timeout
+    reachable: Option<bool>,
+    at: Instant,
+}
+
+/// Probes the subgraphs in a background task, stopped when the last clone is dropped
+#[derive(Clone)]
+pub(crate) struct SubgraphHealth {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    subgraphs: Vec<String>,
+    required: Vec<String>,
+    stale_after: Duration,
+    probes: Arc<Mutex<HashMap<String, Probe>>>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl SubgraphHealth {
+    /// Starts probing the subgraphs. Returns an error naming the required subgraphs
+    /// without a known URL.
+    pub(crate) fn spawn(
+        urls: HashMap<String, Uri>,
+        config: &SubgraphHealthCheck,
+    ) -> Result<Self, String> {
+        let unknown = config
+            .required
+            .iter()
+            .filter(|name| !urls.contains_key(*name))
+            .map(|name| format!("'{name}'"))
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            return Err(format!(
+                "the health check requires unknown subgraphs: {}",
+                unknown.join(", ")
+            ));
+        }
+
+        let mut subgraphs = urls.keys().cloned().collect::<Vec<_>>();
+        subgraphs.sort();
+        let probes = Arc::new(Mutex::new(HashMap::new()));
+        let task = tokio::spawn(probe_periodically(
+            urls,
+            config.interval,
+            config.timeout(),
+            probes.clone(),
+        ));
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                subgraphs,
+                required: config.required.clone(),
+                stale_after: config.stale_after(),
+                probes,
+                task,
+            }),
+        })
+    }
+
+    /// Returns whether all the required subgraphs are up, and the status of every subgraph
+    pub(crate) fn report(&self) -> (bool, BTreeMap<String, SubgraphStatus>) {
+        let probes = self.inner.probes.lock();
+        let statuses = self
+            .inner
+            .subgraphs
+            .iter()
+            .map(|name| {
+                let status = match probes.get(name) {
+                    Some(probe) if probe.at.elapsed() <= self.inner.stale_after => {
+                        match probe.reachable {
+                            Some(true) => SubgraphStatus::Up,
+                            Some(false) => SubgraphStatus::Down,
+                            None => SubgraphStatus::Unknown,
+                        }
+                    }
+                    _ => SubgraphStatus::Unknown,
+                };
+                (name.clone(), status)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let healthy = self
+            .inner
+            .required
+            .iter()
+            .all(|name| statuses.get(name) == Some(&SubgraphStatus::Up));
+
+        (healthy, statuses)
+    }
+}
+
+async fn probe_periodically(
+    urls: HashMap<String, Uri>,
+    interval: Duration,
+    timeout: Duration,
+    probes: Arc<Mutex<HashMap<String, Probe>>>,
+) {
+    // the probes are timed out by the deadline of their round, not by the client
+    let client = match reqwest::Client::builder().build() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!("cannot create the subgraph health check client: {err}");
+            return;
+        }
+    };
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        // all the probes of a round must finish within `timeout` of its start, so a round
+        // ends before the next one starts
+        let deadline = interval.tick().await + timeout;
+        // every result is recorded as soon as it is known, a slow subgraph does not hold
+        // back the others
+        join_all(urls.iter().map(|(name, url)| {
+            let client = &client;
+            let probes = &probes;
+            async move {
+                let reachable =
+                    match tokio::time::timeout_at(deadline, probe(client, name, url)).await {
+                        Ok(reachable) => Some(reachable),
+                        Err(_) => {
+                            tracing::debug!(
+                                subgraph = name.as_str(),
+                                "subgraph health check timed out after {timeout:?}"
+                            );
+                            None
+                        }
+                    };
+                probes.lock().insert(
+                    name.clone(),
+                    Probe {
+                        reachable,
+                        at: Instant::now(),
+                    },
+                );
+            }
+        }))
+        .await;
+    }
+}
+
+async fn probe(client: &reqwest::Client, name: &str, url: &Uri) -> bool {
+    let response = client
+        .post(url.to_string())
+        .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+        .body(PROBE_QUERY)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => true,
+        Ok(response) => {
+            tracing::debug!(
+                subgraph = name,
+                status = response.status().as_u16(),
+                "subgraph health check failed"
+            );
+            false
+        }
+        Err(err) => {
+            tracing::debug!(subgraph = name, "subgraph health check failed: {err}");
+            false
+        }
+    }
+}
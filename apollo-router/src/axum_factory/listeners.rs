@@ -2,22 +2,51 @@
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::io::IoSlice;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
 use std::time::Duration;
 use std::time::Instant;
 
+use axum::extract::ConnectInfo;
 use axum::response::*;
 use axum::Router;
 use futures::channel::oneshot;
 use futures::prelude::*;
+use http::header::CONNECTION;
+use http::HeaderValue;
+use http::Request;
+use http::Version;
 use hyper::server::conn::Http;
+use hyper::Body;
 use multimap::MultiMap;
+use parking_lot::Mutex;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
 #[cfg(unix)]
 use tokio::net::UnixListener;
 use tokio::sync::mpsc;
 use tokio::sync::Notify;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tower::service_fn;
+use tower::util::Either;
+use tower::Service;
+use tower::ServiceExt;
 
 use crate::configuration::Configuration;
+use crate::configuration::Http2;
+use crate::configuration::Supergraph;
+use crate::configuration::Tls;
+#[cfg(unix)]
+use crate::http_server_factory::bind_error;
 use crate::http_server_factory::Listener;
 use crate::http_server_factory::NetworkStream;
 use crate::router::ApolloRouterError;
@@ -142,13 +171,31 @@ pub(super) fn ensure_listenaddrs_consistency(
 pub(super) async fn get_extra_listeners(
     previous_listeners: Vec<(ListenAddr, Listener)>,
     mut extra_routers: MultiMap<ListenAddr, Router>,
+    tls: &Tls,
 ) -> Result<Vec<((ListenAddr, Listener), axum::Router)>, ApolloRouterError> {
     let mut listeners_and_routers: Vec<((ListenAddr, Listener), axum::Router)> =
         Vec::with_capacity(extra_routers.len());
 
-    // reuse previous extra listen addrs
+    // check the TLS configuration of all the listen addrs before using any listener
+    let mut tls_acceptors = extra_routers
+        .keys()
+        .map(|listen_addr| {
+            let acceptor = tls.listener_tls_config(listen_addr)?.map(TlsAcceptor::from);
+            Ok((listen_addr.clone(), acceptor))
+        })
+        .collect::<Result<HashMap<_, _>, ApolloRouterError>>()?;
+
+    // reuse previous extra listen addrs, with their new TLS configuration
     for (listen_addr, listener) in previous_listeners.into_iter() {
         if let Some(routers) = extra_routers.remove(&listen_addr) {
+            let tls_acceptor = tls_acceptors.remove(&listen_addr).flatten();
+            let listener = match listener {
+                Listener::Tcp(listener) | Listener::Tls { listener, .. } => {
+                    Listener::new_from_listener(listener, tls_acceptor)
+                }
+                #[cfg(unix)]
+                listener @ Listener::Unix(_) => listener,
+            };
             listeners_and_routers.push((
                 (listen_addr, listener),
                 routers
@@ -163,10 +210,14 @@ pub(super) async fn get_extra_listeners(
         // if we received a TCP listener, reuse it, otherwise create a new one
         #[cfg_attr(not(unix), allow(unused_mut))]
         let listener = match listen_addr.clone() {
-            ListenAddr::SocketAddr(addr) => Listener::new_from_socket_addr(addr, None).await?,
+            ListenAddr::SocketAddr(addr) => {
+                let tls_acceptor = tls_acceptors.remove(&listen_addr).flatten();
+                Listener::new_from_socket_addr(addr, tls_acceptor).await?
+            }
             #[cfg(unix)]
             ListenAddr::UnixSocket(path) => Listener::Unix(
-                UnixListener::bind(path).map_err(ApolloRouterError::ServerCreationError)?,
+                UnixListener::bind(&path)
+                    .map_err(|source| bind_error(ListenAddr::UnixSocket(path), source))?,
             ),
         };
         listeners_and_routers.push((
@@ -184,6 +235,7 @@ pub(super) fn serve_router_on_listen_addr(
     mut listener: Listener,
     address: ListenAddr,
     router: axum::Router,
+    limits: ConnectionLimits,
     all_connections_stopped_sender: mpsc::Sender<()>,
 ) -> (impl Future<Output = Listener>, oneshot::Sender<()>) {
     let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
@@ -195,13 +247,21 @@ pub(super) fn serve_router_on_listen_addr(
     let server = async move {
         tokio::pin!(shutdown_receiver);
 
-        let connection_shutdown = Arc::new(Notify::new());
+        // unlike a `Notify`, the token stays cancelled: connections spawned right before
+        // the shutdown cannot miss it
+        let connection_shutdown = CancellationToken::new();
         let mut max_open_file_warning = None;
 
         let address = address.to_string();
 
         loop {
             tokio::select! {
+                // the shutdown is checked first, so that no connection is accepted once it
+                // is requested. `Listener::accept` is cancellation safe: the connections it
+                // was waiting for stay queued in the socket, and are refused when it is
+                // closed, or accepted by the next server reusing it
+                biased;
+
                 _ = &mut shutdown_receiver => {
                     break;
                 }
@@ -209,6 +269,7 @@ pub(super) fn serve_router_on_listen_addr(
                     let app = router.clone();
                     let connection_shutdown = connection_shutdown.clone();
                     let connection_stop_signal = all_connections_stopped_sender.clone();
+                    let limits = limits.clone();
 
                     match res {
                         Ok(res) => {
@@ -221,6 +282,10 @@ pub(super) fn serve_router_on_listen_addr(
                                 counter.apollo_router_session_count_total = 1,
                                 listener = &address
                             );
+                            tracing::info!(
+                                monotonic_counter.apollo_router_connections_accepted_total = 1u64,
+                                listener = &address
+                            );
 
                             let address = address.clone();
                             tokio::task::spawn(async move {
@@ -234,79 +299,71 @@ pub(super) fn serve_router_on_listen_addr(
                                             .expect(
                                                 "this should not fail unless the socket is invalid",
                                             );
-                                            let connection = Http::new()
-                                            .http1_keep_alive(true)
-                                            .http1_header_read_timeout(Duration::from_secs(10))
-                                            .serve_connection(stream, app);
-
-                                        tokio::pin!(connection);
-                                        tokio::select! {
-                                            // the connection finished first
-                                            _res = &mut connection => {
-                                            }
-                                            // the shutdown receiver was triggered first,
-                                            // so we tell the connection to do a graceful shutdown
-                                            // on the next request, then we wait for it to finish
-                                            _ = connection_shutdown.notified() => {
-                                                let c = connection.as_mut();
-                                                c.graceful_shutdown();
-
-                                                let _= connection.await;
-                                            }
-                                        }
+                                        let peer_addr = stream.peer_addr().ok();
+
+                                        let mut http = limits.http();
+                                        http.http1_header_read_timeout(Duration::from_secs(10));
+                                        serve_connection(
+                                            stream,
+                                            http,
+                                            app,
+                                            peer_addr,
+                                            &limits,
+                                            &connection_shutdown,
+                                            &address,
+                                        ).await;
                                     }
                                     #[cfg(unix)]
                                     NetworkStream::Unix(stream) => {
-                                        let connection = Http::new()
-                                        .http1_keep_alive(true)
-                                        .serve_connection(stream, app);
-
-                                        tokio::pin!(connection);
-                                        tokio::select! {
-                                            // the connection finished first
-                                            _res = &mut connection => {
-                                            }
-                                            // the shutdown receiver was triggered first,
-                                            // so we tell the connection to do a graceful shutdown
-                                            // on the next request, then we wait for it to finish
-                                            _ = connection_shutdown.notified() => {
-                                                let c = connection.as_mut();
-                                                c.graceful_shutdown();
-
-                                                let _= connection.await;
-                                            }
-                                        }
+                                        serve_connection(
+                                            stream,
+                                            limits.http(),
+                                            app,
+                                            None,
+                                            &limits,
+                                            &connection_shutdown,
+                                            &address,
+                                        ).await;
                                     },
-                                    NetworkStream::Tls(stream) => {
-                                        stream.get_ref().0
+                                    NetworkStream::Tls { stream, acceptor } => {
+                                        stream
                                             .set_nodelay(true)
                                             .expect(
                                                 "this should not fail unless the socket is invalid",
                                             );
-
+                                        let peer_addr = stream.peer_addr().ok();
+
+                                        // a slow handshake must not hold the accept loop, and the
+                                        // connection is dropped if the server shuts down before
+                                        // the handshake completes
+                                        let stream = tokio::select! {
+                                            res = acceptor.accept(stream) => res
+                                                .map_err(|e| tracing::debug!(peer_address = ?peer_addr, "TLS handshake failed: {e}"))
+                                                .ok(),
+                                            _ = connection_shutdown.cancelled() => None,
+                                        };
+                                        if let Some(stream) = stream {
+                                            // serve the protocol negotiated with ALPN, or detect it if
+                                            // the client did not use ALPN
                                             let protocol = stream.get_ref().1.alpn_protocol();
-                                            let http2 = protocol == Some(&b"h2"[..]);
-
-                                            let connection = Http::new()
-                                            .http1_keep_alive(true)
-                                            .http1_header_read_timeout(Duration::from_secs(10))
-                                            .http2_only(http2)
-                                            .serve_connection(stream, app);
-
-                                        tokio::pin!(connection);
-                                        tokio::select! {
-                                            // the connection finished first
-                                            _res = &mut connection => {
-                                            }
-                                            // the shutdown receiver was triggered first,
-                                            // so we tell the connection to do a graceful shutdown
-                                            // on the next request, then we wait for it to finish
-                                            _ = connection_shutdown.notified() => {
-                                                let c = connection.as_mut();
-                                                c.graceful_shutdown();
-
-                                                let _= connection.await;
+
+                                            let mut http = limits.http();
+                                            http.http1_header_read_timeout(Duration::from_secs(10));
+                                            // setting one of them to false would reset the other
+                                            if protocol == Some(&b"h2"[..]) {
+                                                http.http2_only(true);
+                                            } else if protocol == Some(&b"http/1.1"[..]) {
+                                                http.http1_only(true);
                                             }
+                                            serve_connection(
+                                                stream,
+                                                http,
+                                                app,
+                                                peer_addr,
+                                                &limits,
+                                                &connection_shutdown,
+                                                &address,
+                                            ).await;
                                         }
                                     }
                                 }
@@ -399,15 +456,368 @@ pub(super) fn serve_router_on_listen_addr(
         // the shutdown receiver was triggered so we break out of
         // the server loop, tell the currently active connections to stop
         // then return the TCP listen socket
-        connection_shutdown.notify_waiters();
+        connection_shutdown.cancel();
         listener
     };
     (server, shutdown_sender)
 }
 
+/// Limits applied to every client connection
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ConnectionLimits {
+    /// see `Supergraph::connection_idle_timeout`
+    pub(crate) idle_timeout: Option<Duration>,
+    /// see `Supergraph::connection_write_timeout`
+    pub(crate) write_timeout: Option<Duration>,
+    /// see `Supergraph::connection_max_bytes_sent`
+    pub(crate) max_bytes_sent: Option<usize>,
+    /// see `Supergraph::connection_max_requests`
+    pub(crate) max_requests: Option<NonZeroUsize>,
+    /// see `Supergraph::http2`
+    pub(crate) http2: Http2,
+}
+
+impl ConnectionLimits {
+    /// A builder serving HTTP/1 connections with keep-alive, and HTTP/2 connections
+    /// with the configured settings
+    fn http(&self) -> Http {
+        let mut http = Http::new();
+        http.http1_keep_alive(true)
+            .http2_max_concurrent_streams(self.http2.max_concurrent_streams)
+            .http2_initial_stream_window_size(self.http2.initial_stream_window_size)
+            .http2_initial_connection_window_size(self.http2.initial_connection_window_size)
+            .http2_keep_alive_interval(self.http2.keep_alive_interval)
+            .http2_keep_alive_timeout(self.http2.keep_alive_timeout);
+        http
+    }
+}
+
+impl From<&Supergraph> for ConnectionLimits {
+    fn from(supergraph: &Supergraph) -> Self {
+        Self {
+            idle_timeout: supergraph.connection_idle_timeout,
+            write_timeout: supergraph.connection_write_timeout,
+            max_bytes_sent: supergraph.connection_max_bytes_sent,
+            max_requests: supergraph.connection_max_requests,
+            http2: supergraph.http2.clone(),
+        }
+    }
+}
+
+/// Serves the requests of a client connection, until it is closed or the server shuts down
+async fn serve_connection<S>(
+    stream: S,
+    http: Http,
+    app: Router,
+    peer_addr: Option<SocketAddr>,
+    limits: &ConnectionLimits,
+    connection_shutdown: &CancellationToken,
+    address: &str,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (stream, activity) = ActivityTracked::new(stream, limits.max_bytes_sent);
+    // the address of the client, for the access log and the services: Unix sockets
+    // have no address that would be meaningful to them, so their requests have none.
+    // The requests are also counted by listener, to compare with the accepted connections.
+    let listener = address.to_string();
+    let app = app.map_request(move |mut request: Request<Body>| {
+        tracing::info!(
+            monotonic_counter.apollo_router_requests_total = 1u64,
+            listener = listener.as_str()
+        );
+        if let Some(peer_addr) = peer_addr {
+            request.extensions_mut().insert(ConnectInfo(peer_addr));
+        }
+        request
+    });
+    let max_requests_served = Arc::new(Notify::new());
+    let app = match limits.max_requests {
+        Some(max_requests) => Either::A(limit_requests(
+            app,
+            max_requests,
+            max_requests_served.clone(),
+        )),
+        None => Either::B(app),
+    };
+    let connection = http.serve_connection(stream, app);
+
+    tokio::pin!(connection);
+    tokio::select! {
+        // the connection finished first
+        _res = &mut connection => {
+        }
+        // the shutdown receiver was triggered first,
+        // so we tell the connection to do a graceful shutdown
+        // on the next request, then we wait for it to finish
+        _ = connection_shutdown.cancelled() => {
+            let c = connection.as_mut();
+            c.graceful_shutdown();
+
+            let _= connection.await;
+        }
+        // the connection did not send or receive anything for too long
+        _ = idle(&activity, limits.idle_timeout) => {
+            tracing::info!(
+                monotonic_counter.apollo_router_idle_connections_closed_total = 1u64,
+                listener = address
+            );
+            let c = connection.as_mut();
+            c.graceful_shutdown();
+
+            let _= connection.await;
+        }
+        // the connection sent more than its budget, it is closed once the
+        // response in flight is sent
+        _ = over_budget(&activity) => {
+            tracing::info!(
+                monotonic_counter.apollo_router_over_budget_connections_closed_total = 1u64,
+                listener = address
+            );
+            let c = connection.as_mut();
+            c.graceful_shutdown();
+
+            let _= connection.await;
+        }
+        // the connection served as many requests as it could, it is closed once the
+        // last response is sent
+        _ = max_requests_served.notified() => {
+            tracing::info!(
+                monotonic_counter.apollo_router_max_requests_connections_closed_total = 1u64,
+                listener = address
+            );
+            let c = connection.as_mut();
+            c.graceful_shutdown();
+
+            let _= connection.await;
+        }
+        // the client stopped reading what we send: there is no point in
+        // a graceful shutdown, the connection is dropped
+        _ = write_stalled(&activity, limits.write_timeout) => {
+            let bytes_written = activity.lock().bytes_written;
+            tracing::warn!(
+                peer_address = ?peer_addr,
+                bytes_written,
+                listener = address,
+                "closing a connection that has not been able to send data for longer than the write timeout"
+            );
+        }
+    }
+}
+
+/// Counts the requests served by `app` on a connection, and notifies `max_requests_served`
+/// once the response to the last allowed request is ready. HTTP/1 clients are told with a
+/// `Connection: close` header on that response that they cannot send more requests.
+fn limit_requests<S>(
+    app: S,
+    max_requests: NonZeroUsize,
+    max_requests_served: Arc<Notify>,
+) -> impl Service<
+    Request<Body>,
+    Response = Response,
+    Error = S::Error,
+    Future = impl Future<Output = Result<Response, S::Error>> + Send,
+>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    let served = Arc::new(AtomicUsize::new(0));
+    service_fn(move |request: Request<Body>| {
+        let served = served.fetch_add(1, Ordering::Relaxed) + 1;
+        let last = served >= max_requests.get();
+        let http1 = request.version() < Version::HTTP_2;
+        let max_requests_served = max_requests_served.clone();
+        let response = app.clone().oneshot(request);
+        async move {
+            let mut response = response.await?;
+            if last {
+                if http1 {
+                    response
+                        .headers_mut()
+                        .insert(CONNECTION, HeaderValue::from_static("close"));
+                }
+                max_requests_served.notify_one();
+            }
+            Ok(response)
+        }
+    })
+}
+
+/// What happened on a connection, shared between its stream and the task serving it
+struct Activity {
+    /// last time data was read from or written to the connection
+    last_activity: Instant,
+    /// when the current write started waiting for the client to read
+    write_blocked_since: Option<Instant>,
+    bytes_written: usize,
+    /// see `ConnectionLimits::max_bytes_sent`
+    max_bytes_written: Option<usize>,
+    /// notified once more than `max_bytes_written` were written
+    over_budget: Arc<Notify>,
+}
+
+/// Wraps a connection's stream to record its [`Activity`]
+struct ActivityTracked<S> {
+    inner: S,
+    activity: Arc<Mutex<Activity>>,
+}
+
+impl<S> ActivityTracked<S> {
+    fn new(inner: S, max_bytes_written: Option<usize>) -> (Self, Arc<Mutex<Activity>>) {
+        let activity = Arc::new(Mutex::new(Activity {
+            last_activity: Instant::now(),
+            write_blocked_since: None,
+            bytes_written: 0,
+            max_bytes_written,
+            over_budget: Arc::new(Notify::new()),
+        }));
+        (
+            Self {
+                inner,
+                activity: activity.clone(),
+            },
+            activity,
+        )
+    }
+
+    fn record_write<T>(&self, poll: &Poll<std::io::Result<T>>, written: usize) {
+        let mut activity = self.activity.lock();
+        match poll {
+            Poll::Pending => {
+                activity
+                    .write_blocked_since
+                    .get_or_insert_with(Instant::now);
+            }
+            Poll::Ready(res) => {
+                activity.write_blocked_since = None;
+                if res.is_ok() {
+                    activity.last_activity = Instant::now();
+                    activity.bytes_written += written;
+                    if let Some(max_bytes_written) = activity.max_bytes_written {
+                        // only notified once, when crossing the limit
+                        if activity.bytes_written > max_bytes_written
+                            && activity.bytes_written - written <= max_bytes_written
+                        {
+                            activity.over_budget.notify_one();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ActivityTracked<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if buf.filled().len() > filled {
+            self.activity.lock().last_activity = Instant::now();
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ActivityTracked<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        let written = match &poll {
+            Poll::Ready(Ok(written)) => *written,
+            _ => 0,
+        };
+        self.record_write(&poll, written);
+        poll
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        let written = match &poll {
+            Poll::Ready(Ok(written)) => *written,
+            _ => 0,
+        };
+        self.record_write(&poll, written);
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_flush(cx);
+        self.record_write(&poll, 0);
+        poll
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Resolves once the connection has not sent or received anything for `timeout`,
+/// never resolves if there is no timeout
+async fn idle(activity: &Mutex<Activity>, timeout: Option<Duration>) {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return future::pending().await,
+    };
+
+    loop {
+        let deadline = activity.lock().last_activity + timeout;
+        if Instant::now() >= deadline {
+            return;
+        }
+        tokio::time::sleep_until(deadline.into()).await;
+    }
+}
+
+/// Resolves once the connection has written more than its byte budget,
+/// never resolves if there is no budget
+async fn over_budget(activity: &Mutex<Activity>) {
+    let over_budget = activity.lock().over_budget.clone();
+    over_budget.notified().await
+}
+
+/// Resolves once a write on the connection has been waiting for the client to read for
+/// `timeout`, never resolves if there is no timeout
+async fn write_stalled(activity: &Mutex<Activity>, timeout: Option<Duration>) {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return future::pending().await,
+    };
+
+    loop {
+        let blocked_since = activity.lock().write_blocked_since;
+        match blocked_since {
+            Some(blocked_since) => {
+                let deadline = blocked_since + timeout;
+                if Instant::now() >= deadline {
+                    return;
+                }
+                tokio::time::sleep_until(deadline.into()).await;
+            }
+            // check again later: a write blocked in the meantime
+            // is detected after at most twice the timeout
+            None => tokio::time::sleep(timeout).await,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::net::SocketAddr;
     use std::str::FromStr;
 
     use axum::BoxError;
@@ -417,7 +827,6 @@ mod tests {
     use super::*;
     use crate::axum_factory::tests::init_with_config;
     use crate::configuration::Sandbox;
-    use crate::configuration::Supergraph;
     use crate::services::router;
     use crate::services::router_service;
 
@@ -510,4 +919,33 @@ mod tests {
             error.to_string()
         )
     }
+
+    #[test]
+    fn it_applies_the_http2_settings() {
+        let supergraph = Supergraph::fake_builder()
+            .http2(Http2 {
+                max_concurrent_streams: Some(50),
+                initial_stream_window_size: 65_535,
+                initial_connection_window_size: 4 * 1024 * 1024,
+                keep_alive_interval: Some(Duration::from_secs(30)),
+                keep_alive_timeout: Duration::from_secs(5),
+            })
+            .build();
+        // hyper does not expose the settings of its builder
+        let http = format!("{:?}", ConnectionLimits::from(&supergraph).http());
+        for setting in [
+            "max_concurrent_streams: Some(50)",
+            "initial_stream_window_size: 65535",
+            "initial_conn_window_size: 4194304",
+            "keep_alive_interval: Some(30s)",
+            "keep_alive_timeout: 5s",
+        ] {
+            assert!(http.contains(setting), "{setting} not found in {http}");
+        }
+
+        let http = format!("{:?}", ConnectionLimits::default().http());
+        assert!(http.contains("max_concurrent_streams: None"));
+        assert!(http.contains("initial_stream_window_size: 1048576"));
+        assert!(http.contains("keep_alive_timeout: 20s"));
+    }
 }
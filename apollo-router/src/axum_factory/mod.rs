@@ -1,6 +1,8 @@
 //! axum factory is useful to create an [`AxumHttpServerFactory`] which implements [`crate::http_server_factory::HttpServerFactory`]
+mod access_log;
 mod axum_http_server_factory;
 mod compression;
+mod health;
 mod listeners;
 #[cfg(test)]
 pub(crate) mod tests;
@@ -1,12 +1,14 @@
 use std::collections::HashMap;
 use std::io;
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::atomic::AtomicU32;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
 use async_compression::tokio::write::GzipDecoder;
 use async_compression::tokio::write::GzipEncoder;
@@ -26,6 +28,7 @@ use http_body::Body;
 use mime::APPLICATION_JSON;
 use mockall::mock;
 use multimap::MultiMap;
+use opentelemetry_prometheus::PrometheusExporter;
 use reqwest::header::ACCEPT;
 use reqwest::header::ACCESS_CONTROL_ALLOW_HEADERS;
 use reqwest::header::ACCESS_CONTROL_ALLOW_METHODS;
@@ -51,12 +54,17 @@ use tower::service_fn;
 use tower::BoxError;
 use tower::Service;
 use tower::ServiceExt;
+use tracing::subscriber::DefaultGuard;
 
 pub(crate) use super::axum_http_server_factory::make_axum_router;
 use super::*;
 use crate::configuration::cors::Cors;
+use crate::configuration::Admin;
 use crate::configuration::HealthCheck;
 use crate::configuration::Homepage;
+use crate::configuration::HtmlBehavior;
+use crate::configuration::RouteGroup;
+use crate::configuration::RouteTimeouts;
 use crate::configuration::Sandbox;
 use crate::configuration::Supergraph;
 use crate::graphql;
@@ -64,6 +72,10 @@ use crate::http_server_factory::HttpServerFactory;
 use crate::http_server_factory::HttpServerHandle;
 use crate::json_ext::Path;
 use crate::plugin::test::MockSubgraph;
+use crate::plugin::test::MockSupergraphService;
+use crate::plugin::HealthProbe;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
 use crate::query_planner::BridgeQueryPlanner;
 use crate::router_factory::create_plugins;
 use crate::router_factory::Endpoint;
@@ -75,9 +87,11 @@ use crate::services::router;
 use crate::services::router_service;
 use crate::services::router_service::RouterCreator;
 use crate::services::supergraph;
+use crate::services::HasSchema;
 use crate::services::PluggableSupergraphServiceBuilder;
 use crate::services::RouterRequest;
 use crate::services::RouterResponse;
+use crate::services::SupergraphCreator;
 use crate::services::SupergraphResponse;
 use crate::services::MULTIPART_DEFER_CONTENT_TYPE;
 use crate::test_harness::http_client;
@@ -344,6 +358,60 @@ async fn init_unix(
         .expect("Failed to create server factory")
 }
 
+/// Records the metrics of the current thread in a Prometheus registry, until the guard is dropped
+pub(crate) fn prometheus_recorder() -> (PrometheusExporter, DefaultGuard) {
+    use opentelemetry::sdk::export::metrics::aggregation;
+    use opentelemetry::sdk::metrics::controllers;
+    use opentelemetry::sdk::metrics::processors;
+    use opentelemetry::sdk::metrics::selectors;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::plugins::telemetry::metrics::layer::MetricsLayer;
+
+    let controller = controllers::basic(processors::factory(
+        selectors::simple::histogram([0.001, 0.01, 0.1, 1.0]),
+        aggregation::stateless_temporality_selector(),
+    ))
+    .build();
+    let exporter = opentelemetry_prometheus::exporter(controller)
+        .try_init()
+        .unwrap();
+    let guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(MetricsLayer::new(&exporter.meter_provider().unwrap())),
+    );
+    (exporter, guard)
+}
+
+/// Scrapes the value of a metric, selected by its name and optionally some of its labels,
+/// like `apollo_router_rejected_requests_total{reason="body_too_large"}`
+pub(crate) fn scraped_value(exporter: &PrometheusExporter, metric: &str) -> Option<f64> {
+    use prometheus::Encoder;
+
+    let mut scraped = Vec::new();
+    prometheus::TextEncoder::new()
+        .encode(&exporter.registry().gather(), &mut scraped)
+        .unwrap();
+    let scraped = String::from_utf8(scraped).unwrap();
+
+    let (name, labels) = match metric.split_once('{') {
+        Some((name, labels)) => (name, labels.trim_end_matches('}')),
+        None => (metric, ""),
+    };
+    scraped
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .find(|line| {
+            let (series, _) = line.rsplit_once(' ').unwrap_or((line, ""));
+            series.split('{').next() == Some(name)
+                && labels
+                    .split(',')
+                    .filter(|label| !label.is_empty())
+                    .all(|label| series.contains(label))
+        })
+        .and_then(|line| line.rsplit(' ').next())
+        .and_then(|value| value.parse().ok())
+}
+
 #[tokio::test]
 async fn it_displays_sandbox() {
     let conf = Arc::new(
@@ -386,6 +454,121 @@ async fn it_displays_sandbox() {
     assert_eq!(response.text().await.unwrap(), sandbox_page_content());
 }
 
+#[tokio::test]
+async fn it_redirects_browsers_to_studio_with_the_configured_status() {
+    for (redirect_status, expected) in [
+        (None, StatusCode::TEMPORARY_REDIRECT),
+        (Some(302), StatusCode::FOUND),
+        (Some(308), StatusCode::PERMANENT_REDIRECT),
+    ] {
+        let conf = Arc::new(
+            Configuration::fake_builder()
+                .homepage(
+                    Homepage::fake_builder()
+                        .graph_ref("my-graph@staging".to_string())
+                        .redirect_to_studio(true)
+                        .and_redirect_status(redirect_status)
+                        .build(),
+                )
+                .build()
+                .unwrap(),
+        );
+        let router_service = router_service::from_supergraph_mock_callback_and_configuration(
+            move |_| {
+                panic!("this should never be called");
+            },
+            conf.clone(),
+        )
+        .await;
+        let (server, client) = init_with_config(router_service, conf, MultiMap::new())
+            .await
+            .unwrap();
+
+        let response = client
+            .get(&format!(
+                "{}/",
+                server.graphql_listen_address().as_ref().unwrap()
+            ))
+            .header(ACCEPT, "text/html")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "https://studio.apollographql.com/graph/my-graph/explorer?variant=staging"
+        );
+
+        server.shutdown().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn it_answers_html_requests_depending_on_their_path() {
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .homepage(
+                Homepage::fake_builder()
+                    .graph_ref("my-graph@current".to_string())
+                    .redirect_to_studio(true)
+                    .build(),
+            )
+            .supergraph(
+                Supergraph::fake_builder()
+                    .path("/*")
+                    .html_by_path(
+                        [("/graphql".to_string(), HtmlBehavior::Error)]
+                            .into_iter()
+                            .collect(),
+                    )
+                    .build(),
+            )
+            .build()
+            .unwrap(),
+    );
+    let router_service = router_service::from_supergraph_mock_callback_and_configuration(
+        move |_| {
+            panic!("this should never be called");
+        },
+        conf.clone(),
+    )
+    .await;
+    let (server, client) = init_with_config(router_service, conf, MultiMap::new())
+        .await
+        .unwrap();
+    let url = server
+        .graphql_listen_address()
+        .as_ref()
+        .unwrap()
+        .to_string();
+
+    let response = client
+        .get(&format!("{url}/"))
+        .header(ACCEPT, "text/html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+    let response = client
+        .get(&format!("{url}/graphql"))
+        .header(ACCEPT, "text/html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    let response: graphql::Response = response.json().await.unwrap();
+    assert_eq!(
+        response.errors[0]
+            .extensions
+            .get("code")
+            .and_then(|code| code.as_str()),
+        Some("HTML_NOT_SERVED")
+    );
+
+    server.shutdown().await.unwrap();
+}
+
 #[tokio::test]
 async fn it_displays_sandbox_with_different_supergraph_path() {
     let conf = Arc::new(
@@ -589,99 +772,1080 @@ async fn malformed_request() -> Result<(), ApolloRouterError> {
 }
 
 #[tokio::test]
-async fn response() -> Result<(), ApolloRouterError> {
-    let expected_response = graphql::Response::builder()
-        .data(json!({"response": "yay"}))
-        .build();
-    let example_response = expected_response.clone();
-    let router_service = router_service::from_supergraph_mock_callback(move |req| {
-        let example_response = example_response.clone();
+async fn reclaimed_listener_is_reused_without_refusing_connections() -> Result<(), ApolloRouterError>
+{
+    let (server, client) = init(router_service::empty().await).await;
+    let address = server.graphql_listen_address().clone().unwrap();
+    let url = format!("{address}/");
+    let (main_listener, extra_listeners) = server.shutdown_and_reclaim_listeners().await?;
 
-        Ok(SupergraphResponse::new_from_graphql_response(
-            example_response,
-            req.context,
-        ))
-    })
-    .await;
-    let (server, client) = init(router_service).await;
-    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+    // the socket is still bound: this connection is queued until the next server accepts it
+    let request = tokio::spawn(client.post(url).body("Garbage").send());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let (service, mut handle) = tower_test::mock::spawn();
+    let mut mock = router_service::empty().await;
+    tokio::spawn(async move {
+        while let Some((request, responder)) = handle.next_request().await {
+            match mock.ready().await.unwrap().call(request).await {
+                Ok(response) => responder.send_response(response),
+                Err(err) => responder.send_error(err),
+            }
+        }
+    });
+    let (all_connections_stopped_sender, _) = mpsc::channel::<()>(1);
+    let server = AxumHttpServerFactory::new()
+        .create(
+            TestRouterFactory {
+                inner: service.into_inner(),
+            },
+            Arc::new(
+                Configuration::fake_builder()
+                    .supergraph(Supergraph::fake_builder().listen(address.clone()).build())
+                    .build()
+                    .unwrap(),
+            ),
+            Some(main_listener),
+            extra_listeners,
+            MultiMap::new(),
+            EntitlementState::Unentitled,
+            all_connections_stopped_sender,
+        )
+        .await?;
+
+    let response = request
+        .await
+        .unwrap()
+        .expect("the connection should not have been refused");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(server.graphql_listen_address(), &Some(address));
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn get_request_without_query_string() -> Result<(), ApolloRouterError> {
+    let (server, client) = init(router_service::empty().await).await;
 
-    // Post query
     let response = client
-        .post(url.as_str())
-        .body(json!({ "query": "query" }).to_string())
+        .get(format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .header(ACCEPT, APPLICATION_JSON.essence_str())
         .send()
         .await
-        .unwrap()
-        .error_for_status()
         .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        &HeaderValue::from_static("application/json")
+    );
+    let response_json: serde_json::Value = response.json().await.unwrap();
 
+    let error = &response_json.get("errors").unwrap()[0];
     assert_eq!(
-        response.json::<graphql::Response>().await.unwrap(),
-        expected_response,
+        error.get("message").unwrap().as_str().unwrap(),
+        "Must provide query string."
     );
+    assert_eq!(
+        error["extensions"]["code"].as_str().unwrap(),
+        "MISSING_QUERY_STRING"
+    );
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_rejects_websocket_upgrade_requests() -> Result<(), ApolloRouterError> {
+    let (server, client) = init(
+        router_service::from_supergraph_mock_callback(|_| {
+            panic!("this should never be called");
+        })
+        .await,
+    )
+    .await;
 
-    // Get query
     let response = client
-        .get(url.as_str())
-        .query(&json!({ "query": "query" }))
+        .get(format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .header(header::CONNECTION, "keep-alive, Upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header("sec-websocket-protocol", "graphql-transport-ws")
         .send()
         .await
-        .unwrap()
-        .error_for_status()
         .unwrap();
-
+    assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+    let response = response.json::<graphql::Response>().await.unwrap();
     assert_eq!(
-        response.headers().get(header::CONTENT_TYPE),
-        Some(&HeaderValue::from_static(APPLICATION_JSON.essence_str()))
+        response.errors[0].message,
+        "subscriptions are disabled: the router does not accept WebSocket connections"
     );
-
     assert_eq!(
-        response.json::<graphql::Response>().await.unwrap(),
-        expected_response,
+        response.errors[0]
+            .extensions
+            .get("code")
+            .and_then(|code| code.as_str()),
+        Some("SUBSCRIPTIONS_DISABLED")
     );
 
-    server.shutdown().await?;
-    Ok(())
+    server.shutdown().await
 }
 
 #[tokio::test]
-async fn bad_response() -> Result<(), ApolloRouterError> {
-    let (server, client) = init(router_service::empty().await).await;
-    let url = format!("{}/test", server.graphql_listen_address().as_ref().unwrap());
+async fn it_rejects_expect_continue_over_the_body_limit() -> Result<(), ApolloRouterError> {
+    let conf = Configuration::fake_builder()
+        .supergraph(Supergraph::fake_builder().max_request_bytes(10).build())
+        .build()
+        .unwrap();
+    let (server, _client) = init_with_config(
+        router_service::from_supergraph_mock_callback(|_| {
+            panic!("this should never be called");
+        })
+        .await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let addr = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => *addr,
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!("the test listens on a TCP socket"),
+    };
 
-    // Post query
-    let err = client
-        .post(url.as_str())
-        .body(json!({ "query": "query" }).to_string())
+    // only send the headers, the body must not be uploaded until the server answers
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: 1000\r\n\
+            Expect: 100-continue\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+    let mut buf = vec![0u8; 1024];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("the router should answer before the body is sent")
+        .unwrap();
+    let response = String::from_utf8_lossy(&buf[..read]);
+    assert!(
+        response.starts_with("HTTP/1.1 413 Payload Too Large"),
+        "{response}"
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_rejects_chunked_bodies_over_the_limit() -> Result<(), ApolloRouterError> {
+    let conf = Configuration::fake_builder()
+        .supergraph(Supergraph::fake_builder().max_request_bytes(10).build())
+        .build()
+        .unwrap();
+    let (server, _client) = init_with_config(
+        router_service::from_supergraph_mock_callback(|_| {
+            panic!("this should never be called");
+        })
+        .await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let addr = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => *addr,
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!("the test listens on a TCP socket"),
+    };
+
+    // send a chunk over the limit, without ending the body: the router must answer
+    // without waiting for the rest of it
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Content-Type: application/json\r\n\
+            Transfer-Encoding: chunked\r\n\r\n\
+            14\r\n{\"query\":\"{ me { nam\r\n",
+        )
+        .await
+        .unwrap();
+
+    let mut buf = vec![0u8; 1024];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("the router should answer before the body is complete")
+        .unwrap();
+    let response = String::from_utf8_lossy(&buf[..read]);
+    assert!(
+        response.starts_with("HTTP/1.1 413 Payload Too Large"),
+        "{response}"
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_counts_rejected_requests_by_reason() -> Result<(), ApolloRouterError> {
+    let (exporter, _guard) = prometheus_recorder();
+
+    let conf = Configuration::fake_builder()
+        .supergraph(Supergraph::fake_builder().max_request_bytes(10).build())
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(
+        router_service::from_supergraph_mock_callback(|_| {
+            panic!("this should never be called");
+        })
+        .await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    for _ in 0..2 {
+        let response = client
+            .post(&url)
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .body("Garbage")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    let response = client
+        .post(&url)
+        .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+        .body(r#"{"query":"{ me { name } }"}"#)
         .send()
         .await
-        .unwrap()
-        .error_for_status()
-        .expect_err("should be not found");
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
 
-    assert!(err.is_status());
-    assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+    assert_eq!(
+        scraped_value(
+            &exporter,
+            r#"apollo_router_rejected_requests_total{reason="invalid_graphql_request"}"#
+        ),
+        Some(2.0)
+    );
+    assert_eq!(
+        scraped_value(
+            &exporter,
+            r#"apollo_router_rejected_requests_total{reason="body_too_large"}"#
+        ),
+        Some(1.0)
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_answers_rejected_requests_with_graphql_errors() -> Result<(), ApolloRouterError> {
+    let conf = Configuration::fake_builder()
+        .supergraph(Supergraph::fake_builder().max_request_bytes(100).build())
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(
+        router_service::from_supergraph_mock_callback(|_| {
+            panic!("this should never be called");
+        })
+        .await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let rejections = [
+        (
+            client
+                .post(&url)
+                .header(CONTENT_ENCODING, "unknown")
+                .body(r#"{"query":"{ me }"}"#),
+            StatusCode::BAD_REQUEST,
+            "INVALID_CONTENT_ENCODING",
+        ),
+        (
+            client
+                .post(&url)
+                .header(CONTENT_ENCODING, "gzip")
+                .body("not gzip"),
+            StatusCode::BAD_REQUEST,
+            "INVALID_COMPRESSED_BODY",
+        ),
+        (
+            client
+                .post(&url)
+                .header(header::EXPECT, "something")
+                .body(r#"{"query":"{ me }"}"#),
+            StatusCode::EXPECTATION_FAILED,
+            "UNSUPPORTED_EXPECT_HEADER",
+        ),
+        (
+            client.post(&url).body(format!(
+                r#"{{"query":"{{ me }}","variables":{{"a":"{}"}}}}"#,
+                "a".repeat(100)
+            )),
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "REQUEST_BODY_TOO_LARGE",
+        ),
+        (
+            client
+                .request(Method::OPTIONS, &url)
+                .header(ORIGIN, "https://forbidden.example.com")
+                .header(ACCESS_CONTROL_REQUEST_METHOD, "POST"),
+            StatusCode::FORBIDDEN,
+            "CORS_ORIGIN_NOT_ALLOWED",
+        ),
+    ];
+    for (request, status, code) in rejections {
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status(), status);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE),
+            Some(&HeaderValue::from_static(APPLICATION_JSON.essence_str()))
+        );
+        let response = response.json::<graphql::Response>().await.unwrap();
+        assert_eq!(response.data, None);
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .get("code")
+                .and_then(|code| code.as_str()),
+            Some(code)
+        );
+    }
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_closes_idle_connections() -> Result<(), ApolloRouterError> {
+    let conf = Configuration::fake_builder()
+        .supergraph(
+            Supergraph::fake_builder()
+                .connection_idle_timeout(Duration::from_millis(200))
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, _client) = init_with_config(
+        router_service::empty().await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let addr = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => *addr,
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!("the test listens on a TCP socket"),
+    };
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    // the connection is kept alive after the response, until it is closed for being idle
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("the idle connection should be closed")
+        .unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_closes_connections_over_their_byte_budget() -> Result<(), ApolloRouterError> {
+    let conf = Configuration::fake_builder()
+        .supergraph(
+            Supergraph::fake_builder()
+                .connection_max_bytes_sent(10)
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, _client) = init_with_config(
+        router_service::empty().await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let addr = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => *addr,
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!("the test listens on a TCP socket"),
+    };
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    // the response in flight is sent completely, then the keep-alive connection is closed
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("the connection over its budget should be closed")
+        .unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_closes_connections_after_their_maximum_number_of_requests(
+) -> Result<(), ApolloRouterError> {
+    let conf = Configuration::fake_builder()
+        .supergraph(
+            Supergraph::fake_builder()
+                .connection_max_requests(NonZeroUsize::new(3).unwrap())
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, _client) = init_with_config(
+        router_service::empty().await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let addr = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => *addr,
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!("the test listens on a TCP socket"),
+    };
+
+    // the requests are pipelined: the ones after the limit are never answered
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(&b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n".repeat(5))
+        .await
+        .unwrap();
+
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("the connection should be closed after the last request")
+        .unwrap();
+    let response = String::from_utf8_lossy(&response);
+    let responses: Vec<_> = response.split("HTTP/1.1 200 OK").skip(1).collect();
+    assert_eq!(responses.len(), 3, "{response}");
+    assert!(
+        !responses[1].to_lowercase().contains("connection: close"),
+        "{response}"
+    );
+    assert!(
+        responses[2].to_lowercase().contains("connection: close"),
+        "{response}"
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_records_the_queue_time_apart_from_the_service_time() -> Result<(), ApolloRouterError> {
+    let (exporter, _guard) = prometheus_recorder();
+
+    // the service is not ready until it is allowed to handle a request
+    let (service, mut handle) = tower_test::mock::pair::<RouterRequest, RouterResponse>();
+    handle.allow(0);
+    let (all_connections_stopped_sender, _) = mpsc::channel::<()>(1);
+    let server = AxumHttpServerFactory::new()
+        .create(
+            TestRouterFactory { inner: service },
+            Arc::new(Configuration::fake_builder().build().unwrap()),
+            None,
+            vec![],
+            MultiMap::new(),
+            EntitlementState::Unentitled,
+            all_connections_stopped_sender,
+        )
+        .await?;
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handle.allow(1);
+        let (_request, responder) = handle.next_request().await.unwrap();
+        responder.send_response(
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(r#"{"data":{}}"#.into())
+                .unwrap()
+                .into(),
+        );
+    });
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+        .body(r#"{"query":"{ me { name } }"}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let queue_time = scraped_value(&exporter, "apollo_router_queue_time_seconds_sum").unwrap();
+    assert!(queue_time >= 0.05, "{queue_time}");
+    assert!(scraped_value(&exporter, "apollo_router_service_time_seconds_sum").is_some());
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_counts_the_requests_over_the_slow_request_threshold() -> Result<(), ApolloRouterError> {
+    let (exporter, _guard) = prometheus_recorder();
+
+    let conf = Configuration::fake_builder()
+        .supergraph(
+            Supergraph::fake_builder()
+                .slow_request_threshold(Duration::from_millis(50))
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(
+        slow_service(Duration::from_millis(200)),
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+
+    let response = client
+        .post(format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .body(json!({ "query": "{ me }" }).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(
+        scraped_value(&exporter, "apollo_router_slow_requests_total"),
+        Some(1.0)
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_stops_responding_to_clients_that_disconnected() -> Result<(), ApolloRouterError> {
+    let (exporter, _guard) = prometheus_recorder();
+
+    /// Notifies when the response stream is dropped
+    struct Dropped(Arc<tokio::sync::Notify>);
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            self.0.notify_one();
+        }
+    }
+
+    // an endless response
+    let dropped = Arc::new(tokio::sync::Notify::new());
+    let router_service = {
+        let dropped = dropped.clone();
+        service_fn(move |req: router::Request| {
+            let guard = Dropped(dropped.clone());
+            async move {
+                let body = stream::repeat_with(move || {
+                    let _guard = &guard;
+                    Ok::<_, std::io::Error>(bytes::Bytes::from(vec![b' '; 16 * 1024]))
+                });
+                Ok::<_, BoxError>(router::Response {
+                    response: http::Response::builder()
+                        .status(StatusCode::OK)
+                        .body(hyper::Body::wrap_stream(body))
+                        .unwrap(),
+                    context: req.context,
+                })
+            }
+        })
+        .boxed()
+    };
+    let (server, _client) = init(router_service).await;
+    let addr = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => *addr,
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!("the test listens on a TCP socket"),
+    };
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let body = r#"{"query":"{ me { name } }"}"#;
+    stream
+        .write_all(
+            format!(
+                "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut buf = vec![0u8; 1024];
+    let read = stream.read(&mut buf).await.unwrap();
+    assert!(buf[..read].starts_with(b"HTTP/1.1 200 OK"));
+    drop(stream);
+
+    tokio::time::timeout(Duration::from_secs(5), dropped.notified())
+        .await
+        .expect("the response should be dropped once the client disconnected");
+
+    assert_eq!(
+        scraped_value(&exporter, "apollo_router_client_disconnects_total"),
+        Some(1.0)
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_counts_the_requests_served_per_connection() -> Result<(), ApolloRouterError> {
+    let (exporter, _guard) = prometheus_recorder();
+
+    let (server, _client) = init(router_service::empty().await).await;
+    let addr = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => *addr,
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!("the test listens on a TCP socket"),
+    };
+
+    // three requests reusing the same connection
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            &[
+                b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n".repeat(2),
+                b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n".to_vec(),
+            ]
+            .concat(),
+        )
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("the connection should be closed after the last request")
+        .unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 3, "{response}");
+
+    assert_eq!(
+        scraped_value(&exporter, "apollo_router_connections_accepted_total"),
+        Some(1.0)
+    );
+    assert_eq!(
+        scraped_value(&exporter, "apollo_router_requests_total"),
+        Some(3.0)
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn connections_racing_the_shutdown_are_served_or_refused() -> Result<(), ApolloRouterError> {
+    let (server, _client) = init(router_service::empty().await).await;
+    let addr = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => *addr,
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!("the test listens on a TCP socket"),
+    };
+
+    // keep-alive connections are only closed by the shutdown of the server: a connection
+    // missing it would stay open
+    let clients: Vec<_> = (0..50)
+        .map(|_| {
+            tokio::spawn(async move {
+                let mut stream = tokio::net::TcpStream::connect(addr).await?;
+                stream
+                    .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                    .await?;
+                let mut response = Vec::new();
+                stream.read_to_end(&mut response).await?;
+                Ok::<_, std::io::Error>(response)
+            })
+        })
+        .collect();
+    tokio::task::yield_now().await;
+    server.shutdown().await?;
+
+    for client in clients {
+        let response = tokio::time::timeout(Duration::from_secs(5), client)
+            .await
+            .expect("the connection should not be left open after the shutdown")
+            .unwrap();
+        // refused connections are closed before the request is read
+        if let Ok(response) = response {
+            let response = String::from_utf8_lossy(&response);
+            assert!(
+                response.is_empty() || response.starts_with("HTTP/1.1 200 OK"),
+                "{response}"
+            );
+        }
+    }
+    Ok(())
+}
+
+fn slow_service(delay: Duration) -> router::BoxCloneService {
+    service_fn(move |_req: router::Request| async move {
+        tokio::time::sleep(delay).await;
+        Ok::<_, BoxError>(
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body("ok".into())
+                .unwrap()
+                .into(),
+        )
+    })
+    .boxed_clone()
+}
+
+#[tokio::test]
+async fn it_applies_the_timeout_of_each_route_group() -> Result<(), ApolloRouterError> {
+    let route_timeouts = |graphql: u64| {
+        serde_json::from_value::<RouteTimeouts>(json!({
+            "graphql": format!("{graphql}ms"),
+            "health": "50ms",
+        }))
+        .unwrap()
+    };
+    for (graphql_timeout, expected) in [(2000, StatusCode::OK), (50, StatusCode::GATEWAY_TIMEOUT)] {
+        let conf = Configuration::fake_builder()
+            .supergraph(
+                Supergraph::fake_builder()
+                    .route_timeouts(route_timeouts(graphql_timeout))
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let mut web_endpoints = MultiMap::new();
+        web_endpoints.insert(
+            conf.supergraph.listen.clone(),
+            Endpoint::from_router_service(
+                "/slow-health".to_string(),
+                slow_service(Duration::from_millis(500)).boxed(),
+            )
+            .in_group(RouteGroup::Health),
+        );
+        let (server, client) = init_with_config(
+            slow_service(Duration::from_millis(200)),
+            Arc::new(conf),
+            web_endpoints,
+        )
+        .await?;
+        let url = server.graphql_listen_address().clone().unwrap();
+
+        let response = client
+            .post(format!("{url}/"))
+            .body(json!({ "query": "{ me }" }).to_string())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected);
+
+        // the health routes have their own, shorter, timeout
+        let response = client
+            .get(format!("{url}/slow-health"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let response = response.json::<graphql::Response>().await.unwrap();
+        assert_eq!(response.errors[0].message, "request timed out");
+
+        server.shutdown().await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_drops_connections_not_reading_the_response() -> Result<(), ApolloRouterError> {
+    const BODY_SIZE: usize = 64 * 1024 * 1024;
+    let endpoint = service_fn(|_req: router::Request| async move {
+        Ok::<_, BoxError>(
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(vec![b'a'; BODY_SIZE].into())
+                .unwrap()
+                .into(),
+        )
+    })
+    .boxed_clone();
+    let mut web_endpoints = MultiMap::new();
+    web_endpoints.insert(
+        ListenAddr::SocketAddr("127.0.0.1:0".parse().unwrap()),
+        Endpoint::from_router_service("/large".to_string(), endpoint.boxed()),
+    );
+    let conf = Configuration::fake_builder()
+        .supergraph(
+            Supergraph::fake_builder()
+                .connection_write_timeout(Duration::from_millis(200))
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, _client) =
+        init_with_config(router_service::empty().await, Arc::new(conf), web_endpoints).await?;
+    let addr = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => *addr,
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!("the test listens on a TCP socket"),
+    };
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /large HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    // stop reading long enough for the router to give up on sending the response
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let mut received = Vec::new();
+    let _ = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut received))
+        .await
+        .expect("the connection should be closed");
+    assert!(
+        received.len() < BODY_SIZE,
+        "the whole response was sent: {} bytes",
+        received.len()
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn response() -> Result<(), ApolloRouterError> {
+    let expected_response = graphql::Response::builder()
+        .data(json!({"response": "yay"}))
+        .build();
+    let example_response = expected_response.clone();
+    let router_service = router_service::from_supergraph_mock_callback(move |req| {
+        let example_response = example_response.clone();
+
+        Ok(SupergraphResponse::new_from_graphql_response(
+            example_response,
+            req.context,
+        ))
+    })
+    .await;
+    let (server, client) = init(router_service).await;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    // Post query
+    let response = client
+        .post(url.as_str())
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        response.json::<graphql::Response>().await.unwrap(),
+        expected_response,
+    );
+
+    // Get query
+    let response = client
+        .get(url.as_str())
+        .query(&json!({ "query": "query" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE),
+        Some(&HeaderValue::from_static(APPLICATION_JSON.essence_str()))
+    );
+
+    assert_eq!(
+        response.json::<graphql::Response>().await.unwrap(),
+        expected_response,
+    );
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_passes_the_peer_address_to_the_services() -> Result<(), ApolloRouterError> {
+    let peer_addr = Arc::new(parking_lot::Mutex::new(None));
+    let peer_addr_in_callback = peer_addr.clone();
+    let router_service = router_service::from_supergraph_mock_callback(move |req| {
+        *peer_addr_in_callback.lock() = req
+            .supergraph_request
+            .extensions()
+            .get::<axum::extract::ConnectInfo<SocketAddr>>()
+            .map(|info| info.0);
+        Ok(SupergraphResponse::new_from_graphql_response(
+            graphql::Response::builder().build(),
+            req.context,
+        ))
+    })
+    .await;
+    let (server, client) = init(router_service).await;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    client
+        .post(url.as_str())
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let peer_addr = peer_addr.lock().expect("the request has no peer address");
+    assert!(peer_addr.ip().is_loopback());
+    assert_ne!(peer_addr.port(), 0);
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn bad_response() -> Result<(), ApolloRouterError> {
+    let (server, client) = init(router_service::empty().await).await;
+    let url = format!("{}/test", server.graphql_listen_address().as_ref().unwrap());
+
+    // Post query
+    let err = client
+        .post(url.as_str())
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .expect_err("should be not found");
+
+    assert!(err.is_status());
+    assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+
+    // Get query
+    let err = client
+        .get(url.as_str())
+        .query(&json!({ "query": "query" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .expect_err("should be not found");
+
+    assert!(err.is_status());
+    assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn response_with_root_wildcard() -> Result<(), ApolloRouterError> {
+    let expected_response = graphql::Response::builder()
+        .data(json!({"response": "yay"}))
+        .build();
+    let example_response = expected_response.clone();
+
+    let router_service = router_service::from_supergraph_mock_callback(move |req| {
+        let example_response = example_response.clone();
+        Ok(SupergraphResponse::new_from_graphql_response(
+            example_response,
+            req.context,
+        ))
+    })
+    .await;
+
+    let conf = Configuration::fake_builder()
+        .supergraph(
+            crate::configuration::Supergraph::fake_builder()
+                .path(String::from("/*"))
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, client) =
+        init_with_config(router_service, Arc::new(conf), MultiMap::new()).await?;
+    let url = format!(
+        "{}/graphql",
+        server.graphql_listen_address().as_ref().unwrap()
+    );
+
+    // Post query
+    let response = client
+        .post(url.as_str())
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        response.json::<graphql::Response>().await.unwrap(),
+        expected_response,
+    );
+
+    // Post query without path
+    let response = client
+        .post(
+            &server
+                .graphql_listen_address()
+                .as_ref()
+                .unwrap()
+                .to_string(),
+        )
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        response.json::<graphql::Response>().await.unwrap(),
+        expected_response,
+    );
 
     // Get query
-    let err = client
+    let response = client
         .get(url.as_str())
         .query(&json!({ "query": "query" }))
         .send()
         .await
         .unwrap()
         .error_for_status()
-        .expect_err("should be not found");
+        .unwrap();
 
-    assert!(err.is_status());
-    assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+    assert_eq!(
+        response.json::<graphql::Response>().await.unwrap(),
+        expected_response,
+    );
 
     server.shutdown().await?;
     Ok(())
 }
 
 #[tokio::test]
-async fn response_with_root_wildcard() -> Result<(), ApolloRouterError> {
+async fn response_with_custom_endpoint() -> Result<(), ApolloRouterError> {
     let expected_response = graphql::Response::builder()
         .data(json!({"response": "yay"}))
         .build();
@@ -699,7 +1863,7 @@ async fn response_with_root_wildcard() -> Result<(), ApolloRouterError> {
     let conf = Configuration::fake_builder()
         .supergraph(
             crate::configuration::Supergraph::fake_builder()
-                .path(String::from("/*"))
+                .path(String::from("/graphql"))
                 .build(),
         )
         .build()
@@ -726,27 +1890,6 @@ async fn response_with_root_wildcard() -> Result<(), ApolloRouterError> {
         expected_response,
     );
 
-    // Post query without path
-    let response = client
-        .post(
-            &server
-                .graphql_listen_address()
-                .as_ref()
-                .unwrap()
-                .to_string(),
-        )
-        .body(json!({ "query": "query" }).to_string())
-        .send()
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-
-    assert_eq!(
-        response.json::<graphql::Response>().await.unwrap(),
-        expected_response,
-    );
-
     // Get query
     let response = client
         .get(url.as_str())
@@ -767,7 +1910,7 @@ async fn response_with_root_wildcard() -> Result<(), ApolloRouterError> {
 }
 
 #[tokio::test]
-async fn response_with_custom_endpoint() -> Result<(), ApolloRouterError> {
+async fn response_with_path_prefix() -> Result<(), ApolloRouterError> {
     let expected_response = graphql::Response::builder()
         .data(json!({"response": "yay"}))
         .build();
@@ -786,20 +1929,22 @@ async fn response_with_custom_endpoint() -> Result<(), ApolloRouterError> {
         .supergraph(
             crate::configuration::Supergraph::fake_builder()
                 .path(String::from("/graphql"))
+                .path_prefix(String::from("/api"))
                 .build(),
         )
         .build()
         .unwrap();
     let (server, client) =
         init_with_config(router_service, Arc::new(conf), MultiMap::new()).await?;
-    let url = format!(
-        "{}/graphql",
-        server.graphql_listen_address().as_ref().unwrap()
-    );
+    let address = server
+        .graphql_listen_address()
+        .as_ref()
+        .unwrap()
+        .to_string();
 
-    // Post query
+    // Post query with the prefix
     let response = client
-        .post(url.as_str())
+        .post(format!("{address}/api/graphql"))
         .body(json!({ "query": "query" }).to_string())
         .send()
         .await
@@ -812,9 +1957,9 @@ async fn response_with_custom_endpoint() -> Result<(), ApolloRouterError> {
         expected_response,
     );
 
-    // Get query
+    // Get query with the prefix
     let response = client
-        .get(url.as_str())
+        .get(format!("{address}/api/graphql"))
         .query(&json!({ "query": "query" }))
         .send()
         .await
@@ -827,6 +1972,30 @@ async fn response_with_custom_endpoint() -> Result<(), ApolloRouterError> {
         expected_response,
     );
 
+    // Post query without the prefix
+    let response = client
+        .post(format!("{address}/graphql"))
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        response.json::<graphql::Response>().await.unwrap(),
+        expected_response,
+    );
+
+    // Only the configured prefix is stripped
+    let response = client
+        .post(format!("{address}/other/graphql"))
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
     server.shutdown().await?;
     Ok(())
 }
@@ -1015,6 +2184,42 @@ async fn response_failure() -> Result<(), ApolloRouterError> {
     server.shutdown().await
 }
 
+#[tokio::test]
+async fn it_maps_fetch_errors_to_http_statuses() -> Result<(), ApolloRouterError> {
+    let router_service = router_service::from_supergraph_mock_callback(move |_req| {
+        Err(crate::error::FetchError::SubrequestHttpError {
+            status_code: None,
+            service: "Mock service".to_string(),
+            reason: "connection refused".to_string(),
+        }
+        .into())
+    })
+    .await;
+    let (server, client) = init(router_service).await;
+
+    let response = client
+        .post(format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(
+        response.json::<graphql::Response>().await.unwrap(),
+        crate::error::FetchError::SubrequestHttpError {
+            status_code: None,
+            service: "Mock service".to_string(),
+            reason: "connection refused".to_string(),
+        }
+        .to_response()
+    );
+    server.shutdown().await
+}
+
 #[tokio::test]
 async fn cors_preflight() -> Result<(), ApolloRouterError> {
     let conf = Configuration::fake_builder()
@@ -1077,6 +2282,90 @@ async fn cors_preflight() -> Result<(), ApolloRouterError> {
     server.shutdown().await
 }
 
+#[tokio::test]
+async fn it_answers_options_requests_with_the_allowed_methods() -> Result<(), ApolloRouterError> {
+    let conf = Configuration::fake_builder()
+        .cors(Cors::builder().build())
+        .supergraph(
+            crate::configuration::Supergraph::fake_builder()
+                .path(String::from("/graphql"))
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(
+        router_service::empty().await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let url = format!(
+        "{}/graphql",
+        server.graphql_listen_address().as_ref().unwrap()
+    );
+
+    let response = client.request(Method::OPTIONS, &url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        response.headers().get(http::header::ALLOW).unwrap(),
+        "GET, POST, OPTIONS"
+    );
+
+    // preflight requests are still answered by the CORS layer
+    let response = client
+        .request(Method::OPTIONS, &url)
+        .header(ORIGIN, "https://studio.apollographql.com")
+        .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(http::header::ALLOW).is_none());
+    assert_header_contains!(
+        &response,
+        ACCESS_CONTROL_ALLOW_METHODS,
+        &["GET", "POST", "OPTIONS"],
+        "Incorrect access control allow methods header"
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn cors_preflight_with_restricted_methods() -> Result<(), ApolloRouterError> {
+    let conf = Configuration::fake_builder()
+        .cors(Cors::builder().methods(vec!["POST".into()]).build())
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(
+        router_service::empty().await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+
+    let response = client
+        .request(
+            Method::OPTIONS,
+            &format!("{}/", server.graphql_listen_address().as_ref().unwrap()),
+        )
+        .header(ORIGIN, "https://studio.apollographql.com")
+        .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_header!(
+        &response,
+        ACCESS_CONTROL_ALLOW_METHODS,
+        vec!["POST"],
+        "Incorrect access control allow methods header"
+    );
+
+    server.shutdown().await
+}
+
 #[tokio::test]
 async fn test_previous_health_check_returns_four_oh_four() {
     let (server, client) = init(router_service::empty().await).await;
@@ -1254,6 +2543,52 @@ async fn it_doesnt_display_disabled_homepage() {
     server.shutdown().await.unwrap();
 }
 
+#[test(tokio::test)]
+async fn it_never_redirects_browsers_when_landing_pages_are_disabled() {
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .sandbox(Sandbox::fake_builder().enabled(false).build())
+            .homepage(Homepage::fake_builder().enabled(false).build())
+            .build()
+            .unwrap(),
+    );
+
+    let router_service = router_service::from_supergraph_mock_callback_and_configuration(
+        move |_| {
+            panic!("this should never be called");
+        },
+        conf.clone(),
+    )
+    .await;
+
+    let (server, client) = init_with_config(router_service, conf, MultiMap::new())
+        .await
+        .unwrap();
+    // the client does not follow redirects, and the accept header is the one sent by browsers
+    let response = client
+        .get(&format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .header(
+            ACCEPT,
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert!(!response.status().is_redirection());
+    assert!(response.headers().get(header::LOCATION).is_none());
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        &HeaderValue::from_static("application/json")
+    );
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    server.shutdown().await.unwrap();
+}
+
 #[test(tokio::test)]
 async fn it_answers_to_custom_endpoint() -> Result<(), ApolloRouterError> {
     let endpoint = service_fn(|req: router::Request| async move {
@@ -1486,6 +2821,53 @@ async fn cors_origin_list() -> Result<(), ApolloRouterError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn cors_rejects_forbidden_origins() -> Result<(), ApolloRouterError> {
+    let valid_origin = "https://thisoriginisallowed.com";
+
+    let conf = Configuration::fake_builder()
+        .cors(
+            Cors::builder()
+                .origins(vec![valid_origin.to_string()])
+                .reject_forbidden_origins(true)
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(
+        router_service::empty().await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = request_cors_with_origin(&client, url.as_str(), valid_origin).await;
+    assert_cors_origin(response, valid_origin);
+
+    let response =
+        request_cors_with_origin(&client, url.as_str(), "https://thisoriginisinvalid").await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(!origin_valid(
+        response.headers(),
+        "https://thisoriginisinvalid"
+    ));
+    let response = response.json::<graphql::Response>().await.unwrap();
+    assert_eq!(
+        response.errors[0].message,
+        "origin 'https://thisoriginisinvalid' is not allowed by the CORS configuration"
+    );
+    assert_eq!(
+        response.errors[0]
+            .extensions
+            .get("code")
+            .and_then(|code| code.as_str()),
+        Some("CORS_ORIGIN_NOT_ALLOWED")
+    );
+
+    server.shutdown().await
+}
+
 #[tokio::test]
 async fn cors_origin_regex() -> Result<(), ApolloRouterError> {
     let apollo_subdomains = "https://([a-z0-9]+[.])*apollographql[.]com";
@@ -1531,6 +2913,53 @@ async fn cors_origin_regex() -> Result<(), ApolloRouterError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn cors_origin_normalization() -> Result<(), ApolloRouterError> {
+    let conf = Configuration::fake_builder()
+        .cors(
+            Cors::builder()
+                .origins(vec![
+                    "https://thisoriginisallowed.com/".to_string(),
+                    "http://LocalHost:80".to_string(),
+                ])
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(
+        router_service::empty().await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await?;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    // trailing slash and default port equivalence
+    for origin in [
+        "https://thisoriginisallowed.com",
+        "https://thisoriginisallowed.com:443",
+        "https://ThisOriginIsAllowed.com/",
+        "http://localhost",
+        "http://localhost:80/",
+    ] {
+        let response = request_cors_with_origin(&client, url.as_str(), origin).await;
+        assert_cors_origin(response, origin);
+    }
+
+    // different scheme or port
+    for origin in [
+        "http://thisoriginisallowed.com",
+        "https://thisoriginisallowed.com:8443",
+        "https://localhost",
+        "http://localhost:8080",
+    ] {
+        let response = request_cors_with_origin(&client, url.as_str(), origin).await;
+        assert_not_cors_origin(response, origin);
+    }
+
+    Ok(())
+}
+
 async fn request_cors_with_origin(client: &Client, url: &str, origin: &str) -> reqwest::Response {
     client
         .request(Method::OPTIONS, url)
@@ -2047,6 +3476,12 @@ async fn listening_to_unix_socket() {
     let example_response = expected_response.clone();
 
     let router_service = router_service::from_supergraph_mock_callback(move |req| {
+        // Unix sockets have no peer address to pass to the services
+        assert!(req
+            .supergraph_request
+            .extensions()
+            .get::<axum::extract::ConnectInfo<SocketAddr>>()
+            .is_none());
         let example_response = example_response.clone();
         Ok(SupergraphResponse::new_from_graphql_response(
             example_response,
@@ -2173,6 +3608,232 @@ async fn test_health_check() {
     )
 }
 
+#[tokio::test]
+async fn admin_config_endpoint_requires_the_token_and_redacts_secrets() {
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .admin(
+                Admin::fake_builder()
+                    .enabled(true)
+                    .token("admin-secret".to_string())
+                    .build(),
+            )
+            .build()
+            .unwrap(),
+    );
+    let (server, client) = init_with_config(router_service::empty().await, conf, MultiMap::new())
+        .await
+        .unwrap();
+    let url = format!(
+        "{}/admin/config",
+        server.graphql_listen_address().as_ref().unwrap()
+    );
+
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = client
+        .get(&url)
+        .bearer_auth("wrong-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .get(&url)
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("admin-secret"), "{body}");
+    let config: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(config["admin"]["token"], "[REDACTED]");
+    assert_eq!(config["admin"]["enabled"], true);
+
+    server.shutdown().await.unwrap();
+}
+
+async fn negotiated_http_version(alpn: Option<&[&str]>) -> reqwest::Version {
+    let certificate = include_str!("../configuration/testdata/server.crt");
+    let mut tls = json!({
+        "certificate": certificate,
+        "certificate_chain": certificate,
+        "key": include_str!("../configuration/testdata/server.key"),
+    });
+    if let Some(alpn) = alpn {
+        tls["alpn"] = json!(alpn);
+    }
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .tls(crate::configuration::Tls {
+                supergraph: Some(serde_json::from_value(tls).unwrap()),
+                ..Default::default()
+            })
+            .build()
+            .unwrap(),
+    );
+    let (server, _) = init_with_config(router_service::empty().await, conf, MultiMap::new())
+        .await
+        .unwrap();
+    let addr = match server.graphql_listen_address() {
+        Some(ListenAddr::SocketAddr(addr)) => *addr,
+        other => panic!("expected a socket address, got {other:?}"),
+    };
+
+    let client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(certificate.as_bytes()).unwrap())
+        .resolve("local.apollo.dev", addr)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("https://local.apollo.dev:{}/", addr.port()))
+        .body("Garbage")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let version = response.version();
+
+    server.shutdown().await.unwrap();
+    version
+}
+
+#[tokio::test]
+async fn it_serves_the_protocol_negotiated_with_alpn() {
+    assert_eq!(
+        negotiated_http_version(None).await,
+        reqwest::Version::HTTP_2
+    );
+    assert_eq!(
+        negotiated_http_version(Some(&["http/1.1"])).await,
+        reqwest::Version::HTTP_11
+    );
+    assert_eq!(
+        negotiated_http_version(Some(&["http/1.1", "h2"])).await,
+        reqwest::Version::HTTP_11
+    );
+}
+
+fn metrics_endpoints(listen_addr: &str) -> MultiMap<ListenAddr, Endpoint> {
+    let endpoint = service_fn(|req: router::Request| async move {
+        Ok::<_, BoxError>(router::Response {
+            response: http::Response::builder()
+                .body::<hyper::Body>("apollo_router_requests_total 1".into())
+                .unwrap(),
+            context: req.context,
+        })
+    })
+    .boxed();
+    let mut web_endpoints = MultiMap::new();
+    web_endpoints.insert(
+        ListenAddr::SocketAddr(listen_addr.parse().unwrap()),
+        Endpoint::from_router_service("/metrics".to_string(), endpoint),
+    );
+    web_endpoints
+}
+
+#[tokio::test]
+async fn it_serves_extra_listeners_with_tls() {
+    let certificate = include_str!("../configuration/testdata/server.crt");
+    let tls = |listeners: serde_json::Value| {
+        let tls = json!({
+            "supergraph": {
+                "certificate": certificate,
+                "certificate_chain": certificate,
+                "key": include_str!("../configuration/testdata/server.key"),
+            },
+            "listeners": listeners,
+        });
+        Configuration::fake_builder()
+            .tls(serde_json::from_value(tls).unwrap())
+            .build()
+            .unwrap()
+    };
+
+    let conf = tls(json!({ "127.0.0.1:4031": "supergraph" }));
+    let (server, _) = init_with_config(
+        router_service::empty().await,
+        Arc::new(conf),
+        metrics_endpoints("127.0.0.1:4031"),
+    )
+    .await
+    .unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(certificate.as_bytes()).unwrap())
+        .resolve("local.apollo.dev", "127.0.0.1:4031".parse().unwrap())
+        .build()
+        .unwrap();
+    let response = client
+        .get("https://local.apollo.dev:4031/metrics")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "apollo_router_requests_total 1"
+    );
+    // plaintext HTTP is not served anymore
+    assert!(reqwest::get("http://127.0.0.1:4031/metrics").await.is_err());
+    server.shutdown().await.unwrap();
+
+    // an address reachable from other hosts is not served without TLS by accident
+    let error = init_with_config(
+        router_service::empty().await,
+        Arc::new(tls(json!({}))),
+        metrics_endpoints("0.0.0.0:4032"),
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(&error, ApolloRouterError::ServerCreationError(source)
+            if source.kind() == std::io::ErrorKind::InvalidInput),
+        "{error}"
+    );
+    assert!(error.to_string().contains("without TLS"), "{error}");
+
+    let (server, client) = init_with_config(
+        router_service::empty().await,
+        Arc::new(tls(json!({ "0.0.0.0:4032": "plaintext" }))),
+        metrics_endpoints("0.0.0.0:4032"),
+    )
+    .await
+    .unwrap();
+    let response = client
+        .get("http://127.0.0.1:4032/metrics")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn it_is_reachable_on_the_reported_ephemeral_port() {
+    let (server, _) = init(router_service::empty().await).await;
+    let addr = match server.graphql_listen_address() {
+        Some(ListenAddr::SocketAddr(addr)) => *addr,
+        other => panic!("expected a socket address, got {other:?}"),
+    };
+    assert_ne!(addr.port(), 0);
+
+    // connect without yielding to the runtime: the socket must already be bound and listening
+    let stream = std::net::TcpStream::connect(addr).unwrap();
+    stream.set_nonblocking(true).unwrap();
+    let mut stream = tokio::net::TcpStream::from_std(stream).unwrap();
+    stream
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+
+    server.shutdown().await.unwrap();
+}
+
 #[tokio::test]
 async fn test_health_check_custom_listener() {
     let conf = Configuration::fake_builder()
@@ -2203,6 +3864,315 @@ async fn test_health_check_custom_listener() {
     )
 }
 
+#[tokio::test]
+async fn test_detailed_health_check() {
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .health_check(HealthCheck::fake_builder().detailed(true).build())
+            .build()
+            .unwrap(),
+    );
+    let supergraph_creator = SupergraphCreator::for_tests(MockSupergraphService::new()).await;
+    let schema_hash = supergraph_creator.schema().schema_id.clone().unwrap();
+    let router_creator = RouterCreator::new(Arc::new(supergraph_creator), &conf)
+        .await
+        .unwrap();
+    let (all_connections_stopped_sender, _) = mpsc::channel::<()>(1);
+
+    let server = AxumHttpServerFactory::new()
+        .create(
+            router_creator,
+            conf,
+            None,
+            vec![],
+            MultiMap::new(),
+            EntitlementState::Unentitled,
+            all_connections_stopped_sender,
+        )
+        .await
+        .unwrap();
+    let url = format!(
+        "{}/health",
+        server.graphql_listen_address().as_ref().unwrap()
+    );
+
+    let response = reqwest::get(url).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        json!({
+            "status": "UP",
+            "version": env!("CARGO_PKG_VERSION"),
+            "schema_hash": schema_hash,
+        }),
+        response.json::<serde_json::Value>().await.unwrap()
+    );
+    server.shutdown().await.unwrap();
+}
+
+/// A plugin only providing a health probe
+struct ProbePlugin(HealthProbe);
+
+#[async_trait::async_trait]
+impl Plugin for ProbePlugin {
+    type Config = ();
+
+    async fn new(_init: PluginInit<()>) -> Result<Self, BoxError> {
+        Ok(Self(HealthProbe::default()))
+    }
+
+    fn health_probe(&self) -> HealthProbe {
+        self.0.clone()
+    }
+}
+
+#[tokio::test]
+async fn test_health_check_runs_the_probes_of_plugins() {
+    let health = |probe: HealthProbe| async move {
+        let conf = Arc::new(
+            Configuration::fake_builder()
+                .health_check(
+                    HealthCheck::fake_builder()
+                        .probe_timeout(Duration::from_millis(100))
+                        .build(),
+                )
+                .build()
+                .unwrap(),
+        );
+        let schema = include_str!("..//testdata/minimal_supergraph.graphql");
+        let planner = BridgeQueryPlanner::new(schema.to_string(), conf.clone())
+            .await
+            .unwrap();
+        let supergraph_creator = PluggableSupergraphServiceBuilder::new(planner)
+            .with_configuration(conf.clone())
+            .with_subgraph_service("accounts", MockSubgraph::new(HashMap::new()))
+            .with_dyn_plugin("test.probe".to_string(), Box::new(ProbePlugin(probe)))
+            .build()
+            .await
+            .unwrap();
+        let router_creator = RouterCreator::new(Arc::new(supergraph_creator), &conf)
+            .await
+            .unwrap();
+        let (all_connections_stopped_sender, _) = mpsc::channel::<()>(1);
+
+        let server = AxumHttpServerFactory::new()
+            .create(
+                router_creator,
+                conf,
+                None,
+                vec![],
+                MultiMap::new(),
+                EntitlementState::Unentitled,
+                all_connections_stopped_sender,
+            )
+            .await
+            .unwrap();
+        let url = format!(
+            "{}/health",
+            server.graphql_listen_address().as_ref().unwrap()
+        );
+
+        let response = reqwest::get(url).await.unwrap();
+        let status = response.status();
+        let body = response.json::<serde_json::Value>().await.unwrap();
+        server.shutdown().await.unwrap();
+        (status, body)
+    };
+
+    let (status, body) = health(HealthProbe::default()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "status": "UP" }));
+
+    let (status, body) = health(HealthProbe::new(|| async { false })).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body, json!({ "status": "DOWN" }));
+
+    // a probe that does not complete in time fails
+    let (status, body) = health(HealthProbe::new(|| futures::future::pending())).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body, json!({ "status": "DOWN" }));
+}
+
+#[tokio::test]
+async fn test_subgraph_health_check() {
+    // a subgraph answering the probes
+    let subgraph = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(
+        axum::Router::new()
+            .route(
+                "/",
+                axum::routing::post(|| async { r#"{"data":{"__typename":"Query"}}"# }),
+            )
+            .into_make_service(),
+    );
+    let subgraph_url = format!("http://{}/", subgraph.local_addr());
+    tokio::spawn(subgraph);
+    // and one that is not reachable
+    let closed_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let health = |required: Vec<&str>| {
+        let subgraph_url = subgraph_url.clone();
+        async move {
+            let conf = Arc::new(
+                Configuration::fake_builder()
+                    .health_check(
+                        HealthCheck::fake_builder()
+                            .subgraphs(crate::configuration::SubgraphHealthCheck {
+                                required: required.into_iter().map(String::from).collect(),
+                                interval: Duration::from_millis(100),
+                                stale_after: None,
+                                timeout: None,
+                            })
+                            .build(),
+                    )
+                    .apollo_plugin(
+                        "override_subgraph_url",
+                        json!({
+                            "accounts": subgraph_url,
+                            "products": format!("http://{closed_port}/"),
+                        }),
+                    )
+                    .build()
+                    .unwrap(),
+            );
+            let supergraph_creator =
+                SupergraphCreator::for_tests(MockSupergraphService::new()).await;
+            let router_creator = RouterCreator::new(Arc::new(supergraph_creator), &conf)
+                .await
+                .unwrap();
+            let (all_connections_stopped_sender, _) = mpsc::channel::<()>(1);
+
+            let server = AxumHttpServerFactory::new()
+                .create(
+                    router_creator,
+                    conf,
+                    None,
+                    vec![],
+                    MultiMap::new(),
+                    EntitlementState::Unentitled,
+                    all_connections_stopped_sender,
+                )
+                .await
+                .unwrap();
+            let url = format!(
+                "{}/health",
+                server.graphql_listen_address().as_ref().unwrap()
+            );
+
+            // wait for the first probes
+            let mut attempts = 0;
+            let (status, body) = loop {
+                let response = reqwest::get(&url).await.unwrap();
+                let status = response.status();
+                let body = response.json::<serde_json::Value>().await.unwrap();
+                if body["subgraphs"]["accounts"] != "UNKNOWN"
+                    && body["subgraphs"]["products"] != "UNKNOWN"
+                {
+                    break (status, body);
+                }
+                attempts += 1;
+                assert!(attempts < 50, "the subgraphs were not probed");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            };
+            server.shutdown().await.unwrap();
+            (status, body)
+        }
+    };
+
+    let (status, body) = health(vec!["accounts", "products"]).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "DOWN");
+    assert_eq!(body["subgraphs"]["accounts"], "UP");
+    assert_eq!(body["subgraphs"]["products"], "DOWN");
+
+    let (status, body) = health(vec!["accounts"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "UP");
+    assert_eq!(body["subgraphs"]["accounts"], "UP");
+    assert_eq!(body["subgraphs"]["products"], "DOWN");
+}
+
+#[tokio::test]
+async fn test_subgraph_health_check_reports_timed_out_probes_as_unknown() {
+    let subgraph = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(
+        axum::Router::new()
+            .route(
+                "/",
+                axum::routing::post(|| async { r#"{"data":{"__typename":"Query"}}"# }),
+            )
+            .into_make_service(),
+    );
+    let subgraph_url = format!("http://{}/", subgraph.local_addr());
+    tokio::spawn(subgraph);
+    // a subgraph accepting connections but never answering
+    let hanging = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let hanging_addr = hanging.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((stream, _)) = hanging.accept().await {
+            connections.push(stream);
+        }
+    });
+
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .health_check(
+                HealthCheck::fake_builder()
+                    .subgraphs(crate::configuration::SubgraphHealthCheck {
+                        required: vec!["accounts".to_string()],
+                        interval: Duration::from_millis(100),
+                        stale_after: None,
+                        timeout: Some(Duration::from_millis(50)),
+                    })
+                    .build(),
+            )
+            .apollo_plugin(
+                "override_subgraph_url",
+                json!({
+                    "accounts": subgraph_url,
+                    "products": format!("http://{hanging_addr}/"),
+                }),
+            )
+            .build()
+            .unwrap(),
+    );
+    let supergraph_creator = SupergraphCreator::for_tests(MockSupergraphService::new()).await;
+    let router_creator = RouterCreator::new(Arc::new(supergraph_creator), &conf)
+        .await
+        .unwrap();
+    let (all_connections_stopped_sender, _) = mpsc::channel::<()>(1);
+    let server = AxumHttpServerFactory::new()
+        .create(
+            router_creator,
+            conf,
+            None,
+            vec![],
+            MultiMap::new(),
+            EntitlementState::Unentitled,
+            all_connections_stopped_sender,
+        )
+        .await
+        .unwrap();
+    let url = format!(
+        "{}/health",
+        server.graphql_listen_address().as_ref().unwrap()
+    );
+
+    // wait for a few rounds of probes, the hanging subgraph is probed every time
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    let started = Instant::now();
+    let response = reqwest::get(&url).await.unwrap();
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["status"], "UP");
+    assert_eq!(body["subgraphs"]["accounts"], "UP");
+    assert_eq!(body["subgraphs"]["products"], "UNKNOWN");
+
+    server.shutdown().await.unwrap();
+}
+
 #[tokio::test]
 async fn test_sneaky_supergraph_and_health_check_configuration() {
     let conf = Configuration::fake_builder()
@@ -2316,6 +4286,7 @@ async fn test_supergraph_timeout() {
 
     let service = RouterCreator::new(Arc::new(supergraph_creator), &conf)
         .await
+        .unwrap()
         .make();
 
     // keep the server handle around otherwise it will immediately shutdown
@@ -2332,6 +4303,6 @@ async fn test_supergraph_timeout() {
         .unwrap();
 
     assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
-    let body = response.bytes().await.unwrap();
-    assert_eq!(std::str::from_utf8(&body).unwrap(), "request timed out");
+    let response = response.json::<graphql::Response>().await.unwrap();
+    assert_eq!(response.errors[0].message, "request timed out");
 }
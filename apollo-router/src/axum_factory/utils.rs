@@ -1,15 +1,37 @@
 // With regards to ELv2 licensing, this entire file is license key functionality
 //! Utilities used for [`super::AxumHttpServerFactory`]
 
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
 use async_compression::tokio::write::BrotliDecoder;
 use async_compression::tokio::write::GzipDecoder;
 use async_compression::tokio::write::ZlibDecoder;
+use axum::extract::ConnectInfo;
+use axum::extract::State;
 use axum::http::StatusCode;
 use axum::middleware::Next;
 use axum::response::*;
+use axum::Json;
+use bytes::Bytes;
+use bytes::BytesMut;
 use futures::prelude::*;
+use http::header::ACCESS_CONTROL_REQUEST_METHOD;
+use http::header::ALLOW;
+use http::header::CONNECTION;
 use http::header::CONTENT_ENCODING;
+use http::header::CONTENT_LENGTH;
+use http::header::EXPECT;
+use http::header::ORIGIN;
+use http::header::UPGRADE;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::Method;
 use http::Request;
+use hyper::body::HttpBody as _;
 use hyper::Body;
 use opentelemetry::global;
 use opentelemetry::trace::TraceContextExt;
@@ -17,6 +39,10 @@ use tokio::io::AsyncWriteExt;
 use tower_http::trace::MakeSpan;
 use tracing::Span;
 
+use crate::configuration::cors::AllowedOrigins;
+use crate::configuration::ip_net::IpNet;
+use crate::graphql;
+use crate::plugins::telemetry::metrics::count_rejected_request;
 use crate::uplink::entitlement::EntitlementState;
 use crate::uplink::entitlement::ENTITLEMENT_EXPIRED_SHORT_MESSAGE;
 
@@ -32,27 +58,29 @@ pub(super) async fn decompress_request_body(
         ($decoder: ident, $error_message: expr) => {{
             let body_bytes = hyper::body::to_bytes(body)
                 .map_err(|err| {
-                    (
+                    graphql_error_response(
                         StatusCode::BAD_REQUEST,
                         format!("cannot read request body: {err}"),
+                        "INVALID_REQUEST_BODY",
                     )
-                        .into_response()
                 })
                 .await?;
             let mut decoder = $decoder::new(Vec::new());
             decoder.write_all(&body_bytes).await.map_err(|err| {
-                (
+                count_rejected_request("invalid_compressed_body");
+                graphql_error_response(
                     StatusCode::BAD_REQUEST,
                     format!("{}: {err}", $error_message),
+                    "INVALID_COMPRESSED_BODY",
                 )
-                    .into_response()
             })?;
             decoder.shutdown().await.map_err(|err| {
-                (
+                count_rejected_request("invalid_compressed_body");
+                graphql_error_response(
                     StatusCode::BAD_REQUEST,
                     format!("{}: {err}", $error_message),
+                    "INVALID_COMPRESSED_BODY",
                 )
-                    .into_response()
             })?;
 
             Ok(next
@@ -76,8 +104,13 @@ pub(super) async fn decompress_request_body(
                        status = %400u16,
                        error = %message,
                     );
+                    count_rejected_request("invalid_content_encoding");
 
-                    Err((StatusCode::BAD_REQUEST, message).into_response())
+                    Err(graphql_error_response(
+                        StatusCode::BAD_REQUEST,
+                        message,
+                        "INVALID_CONTENT_ENCODING",
+                    ))
                 }
             },
 
@@ -88,13 +121,279 @@ pub(super) async fn decompress_request_body(
                    status = %400u16,
                    error = %message,
                 );
-                Err((StatusCode::BAD_REQUEST, message).into_response())
+                count_rejected_request("invalid_content_encoding");
+                Err(graphql_error_response(
+                    StatusCode::BAD_REQUEST,
+                    message,
+                    "INVALID_CONTENT_ENCODING",
+                ))
             }
         },
         None => Ok(next.run(Request::from_parts(parts, body)).await),
     }
 }
 
+/// Rejects requests whose declared `Content-Length` is over the configured limit,
+/// before their body is read.
+///
+/// hyper only sends the interim `100 Continue` response to a request with an
+/// `Expect: 100-continue` header once its body is polled: by answering here, such clients
+/// get the final 413 response instead and never upload their body.
+///
+/// Bodies without a `Content-Length`, sent with `Transfer-Encoding: chunked`, are read here
+/// while counting their bytes, and rejected as soon as they go over the limit.
+pub(super) async fn limit_request_body(
+    State(max_request_bytes): State<Option<usize>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, Response> {
+    let max_request_bytes = match max_request_bytes {
+        Some(max_request_bytes) => max_request_bytes,
+        None => return Ok(next.run(req).await),
+    };
+
+    if let Some(expect) = req.headers().get(&EXPECT) {
+        if !expect.as_bytes().eq_ignore_ascii_case(b"100-continue") {
+            let message = format!("unsupported expect header value {expect:?}");
+            ::tracing::error!(
+               monotonic_counter.apollo_router_http_requests_total = 1u64,
+               status = %417u16,
+               error = %message,
+            );
+            count_rejected_request("unsupported_expect");
+            return Err(graphql_error_response(
+                StatusCode::EXPECTATION_FAILED,
+                message,
+                "UNSUPPORTED_EXPECT_HEADER",
+            ));
+        }
+    }
+
+    let content_length = req
+        .headers()
+        .get(&CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    match content_length {
+        Some(content_length) if content_length > max_request_bytes => Err(payload_too_large(
+            format!(
+                "request body is too large: {content_length} bytes, the limit is {max_request_bytes} bytes"
+            ),
+        )),
+        // hyper does not read more than the declared length
+        Some(_) => Ok(next.run(req).await),
+        None => {
+            let (parts, mut body) = req.into_parts();
+            let mut body_bytes = BytesMut::new();
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk.map_err(|err| {
+                    graphql_error_response(
+                        StatusCode::BAD_REQUEST,
+                        format!("cannot read request body: {err}"),
+                        "INVALID_REQUEST_BODY",
+                    )
+                })?;
+                if body_bytes.len() + chunk.len() > max_request_bytes {
+                    return Err(payload_too_large(format!(
+                        "request body is too large: more than {max_request_bytes} bytes were sent, the limit is {max_request_bytes} bytes"
+                    )));
+                }
+                body_bytes.extend_from_slice(&chunk);
+            }
+
+            Ok(next
+                .run(Request::from_parts(parts, Body::from(body_bytes.freeze())))
+                .await)
+        }
+    }
+}
+
+/// Answers preflight requests from origins that are not allowed with a `403 Forbidden`
+/// status, instead of letting the CORS layer omit the `Access-Control-Allow-Origin` header.
+pub(super) async fn reject_forbidden_preflight<B>(
+    State(allowed_origins): State<AllowedOrigins>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let is_preflight = req.method() == Method::OPTIONS
+        && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+    match req.headers().get(ORIGIN) {
+        Some(origin) if is_preflight && !allowed_origins.allows(origin) => {
+            let message = format!(
+                "origin '{}' is not allowed by the CORS configuration",
+                String::from_utf8_lossy(origin.as_bytes())
+            );
+            ::tracing::error!(
+               monotonic_counter.apollo_router_http_requests_total = 1u64,
+               status = %403u16,
+               error = %message,
+            );
+            count_rejected_request("origin_not_allowed");
+            graphql_error_response(StatusCode::FORBIDDEN, message, "CORS_ORIGIN_NOT_ALLOWED")
+        }
+        _ => next.run(req).await,
+    }
+}
+
+/// Answers the `OPTIONS` requests that are not CORS preflight requests, like the ones of
+/// tools probing the methods supported by the GraphQL endpoint, with a `204 No Content`
+/// status and an `Allow` header, instead of letting the CORS layer answer them as preflight
+/// requests.
+pub(super) async fn answer_options_requests<B>(req: Request<B>, next: Next<B>) -> Response {
+    if req.method() == Method::OPTIONS && !req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+    {
+        return (
+            StatusCode::NO_CONTENT,
+            [(ALLOW, HeaderValue::from_static("GET, POST, OPTIONS"))],
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
+
+/// Answers the WebSocket upgrade requests sent to the GraphQL endpoint with a
+/// `501 Not Implemented` status, since the router does not serve subscriptions over
+/// WebSocket. Otherwise the client gets an error about the missing query, which does not
+/// tell what is wrong.
+pub(super) async fn reject_upgrade_requests<B>(req: Request<B>, next: Next<B>) -> Response {
+    if is_websocket_upgrade(req.headers()) {
+        count_rejected_request("websocket_upgrade");
+        return graphql_error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "subscriptions are disabled: the router does not accept WebSocket connections",
+            "SUBSCRIPTIONS_DISABLED",
+        );
+    }
+    next.run(req).await
+}
+
+/// The `Connection` header lists the `upgrade` option, and the `Upgrade` header the
+/// `websocket` protocol. Both headers are lists of case insensitive tokens.
+fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    let contains_token = |name, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+    contains_token(CONNECTION, "upgrade") && contains_token(UPGRADE, "websocket")
+}
+
+/// Set on the requests from a client outside of the trusted proxies, whose
+/// forwarding and client identification headers are ignored
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct UntrustedPeer;
+
+/// Marks the requests from clients outside of the trusted proxies with [`UntrustedPeer`].
+/// Clients connected through a Unix socket are on the same host, and are trusted.
+pub(super) async fn mark_untrusted_peer<B>(
+    State(trusted_proxies): State<Arc<Vec<IpNet>>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let trusted = match req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(peer_addr)) => trusted_proxies
+            .iter()
+            .any(|net| net.contains(peer_addr.ip())),
+        None => true,
+    };
+    if !trusted {
+        req.extensions_mut().insert(UntrustedPeer);
+    }
+    next.run(req).await
+}
+
+fn payload_too_large(message: String) -> Response {
+    ::tracing::error!(
+       monotonic_counter.apollo_router_http_requests_total = 1u64,
+       status = %413u16,
+       error = %message,
+    );
+    count_rejected_request("body_too_large");
+    graphql_error_response(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        message,
+        "REQUEST_BODY_TOO_LARGE",
+    )
+}
+
+/// Builds the response to a request rejected by the HTTP server, with the same
+/// `{"errors":[...]}` JSON shape as the errors of GraphQL requests
+pub(crate) fn graphql_error_response(
+    status: StatusCode,
+    message: impl Into<String>,
+    extension_code: &str,
+) -> Response {
+    let body = graphql::Response::builder()
+        .error(
+            graphql::Error::builder()
+                .message(message)
+                .extension_code(extension_code)
+                .build(),
+        )
+        .build();
+    (status, Json(body)).into_response()
+}
+
+/// A response body that detects the clients disconnecting before it was completely sent
+///
+/// When the client goes away, hyper drops the body it was writing: this also drops the
+/// stream producing it, which stops the work still running for the request, like the
+/// execution of deferred fragments or the serialization of a streamed response.
+pub(crate) struct DisconnectTracked {
+    inner: Body,
+    done: bool,
+}
+
+impl DisconnectTracked {
+    pub(crate) fn new(inner: Body) -> Self {
+        Self { inner, done: false }
+    }
+}
+
+impl http_body::Body for DisconnectTracked {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(None) = poll {
+            self.done = true;
+        }
+        poll
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for DisconnectTracked {
+    fn drop(&mut self) {
+        // hyper stops polling bodies of a known length after their last chunk
+        if !self.done && !self.inner.is_end_stream() {
+            ::tracing::debug!("the client disconnected before the response was completely sent");
+            ::tracing::info!(monotonic_counter.apollo_router_client_disconnects_total = 1u64);
+        }
+    }
+}
+
 #[derive(Clone, Default)]
 pub(crate) struct PropagatingMakeSpan {
     pub(crate) entitlement: EntitlementState,
@@ -105,6 +404,8 @@ impl<B> MakeSpan<B> for PropagatingMakeSpan {
         // This method needs to be moved to the telemetry plugin once we have a hook for the http request.
 
         // Before we make the span we need to attach span info that may have come in from the request.
+        // Header names are compared case-insensitively by the `HeaderMap` behind the extractor,
+        // so propagators find their headers whatever the case used by the client or the HTTP version.
         let context = global::get_text_map_propagator(|propagator| {
             propagator.extract(&opentelemetry_http::HeaderExtractor(request.headers()))
         });
@@ -1,6 +1,7 @@
 //! Cross Origin Resource Sharing (CORS configuration)
 // This entire file is license key functionality
 
+use std::collections::HashSet;
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -12,6 +13,7 @@ use serde::Deserialize;
 use serde::Serialize;
 use tower_http::cors;
 use tower_http::cors::CorsLayer;
+use url::Url;
 
 /// Cross origin request configuration.
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
@@ -45,6 +47,8 @@ pub(crate) struct Cors {
 
     /// The origin(s) to allow requests from.
     /// Defaults to `https://studio.apollographql.com/` for Apollo Studio.
+    /// Origins are compared after normalization: the scheme and host are case insensitive,
+    /// default ports (80 for http, 443 for https) and trailing slashes are ignored.
     pub(crate) origins: Vec<String>,
 
     /// `Regex`es you want to match the origins against to determine if they're allowed.
@@ -59,6 +63,13 @@ pub(crate) struct Cors {
     #[serde(deserialize_with = "humantime_serde::deserialize", default)]
     #[schemars(with = "String", default)]
     pub(crate) max_age: Option<Duration>,
+
+    /// Set to true to answer preflight requests from origins that are not allowed
+    /// with a `403 Forbidden` status and a JSON body giving the reason, instead of
+    /// a response without the `Access-Control-Allow-Origin` header.
+    ///
+    /// Defaults to false
+    pub(crate) reject_forbidden_origins: bool,
 }
 
 impl Default for Cors {
@@ -88,6 +99,7 @@ impl Cors {
         match_origins: Option<Vec<String>>,
         methods: Option<Vec<String>>,
         max_age: Option<Duration>,
+        reject_forbidden_origins: Option<bool>,
     ) -> Self {
         Self {
             expose_headers,
@@ -98,6 +110,7 @@ impl Cors {
             allow_any_origin: allow_any_origin.unwrap_or_default(),
             allow_credentials: allow_credentials.unwrap_or_default(),
             allow_headers: allow_headers.unwrap_or_default(),
+            reject_forbidden_origins: reject_forbidden_origins.unwrap_or_default(),
         }
     }
 }
@@ -122,15 +135,12 @@ impl Cors {
             .allow_credentials(self.allow_credentials)
             .allow_headers(allow_headers)
             .expose_headers(cors::ExposeHeaders::list(
-                self.expose_headers
-                    .unwrap_or_default()
-                    .iter()
-                    .filter_map(|header| {
-                        header
-                            .parse()
-                            .map_err(|_| tracing::error!("header name '{header}' is not valid"))
-                            .ok()
-                    }),
+                self.expose_headers.iter().flatten().filter_map(|header| {
+                    header
+                        .parse()
+                        .map_err(|_| tracing::error!("header name '{header}' is not valid"))
+                        .ok()
+                }),
             ))
             .allow_methods(cors::AllowMethods::list(self.methods.iter().filter_map(
                 |method| {
@@ -148,36 +158,37 @@ impl Cors {
 
         if self.allow_any_origin {
             Ok(cors.allow_origin(cors::Any))
-        } else if let Some(match_origins) = self.match_origins {
-            let regexes = match_origins
-                .into_iter()
+        } else {
+            let allowed_origins = self.allowed_origins();
+            Ok(cors.allow_origin(cors::AllowOrigin::predicate(
+                move |origin: &HeaderValue, _: &Parts| allowed_origins.allows(origin),
+            )))
+        }
+    }
+
+    /// The origins to check preflight requests against, when forbidden origins
+    /// must be rejected with a `403 Forbidden` status.
+    pub(crate) fn rejected_preflight_origins(&self) -> Option<AllowedOrigins> {
+        (self.reject_forbidden_origins && !self.allow_any_origin).then(|| self.allowed_origins())
+    }
+
+    fn allowed_origins(&self) -> AllowedOrigins {
+        AllowedOrigins {
+            origins: self
+                .origins
+                .iter()
+                .map(|origin| normalize_origin(origin))
+                .collect(),
+            regexes: self
+                .match_origins
+                .iter()
+                .flatten()
                 .filter_map(|regex| {
                     Regex::from_str(regex.as_str())
                         .map_err(|_| tracing::error!("origin regex '{regex}' is not valid"))
                         .ok()
                 })
-                .collect::<Vec<_>>();
-
-            Ok(cors.allow_origin(cors::AllowOrigin::predicate(
-                move |origin: &HeaderValue, _: &Parts| {
-                    origin
-                        .to_str()
-                        .map(|o| {
-                            self.origins.iter().any(|origin| origin.as_str() == o)
-                                || regexes.iter().any(|regex| regex.is_match(o))
-                        })
-                        .unwrap_or_default()
-                },
-            )))
-        } else {
-            Ok(cors.allow_origin(cors::AllowOrigin::list(
-                self.origins.into_iter().filter_map(|origin| {
-                    origin
-                        .parse()
-                        .map_err(|_| tracing::error!("origin '{origin}' is not valid"))
-                        .ok()
-                }),
-            )))
+                .collect(),
         }
     }
 
@@ -215,3 +226,36 @@ impl Cors {
         Ok(())
     }
 }
+
+/// The origins allowed by the `origins` and `match_origins` settings
+#[derive(Clone, Debug)]
+pub(crate) struct AllowedOrigins {
+    origins: HashSet<String>,
+    regexes: Vec<Regex>,
+}
+
+impl AllowedOrigins {
+    pub(crate) fn allows(&self, origin: &HeaderValue) -> bool {
+        origin
+            .to_str()
+            .map(|o| {
+                self.origins.contains(&normalize_origin(o))
+                    || self.regexes.iter().any(|regex| regex.is_match(o))
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Normalizes an origin so that equivalent origins compare equal when matched against
+/// the `origins` list:
+/// - the scheme and host are lowercased
+/// - the default port of the scheme is removed (80 for `http`, 443 for `https`)
+/// - the path is removed, including a trailing `/`
+///
+/// Origins that are not URLs with a host, such as `null`, are left as they are.
+fn normalize_origin(origin: &str) -> String {
+    match Url::parse(origin).map(|url| url.origin()) {
+        Ok(url_origin @ url::Origin::Tuple(..)) => url_origin.ascii_serialization(),
+        _ => origin.to_string(),
+    }
+}
@@ -4,6 +4,7 @@
 pub(crate) mod cors;
 mod expansion;
 mod experimental;
+pub(crate) mod ip_net;
 mod schema;
 pub(crate) mod subgraph;
 #[cfg(test)]
@@ -11,6 +12,7 @@ mod tests;
 mod upgrade;
 mod yaml;
 
+use std::collections::HashMap;
 use std::fmt;
 use std::io;
 use std::io::BufReader;
@@ -18,8 +20,10 @@ use std::iter;
 use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use derivative::Derivative;
 use displaydoc::Display;
@@ -47,12 +51,16 @@ use thiserror::Error;
 use self::cors::Cors;
 use self::expansion::Expansion;
 pub(crate) use self::experimental::Discussed;
+use self::ip_net::IpNet;
 pub(crate) use self::schema::generate_config_schema;
 pub(crate) use self::schema::generate_upgrade;
 use self::subgraph::SubgraphConfiguration;
 use crate::cache::DEFAULT_CACHE_CAPACITY;
 use crate::configuration::schema::Mode;
+use crate::json_ext::MergeConflicts;
+use crate::json_ext::Object;
 use crate::plugin::plugins;
+use crate::query_planner::OperationKind;
 use crate::ApolloRouterError;
 
 static SUPERGRAPH_ENDPOINT_REGEX: Lazy<Regex> = Lazy::new(|| {
@@ -109,6 +117,10 @@ pub struct Configuration {
     #[serde(default)]
     pub(crate) health_check: HealthCheck,
 
+    /// Administration endpoints configuration
+    #[serde(default)]
+    pub(crate) admin: Admin,
+
     /// Sandbox configuration
     #[serde(default)]
     pub(crate) sandbox: Sandbox,
@@ -164,6 +176,7 @@ impl<'de> serde::Deserialize<'de> for Configuration {
         #[serde(default)]
         struct AdHocConfiguration {
             health_check: HealthCheck,
+            admin: Admin,
             sandbox: Sandbox,
             homepage: Homepage,
             supergraph: Supergraph,
@@ -180,6 +193,7 @@ impl<'de> serde::Deserialize<'de> for Configuration {
 
         Configuration::builder()
             .health_check(ad_hoc.health_check)
+            .admin(ad_hoc.admin)
             .sandbox(ad_hoc.sandbox)
             .homepage(ad_hoc.homepage)
             .supergraph(ad_hoc.supergraph)
@@ -213,6 +227,7 @@ impl Configuration {
     pub(crate) fn new(
         supergraph: Option<Supergraph>,
         health_check: Option<HealthCheck>,
+        admin: Option<Admin>,
         sandbox: Option<Sandbox>,
         homepage: Option<Homepage>,
         cors: Option<Cors>,
@@ -227,6 +242,7 @@ impl Configuration {
             validated_yaml: Default::default(),
             supergraph: supergraph.unwrap_or_default(),
             health_check: health_check.unwrap_or_default(),
+            admin: admin.unwrap_or_default(),
             sandbox: sandbox.unwrap_or_default(),
             homepage: homepage.unwrap_or_default(),
             cors: cors.unwrap_or_default(),
@@ -283,6 +299,7 @@ impl Configuration {
     pub(crate) fn fake_new(
         supergraph: Option<Supergraph>,
         health_check: Option<HealthCheck>,
+        admin: Option<Admin>,
         sandbox: Option<Sandbox>,
         homepage: Option<Homepage>,
         cors: Option<Cors>,
@@ -297,6 +314,7 @@ impl Configuration {
             validated_yaml: Default::default(),
             supergraph: supergraph.unwrap_or_else(|| Supergraph::fake_builder().build()),
             health_check: health_check.unwrap_or_else(|| HealthCheck::fake_builder().build()),
+            admin: admin.unwrap_or_else(|| Admin::fake_builder().build()),
             sandbox: sandbox.unwrap_or_else(|| Sandbox::fake_builder().build()),
             homepage: homepage.unwrap_or_else(|| Homepage::fake_builder().build()),
             cors: cors.unwrap_or_default(),
@@ -332,6 +350,117 @@ impl Configuration {
                 error: "sandbox needs introspection to be enabled".to_string(),
             });
         }
+        let html_redirect = self
+            .supergraph
+            .html_by_path
+            .values()
+            .any(|behavior| *behavior == HtmlBehavior::Redirect);
+        if self.homepage.redirect_to_studio || html_redirect {
+            if self.homepage.graph_ref.is_none() {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "the redirection to Apollo Studio requires a graph reference",
+                    error: "set 'homepage.graph_ref' to redirect to Apollo Studio".to_string(),
+                });
+            }
+            if ![302, 307, 308].contains(&self.homepage.redirect_status) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'homepage.redirect_status' configuration",
+                    error: format!(
+                        "{} is not a redirect status, expected one of: 302, 307, 308",
+                        self.homepage.redirect_status
+                    ),
+                });
+            }
+        }
+        if self
+            .supergraph
+            .html_by_path
+            .values()
+            .any(|behavior| *behavior == HtmlBehavior::LandingPage)
+            && !self.sandbox.enabled
+            && !self.homepage.enabled
+        {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid 'supergraph.html_by_path' configuration",
+                error: "serving a landing page requires the sandbox or the homepage".to_string(),
+            });
+        }
+        if let Some(path) = self
+            .supergraph
+            .html_by_path
+            .keys()
+            .find(|path| !path.starts_with('/'))
+        {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid 'supergraph.html_by_path' configuration",
+                error: format!(
+                    "'{path}' is invalid, it must be an absolute path and start with '/'"
+                ),
+            });
+        }
+        for (name, value) in &self.supergraph.response_headers {
+            if let Err(e) = http::HeaderName::try_from(name) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'supergraph.response_headers' configuration",
+                    error: format!("'{name}' is not a valid header name: {e}"),
+                });
+            }
+            if let Err(e) = http::HeaderValue::try_from(value) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'supergraph.response_headers' configuration",
+                    error: format!("the value of the '{name}' header is invalid: {e}"),
+                });
+            }
+        }
+        // the maximum flow control window of HTTP/2
+        let max_window_size = (1 << 31) - 1;
+        if self.supergraph.http2.initial_stream_window_size > max_window_size
+            || self.supergraph.http2.initial_connection_window_size > max_window_size
+        {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid 'supergraph.http2' configuration",
+                error: format!("the window sizes cannot be larger than {max_window_size} bytes"),
+            });
+        }
+        if self.tls.supergraph.is_none() {
+            if let Some((listen_addr, _)) = self
+                .tls
+                .listeners
+                .iter()
+                .find(|(_, tls)| matches!(tls, ListenerTls::Shared(SharedTls::Supergraph)))
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'tls.listeners' configuration",
+                    error: format!(
+                        "'{listen_addr}' uses the TLS configuration of the GraphQL endpoint, but 'tls.supergraph' is not set"
+                    ),
+                });
+            }
+        }
+        if let Some(subgraphs) = &self.health_check.subgraphs {
+            if subgraphs.interval.is_zero() {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'health_check.subgraphs' configuration",
+                    error: "the interval cannot be zero".to_string(),
+                });
+            }
+            if subgraphs.timeout() > subgraphs.interval {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'health_check.subgraphs' configuration",
+                    error: format!(
+                        "the timeout ({:?}) cannot be longer than the interval ({:?})",
+                        subgraphs.timeout(),
+                        subgraphs.interval
+                    ),
+                });
+            }
+        }
+        if self.supergraph.allowed_operation_types.is_empty() {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid 'supergraph.allowed_operation_types' configuration",
+                error: "at least one operation type must be allowed".to_string(),
+            });
+        }
         if !self.supergraph.path.starts_with('/') {
             return Err(ConfigurationError::InvalidConfiguration {
             message: "invalid 'server.graphql_path' configuration",
@@ -368,6 +497,70 @@ impl Configuration {
 
         Ok(self)
     }
+
+    /// The configuration as JSON, with the values of the fields that look like secrets,
+    /// like keys, tokens or passwords, and the passwords of URLs replaced with `[REDACTED]`
+    pub(crate) fn redacted(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        redact(&mut value);
+        value
+    }
+}
+
+const REDACTED: &str = "[REDACTED]";
+
+fn is_secret_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    [
+        "key",
+        "secret",
+        "password",
+        "token",
+        "authorization",
+        "credential",
+        "cookie",
+    ]
+    .iter()
+    .any(|secret| name.contains(secret))
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            // header rules, like `{"name": "authorization", "value": "..."}`
+            let secret_header = object
+                .get("name")
+                .and_then(Value::as_str)
+                .map(is_secret_name)
+                .unwrap_or_default();
+            for (name, value) in object.iter_mut() {
+                if is_secret_name(name) || (secret_header && name == "value") {
+                    redact_strings(value);
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(redact),
+        Value::String(s) => {
+            if let Ok(mut url) = url::Url::parse(s) {
+                if url.password().is_some() && url.set_password(Some(REDACTED)).is_ok() {
+                    *s = url.to_string();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Secrets are strings: numbers like `parser_max_tokens` are kept
+fn redact_strings(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = REDACTED.to_string(),
+        Value::Object(object) => object.values_mut().for_each(redact_strings),
+        Value::Array(array) => array.iter_mut().for_each(redact_strings),
+        _ => {}
+    }
 }
 
 /// Parse configuration from a string in YAML syntax
@@ -476,6 +669,200 @@ pub(crate) struct Supergraph {
 
     /// Query planning options
     pub(crate) query_planning: QueryPlanning,
+
+    /// Limit the size of incoming HTTP request bodies, in bytes.
+    /// Requests declaring a larger `Content-Length` are rejected with a HTTP 413 Payload Too Large
+    /// response before their body is read. Clients sending `Expect: 100-continue` receive the 413
+    /// instead of the interim `100 Continue` response, and so never upload the body.
+    /// Default: no limit
+    pub(crate) max_request_bytes: Option<usize>,
+
+    /// Limit the size of the GraphQL responses sent to clients, in bytes.
+    /// A response over this limit is replaced with a GraphQL error with
+    /// `"extensions": {"code": "RESPONSE_TOO_LARGE"}` and no data. The serialization stops
+    /// as soon as the limit is reached. Responses streamed as multiple parts (with `@defer`) are not limited.
+    /// Default: no limit
+    pub(crate) max_response_bytes: Option<usize>,
+
+    /// Close client connections that did not send or receive any data for this long,
+    /// like keep-alive connections waiting for their next request.
+    /// A request in flight on an idle connection is answered before the connection is closed.
+    /// Default: idle connections are kept open
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) connection_idle_timeout: Option<Duration>,
+
+    /// Close client connections when sending data to the client has been blocked for this long,
+    /// because the client does not read what the router sends.
+    /// Default: no timeout
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) connection_write_timeout: Option<Duration>,
+
+    /// Limit the number of bytes sent to a client over the lifetime of a connection.
+    /// Once a connection has sent more than this, it is closed after the response in flight
+    /// is sent, and the client has to open a new connection for its next requests.
+    /// Default: no limit
+    pub(crate) connection_max_bytes_sent: Option<usize>,
+
+    /// Limit the number of requests served on a connection. Once a connection has served this
+    /// many requests, it is closed after sending the last response, with a
+    /// `Connection: close` header for HTTP/1.1 clients, and the client has to open a new
+    /// connection for its next requests. This spreads the clients over the routers
+    /// behind a load balancer.
+    /// Default: no limit
+    pub(crate) connection_max_requests: Option<NonZeroUsize>,
+
+    /// Settings of the client connections using HTTP/2
+    pub(crate) http2: Http2,
+
+    /// Add an `ETag` header, a hash of the body, to the GraphQL responses
+    /// sent in a single part, and answer GET requests with a `If-None-Match` header
+    /// matching it with a HTTP 304 Not Modified response without a body.
+    /// This is mainly useful for clients repeatedly sending the same introspection query.
+    /// Default: false
+    pub(crate) etag: bool,
+
+    /// How type conflicts between subgraph responses merged at the same place
+    /// (an object and an array for the same field) are reported.
+    /// Default: debug
+    pub(crate) merge_conflicts: MergeConflicts,
+
+    /// Reject POST requests with top-level fields other than `query`, `operationName`,
+    /// `variables` and `extensions` with a HTTP 400 error naming the unknown field,
+    /// instead of ignoring them.
+    /// Default: false
+    pub(crate) strict_request_parsing: bool,
+
+    /// Limit the nesting depth of arrays and objects in the JSON body of POST requests.
+    /// Bodies nested deeper are rejected with a HTTP 400 error as soon as the parser reaches
+    /// the limit.
+    /// Default: no limit other than the 128 levels supported by the JSON parser
+    pub(crate) max_request_depth: Option<usize>,
+
+    /// Limit the number of object keys and values in the JSON body of POST requests, like
+    /// the elements of huge variable arrays. Bodies with more are rejected with a HTTP 400
+    /// error as soon as the parser reaches the limit. This bounds the structure of requests,
+    /// where `max_request_bytes` bounds their size.
+    /// Default: no limit
+    pub(crate) max_request_tokens: Option<usize>,
+
+    /// A path prefix, like `/api`, that is stripped from the requests received on the
+    /// supergraph listener before they are routed, for deployments behind a proxy that
+    /// forwards requests without rewriting their path.
+    /// Requests without the prefix are still accepted.
+    /// Default: no prefix
+    pub(crate) path_prefix: Option<String>,
+
+    /// Decode `+` as a space in the query string of GET requests, as form encoding does.
+    /// Set to false to keep `+` as is, for clients that do not percent-encode it as `%2B`.
+    /// Default: true
+    pub(crate) get_plus_as_space: bool,
+
+    /// Log a record for every completed request on the supergraph listener
+    pub(crate) access_log: AccessLog,
+
+    /// The networks of the proxies in front of the router, like `10.0.0.0/8`.
+    /// When set, the client name and version headers are only used for requests coming from
+    /// these networks. The client address is the first address of the `X-Forwarded-For`
+    /// header, walked from the right, that is not a trusted proxy. The `Forwarded` header is
+    /// ignored.
+    /// Default: the headers are used for all requests
+    #[schemars(with = "Vec<String>")]
+    pub(crate) trusted_proxies: Vec<IpNet>,
+
+    /// Send the GraphQL responses that are not deferred in chunks as they are serialized,
+    /// instead of serializing them completely first, so that clients can start parsing
+    /// large responses earlier. These responses have no `Content-Length` header.
+    /// This has no effect when `max_response_bytes` or `etag` are set, as they need the
+    /// whole serialized response.
+    /// Default: false
+    pub(crate) stream_json_responses: bool,
+
+    /// Timeouts of the HTTP requests, per group of routes
+    pub(crate) route_timeouts: RouteTimeouts,
+
+    /// Log a warning and count the GraphQL requests taking longer than this to answer,
+    /// in the `apollo_router_slow_requests_total` metric, as a cheaper alerting signal than
+    /// the quantiles of the request duration histogram. The duration covers the time until
+    /// the response is ready to be sent.
+    /// Default: no threshold
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) slow_request_threshold: Option<Duration>,
+
+    /// How long the router waits for the connections of all its listeners to be drained when
+    /// shutting down. The listeners all stop accepting connections at the same time, and the
+    /// router stops waiting for the connections still open at the deadline.
+    /// Default: no deadline
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) shutdown_deadline: Option<Duration>,
+
+    /// Batches of GraphQL requests, sent as a JSON array in the body of a POST request
+    pub(crate) batch_execution: BatchExecution,
+
+    /// How GET requests preferring HTML, like the ones of browsers, are answered, by request
+    /// path, like `/graphql`. This overrides the behavior set by the `sandbox` and `homepage`
+    /// options for these paths.
+    /// Default: the same behavior for all paths
+    pub(crate) html_by_path: HashMap<String, HtmlBehavior>,
+
+    /// Variables added to the GraphQL requests that do not set them. The variables sent by
+    /// clients take precedence, including `null` and lists, and objects are merged recursively.
+    /// Default: no variables
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
+    pub(crate) default_variables: Object,
+
+    /// Execute identical queries received at the same time only once, sharing the responses.
+    /// Queries are identical when they have the same operation, variables and extensions, and the
+    /// same `Authorization` and `Cookie` headers. Mutations and deferred queries are never shared.
+    /// Default: false
+    pub(crate) coalesce_requests: bool,
+
+    /// The types of the operations that are executed, like `query` for a read-only endpoint.
+    /// Other operations are rejected with a `400 Bad Request` status.
+    /// Default: all the operation types
+    #[serde(default = "default_allowed_operation_types")]
+    pub(crate) allowed_operation_types: Vec<OperationKind>,
+
+    /// Keep the GraphQL requests decoded from the query strings of this many distinct GET
+    /// requests, so that repeated GET requests, like the ones of persisted queries cached by
+    /// a CDN, skip the decoding and parsing of their query string.
+    /// Default: GET requests are always decoded
+    pub(crate) get_query_cache_size: Option<NonZeroUsize>,
+
+    /// How the `operationName` and `variables` parameters in the query string of the URL of
+    /// POST requests are handled, when the GraphQL request is in the body.
+    /// Default: ignore
+    pub(crate) query_string_parameters: QueryStringParameters,
+
+    /// Headers added to the responses of GraphQL requests, like `Cache-Control` or a header
+    /// naming the region of the router. The headers already set on a response, by the router
+    /// or by plugins, are kept. The CORS headers are set after these ones, and can replace them.
+    /// Default: no headers
+    pub(crate) response_headers: HashMap<String, String>,
+
+    /// Path to a safelist manifest, a JSON array of the hexadecimal SHA-256 hashes of the
+    /// queries allowed to run. The manifest is read when the router starts, and again on
+    /// every reload of the configuration or of the schema. Requests for other queries,
+    /// including automatic persisted queries, are rejected with a HTTP 403 error.
+    /// Default: all the queries are allowed
+    pub(crate) safelist: Option<PathBuf>,
+
+    /// Values added to the `extensions` of the responses of GraphQL requests, like a notice.
+    /// The overlay only fills the keys that are missing, recursively: the values set by the
+    /// router or by plugins are kept.
+    /// Default: no overlay
+    pub(crate) response_overlay: ResponseOverlay,
+}
+
+fn default_allowed_operation_types() -> Vec<OperationKind> {
+    vec![
+        OperationKind::Query,
+        OperationKind::Mutation,
+        OperationKind::Subscription,
+    ]
 }
 
 fn default_defer_support() -> bool {
@@ -491,6 +878,36 @@ impl Supergraph {
         introspection: Option<bool>,
         defer_support: Option<bool>,
         query_planning: Option<QueryPlanning>,
+        max_request_bytes: Option<usize>,
+        max_response_bytes: Option<usize>,
+        connection_idle_timeout: Option<Duration>,
+        connection_write_timeout: Option<Duration>,
+        connection_max_bytes_sent: Option<usize>,
+        connection_max_requests: Option<NonZeroUsize>,
+        http2: Option<Http2>,
+        etag: Option<bool>,
+        merge_conflicts: Option<MergeConflicts>,
+        strict_request_parsing: Option<bool>,
+        max_request_depth: Option<usize>,
+        max_request_tokens: Option<usize>,
+        path_prefix: Option<String>,
+        get_plus_as_space: Option<bool>,
+        access_log: Option<AccessLog>,
+        trusted_proxies: Option<Vec<IpNet>>,
+        stream_json_responses: Option<bool>,
+        route_timeouts: Option<RouteTimeouts>,
+        slow_request_threshold: Option<Duration>,
+        shutdown_deadline: Option<Duration>,
+        batch_execution: Option<BatchExecution>,
+        html_by_path: Option<HashMap<String, HtmlBehavior>>,
+        default_variables: Option<Object>,
+        coalesce_requests: Option<bool>,
+        allowed_operation_types: Option<Vec<OperationKind>>,
+        get_query_cache_size: Option<NonZeroUsize>,
+        query_string_parameters: Option<QueryStringParameters>,
+        response_headers: Option<HashMap<String, String>>,
+        safelist: Option<PathBuf>,
+        response_overlay: Option<ResponseOverlay>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(default_graphql_listen),
@@ -498,6 +915,37 @@ impl Supergraph {
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
             defer_support: defer_support.unwrap_or_else(default_defer_support),
             query_planning: query_planning.unwrap_or_default(),
+            max_request_bytes,
+            max_response_bytes,
+            connection_idle_timeout,
+            connection_write_timeout,
+            connection_max_bytes_sent,
+            connection_max_requests,
+            http2: http2.unwrap_or_default(),
+            etag: etag.unwrap_or_default(),
+            merge_conflicts: merge_conflicts.unwrap_or_default(),
+            strict_request_parsing: strict_request_parsing.unwrap_or_default(),
+            max_request_depth,
+            max_request_tokens,
+            path_prefix,
+            get_plus_as_space: get_plus_as_space.unwrap_or(true),
+            access_log: access_log.unwrap_or_default(),
+            trusted_proxies: trusted_proxies.unwrap_or_default(),
+            stream_json_responses: stream_json_responses.unwrap_or_default(),
+            route_timeouts: route_timeouts.unwrap_or_default(),
+            slow_request_threshold,
+            shutdown_deadline,
+            batch_execution: batch_execution.unwrap_or_default(),
+            html_by_path: html_by_path.unwrap_or_default(),
+            default_variables: default_variables.unwrap_or_default(),
+            coalesce_requests: coalesce_requests.unwrap_or_default(),
+            allowed_operation_types: allowed_operation_types
+                .unwrap_or_else(default_allowed_operation_types),
+            get_query_cache_size,
+            query_string_parameters: query_string_parameters.unwrap_or_default(),
+            response_headers: response_headers.unwrap_or_default(),
+            safelist,
+            response_overlay: response_overlay.unwrap_or_default(),
         }
     }
 }
@@ -512,6 +960,36 @@ impl Supergraph {
         introspection: Option<bool>,
         defer_support: Option<bool>,
         query_planning: Option<QueryPlanning>,
+        max_request_bytes: Option<usize>,
+        max_response_bytes: Option<usize>,
+        connection_idle_timeout: Option<Duration>,
+        connection_write_timeout: Option<Duration>,
+        connection_max_bytes_sent: Option<usize>,
+        connection_max_requests: Option<NonZeroUsize>,
+        http2: Option<Http2>,
+        etag: Option<bool>,
+        merge_conflicts: Option<MergeConflicts>,
+        strict_request_parsing: Option<bool>,
+        max_request_depth: Option<usize>,
+        max_request_tokens: Option<usize>,
+        path_prefix: Option<String>,
+        get_plus_as_space: Option<bool>,
+        access_log: Option<AccessLog>,
+        trusted_proxies: Option<Vec<IpNet>>,
+        stream_json_responses: Option<bool>,
+        route_timeouts: Option<RouteTimeouts>,
+        slow_request_threshold: Option<Duration>,
+        shutdown_deadline: Option<Duration>,
+        batch_execution: Option<BatchExecution>,
+        html_by_path: Option<HashMap<String, HtmlBehavior>>,
+        default_variables: Option<Object>,
+        coalesce_requests: Option<bool>,
+        allowed_operation_types: Option<Vec<OperationKind>>,
+        get_query_cache_size: Option<NonZeroUsize>,
+        query_string_parameters: Option<QueryStringParameters>,
+        response_headers: Option<HashMap<String, String>>,
+        safelist: Option<PathBuf>,
+        response_overlay: Option<ResponseOverlay>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(test_listen),
@@ -519,6 +997,37 @@ impl Supergraph {
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
             defer_support: defer_support.unwrap_or_else(default_defer_support),
             query_planning: query_planning.unwrap_or_default(),
+            max_request_bytes,
+            max_response_bytes,
+            connection_idle_timeout,
+            connection_write_timeout,
+            connection_max_bytes_sent,
+            connection_max_requests,
+            http2: http2.unwrap_or_default(),
+            etag: etag.unwrap_or_default(),
+            merge_conflicts: merge_conflicts.unwrap_or_default(),
+            strict_request_parsing: strict_request_parsing.unwrap_or_default(),
+            max_request_depth,
+            max_request_tokens,
+            path_prefix,
+            get_plus_as_space: get_plus_as_space.unwrap_or(true),
+            access_log: access_log.unwrap_or_default(),
+            trusted_proxies: trusted_proxies.unwrap_or_default(),
+            stream_json_responses: stream_json_responses.unwrap_or_default(),
+            route_timeouts: route_timeouts.unwrap_or_default(),
+            slow_request_threshold,
+            shutdown_deadline,
+            batch_execution: batch_execution.unwrap_or_default(),
+            html_by_path: html_by_path.unwrap_or_default(),
+            default_variables: default_variables.unwrap_or_default(),
+            coalesce_requests: coalesce_requests.unwrap_or_default(),
+            allowed_operation_types: allowed_operation_types
+                .unwrap_or_else(default_allowed_operation_types),
+            get_query_cache_size,
+            query_string_parameters: query_string_parameters.unwrap_or_default(),
+            response_headers: response_headers.unwrap_or_default(),
+            safelist,
+            response_overlay: response_overlay.unwrap_or_default(),
         }
     }
 }
@@ -530,6 +1039,19 @@ impl Default for Supergraph {
 }
 
 impl Supergraph {
+    /// The `response_headers`, which are valid once the configuration is validated
+    pub(crate) fn response_headers(&self) -> http::HeaderMap {
+        self.response_headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    http::HeaderName::try_from(name).ok()?,
+                    http::HeaderValue::try_from(value).ok()?,
+                ))
+            })
+            .collect()
+    }
+
     /// To sanitize the path for axum router
     pub(crate) fn sanitized_path(&self) -> String {
         let mut path = self.path.clone();
@@ -546,6 +1068,261 @@ impl Supergraph {
     }
 }
 
+/// Access log configuration
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct AccessLog {
+    /// Log a record for every completed request, at the info level with the
+    /// `apollo_router::access_log` target
+    /// Default: false
+    pub(crate) enabled: bool,
+
+    /// The format of the records
+    /// Default: json
+    pub(crate) format: AccessLogFormat,
+
+    /// The fields of the records, in this order
+    /// Default: all the fields
+    pub(crate) fields: Vec<AccessLogField>,
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: AccessLogFormat::default(),
+            fields: vec![
+                AccessLogField::Method,
+                AccessLogField::Path,
+                AccessLogField::Status,
+                AccessLogField::Duration,
+                AccessLogField::Bytes,
+                AccessLogField::ClientIp,
+                AccessLogField::OperationName,
+            ],
+        }
+    }
+}
+
+/// Access log record format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AccessLogFormat {
+    /// A JSON object
+    #[default]
+    Json,
+    /// `key=value` pairs separated by spaces
+    Text,
+}
+
+/// A field of the access log records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AccessLogField {
+    /// The HTTP method
+    Method,
+    /// The path of the request, without the query string
+    Path,
+    /// The HTTP status code of the response
+    Status,
+    /// The time between receiving the request and sending the end of the response, in seconds
+    Duration,
+    /// The size of the response body, after compression
+    Bytes,
+    /// The first address of the `X-Forwarded-For` header, or the address of the client connection
+    ClientIp,
+    /// The GraphQL operation name, when the request has one
+    OperationName,
+}
+
+/// Timeouts of the HTTP requests, from receiving the request to sending the response headers.
+/// A request that times out is answered with a HTTP 504 Gateway Timeout response.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct RouteTimeouts {
+    /// Timeout of the GraphQL requests, on top of the `traffic_shaping` router timeout:
+    /// the shortest one applies
+    /// Default: no timeout
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) graphql: Option<Duration>,
+
+    /// Timeout of the health check requests
+    /// Default: no timeout
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) health: Option<Duration>,
+
+    /// Timeout of the Prometheus metrics requests
+    /// Default: no timeout
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) metrics: Option<Duration>,
+
+    /// Timeout of the admin requests
+    /// Default: no timeout
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) admin: Option<Duration>,
+}
+
+impl RouteTimeouts {
+    pub(crate) fn for_group(&self, group: RouteGroup) -> Option<Duration> {
+        match group {
+            RouteGroup::Health => self.health,
+            RouteGroup::Metrics => self.metrics,
+            RouteGroup::Admin => self.admin,
+        }
+    }
+}
+
+/// The groups of routes served by the router itself, other than the GraphQL one,
+/// that have their own timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RouteGroup {
+    Health,
+    Metrics,
+    Admin,
+}
+
+/// How a GET request preferring HTML is answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HtmlBehavior {
+    /// Redirect to Apollo Studio Explorer, with the graph reference and the redirect status
+    /// of the `homepage` options
+    Redirect,
+    /// Serve the Sandbox or the homepage, whichever is enabled
+    LandingPage,
+    /// Answer with a GraphQL error, for paths only meant for GraphQL clients
+    Error,
+}
+
+/// How the parameters in the query string of a POST request are combined with the GraphQL
+/// request of its body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum QueryStringParameters {
+    /// Use the body only
+    #[default]
+    Ignore,
+    /// The parameters of the query string take precedence over the ones of the body
+    Override,
+    /// Use the parameters of the query string missing from the body, and reject the
+    /// requests with different values in both with a `400 Bad Request` status
+    RejectConflicts,
+}
+
+/// Settings of the client connections using HTTP/2, applied to each connection
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct Http2 {
+    /// The maximum number of requests a client can send at the same time on a connection
+    /// Default: no limit
+    pub(crate) max_concurrent_streams: Option<u32>,
+
+    /// The initial flow control window of each request, in bytes: the size of the request
+    /// body a client can send before the router reads it
+    /// Default: 1048576
+    pub(crate) initial_stream_window_size: u32,
+
+    /// The initial flow control window of a connection, in bytes, shared by its requests
+    /// Default: 1048576
+    pub(crate) initial_connection_window_size: u32,
+
+    /// Send pings to the clients at this interval, to detect the connections that are
+    /// not used anymore
+    /// Default: no pings
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) keep_alive_interval: Option<Duration>,
+
+    /// Close the connection when a ping is not acknowledged within this duration
+    /// Default: 20s
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub(crate) keep_alive_timeout: Duration,
+}
+
+impl Default for Http2 {
+    // the defaults of hyper
+    fn default() -> Self {
+        Self {
+            max_concurrent_streams: None,
+            initial_stream_window_size: 1024 * 1024,
+            initial_connection_window_size: 1024 * 1024,
+            keep_alive_interval: None,
+            keep_alive_timeout: Duration::from_secs(20),
+        }
+    }
+}
+
+/// Batches of GraphQL requests, answered with a JSON array of the responses in the order
+/// of the requests, whatever the execution mode
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct BatchExecution {
+    /// Accept batches of GraphQL requests. When disabled, a batch is rejected as an invalid
+    /// GraphQL request.
+    /// Default: false
+    pub(crate) enabled: bool,
+
+    /// How the operations of a batch are executed
+    /// Default: sequential
+    pub(crate) mode: BatchMode,
+
+    /// The maximum number of operations of a batch executed at the same time
+    /// in the `concurrent` mode
+    /// Default: 10
+    pub(crate) max_concurrency: NonZeroUsize,
+
+    /// The maximum number of operations of a batch. Larger batches are rejected as invalid
+    /// GraphQL requests.
+    /// Default: 100
+    pub(crate) max_batch_size: NonZeroUsize,
+}
+
+impl Default for BatchExecution {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: BatchMode::default(),
+            max_concurrency: NonZeroUsize::new(10).expect("not zero"),
+            max_batch_size: NonZeroUsize::new(100).expect("not zero"),
+        }
+    }
+}
+
+/// Execution mode of the operations of a batch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BatchMode {
+    /// Each operation starts once the previous one completed, so mutations are executed
+    /// in the order of the batch
+    #[default]
+    Sequential,
+    /// Operations are executed at the same time: the mutations of a batch are executed
+    /// in no particular order
+    Concurrent,
+}
+
+/// Values added to the responses of GraphQL requests
+///
+/// There is no overlay for the `data`: its shape is defined by the query, and adding fields
+/// that were not selected, or replacing nulls that were resolved, would break that contract.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct ResponseOverlay {
+    /// Added to the `extensions` of the responses
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
+    pub(crate) extensions: Object,
+}
+
 /// Configuration for operation limits
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
@@ -621,6 +1398,12 @@ pub(crate) struct OperationLimits {
 
     /// Limit the number of tokens the GraphQL parser processes before aborting.
     pub(crate) parser_max_tokens: usize,
+
+    /// If set, documents containing more operations than this maximum
+    /// are rejected with a HTTP 400 Bad Request response and GraphQL error with
+    /// `"extensions": {"code": "MAX_OPERATIONS_LIMIT"}`, even when the request selects
+    /// one of them with its operation name.
+    pub(crate) max_operations_per_document: Option<usize>,
 }
 
 impl Default for OperationLimits {
@@ -638,6 +1421,7 @@ impl Default for OperationLimits {
             // https://docs.rs/apollo-parser/0.2.8/src/apollo_parser/parser/mod.rs.html#368
             parser_max_recursion: 4096,
             parser_max_tokens: 15_000,
+            max_operations_per_document: None,
         }
     }
 }
@@ -749,6 +1533,62 @@ pub(crate) struct Tls {
     /// this will affect the GraphQL endpoint and any other endpoint targeting the same listen address
     pub(crate) supergraph: Option<TlsSupergraph>,
     pub(crate) subgraph: SubgraphConfiguration<TlsSubgraph>,
+    /// TLS of the listen addresses other than the one of the GraphQL endpoint, like the ones
+    /// of the health check or the Prometheus endpoint.
+    ///
+    /// When the GraphQL endpoint uses TLS, the router does not start if another listen address
+    /// that is not a loopback address is missing from this map: set it to `plaintext` to serve
+    /// it without TLS.
+    pub(crate) listeners: HashMap<ListenAddr, ListenerTls>,
+}
+
+impl Tls {
+    /// The TLS configuration of a listen address other than the one of the GraphQL endpoint,
+    /// or `None` to serve it without TLS
+    pub(crate) fn listener_tls_config(
+        &self,
+        listen_addr: &ListenAddr,
+    ) -> Result<Option<Arc<rustls::ServerConfig>>, ApolloRouterError> {
+        match self.listeners.get(listen_addr) {
+            Some(ListenerTls::Shared(SharedTls::Supergraph)) => self
+                .supergraph
+                .as_ref()
+                .map(|tls| tls.tls_config())
+                .transpose(),
+            Some(ListenerTls::Shared(SharedTls::Plaintext)) => Ok(None),
+            Some(ListenerTls::Custom(tls)) => tls.tls_config().map(Some),
+            None => match listen_addr.ip_and_port() {
+                Some((ip, _)) if self.supergraph.is_some() && !ip.is_loopback() => {
+                    Err(ApolloRouterError::ServerCreationError(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "the endpoints on {listen_addr} would be served without TLS while the GraphQL endpoint uses TLS: set `tls.listeners` for this address, to `plaintext` to serve it without TLS"
+                        ),
+                    )))
+                }
+                _ => Ok(None),
+            },
+        }
+    }
+}
+
+/// TLS of a listen address other than the one of the GraphQL endpoint
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum ListenerTls {
+    /// Share the TLS configuration of the GraphQL endpoint, or serve without TLS
+    Shared(SharedTls),
+    /// A TLS configuration of its own
+    Custom(TlsSupergraph),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SharedTls {
+    /// The TLS configuration of the GraphQL endpoint, `tls.supergraph`
+    Supergraph,
+    /// No TLS, like for an interface only reachable from an internal network
+    Plaintext,
 }
 
 /// Configuration options pertaining to the supergraph server component.
@@ -767,6 +1607,13 @@ pub(crate) struct TlsSupergraph {
     #[serde(deserialize_with = "deserialize_certificate_chain", skip_serializing)]
     #[schemars(with = "String")]
     pub(crate) certificate_chain: Vec<Certificate>,
+    /// ALPN protocols advertised during the TLS handshake, in order of preference:
+    /// `h2` and `http/1.1` are supported
+    #[serde(
+        default = "default_alpn_protocols",
+        deserialize_with = "deserialize_alpn_protocols"
+    )]
+    pub(crate) alpn: Vec<String>,
 }
 
 impl TlsSupergraph {
@@ -779,12 +1626,42 @@ impl TlsSupergraph {
             .with_no_client_auth()
             .with_single_cert(certificates, self.key.clone())
             .map_err(ApolloRouterError::Rustls)?;
-        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        config.alpn_protocols = self
+            .alpn
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect();
 
         Ok(Arc::new(config))
     }
 }
 
+/// The protocols the HTTP server can serve after the TLS handshake
+const SUPPORTED_ALPN_PROTOCOLS: [&str; 2] = ["h2", "http/1.1"];
+
+fn default_alpn_protocols() -> Vec<String> {
+    SUPPORTED_ALPN_PROTOCOLS
+        .iter()
+        .map(|protocol| protocol.to_string())
+        .collect()
+}
+
+fn deserialize_alpn_protocols<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let protocols = Vec::<String>::deserialize(deserializer)?;
+    if let Some(protocol) = protocols
+        .iter()
+        .find(|protocol| !SUPPORTED_ALPN_PROTOCOLS.contains(&protocol.as_str()))
+    {
+        return Err(serde::de::Error::custom(format!(
+            "unsupported ALPN protocol '{protocol}', expected one of: h2, http/1.1"
+        )));
+    }
+    Ok(protocols)
+}
+
 fn deserialize_certificate<'de, D>(deserializer: D) -> Result<Certificate, D::Error>
 where
     D: Deserializer<'de>,
@@ -942,19 +1819,35 @@ pub(crate) struct Homepage {
     /// Graph reference
     /// This will allow you to redirect from the Apollo Router landing page back to Apollo Studio Explorer
     pub(crate) graph_ref: Option<String>,
+    /// Set to true to redirect browsers to Apollo Studio Explorer for the graph reference,
+    /// instead of serving the landing page
+    pub(crate) redirect_to_studio: bool,
+    /// The status code of the redirection to Apollo Studio Explorer: 302, 307 or 308
+    /// Defaults to 307
+    pub(crate) redirect_status: u16,
 }
 
 fn default_homepage() -> bool {
     true
 }
 
+fn default_redirect_status() -> u16 {
+    http::StatusCode::TEMPORARY_REDIRECT.as_u16()
+}
+
 #[buildstructor::buildstructor]
 impl Homepage {
     #[builder]
-    pub(crate) fn new(enabled: Option<bool>) -> Self {
+    pub(crate) fn new(
+        enabled: Option<bool>,
+        redirect_to_studio: Option<bool>,
+        redirect_status: Option<u16>,
+    ) -> Self {
         Self {
             enabled: enabled.unwrap_or_else(default_homepage),
             graph_ref: None,
+            redirect_to_studio: redirect_to_studio.unwrap_or_default(),
+            redirect_status: redirect_status.unwrap_or_else(default_redirect_status),
         }
     }
 }
@@ -963,10 +1856,17 @@ impl Homepage {
 #[buildstructor::buildstructor]
 impl Homepage {
     #[builder]
-    pub(crate) fn fake_new(enabled: Option<bool>) -> Self {
+    pub(crate) fn fake_new(
+        enabled: Option<bool>,
+        graph_ref: Option<String>,
+        redirect_to_studio: Option<bool>,
+        redirect_status: Option<u16>,
+    ) -> Self {
         Self {
             enabled: enabled.unwrap_or_else(default_homepage),
-            graph_ref: None,
+            graph_ref,
+            redirect_to_studio: redirect_to_studio.unwrap_or_default(),
+            redirect_status: redirect_status.unwrap_or_else(default_redirect_status),
         }
     }
 }
@@ -988,6 +1888,74 @@ pub(crate) struct HealthCheck {
 
     /// Set to false to disable the health check endpoint
     pub(crate) enabled: bool,
+
+    /// Set to true to add the router version and the schema hash to the health check response
+    /// Defaults to false
+    pub(crate) detailed: bool,
+
+    /// Probe the subgraphs in the background and report their reachability in the health
+    /// check response, answering with a HTTP 503 when a required subgraph is not reachable.
+    /// Defaults to the process only check
+    pub(crate) subgraphs: Option<SubgraphHealthCheck>,
+
+    /// How long the health probes of plugins can run before they are considered failed
+    /// Defaults to 1s
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub(crate) probe_timeout: Duration,
+}
+
+/// Subgraph reachability reported by the health check
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct SubgraphHealthCheck {
+    /// The subgraphs that must be reachable for the router to be reported as up
+    pub(crate) required: Vec<String>,
+
+    /// How often the subgraphs are probed
+    /// Defaults to 10s
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub(crate) interval: Duration,
+
+    /// How long the result of a probe is used. A subgraph without a more recent result,
+    /// like when the router just started, is reported as unknown, which counts as
+    /// unreachable for a required subgraph.
+    /// Defaults to three times the interval
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) stale_after: Option<Duration>,
+
+    /// How long a probe waits for the subgraph to answer, from the start of its round of
+    /// probes. A subgraph that does not answer in time is reported as unknown rather than
+    /// unreachable, and does not delay the results of the other subgraphs. It cannot be
+    /// longer than the interval.
+    /// Defaults to the interval
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl Default for SubgraphHealthCheck {
+    fn default() -> Self {
+        Self {
+            required: Vec::new(),
+            interval: Duration::from_secs(10),
+            stale_after: None,
+            timeout: None,
+        }
+    }
+}
+
+impl SubgraphHealthCheck {
+    pub(crate) fn stale_after(&self) -> Duration {
+        self.stale_after.unwrap_or(self.interval * 3)
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout.unwrap_or(self.interval)
+    }
 }
 
 fn default_health_check_listen() -> ListenAddr {
@@ -998,13 +1966,26 @@ fn default_health_check() -> bool {
     true
 }
 
+fn default_health_probe_timeout() -> Duration {
+    Duration::from_secs(1)
+}
+
 #[buildstructor::buildstructor]
 impl HealthCheck {
     #[builder]
-    pub(crate) fn new(listen: Option<ListenAddr>, enabled: Option<bool>) -> Self {
+    pub(crate) fn new(
+        listen: Option<ListenAddr>,
+        enabled: Option<bool>,
+        detailed: Option<bool>,
+        subgraphs: Option<SubgraphHealthCheck>,
+        probe_timeout: Option<Duration>,
+    ) -> Self {
         Self {
             listen: listen.unwrap_or_else(default_health_check_listen),
             enabled: enabled.unwrap_or_else(default_health_check),
+            detailed: detailed.unwrap_or_default(),
+            subgraphs,
+            probe_timeout: probe_timeout.unwrap_or_else(default_health_probe_timeout),
         }
     }
 }
@@ -1013,10 +1994,19 @@ impl HealthCheck {
 #[buildstructor::buildstructor]
 impl HealthCheck {
     #[builder]
-    pub(crate) fn fake_new(listen: Option<ListenAddr>, enabled: Option<bool>) -> Self {
+    pub(crate) fn fake_new(
+        listen: Option<ListenAddr>,
+        enabled: Option<bool>,
+        detailed: Option<bool>,
+        subgraphs: Option<SubgraphHealthCheck>,
+        probe_timeout: Option<Duration>,
+    ) -> Self {
         Self {
             listen: listen.unwrap_or_else(test_listen),
             enabled: enabled.unwrap_or_else(default_health_check),
+            detailed: detailed.unwrap_or_default(),
+            subgraphs,
+            probe_timeout: probe_timeout.unwrap_or_else(default_health_probe_timeout),
         }
     }
 }
@@ -1027,6 +2017,64 @@ impl Default for HealthCheck {
     }
 }
 
+/// Configuration of the administration endpoints
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct Admin {
+    /// The socket address and port to listen on
+    /// Defaults to 127.0.0.1:8088
+    pub(crate) listen: ListenAddr,
+
+    /// Set to true to serve the configuration the router is running with at `/admin/config`,
+    /// as JSON with the secrets redacted
+    /// Defaults to false
+    pub(crate) enabled: bool,
+
+    /// Require an `Authorization: Bearer <token>` header with this token
+    /// Defaults to no authentication
+    pub(crate) token: Option<String>,
+}
+
+#[buildstructor::buildstructor]
+impl Admin {
+    #[builder]
+    pub(crate) fn new(
+        listen: Option<ListenAddr>,
+        enabled: Option<bool>,
+        token: Option<String>,
+    ) -> Self {
+        Self {
+            listen: listen.unwrap_or_else(default_health_check_listen),
+            enabled: enabled.unwrap_or_default(),
+            token,
+        }
+    }
+}
+
+#[cfg(test)]
+#[buildstructor::buildstructor]
+impl Admin {
+    #[builder]
+    pub(crate) fn fake_new(
+        listen: Option<ListenAddr>,
+        enabled: Option<bool>,
+        token: Option<String>,
+    ) -> Self {
+        Self {
+            listen: listen.unwrap_or_else(test_listen),
+            enabled: enabled.unwrap_or_default(),
+            token,
+        }
+    }
+}
+
+impl Default for Admin {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
 /// Configuration for chaos testing, trying to reproduce bugs that require uncommon conditions.
 /// You probably don’t want this in production!
 #[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
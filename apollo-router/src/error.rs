@@ -2,6 +2,7 @@
 use std::sync::Arc;
 
 use displaydoc::Display;
+use http::StatusCode;
 use lazy_static::__Deref;
 use miette::Diagnostic;
 use miette::NamedSource;
@@ -169,6 +170,27 @@ impl FetchError {
             ..Response::default()
         }
     }
+
+    /// The HTTP status of the response to a request that failed with this error before
+    /// a GraphQL response could be produced.
+    ///
+    /// Errors reported in a GraphQL response keep the `200 OK` status.
+    pub(crate) fn http_status(&self) -> StatusCode {
+        match self {
+            FetchError::ValidationInvalidTypeVariable { .. }
+            | FetchError::ValidationPlanningError { .. }
+            | FetchError::MalformedRequest { .. } => StatusCode::BAD_REQUEST,
+            FetchError::SubrequestHttpError { .. }
+            | FetchError::SubrequestMalformedResponse { .. }
+            | FetchError::SubrequestUnexpectedPatchResponse { .. } => StatusCode::BAD_GATEWAY,
+            FetchError::MalformedResponse { .. }
+            | FetchError::ExecutionFieldNotFound { .. }
+            | FetchError::ExecutionPathNotFound { .. }
+            | FetchError::CompressionError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            #[cfg(test)]
+            FetchError::ExecutionInvalidContent { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 impl ErrorExtension for FetchError {
@@ -561,4 +583,72 @@ mod tests {
 
         assert_eq!(expected_gql_error, error.to_graphql_error(None));
     }
+
+    #[test]
+    fn test_http_status() {
+        let reason = || String::from("reason");
+        let service = || String::from("my_service");
+        for (error, status) in [
+            (
+                FetchError::ValidationInvalidTypeVariable {
+                    name: String::from("id"),
+                },
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                FetchError::ValidationPlanningError { reason: reason() },
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                FetchError::MalformedRequest { reason: reason() },
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                FetchError::MalformedResponse { reason: reason() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                FetchError::SubrequestMalformedResponse {
+                    service: service(),
+                    reason: reason(),
+                },
+                StatusCode::BAD_GATEWAY,
+            ),
+            (
+                FetchError::SubrequestUnexpectedPatchResponse { service: service() },
+                StatusCode::BAD_GATEWAY,
+            ),
+            (
+                FetchError::SubrequestHttpError {
+                    status_code: Some(503),
+                    service: service(),
+                    reason: reason(),
+                },
+                StatusCode::BAD_GATEWAY,
+            ),
+            (
+                FetchError::ExecutionFieldNotFound {
+                    field: String::from("id"),
+                },
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                FetchError::ExecutionInvalidContent { reason: reason() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                FetchError::ExecutionPathNotFound { reason: reason() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                FetchError::CompressionError {
+                    service: service(),
+                    reason: reason(),
+                },
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ] {
+            assert_eq!(error.http_status(), status, "{error}");
+        }
+    }
 }
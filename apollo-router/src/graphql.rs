@@ -22,6 +22,8 @@ use crate::error::FetchError;
 use crate::json_ext::Object;
 use crate::json_ext::Path;
 pub use crate::json_ext::Path as JsonPath;
+pub use crate::json_ext::PathBuilder as JsonPathBuilder;
+pub use crate::json_ext::PathCache as JsonPathCache;
 pub use crate::json_ext::PathElement as JsonPathElement;
 pub use crate::request::Request;
 pub use crate::response::IncrementalResponse;
@@ -12,9 +12,12 @@ use axum::response::IntoResponse;
 use bytes::Bytes;
 use http::header;
 use http::header::HeaderName;
+use http::HeaderMap;
 use http::HeaderValue;
 use mime::APPLICATION_JSON;
 use multimap::MultiMap;
+use sha2::Digest;
+use sha2::Sha256;
 
 use crate::graphql;
 
@@ -218,6 +221,98 @@ pub(crate) fn header_map(
     Ok(http)
 }
 
+/// Headers that only apply to a single connection, from
+/// <https://datatracker.ietf.org/doc/html/rfc7230#section-6.1>, in addition to `Keep-Alive`
+/// and the headers listed in the `Connection` header
+const HOP_BY_HOP_HEADERS: [HeaderName; 7] = [
+    header::CONNECTION,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Returns whether a header is a hop-by-hop header of a message with these headers
+fn hop_by_hop_headers(headers: &HeaderMap) -> impl Fn(&HeaderName) -> bool + '_ {
+    let connection_headers: Vec<&str> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    move |name| {
+        HOP_BY_HOP_HEADERS.contains(name)
+            || name == "keep-alive"
+            || connection_headers
+                .iter()
+                .any(|header| header.eq_ignore_ascii_case(name.as_str()))
+    }
+}
+
+/// Removes the hop-by-hop headers, which only apply to the connection a message was received
+/// on, and must not be forwarded with it
+pub(crate) fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let is_hop_by_hop = hop_by_hop_headers(headers);
+    let hop_by_hop: Vec<HeaderName> = headers
+        .keys()
+        .filter(|name| is_hop_by_hop(name))
+        .cloned()
+        .collect();
+    for name in hop_by_hop {
+        headers.remove(name);
+    }
+}
+
+/// Copies all the values of the allowlisted headers from one request to another, replacing
+/// the values the other request had for them.
+///
+/// Hop-by-hop headers are never copied, even when allowlisted, and neither is `Host`,
+/// which names the destination of the request it was sent with.
+pub(crate) fn copy_headers(from: &HeaderMap, to: &mut HeaderMap, allowlist: &[HeaderName]) {
+    let is_hop_by_hop = hop_by_hop_headers(from);
+    for name in allowlist {
+        if is_hop_by_hop(name) || name == header::HOST {
+            continue;
+        }
+        let mut values = from.get_all(name).iter();
+        if let Some(first) = values.next() {
+            to.insert(name.clone(), first.clone());
+            for value in values {
+                to.append(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Whether the request has an `Authorization: Bearer <token>` header with the expected token.
+/// Every request is authorized when no token is expected.
+///
+/// The SHA-256 digests of the tokens are compared instead of the tokens, so that the
+/// comparison takes the same time whatever the length of the tokens and the position of
+/// their first difference.
+pub(crate) fn is_authorized(headers: &HeaderMap, token: Option<&str>) -> bool {
+    let token = match token {
+        Some(token) => token,
+        None => return true,
+    };
+    match headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.as_bytes().strip_prefix(b"Bearer "))
+    {
+        Some(received) => {
+            Sha256::digest(received)
+                .iter()
+                .zip(Sha256::digest(token.as_bytes()).iter())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+        }
+        None => false,
+    }
+}
+
 /// Ignores `http::Extensions`
 pub(crate) fn clone_http_request<B: Clone>(request: &http::Request<B>) -> http::Request<B> {
     let mut new = http::Request::builder()
@@ -461,12 +556,104 @@ impl IntoResponse for Response<Bytes> {
 
 #[cfg(test)]
 mod test {
+    use http::header;
+    use http::header::HeaderName;
+    use http::HeaderMap;
     use http::HeaderValue;
     use http::Method;
     use http::Uri;
 
+    use crate::http_ext::copy_headers;
+    use crate::http_ext::is_authorized;
+    use crate::http_ext::remove_hop_by_hop_headers;
     use crate::http_ext::Request;
 
+    #[test]
+    fn it_authorizes_the_requests_with_the_expected_bearer_token() {
+        let headers = |authorization: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::AUTHORIZATION,
+                HeaderValue::from_static(authorization),
+            );
+            headers
+        };
+        assert!(is_authorized(&HeaderMap::new(), None));
+        assert!(is_authorized(&headers("Bearer secret"), Some("secret")));
+        assert!(!is_authorized(&HeaderMap::new(), Some("secret")));
+        assert!(!is_authorized(&headers("Bearer secre"), Some("secret")));
+        assert!(!is_authorized(&headers("Bearer secret2"), Some("secret")));
+        assert!(!is_authorized(&headers("Basic secret"), Some("secret")));
+    }
+
+    #[test]
+    fn copy_headers_only_copies_the_allowlisted_end_to_end_headers() {
+        let mut from = HeaderMap::new();
+        for (name, value) in [
+            ("x-tenant", "a"),
+            ("accept-language", "fr"),
+            ("accept-language", "en"),
+            ("x-internal", "secret"),
+            ("connection", "keep-alive, x-per-hop"),
+            ("keep-alive", "timeout=5"),
+            ("x-per-hop", "1"),
+            ("host", "router.example.com"),
+            ("transfer-encoding", "chunked"),
+        ] {
+            from.append(name, HeaderValue::from_static(value));
+        }
+        let mut to = HeaderMap::new();
+        to.insert("accept-language", HeaderValue::from_static("de"));
+        to.insert("x-subgraph", HeaderValue::from_static("kept"));
+
+        let allowlist: Vec<HeaderName> = [
+            "x-tenant",
+            "accept-language",
+            "x-missing",
+            "connection",
+            "keep-alive",
+            "x-per-hop",
+            "host",
+            "transfer-encoding",
+        ]
+        .into_iter()
+        .map(HeaderName::from_static)
+        .collect();
+        copy_headers(&from, &mut to, &allowlist);
+
+        let mut expected = HeaderMap::new();
+        expected.insert("accept-language", HeaderValue::from_static("fr"));
+        expected.append("accept-language", HeaderValue::from_static("en"));
+        expected.insert("x-subgraph", HeaderValue::from_static("kept"));
+        expected.insert("x-tenant", HeaderValue::from_static("a"));
+        assert_eq!(to, expected);
+        assert!(!to.contains_key(header::HOST));
+    }
+
+    #[test]
+    fn remove_hop_by_hop_headers_keeps_end_to_end_headers() {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("connection", "keep-alive, X-Per-Hop"),
+            ("keep-alive", "timeout=5"),
+            ("x-per-hop", "1"),
+            ("upgrade", "websocket"),
+            ("proxy-authorization", "Basic cHJveHk="),
+            ("te", "trailers"),
+            ("host", "router.example.com"),
+            ("authorization", "Bearer token"),
+            ("x-tenant", "a"),
+        ] {
+            headers.append(name, HeaderValue::from_static(value));
+        }
+
+        remove_hop_by_hop_headers(&mut headers);
+
+        let mut names: Vec<_> = headers.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["authorization", "host", "x-tenant"]);
+    }
+
     #[test]
     fn builder() {
         let request = Request::builder()
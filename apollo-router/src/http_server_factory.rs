@@ -2,6 +2,7 @@
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 use derivative::Derivative;
 use futures::channel::oneshot;
@@ -39,7 +40,8 @@ pub(crate) trait HttpServerFactory {
         RF: RouterFactory;
 }
 
-type MainAndExtraListeners = (Listener, Vec<(ListenAddr, Listener)>);
+pub(crate) type MainAndExtraListeners = (Listener, Vec<(ListenAddr, Listener)>);
+
 /// A handle with with a client can shut down the server gracefully.
 /// This relies on the underlying server implementation doing the right thing.
 /// There are various ways that a user could prevent this working, including holding open connections
@@ -91,19 +93,73 @@ impl HttpServerHandle {
         }
     }
 
+    /// Shuts down a single server, the router shuts its servers down with
+    /// [`HttpServerHandle::shutdown_all`]
+    #[cfg(test)]
     pub(crate) async fn shutdown(self) -> Result<(), ApolloRouterError> {
+        self.stop().await
+    }
+
+    /// Shuts down the server gracefully, and returns its listeners instead of closing them.
+    ///
+    /// The sockets stay bound, so connections made until the listeners are passed back
+    /// to `HttpServerFactory::create` are queued instead of refused. Unix socket files
+    /// are kept as well.
+    pub(crate) async fn shutdown_and_reclaim_listeners(
+        self,
+    ) -> Result<MainAndExtraListeners, ApolloRouterError> {
         if let Err(_err) = self.shutdown_sender.send(()) {
             tracing::error!("Failed to notify http thread of shutdown")
         };
-        let _listener = self.server_future.await?;
-        #[cfg(unix)]
+
+        // when the server receives the shutdown signal, it stops accepting new
+        // connections, and returns the listeners
+        self.server_future.await
+    }
+
+    /// Shuts down several servers at once.
+    ///
+    /// All the servers are notified before waiting on any of them, so they stop accepting
+    /// connections together, then the returned future resolves once all of them are drained.
+    /// If a `deadline` is set and some servers are still draining when it expires,
+    /// this stops waiting for them and returns an error.
+    pub(crate) async fn shutdown_all(
+        handles: impl IntoIterator<Item = HttpServerHandle>,
+        deadline: Option<Duration>,
+    ) -> Result<(), ApolloRouterError> {
+        let stopped = future::join_all(handles.into_iter().map(HttpServerHandle::stop));
+        let results = match deadline {
+            Some(deadline) => tokio::time::timeout(deadline, stopped).await.map_err(|_| {
+                tracing::error!("http servers did not stop before the {deadline:?} deadline");
+                ApolloRouterError::HttpServerLifecycleError
+            })?,
+            None => stopped.await,
+        };
+
+        results.into_iter().collect()
+    }
+
+    /// Notifies the server of the shutdown right away, and returns a future
+    /// resolving once it has stopped
+    fn stop(self) -> impl Future<Output = Result<(), ApolloRouterError>> {
+        if let Err(_err) = self.shutdown_sender.send(()) {
+            tracing::error!("Failed to notify http thread of shutdown")
+        };
+        let server_future = self.server_future;
         // listen_addresses includes the main graphql_address
-        for listen_address in self.listen_addresses {
-            if let ListenAddr::UnixSocket(path) = listen_address {
-                let _ = tokio::fs::remove_file(path).await;
+        #[cfg(unix)]
+        let listen_addresses = self.listen_addresses;
+
+        async move {
+            let _listener = server_future.await?;
+            #[cfg(unix)]
+            for listen_address in listen_addresses {
+                if let ListenAddr::UnixSocket(path) = listen_address {
+                    let _ = tokio::fs::remove_file(path).await;
+                }
             }
+            Ok(())
         }
-        Ok(())
     }
 
     pub(crate) async fn restart<RF, SF>(
@@ -118,16 +174,12 @@ impl HttpServerHandle {
         SF: HttpServerFactory,
         RF: RouterFactory,
     {
-        // we tell the currently running server to stop
-        if let Err(_err) = self.shutdown_sender.send(()) {
-            tracing::error!("Failed to notify http thread of shutdown")
-        };
+        let all_connections_stopped_sender = self.all_connections_stopped_sender.clone();
 
-        // when the server receives the shutdown signal, it stops accepting new
-        // connections, and returns the TCP listener, to reuse it in the next server
-        // it is necessary to keep the queue of new TCP sockets associated with
-        // the listener instead of dropping them
-        let (main_listener, extra_listeners) = self.server_future.await?;
+        // we tell the currently running server to stop, and reuse its TCP listener
+        // in the next server: it is necessary to keep the queue of new TCP sockets
+        // associated with the listener instead of dropping them
+        let (main_listener, extra_listeners) = self.shutdown_and_reclaim_listeners().await?;
         tracing::debug!("previous server stopped");
 
         // we give the listeners to the new configuration, they'll clean up whatever needs to
@@ -139,7 +191,7 @@ impl HttpServerHandle {
                 extra_listeners,
                 web_endpoints,
                 entitlement,
-                self.all_connections_stopped_sender.clone(),
+                all_connections_stopped_sender,
             )
             .await?;
         tracing::debug!(
@@ -154,15 +206,56 @@ impl HttpServerHandle {
         Ok(handle)
     }
 
+    /// The addresses the server is bound to.
+    ///
+    /// The sockets are bound before the `HttpServerFactory::create` future resolves, so
+    /// these are the real addresses, with the port assigned by the OS when port zero was
+    /// configured, and connections made to them right away are queued until they are accepted.
     pub(crate) fn listen_addresses(&self) -> &[ListenAddr] {
         self.listen_addresses.as_slice()
     }
 
+    /// The address the GraphQL endpoint is bound to, with the same guarantees as `listen_addresses`.
     pub(crate) fn graphql_listen_address(&self) -> &Option<ListenAddr> {
         &self.graphql_listen_address
     }
 }
 
+#[cfg(test)]
+#[buildstructor::buildstructor]
+impl HttpServerHandle {
+    /// Creates a handle without an HTTP server, to test the lifecycle of handles.
+    ///
+    /// Like a real server, `drain` starts once the handle notifies the server of the shutdown,
+    /// and the handle is stopped when it resolves, with the listeners to give to the next server.
+    #[builder(visibility = "pub(crate)")]
+    fn fake_new(
+        drain: Pin<
+            Box<
+                dyn Future<Output = Result<MainAndExtraListeners, ApolloRouterError>>
+                    + Send
+                    + 'static,
+            >,
+        >,
+        graphql_listen_address: Option<ListenAddr>,
+        listen_addresses: Vec<ListenAddr>,
+        all_connections_stopped_sender: Option<mpsc::Sender<()>>,
+    ) -> Self {
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+        let server_future = async move {
+            let _ = shutdown_receiver.await;
+            drain.await
+        };
+        HttpServerHandle::new(
+            shutdown_sender,
+            server_future.boxed(),
+            graphql_listen_address,
+            listen_addresses,
+            all_connections_stopped_sender.unwrap_or_else(|| mpsc::channel(1).0),
+        )
+    }
+}
+
 pub(crate) enum Listener {
     Tcp(tokio::net::TcpListener),
     #[cfg(unix)]
@@ -177,7 +270,19 @@ pub(crate) enum NetworkStream {
     Tcp(tokio::net::TcpStream),
     #[cfg(unix)]
     Unix(tokio::net::UnixStream),
-    Tls(tokio_rustls::server::TlsStream<tokio::net::TcpStream>),
+    /// The TLS handshake is left to the task serving the connection
+    Tls {
+        stream: tokio::net::TcpStream,
+        acceptor: tokio_rustls::TlsAcceptor,
+    },
+}
+
+/// The error of a listener that could not be bound, naming its address
+pub(crate) fn bind_error(addr: ListenAddr, source: std::io::Error) -> ApolloRouterError {
+    ApolloRouterError::ServerCreationError(std::io::Error::new(
+        source.kind(),
+        format!("could not listen on {addr}: {source}"),
+    ))
 }
 
 impl Listener {
@@ -187,7 +292,7 @@ impl Listener {
     ) -> Result<Self, ApolloRouterError> {
         let listener = tokio::net::TcpListener::bind(address)
             .await
-            .map_err(ApolloRouterError::ServerCreationError)?;
+            .map_err(|source| bind_error(address.into(), source))?;
         match tls_acceptor {
             None => Ok(Listener::Tcp(listener)),
             Some(acceptor) => Ok(Listener::Tls { listener, acceptor }),
@@ -219,6 +324,10 @@ impl Listener {
         }
     }
 
+    /// Accepts the next connection.
+    ///
+    /// This is cancellation safe: if the future is dropped before completing, no connection
+    /// was taken from the queue of the socket, and it will be returned by the next call.
     pub(crate) async fn accept(&mut self) -> std::io::Result<NetworkStream> {
         match self {
             Listener::Tcp(listener) => listener
@@ -231,9 +340,13 @@ impl Listener {
                 .await
                 .map(|(stream, _)| NetworkStream::Unix(stream)),
             Listener::Tls { listener, acceptor } => {
-                let (stream, _) = listener.accept().await?;
-
-                Ok(NetworkStream::Tls(acceptor.accept(stream).await?))
+                listener
+                    .accept()
+                    .await
+                    .map(|(stream, _)| NetworkStream::Tls {
+                        stream,
+                        acceptor: acceptor.clone(),
+                    })
             }
         }
     }
@@ -297,4 +410,122 @@ mod tests {
             .await
             .expect("Should have sent notification to shutdown");
     }
+
+    #[test(tokio::test)]
+    async fn bind_errors_include_the_address() {
+        let taken = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = taken.local_addr().unwrap();
+
+        let error = Listener::new_from_socket_addr(address, None)
+            .await
+            .err()
+            .expect("the address is already in use");
+        assert!(matches!(
+            &error,
+            ApolloRouterError::ServerCreationError(source)
+                if source.kind() == std::io::ErrorKind::AddrInUse
+        ));
+        assert!(error.to_string().contains(&address.to_string()), "{error}");
+    }
+
+    #[test(tokio::test)]
+    async fn shutdown_waits_for_the_server_to_drain() {
+        let drained = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let drained_by_server = drained.clone();
+        let handle = HttpServerHandle::fake_builder()
+            .drain(
+                async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    drained_by_server.store(true, std::sync::atomic::Ordering::SeqCst);
+                    Err(ApolloRouterError::HttpServerLifecycleError)
+                }
+                .boxed(),
+            )
+            .graphql_listen_address(SocketAddr::from_str("127.0.0.1:4000").unwrap().into())
+            .listen_addresses(vec![SocketAddr::from_str("127.0.0.1:4000").unwrap().into()])
+            .build();
+        assert_eq!(
+            handle.graphql_listen_address(),
+            &Some(SocketAddr::from_str("127.0.0.1:4000").unwrap().into())
+        );
+        assert_eq!(handle.listen_addresses().len(), 1);
+
+        assert!(!drained.load(std::sync::atomic::Ordering::SeqCst));
+
+        // the error of the server is returned once it has drained
+        assert!(matches!(
+            handle.shutdown().await,
+            Err(ApolloRouterError::HttpServerLifecycleError)
+        ));
+        assert!(drained.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test(tokio::test)]
+    async fn shutdown_all() {
+        let mut handles = Vec::new();
+        let mut shutdown_receivers = Vec::new();
+        for _ in 0..3 {
+            let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+            let listener =
+                Listener::Tcp(tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap());
+            let (all_connections_stopped_sender, _) = mpsc::channel::<()>(1);
+            handles.push(HttpServerHandle::new(
+                shutdown_sender,
+                futures::future::ready(Ok((listener, vec![]))).boxed(),
+                Some(SocketAddr::from_str("127.0.0.1:0").unwrap().into()),
+                Default::default(),
+                all_connections_stopped_sender,
+            ));
+            shutdown_receivers.push(shutdown_receiver);
+        }
+
+        HttpServerHandle::shutdown_all(handles, None)
+            .await
+            .expect("Should have waited for shutdown");
+
+        for shutdown_receiver in shutdown_receivers {
+            shutdown_receiver
+                .await
+                .expect("Should have sent notification to shutdown");
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn shutdown_all_deadline() {
+        let (stopped_sender, stopped_receiver) = oneshot::channel();
+        let (draining_sender, draining_receiver) = oneshot::channel();
+        let listener = Listener::Tcp(tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap());
+        let (all_connections_stopped_sender, _) = mpsc::channel::<()>(1);
+
+        let stopped = HttpServerHandle::new(
+            stopped_sender,
+            futures::future::ready(Ok((listener, vec![]))).boxed(),
+            Some(SocketAddr::from_str("127.0.0.1:0").unwrap().into()),
+            Default::default(),
+            all_connections_stopped_sender.clone(),
+        );
+        // this server never finishes draining its connections
+        let draining = HttpServerHandle::new(
+            draining_sender,
+            futures::future::pending().boxed(),
+            Some(SocketAddr::from_str("127.0.0.1:0").unwrap().into()),
+            Default::default(),
+            all_connections_stopped_sender,
+        );
+
+        let result =
+            HttpServerHandle::shutdown_all([stopped, draining], Some(Duration::from_millis(50)))
+                .await;
+        assert!(matches!(
+            result,
+            Err(ApolloRouterError::HttpServerLifecycleError)
+        ));
+
+        stopped_receiver
+            .await
+            .expect("Should have sent notification to shutdown");
+        draining_receiver
+            .await
+            .expect("Should have sent notification to shutdown");
+    }
 }
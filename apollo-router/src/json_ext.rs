@@ -3,8 +3,16 @@
 #![allow(missing_docs)] // FIXME
 
 use std::cmp::min;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
 use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::sync::Arc;
 
+use parking_lot::Mutex;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json_bytes::ByteString;
@@ -19,6 +27,42 @@ use crate::spec::TYPENAME;
 /// A JSON object.
 pub(crate) type Object = Map<ByteString, Value>;
 
+/// [`ValueExt::is_subset`], comparing the values other than objects and arrays with `eq`
+fn is_subset_with(subset: &Value, superset: &Value, eq: fn(&Value, &Value) -> bool) -> bool {
+    match (subset, superset) {
+        (Value::Object(subset), Value::Object(superset)) => subset.iter().all(|(key, value)| {
+            if let Some(other) = superset.get(key) {
+                is_subset_with(value, other, eq)
+            } else {
+                false
+            }
+        }),
+        (Value::Array(subset), Value::Array(superset)) => {
+            subset.len() == superset.len()
+                && subset.iter().enumerate().all(|(index, value)| {
+                    if let Some(other) = superset.get(index) {
+                        is_subset_with(value, other, eq)
+                    } else {
+                        false
+                    }
+                })
+        }
+        (a, b) => eq(a, b),
+    }
+}
+
+/// Compares numbers by value: integers exactly, and as `f64` if one of them is a float
+fn numbers_eq(a: &serde_json::Number, b: &serde_json::Number) -> bool {
+    if a.is_f64() || b.is_f64() {
+        return a.as_f64() == b.as_f64();
+    }
+    match (a.as_i64(), b.as_i64()) {
+        (Some(a), Some(b)) => a == b,
+        // one of them is larger than `i64::MAX`
+        _ => a.as_u64().is_some() && a.as_u64() == b.as_u64(),
+    }
+}
+
 const FRAGMENT_PREFIX: &str = "... on ";
 
 macro_rules! extract_key_value_from_object {
@@ -46,6 +90,30 @@ macro_rules! ensure_object {
     }};
 }
 
+/// How [`ValueExt::deep_merge_with`] reports type conflicts, when an object and an array
+/// are merged at the same place
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MergeConflicts {
+    /// Do not report conflicts
+    Ignore,
+    /// Log conflicts at the debug level
+    #[default]
+    Debug,
+    /// Log conflicts at the warn level and count them in the `apollo_router_merge_conflicts_total` metric
+    Warn,
+}
+
+/// How [`ValueExt::insert_with`] handles the elements of a flattened array
+/// that do not match the rest of the path
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FlattenMismatch {
+    /// Return an error
+    Error,
+    /// Leave the element as it is
+    Skip,
+}
+
 #[doc(hidden)]
 /// Extension trait for [`serde_json::Value`].
 pub(crate) trait ValueExt {
@@ -54,6 +122,13 @@ pub(crate) trait ValueExt {
     #[track_caller]
     fn deep_merge(&mut self, other: Self);
 
+    /// Like [`ValueExt::deep_merge`], reporting type conflicts as configured by `conflicts`.
+    /// The conflicting values are left as they are in `&mut self`.
+    ///
+    /// Returns the number of conflicts found.
+    #[track_caller]
+    fn deep_merge_with(&mut self, other: Self, conflicts: MergeConflicts) -> usize;
+
     /// Returns `true` if the values are equal and the objects are ordered the same.
     ///
     /// **Note:** this is recursive.
@@ -64,22 +139,98 @@ pub(crate) trait ValueExt {
     #[track_caller]
     fn is_subset(&self, superset: &Value) -> bool;
 
+    /// Like `==`, but numbers are compared by value whatever their representation: `1` and
+    /// `1.0` are equal.
+    ///
+    /// **Note:** an integer and a float are compared as `f64`, so integers larger than 2^53
+    /// can be equal to a float they are not exactly equal to.
+    fn eq_numeric(&self, other: &Value) -> bool;
+
+    /// Like [`ValueExt::is_subset`], comparing numbers as [`ValueExt::eq_numeric`] does
+    fn is_subset_numeric(&self, superset: &Value) -> bool;
+
+    /// Add the keys of `overlay` that are missing from the objects of `&mut self`, recursing
+    /// into the objects present in both. Unlike [`ValueExt::deep_merge`], the values of
+    /// `&mut self`, including nulls, are never replaced, and arrays are left as they are.
+    fn fill_absent(&mut self, overlay: &Value);
+
     /// Create a `Value` by inserting a value at a subpath.
     ///
     /// This will create objects, arrays and null nodes as needed if they
     /// are not present: the resulting Value is meant to be merged with an
     /// existing one that contains those nodes.
+    ///
+    /// The nodes below a `Flatten` element are not created: the node where it applies is
+    /// `null`. Returns an error for an index too large to create the array holding it.
     #[track_caller]
-    fn from_path(path: &Path, value: Value) -> Value;
+    fn from_path(path: &Path, value: Value) -> Result<Value, FetchError>;
+
+    /// Deep merge a `Value` into the node at a `Path`, creating the missing nodes
+    ///
+    /// This gives the same result as `self.deep_merge(Value::from_path(path, value)?)`,
+    /// without allocating the intermediate document: a null or scalar node in the way is
+    /// replaced, an object where an array is expected, or the reverse, is a merge conflict
+    /// that stops the merge, and nothing is merged past a `Flatten`.
+    fn deep_merge_at(&mut self, path: &Path, value: Value);
 
     /// Insert a `Value` at a `Path`
+    ///
+    /// Missing intermediate objects and arrays are created, and the final node is overwritten.
+    /// The value is inserted in every element of the arrays matched by a `Flatten`,
+    /// like `users/@/verified` setting `verified` in every user. Nothing is inserted
+    /// for an empty array. An element that does not match the rest of the path is an error:
+    /// see [`ValueExt::insert_with`] to skip it instead.
     #[track_caller]
     fn insert(&mut self, path: &Path, value: Value) -> Result<(), FetchError>;
 
+    /// Like [`ValueExt::insert`], with the handling of array elements matched by a `Flatten`
+    /// that do not match the rest of the path, like a number for a key, set by `mismatch`.
+    ///
+    /// With [`FlattenMismatch::Error`], the elements before the mismatching one are
+    /// already modified when the error is returned.
+    #[track_caller]
+    fn insert_with(
+        &mut self,
+        path: &Path,
+        value: Value,
+        mismatch: FlattenMismatch,
+    ) -> Result<(), FetchError>;
+
+    /// Get a mutable reference to the `Value` at a `Path`, setting it to the result of `f`
+    /// if it is absent or `null`. An existing value is never overwritten.
+    ///
+    /// Intermediate objects and arrays are created as needed, like [`ValueExt::insert`] does.
+    /// Returns an error if an intermediate node is neither `null` nor of the type expected
+    /// by the path: an object for a key, an array for an index.
+    #[track_caller]
+    fn get_or_insert_with<F>(&mut self, path: &Path, f: F) -> Result<&mut Value, FetchError>
+    where
+        F: FnOnce() -> Value;
+
+    /// Insert a `Value` at a `Path` if there is no value or a `null` there, leaving an existing
+    /// value untouched, unlike [`ValueExt::insert`].
+    ///
+    /// Returns whether the value was inserted. Intermediate nodes are handled like
+    /// [`ValueExt::get_or_insert_with`] does.
+    #[track_caller]
+    fn insert_if_absent(&mut self, path: &Path, value: Value) -> Result<bool, FetchError>;
+
     /// Get a `Value` from a `Path`
     #[track_caller]
     fn get_path<'a>(&'a self, schema: &Schema, path: &'a Path) -> Result<&'a Value, FetchError>;
 
+    /// Get a `Value` from a JSON pointer, like [`serde_json::Value::pointer`].
+    ///
+    /// The pointer is a string of `/`-prefixed tokens as defined by
+    /// [RFC 6901](https://www.rfc-editor.org/rfc/rfc6901), in which `~1` stands for `/` and
+    /// `~0` for `~`. A token selects the key of an object, or the index of an array. The
+    /// empty pointer selects the value itself. Returns `None` if the pointer is invalid or
+    /// does not lead to a value.
+    fn pointer<'a>(&'a self, pointer: &str) -> Option<&'a Value>;
+
+    /// Like [`ValueExt::pointer`], but returns a mutable reference
+    fn pointer_mut<'a>(&'a mut self, pointer: &str) -> Option<&'a mut Value>;
+
     /// Select all values matching a `Path`.
     ///
     /// the function passed as argument will be called with the values found and their Path
@@ -89,6 +240,18 @@ pub(crate) trait ValueExt {
     where
         F: FnMut(&Path, &'a Value);
 
+    /// Like [`ValueExt::select_values_and_paths`], but returns the values and their paths
+    /// from an iterator, in the same order.
+    ///
+    /// The value is walked as the iterator is advanced, so stopping early, like with
+    /// `take` or `find`, skips the rest of the walk.
+    #[track_caller]
+    fn iter_values_and_paths<'a, 's>(
+        &'a self,
+        schema: &'s Schema,
+        path: &'a Path,
+    ) -> ValuesAndPaths<'a, 's>;
+
     /// Select all values matching a `Path`, and allows to mutate those values.
     ///
     /// The behavior of the method is otherwise the same as it's non-mutable counterpart
@@ -97,6 +260,31 @@ pub(crate) trait ValueExt {
     where
         F: FnMut(&Path, &'a mut Value);
 
+    /// Like [`ValueExt::select_values_and_paths`], but stops after calling `f` for
+    /// `max_matches` values, to bound the work spent on a large untrusted response.
+    ///
+    /// Returns `true` if values were left out because there were more than `max_matches`.
+    #[track_caller]
+    fn select_values_and_paths_bounded<'a, F>(
+        &'a self,
+        schema: &Schema,
+        path: &'a Path,
+        max_matches: usize,
+        f: F,
+    ) -> bool
+    where
+        F: FnMut(&Path, &'a Value);
+
+    /// Select the values matching several `Path`s, walking the value once for all of them
+    /// instead of once per path.
+    ///
+    /// Each value is returned with the index in `paths` of the path it matched, and its own
+    /// path, like [`ValueExt::select_values_and_paths`] would give it. A value matched by
+    /// several paths is returned once for each of them. The values matched by a given path
+    /// are in the same order as with [`ValueExt::select_values_and_paths`].
+    #[track_caller]
+    fn select_many<'a>(&'a self, schema: &Schema, paths: &[Path]) -> Vec<(usize, Path, &'a Value)>;
+
     #[track_caller]
     fn is_valid_float_input(&self) -> bool;
 
@@ -122,41 +310,120 @@ pub(crate) trait ValueExt {
     /// function to handle `PathElement::Fragment`).
     #[track_caller]
     fn is_object_of_type(&self, schema: &Schema, maybe_type: &str) -> bool;
+
+    /// Returns the path to every leaf of the document, in document order.
+    ///
+    /// Leaves are scalars (including `null`), as well as empty arrays and objects
+    /// since there is nothing below them. Array elements are addressed with their index.
+    /// The paths to the scalars only can be obtained by filtering out the empty containers.
+    fn leaf_paths(&self) -> Vec<Path>;
+
+    /// Returns the number of elements of the array, or of keys of the object, at `path`,
+    /// without collecting them.
+    ///
+    /// Returns `None` when there is no value at `path`, or when it is a scalar. A `Flatten`
+    /// element sums the lengths found below every element of the array, skipping the
+    /// elements without one.
+    ///
+    /// Fragment elements are skipped without checking their type condition, as that needs
+    /// the schema: unlike with [`ValueExt::select_values_and_paths`], the values of other
+    /// types are counted too. This is meant for response paths, which have no fragments.
+    fn len_at(&self, path: &Path) -> Option<usize>;
+
+    /// Returns the string at `path`.
+    ///
+    /// Returns `None` when there is no value at `path`, or when it has another type. The
+    /// `get_*` methods only follow keys and indexes: a `Flatten` element selects several
+    /// values so it never leads to one, and fragment elements are skipped without checking
+    /// their type condition, like [`ValueExt::len_at`] does.
+    fn get_str(&self, path: &Path) -> Option<&str>;
+
+    /// Returns the integer at `path`, see [`ValueExt::get_str`]
+    fn get_i64(&self, path: &Path) -> Option<i64>;
+
+    /// Returns the boolean at `path`, see [`ValueExt::get_str`]
+    fn get_bool(&self, path: &Path) -> Option<bool>;
+
+    /// Returns the array at `path`, see [`ValueExt::get_str`]
+    fn get_array(&self, path: &Path) -> Option<&Vec<Value>>;
+
+    /// Returns the object at `path`, see [`ValueExt::get_str`]
+    fn get_object(&self, path: &Path) -> Option<&Object>;
+
+    /// Removes the value at `path` and returns it, so that it can be inserted somewhere else
+    /// without a clone.
+    ///
+    /// The key is removed from its object, and the element from its array, shifting the
+    /// following elements. The path is followed like [`ValueExt::get_str`] does: a `Flatten`
+    /// element never leads to a value, and the type conditions of fragment elements are not
+    /// checked. The empty path takes the whole value, leaving `null` in its place. Named
+    /// `take_at_path` so it does not clash with `Value::take`.
+    fn take_at_path(&mut self, path: &Path) -> Option<Value>;
+
+    /// Removes the object keys and array elements for which `f` returns `false`, and
+    /// recurses into the ones it keeps.
+    ///
+    /// `f` gets the path of each value in the document before anything is removed: removing
+    /// an array element shifts the indexes of the following elements in the resulting value,
+    /// but not in the paths given to `f`. The value itself is always kept.
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Path, &Value) -> bool;
+
+    /// Hash the value independently of the order of the object keys, so that two equal values
+    /// have the same hash. Array elements are hashed in order.
+    ///
+    /// The hash is computed with [`DefaultHasher`]: it is stable for a given router build,
+    /// but should not be persisted across router versions. See [`ValueExt::stable_hash_into`]
+    /// to use another hasher.
+    fn stable_hash(&self) -> u64;
+
+    /// Feed the value into a `Hasher`, visiting the object keys in sorted order
+    fn stable_hash_into<H: Hasher>(&self, state: &mut H);
 }
 
 impl ValueExt for Value {
     fn deep_merge(&mut self, other: Self) {
+        self.deep_merge_with(other, MergeConflicts::default());
+    }
+
+    fn deep_merge_with(&mut self, other: Self, conflicts: MergeConflicts) -> usize {
         match (self, other) {
             (Value::Object(a), Value::Object(b)) => {
+                let mut count = 0;
                 for (key, value) in b.into_iter() {
                     match a.entry(key) {
                         Entry::Vacant(e) => {
                             e.insert(value);
                         }
                         Entry::Occupied(e) => {
-                            e.into_mut().deep_merge(value);
+                            count += e.into_mut().deep_merge_with(value, conflicts);
                         }
                     }
                 }
+                count
             }
             (Value::Array(a), Value::Array(mut b)) => {
+                let mut count = 0;
                 for (b_value, a_value) in b.drain(..min(a.len(), b.len())).zip(a.iter_mut()) {
-                    a_value.deep_merge(b_value);
+                    count += a_value.deep_merge_with(b_value, conflicts);
                 }
 
                 a.extend(b.into_iter());
+                count
             }
-            (_, Value::Null) => {}
+            (_, Value::Null) => 0,
             (Value::Object(_), Value::Array(_)) => {
-                failfast_debug!("trying to replace an object with an array");
+                report_merge_conflict(conflicts, "trying to replace an object with an array");
+                1
             }
             (Value::Array(_), Value::Object(_)) => {
-                failfast_debug!("trying to replace an array with an object");
+                report_merge_conflict(conflicts, "trying to replace an array with an object");
+                1
             }
             (a, b) => {
-                if b != Value::Null {
-                    *a = b;
-                }
+                *a = b;
+                0
             }
         }
     }
@@ -202,180 +469,250 @@ impl ValueExt for Value {
     }
 
     fn is_subset(&self, superset: &Value) -> bool {
-        match (self, superset) {
-            (Value::Object(subset), Value::Object(superset)) => {
-                subset.iter().all(|(key, value)| {
-                    if let Some(other) = superset.get(key) {
-                        value.is_subset(other)
-                    } else {
-                        false
-                    }
-                })
-            }
-            (Value::Array(subset), Value::Array(superset)) => {
-                subset.len() == superset.len()
-                    && subset.iter().enumerate().all(|(index, value)| {
-                        if let Some(other) = superset.get(index) {
-                            value.is_subset(other)
-                        } else {
-                            false
-                        }
+        is_subset_with(self, superset, |a, b| a == b)
+    }
+
+    fn eq_numeric(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter().all(|(key, value)| {
+                        b.get(key)
+                            .map(|other| value.eq_numeric(other))
+                            .unwrap_or(false)
                     })
             }
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_numeric(b))
+            }
+            (Value::Number(a), Value::Number(b)) => numbers_eq(a, b),
+            (a, b) => a == b,
+        }
+    }
+
+    fn is_subset_numeric(&self, superset: &Value) -> bool {
+        is_subset_with(self, superset, |a, b| match (a, b) {
+            (Value::Number(a), Value::Number(b)) => numbers_eq(a, b),
             (a, b) => a == b,
+        })
+    }
+
+    fn fill_absent(&mut self, overlay: &Value) {
+        if let (Value::Object(a), Value::Object(b)) = (self, overlay) {
+            for (key, value) in b.iter() {
+                match a.entry(key.clone()) {
+                    Entry::Vacant(e) => {
+                        e.insert(value.clone());
+                    }
+                    Entry::Occupied(e) => e.into_mut().fill_absent(value),
+                }
+            }
         }
     }
 
     #[track_caller]
-    fn from_path(path: &Path, value: Value) -> Value {
-        let mut res_value = Value::default();
-        let mut current_node = &mut res_value;
+    fn from_path(path: &Path, value: Value) -> Result<Value, FetchError> {
+        // the value is built from the innermost node, so every node is created only once
+        let (elements, mut res_value) = match path
+            .iter()
+            .position(|element| matches!(element, PathElement::Flatten))
+        {
+            Some(flatten) => (&path.0[..flatten], Value::Null),
+            None => (&path.0[..], value),
+        };
 
-        for p in path.iter() {
-            match p {
-                PathElement::Flatten => {
-                    return res_value;
+        for element in elements.iter().rev() {
+            res_value = match element {
+                PathElement::Key(k) => {
+                    let mut object = Map::new();
+                    object.insert(k.as_str(), res_value);
+                    Value::Object(object)
                 }
+                &PathElement::Index(index) => {
+                    let mut array = Vec::new();
+                    index
+                        .checked_add(1)
+                        .and_then(|len| array.try_reserve_exact(len).ok())
+                        .ok_or_else(|| FetchError::ExecutionPathNotFound {
+                            reason: format!("cannot create index {index} of {path}: too large"),
+                        })?;
+                    array.resize(index, Value::Null);
+                    array.push(res_value);
+                    Value::Array(array)
+                }
+                PathElement::Flatten | PathElement::Fragment(_) => res_value,
+            };
+        }
 
-                &PathElement::Index(index) => match current_node {
-                    Value::Array(a) => {
-                        for _ in 0..index {
-                            a.push(Value::default());
+        Ok(res_value)
+    }
+
+    fn deep_merge_at(&mut self, path: &Path, value: Value) {
+        let mut current_node = self;
+
+        for p in path.iter() {
+            match p {
+                PathElement::Flatten => return,
+                &PathElement::Index(index) => {
+                    match current_node {
+                        Value::Array(_) => {}
+                        Value::Object(_) => {
+                            report_merge_conflict(
+                                MergeConflicts::default(),
+                                "trying to replace an object with an array",
+                            );
+                            return;
                         }
-                        a.push(Value::default());
-                        current_node = a
-                            .get_mut(index)
-                            .expect("we just created the value at that index");
+                        other => *other = Value::Array(Vec::new()),
                     }
-                    Value::Null => {
-                        let mut a = Vec::new();
-                        for _ in 0..index {
-                            a.push(Value::default());
-                        }
-                        a.push(Value::default());
-
-                        *current_node = Value::Array(a);
-                        current_node = current_node
-                            .as_array_mut()
-                            .expect("current_node was just set to a Value::Array")
-                            .get_mut(index)
-                            .expect("we just created the value at that index");
+                    let a = current_node.as_array_mut().expect("the node is an array");
+                    if extend_to_index(a, index).is_err() {
+                        // the index cannot be created: there is nothing to merge into
+                        return;
                     }
-                    other => unreachable!("unreachable node: {:?}", other),
-                },
+                    current_node = &mut a[index];
+                }
                 PathElement::Key(k) => {
-                    let mut m = Map::new();
-                    m.insert(k.as_str(), Value::default());
-
-                    *current_node = Value::Object(m);
+                    match current_node {
+                        Value::Object(_) => {}
+                        Value::Array(_) => {
+                            report_merge_conflict(
+                                MergeConflicts::default(),
+                                "trying to replace an array with an object",
+                            );
+                            return;
+                        }
+                        other => *other = Value::Object(Map::new()),
+                    }
                     current_node = current_node
                         .as_object_mut()
-                        .expect("current_node was just set to a Value::Object")
-                        .get_mut(k.as_str())
-                        .expect("the value at that key was just inserted");
+                        .expect("the node is an object")
+                        .entry(k.as_str())
+                        .or_insert(Value::Null);
                 }
                 PathElement::Fragment(_) => {}
             }
         }
 
-        *current_node = value;
-        res_value
+        current_node.deep_merge(value);
     }
 
     /// Insert a `Value` at a `Path`
     #[track_caller]
     fn insert(&mut self, path: &Path, value: Value) -> Result<(), FetchError> {
+        self.insert_with(path, value, FlattenMismatch::Error)
+    }
+
+    /// Insert a `Value` at a `Path`, broadcasting it through flattened arrays
+    #[track_caller]
+    fn insert_with(
+        &mut self,
+        path: &Path,
+        value: Value,
+        mismatch: FlattenMismatch,
+    ) -> Result<(), FetchError> {
+        insert_at(self, &path.0, value, mismatch)
+    }
+
+    /// Get a mutable reference to the `Value` at a `Path`, inserting it if needed
+    #[track_caller]
+    fn get_or_insert_with<F>(&mut self, path: &Path, f: F) -> Result<&mut Value, FetchError>
+    where
+        F: FnOnce() -> Value,
+    {
         let mut current_node = self;
 
         for p in path.iter() {
             match p {
                 PathElement::Flatten => {
                     if current_node.is_null() {
-                        let a = Vec::new();
-                        *current_node = Value::Array(a);
+                        *current_node = Value::Array(Vec::new());
                     } else if !current_node.is_array() {
                         return Err(FetchError::ExecutionPathNotFound {
                             reason: "expected an array".to_string(),
                         });
                     }
                 }
-
-                &PathElement::Index(index) => match current_node {
-                    Value::Array(a) => {
-                        // add more elements if the index is after the end
-                        for _ in a.len()..index + 1 {
-                            a.push(Value::default());
-                        }
-                        current_node = a
-                            .get_mut(index)
-                            .expect("we just created the value at that index");
+                &PathElement::Index(index) => {
+                    if current_node.is_null() {
+                        *current_node = Value::Array(Vec::new());
                     }
-                    Value::Null => {
-                        let mut a = Vec::new();
-                        for _ in 0..index + 1 {
-                            a.push(Value::default());
+                    match current_node {
+                        Value::Array(a) => {
+                            // add more elements if the index is after the end
+                            extend_to_index(a, index)?;
+                            current_node = &mut a[index];
+                        }
+                        _other => {
+                            return Err(FetchError::ExecutionPathNotFound {
+                                reason: "expected an array".to_string(),
+                            })
                         }
-
-                        *current_node = Value::Array(a);
-                        current_node = current_node
-                            .as_array_mut()
-                            .expect("current_node was just set to a Value::Array")
-                            .get_mut(index)
-                            .expect("we just created the value at that index");
-                    }
-                    _other => {
-                        return Err(FetchError::ExecutionPathNotFound {
-                            reason: "expected an array".to_string(),
-                        })
-                    }
-                },
-                PathElement::Key(k) => match current_node {
-                    Value::Object(o) => {
-                        current_node = o
-                            .get_mut(k.as_str())
-                            .expect("the value at that key was just inserted");
                     }
-                    Value::Null => {
-                        let mut m = Map::new();
-                        m.insert(k.as_str(), Value::default());
-
-                        *current_node = Value::Object(m);
-                        current_node = current_node
-                            .as_object_mut()
-                            .expect("current_node was just set to a Value::Object")
-                            .get_mut(k.as_str())
-                            .expect("the value at that key was just inserted");
+                }
+                PathElement::Key(k) => {
+                    if current_node.is_null() {
+                        *current_node = Value::Object(Map::new());
                     }
-                    _other => {
-                        return Err(FetchError::ExecutionPathNotFound {
-                            reason: "expected an object".to_string(),
-                        })
+                    match current_node {
+                        Value::Object(o) => {
+                            current_node = o.entry(k.as_str()).or_insert(Value::Null);
+                        }
+                        _other => {
+                            return Err(FetchError::ExecutionPathNotFound {
+                                reason: "expected an object".to_string(),
+                            })
+                        }
                     }
-                },
+                }
                 PathElement::Fragment(_) => {}
             }
         }
 
-        *current_node = value;
-        Ok(())
+        if current_node.is_null() {
+            *current_node = f();
+        }
+        Ok(current_node)
+    }
+
+    /// Insert a `Value` at a `Path` if there is none
+    #[track_caller]
+    fn insert_if_absent(&mut self, path: &Path, value: Value) -> Result<bool, FetchError> {
+        let mut inserted = false;
+        self.get_or_insert_with(path, || {
+            inserted = true;
+            value
+        })?;
+        Ok(inserted)
     }
 
     /// Get a `Value` from a `Path`
     #[track_caller]
     fn get_path<'a>(&'a self, schema: &Schema, path: &'a Path) -> Result<&'a Value, FetchError> {
-        let mut res = Err(FetchError::ExecutionPathNotFound {
+        let mut values = ValuesAndPaths::new(schema, path, self);
+        let mut res = None;
+        while let Some(value) = values.advance() {
+            res = Some(value);
+        }
+        res.ok_or_else(|| FetchError::ExecutionPathNotFound {
             reason: "value not found".to_string(),
-        });
-        iterate_path(
-            schema,
-            &mut Path::default(),
-            &path.0,
-            self,
-            &mut |_path, value| {
-                res = Ok(value);
-            },
-        );
-        res
+        })
+    }
+
+    fn pointer<'a>(&'a self, pointer: &str) -> Option<&'a Value> {
+        pointer_tokens(pointer)?.try_fold(self, |value, token| match value {
+            Value::Object(object) => object.get(token.as_str()),
+            Value::Array(array) => array.get(pointer_index(&token)?),
+            _ => None,
+        })
+    }
+
+    fn pointer_mut<'a>(&'a mut self, pointer: &str) -> Option<&'a mut Value> {
+        pointer_tokens(pointer)?.try_fold(self, |value, token| match value {
+            Value::Object(object) => object.get_mut(token.as_str()),
+            Value::Array(array) => array.get_mut(pointer_index(&token)?),
+            _ => None,
+        })
     }
 
     #[track_caller]
@@ -383,7 +720,42 @@ impl ValueExt for Value {
     where
         F: FnMut(&Path, &'a Value),
     {
-        iterate_path(schema, &mut Path::default(), &path.0, self, &mut f)
+        let mut values = ValuesAndPaths::new(schema, path, self);
+        while let Some(value) = values.advance() {
+            f(&values.current, value);
+        }
+    }
+
+    #[track_caller]
+    fn select_values_and_paths_bounded<'a, F>(
+        &'a self,
+        schema: &Schema,
+        path: &'a Path,
+        max_matches: usize,
+        mut f: F,
+    ) -> bool
+    where
+        F: FnMut(&Path, &'a Value),
+    {
+        let mut values = ValuesAndPaths::new(schema, path, self);
+        let mut matches = 0;
+        while let Some(value) = values.advance() {
+            if matches == max_matches {
+                return true;
+            }
+            matches += 1;
+            f(&values.current, value);
+        }
+        false
+    }
+
+    #[track_caller]
+    fn iter_values_and_paths<'a, 's>(
+        &'a self,
+        schema: &'s Schema,
+        path: &'a Path,
+    ) -> ValuesAndPaths<'a, 's> {
+        ValuesAndPaths::new(schema, path, self)
     }
 
     #[track_caller]
@@ -394,6 +766,18 @@ impl ValueExt for Value {
         iterate_path_mut(schema, &mut Path::default(), &path.0, self, &mut f)
     }
 
+    #[track_caller]
+    fn select_many<'a>(&'a self, schema: &Schema, paths: &[Path]) -> Vec<(usize, Path, &'a Value)> {
+        let mut selected = Vec::new();
+        let cursors = paths
+            .iter()
+            .enumerate()
+            .map(|(index, path)| (index, &path.0[..]))
+            .collect();
+        select_many_into(schema, &mut Path::default(), cursors, self, &mut selected);
+        selected
+    }
+
     #[track_caller]
     fn is_valid_float_input(&self) -> bool {
         // https://spec.graphql.org/draft/#sec-Float.Input-Coercion
@@ -427,106 +811,486 @@ impl ValueExt for Value {
                     typename == maybe_type || schema.is_subtype(maybe_type, typename)
                 })
     }
-}
 
-fn iterate_path<'a, F>(
-    schema: &Schema,
-    parent: &mut Path,
-    path: &'a [PathElement],
-    data: &'a Value,
-    f: &mut F,
-) where
-    F: FnMut(&Path, &'a Value),
-{
-    match path.get(0) {
-        None => f(parent, data),
-        Some(PathElement::Flatten) => {
-            if let Some(array) = data.as_array() {
-                for (i, value) in array.iter().enumerate() {
-                    parent.push(PathElement::Index(i));
-                    iterate_path(schema, parent, &path[1..], value, f);
-                    parent.pop();
-                }
+    fn leaf_paths(&self) -> Vec<Path> {
+        let mut paths = Vec::new();
+        collect_leaf_paths(self, &mut Path::default(), &mut paths);
+        paths
+    }
+
+    fn len_at(&self, path: &Path) -> Option<usize> {
+        len_below(self, &path.0)
+    }
+
+    fn get_str(&self, path: &Path) -> Option<&str> {
+        value_at(self, &path.0)?.as_str()
+    }
+
+    fn get_i64(&self, path: &Path) -> Option<i64> {
+        value_at(self, &path.0)?.as_i64()
+    }
+
+    fn get_bool(&self, path: &Path) -> Option<bool> {
+        value_at(self, &path.0)?.as_bool()
+    }
+
+    fn get_array(&self, path: &Path) -> Option<&Vec<Value>> {
+        value_at(self, &path.0)?.as_array()
+    }
+
+    fn get_object(&self, path: &Path) -> Option<&Object> {
+        value_at(self, &path.0)?.as_object()
+    }
+
+    fn take_at_path(&mut self, path: &Path) -> Option<Value> {
+        // fragment elements are ignored, the value is removed from the last key or index
+        let last = match path
+            .0
+            .iter()
+            .rposition(|element| !matches!(element, PathElement::Fragment(_)))
+        {
+            Some(last) => last,
+            None => return Some(std::mem::replace(self, Value::Null)),
+        };
+        let parent = value_at_mut(self, &path.0[..last])?;
+        match &path.0[last] {
+            PathElement::Key(key) => parent.as_object_mut()?.remove(key.as_str()),
+            PathElement::Index(index) => {
+                let array = parent.as_array_mut()?;
+                (*index < array.len()).then(|| array.remove(*index))
             }
+            PathElement::Flatten | PathElement::Fragment(_) => None,
         }
-        Some(PathElement::Index(i)) => {
-            if let Value::Array(a) = data {
-                if let Some(value) = a.get(*i) {
-                    parent.push(PathElement::Index(*i));
+    }
 
-                    iterate_path(schema, parent, &path[1..], value, f);
-                    parent.pop();
-                }
+    fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&Path, &Value) -> bool,
+    {
+        retain_below(self, &mut Path::default(), &mut f)
+    }
+
+    fn stable_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.stable_hash_into(&mut hasher);
+        hasher.finish()
+    }
+
+    fn stable_hash_into<H: Hasher>(&self, state: &mut H) {
+        // the variant is hashed first, so that `"1"` and `1` have different hashes
+        match self {
+            Value::Null => state.write_u8(0),
+            Value::Bool(b) => {
+                state.write_u8(1);
+                b.hash(state);
             }
-        }
-        Some(PathElement::Key(k)) => {
-            if let Value::Object(o) = data {
-                if let Some(value) = o.get(k.as_str()) {
-                    parent.push(PathElement::Key(k.to_string()));
-                    iterate_path(schema, parent, &path[1..], value, f);
-                    parent.pop();
+            Value::Number(n) => {
+                state.write_u8(2);
+                if let Some(n) = n.as_u64() {
+                    state.write_u8(0);
+                    n.hash(state);
+                } else if let Some(n) = n.as_i64() {
+                    state.write_u8(1);
+                    n.hash(state);
+                } else if let Some(n) = n.as_f64() {
+                    state.write_u8(2);
+                    // 0.0 and -0.0 are equal
+                    let n = if n == 0.0 { 0.0f64 } else { n };
+                    n.to_bits().hash(state);
                 }
-            } else if let Value::Array(array) = data {
-                for (i, value) in array.iter().enumerate() {
-                    parent.push(PathElement::Index(i));
-                    iterate_path(schema, parent, path, value, f);
-                    parent.pop();
+            }
+            Value::String(s) => {
+                state.write_u8(3);
+                s.as_str().hash(state);
+            }
+            Value::Array(a) => {
+                state.write_u8(4);
+                a.len().hash(state);
+                for value in a {
+                    value.stable_hash_into(state);
                 }
             }
-        }
-        Some(PathElement::Fragment(name)) => {
-            if data.is_object_of_type(schema, name) {
-                // Note that (not unlike `Flatten`) we do not include the fragment in the `parent`
-                // path, because we want that path to be a "pure" response path. Fragments in path
-                // are used to essentially create a type-based choice in a "selection" path, but
-                // `parent` is a direct path to a specific position in the value and do not need
-                // fragments.
-                iterate_path(schema, parent, &path[1..], data, f);
-            } else if let Value::Array(array) = data {
-                for (i, value) in array.iter().enumerate() {
-                    parent.push(PathElement::Index(i));
-                    iterate_path(schema, parent, path, value, f);
-                    parent.pop();
+            Value::Object(o) => {
+                state.write_u8(5);
+                o.len().hash(state);
+                let mut entries: Vec<_> = o.iter().collect();
+                entries.sort_unstable_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+                for (key, value) in entries {
+                    key.as_str().hash(state);
+                    value.stable_hash_into(state);
                 }
             }
         }
     }
 }
 
-fn iterate_path_mut<'a, F>(
-    schema: &Schema,
-    parent: &mut Path,
-    path: &'a [PathElement],
-    data: &'a mut Value,
-    f: &mut F,
-) where
-    F: FnMut(&Path, &'a mut Value),
-{
-    match path.get(0) {
-        None => f(parent, data),
-        Some(PathElement::Flatten) => {
-            if let Some(array) = data.as_array_mut() {
-                for (i, value) in array.iter_mut().enumerate() {
-                    parent.push(PathElement::Index(i));
-                    iterate_path_mut(schema, parent, &path[1..], value, f);
-                    parent.pop();
-                }
+/// Splits a JSON pointer into its unescaped tokens, or returns `None` if it does not start
+/// with a `/`
+fn pointer_tokens(pointer: &str) -> Option<impl Iterator<Item = String> + '_> {
+    let tokens = if pointer.is_empty() {
+        None
+    } else {
+        Some(pointer.strip_prefix('/')?.split('/'))
+    };
+    Some(
+        tokens
+            .into_iter()
+            .flatten()
+            .map(|token| token.replace("~1", "/").replace("~0", "~")),
+    )
+}
+
+/// Array indexes are written in decimal without leading zeros
+fn pointer_index(token: &str) -> Option<usize> {
+    if token.starts_with('+') || (token.starts_with('0') && token.len() > 1) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+fn len_below(value: &Value, path: &[PathElement]) -> Option<usize> {
+    match path.split_first() {
+        None => match value {
+            Value::Array(array) => Some(array.len()),
+            Value::Object(object) => Some(object.len()),
+            _ => None,
+        },
+        Some((PathElement::Key(key), rest)) => {
+            len_below(value.as_object()?.get(key.as_str())?, rest)
+        }
+        Some((PathElement::Index(index), rest)) => len_below(value.as_array()?.get(*index)?, rest),
+        Some((PathElement::Flatten, rest)) => Some(
+            value
+                .as_array()?
+                .iter()
+                .filter_map(|element| len_below(element, rest))
+                .sum(),
+        ),
+        Some((PathElement::Fragment(_), rest)) => len_below(value, rest),
+    }
+}
+
+/// Follows the keys and indexes of `path`. Fragments are skipped without the schema needed
+/// to check their type condition, see [`ValueExt::len_at`].
+fn value_at<'a>(value: &'a Value, path: &[PathElement]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, element| match element {
+        PathElement::Key(key) => value.as_object()?.get(key.as_str()),
+        PathElement::Index(index) => value.as_array()?.get(*index),
+        PathElement::Flatten => None,
+        PathElement::Fragment(_) => Some(value),
+    })
+}
+
+/// Like [`value_at`], but returns a mutable reference
+fn value_at_mut<'a>(value: &'a mut Value, path: &[PathElement]) -> Option<&'a mut Value> {
+    path.iter().try_fold(value, |value, element| match element {
+        PathElement::Key(key) => value.as_object_mut()?.get_mut(key.as_str()),
+        PathElement::Index(index) => value.as_array_mut()?.get_mut(*index),
+        PathElement::Flatten => None,
+        PathElement::Fragment(_) => Some(value),
+    })
+}
+
+fn collect_leaf_paths(value: &Value, current: &mut Path, paths: &mut Vec<Path>) {
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, value) in object.iter() {
+                current.push(PathElement::Key(key.as_str().to_string()));
+                collect_leaf_paths(value, current, paths);
+                current.pop();
+            }
+        }
+        Value::Array(array) if !array.is_empty() => {
+            for (i, value) in array.iter().enumerate() {
+                current.push(PathElement::Index(i));
+                collect_leaf_paths(value, current, paths);
+                current.pop();
+            }
+        }
+        _ => paths.push(current.clone()),
+    }
+}
+
+fn retain_below<F>(value: &mut Value, current: &mut Path, f: &mut F)
+where
+    F: FnMut(&Path, &Value) -> bool,
+{
+    match value {
+        Value::Object(object) => {
+            *object = std::mem::take(object)
+                .into_iter()
+                .filter_map(|(key, mut value)| {
+                    current.push(PathElement::Key(key.as_str().to_string()));
+                    let keep = f(current, &value);
+                    if keep {
+                        retain_below(&mut value, current, f);
+                    }
+                    current.pop();
+                    keep.then_some((key, value))
+                })
+                .collect();
+        }
+        Value::Array(array) => {
+            let mut i = 0;
+            array.retain_mut(|value| {
+                current.push(PathElement::Index(i));
+                let keep = f(current, value);
+                if keep {
+                    retain_below(value, current, f);
+                }
+                current.pop();
+                i += 1;
+                keep
+            });
+        }
+        _ => {}
+    }
+}
+
+/// The iterator returned by [`ValueExt::iter_values_and_paths`], which also implements
+/// [`ValueExt::select_values_and_paths`] and the methods built on it
+///
+/// The value is walked with an explicit stack instead of recursion: keys, indexes and
+/// fragments lead to a single value and are followed in place, the arrays being walked are
+/// kept on the stack along with the elements of the path left to match below their elements.
+/// Keys and fragments that don't apply to an array apply to its elements.
+pub(crate) struct ValuesAndPaths<'a, 's> {
+    schema: &'s Schema,
+    /// The value and path to walk when the iterator is first advanced
+    root: Option<(&'a [PathElement], &'a Value)>,
+    /// The path of the value being walked
+    current: Path,
+    arrays: Vec<ArrayWalk<'a>>,
+}
+
+struct ArrayWalk<'a> {
+    /// The length of the path of the array
+    depth: usize,
+    rest: &'a [PathElement],
+    elements: std::iter::Enumerate<std::slice::Iter<'a, Value>>,
+}
+
+impl<'a, 's> ValuesAndPaths<'a, 's> {
+    fn new(schema: &'s Schema, path: &'a Path, value: &'a Value) -> Self {
+        Self {
+            schema,
+            root: Some((&path.0[..], value)),
+            current: Path::default(),
+            arrays: Vec::new(),
+        }
+    }
+
+    /// Returns the next matching value, leaving its path in `self.current`
+    fn advance(&mut self) -> Option<&'a Value> {
+        if let Some((path, value)) = self.root.take() {
+            if let Some(value) = self.descend(path, value) {
+                return Some(value);
+            }
+        }
+        loop {
+            let array = self.arrays.last_mut()?;
+            match array.elements.next() {
+                None => {
+                    self.arrays.pop();
+                }
+                Some((i, value)) => {
+                    let rest = array.rest;
+                    self.current.0.truncate(array.depth);
+                    self.current.push(PathElement::Index(i));
+                    if let Some(value) = self.descend(rest, value) {
+                        return Some(value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Follows `path` below `value` until it is matched, or until it leads to an array whose
+    /// elements are walked later, or to nothing
+    fn descend(&mut self, mut path: &'a [PathElement], mut value: &'a Value) -> Option<&'a Value> {
+        loop {
+            match path.split_first() {
+                None => return Some(value),
+                Some((PathElement::Flatten, rest)) => {
+                    self.walk_array(rest, value);
+                    return None;
+                }
+                Some((PathElement::Index(i), rest)) => {
+                    value = value.as_array()?.get(*i)?;
+                    self.current.push(PathElement::Index(*i));
+                    path = rest;
+                }
+                Some((PathElement::Key(k), rest)) => match value {
+                    Value::Object(o) => {
+                        value = o.get(k.as_str())?;
+                        self.current.push(PathElement::Key(k.to_string()));
+                        path = rest;
+                    }
+                    _ => {
+                        self.walk_array(path, value);
+                        return None;
+                    }
+                },
+                Some((PathElement::Fragment(name), rest)) => {
+                    if value.is_object_of_type(self.schema, name) {
+                        // like `Flatten`, the fragment is not part of the path of the value,
+                        // which is a "pure" response path: fragments only choose the values
+                        // by type in a selection path
+                        path = rest;
+                    } else {
+                        self.walk_array(path, value);
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    fn walk_array(&mut self, rest: &'a [PathElement], value: &'a Value) {
+        if let Value::Array(array) = value {
+            self.arrays.push(ArrayWalk {
+                depth: self.current.len(),
+                rest,
+                elements: array.iter().enumerate(),
+            });
+        }
+    }
+}
+
+impl<'a, 's> Iterator for ValuesAndPaths<'a, 's> {
+    type Item = (Path, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = self.advance()?;
+        Some((self.current.clone(), value))
+    }
+}
+
+/// Walks the value along all the paths at once: `cursors` holds, for every path still matching
+/// at `data`, its index and the elements left to match below `data`
+fn select_many_into<'a>(
+    schema: &Schema,
+    parent: &mut Path,
+    cursors: Vec<(usize, &[PathElement])>,
+    data: &'a Value,
+    selected: &mut Vec<(usize, Path, &'a Value)>,
+) {
+    let mut below = Vec::with_capacity(cursors.len());
+    for (index, mut path) in cursors {
+        // like in `ValuesAndPaths`, the fragments matching the value are not part of its path
+        while let Some((PathElement::Fragment(name), rest)) = path.split_first() {
+            if !data.is_object_of_type(schema, name) {
+                break;
+            }
+            path = rest;
+        }
+        if path.is_empty() {
+            selected.push((index, parent.clone(), data));
+        } else {
+            below.push((index, path));
+        }
+    }
+    if below.is_empty() {
+        return;
+    }
+
+    match data {
+        Value::Object(object) => {
+            // each key is visited once, whatever the number of paths going through it
+            let mut by_key: Vec<(&str, Vec<(usize, &[PathElement])>)> = Vec::new();
+            for (index, path) in below {
+                if let Some((PathElement::Key(key), rest)) = path.split_first() {
+                    match by_key.iter_mut().find(|(k, _)| k == key) {
+                        Some((_, cursors)) => cursors.push((index, rest)),
+                        None => by_key.push((key.as_str(), vec![(index, rest)])),
+                    }
+                }
+            }
+            for (key, cursors) in by_key {
+                if let Some(value) = object.get(key) {
+                    parent.push(PathElement::Key(key.to_string()));
+                    select_many_into(schema, parent, cursors, value, selected);
+                    parent.pop();
+                }
+            }
+        }
+        Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                let cursors: Vec<_> = below
+                    .iter()
+                    .filter_map(|&(index, path)| match path.split_first() {
+                        Some((PathElement::Flatten, rest)) => Some((index, rest)),
+                        Some((PathElement::Index(j), rest)) if *j == i => Some((index, rest)),
+                        Some((PathElement::Index(_), _)) | None => None,
+                        // keys and fragments apply to the elements of arrays
+                        Some((PathElement::Key(_), _)) | Some((PathElement::Fragment(_), _)) => {
+                            Some((index, path))
+                        }
+                    })
+                    .collect();
+                if !cursors.is_empty() {
+                    parent.push(PathElement::Index(i));
+                    select_many_into(schema, parent, cursors, value, selected);
+                    parent.pop();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Adds nulls at the end of `array` until it has an element at `index`.
+///
+/// The index comes from a path, which can be controlled by a subgraph: an index too large
+/// to be allocated is an error instead of an overflow or an aborted allocation.
+fn extend_to_index(array: &mut Vec<Value>, index: usize) -> Result<(), FetchError> {
+    if index < array.len() {
+        return Ok(());
+    }
+    index
+        .checked_add(1)
+        .and_then(|len| array.try_reserve_exact(len - array.len()).ok())
+        .ok_or_else(|| FetchError::ExecutionPathNotFound {
+            reason: format!("cannot create index {index}: too large"),
+        })?;
+    array.resize(index + 1, Value::Null);
+    Ok(())
+}
+
+fn iterate_path_mut<'a, F>(
+    schema: &Schema,
+    parent: &mut Path,
+    path: &'a [PathElement],
+    data: &'a mut Value,
+    f: &mut F,
+) where
+    F: FnMut(&Path, &'a mut Value),
+{
+    match path.split_first() {
+        None => f(parent, data),
+        Some((PathElement::Flatten, rest)) => {
+            if let Some(array) = data.as_array_mut() {
+                for (i, value) in array.iter_mut().enumerate() {
+                    parent.push(PathElement::Index(i));
+                    iterate_path_mut(schema, parent, rest, value, f);
+                    parent.pop();
+                }
             }
         }
-        Some(PathElement::Index(i)) => {
+        Some((PathElement::Index(i), rest)) => {
             if let Value::Array(a) = data {
                 if let Some(value) = a.get_mut(*i) {
                     parent.push(PathElement::Index(*i));
-                    iterate_path_mut(schema, parent, &path[1..], value, f);
+                    iterate_path_mut(schema, parent, rest, value, f);
                     parent.pop();
                 }
             }
         }
-        Some(PathElement::Key(k)) => {
+        Some((PathElement::Key(k), rest)) => {
             if let Value::Object(o) = data {
                 if let Some(value) = o.get_mut(k.as_str()) {
                     parent.push(PathElement::Key(k.to_string()));
-                    iterate_path_mut(schema, parent, &path[1..], value, f);
+                    iterate_path_mut(schema, parent, rest, value, f);
                     parent.pop();
                 }
             } else if let Value::Array(array) = data {
@@ -537,9 +1301,9 @@ fn iterate_path_mut<'a, F>(
                 }
             }
         }
-        Some(PathElement::Fragment(name)) => {
+        Some((PathElement::Fragment(name), rest)) => {
             if data.is_object_of_type(schema, name) {
-                iterate_path_mut(schema, parent, &path[1..], data, f);
+                iterate_path_mut(schema, parent, rest, data, f);
             } else if let Value::Array(array) = data {
                 for (i, value) in array.iter_mut().enumerate() {
                     parent.push(PathElement::Index(i));
@@ -551,6 +1315,114 @@ fn iterate_path_mut<'a, F>(
     }
 }
 
+fn insert_at(
+    node: &mut Value,
+    path: &[PathElement],
+    value: Value,
+    mismatch: FlattenMismatch,
+) -> Result<(), FetchError> {
+    let mut current_node = node;
+
+    for (p, rest) in elements_with_rest(path) {
+        match p {
+            PathElement::Flatten => {
+                if current_node.is_null() {
+                    *current_node = Value::Array(Vec::new());
+                }
+                return match current_node {
+                    Value::Array(a) => {
+                        for element in a.iter_mut() {
+                            match insert_at(element, rest, value.clone(), mismatch) {
+                                Err(err) if mismatch == FlattenMismatch::Error => return Err(err),
+                                _ => {}
+                            }
+                        }
+                        Ok(())
+                    }
+                    _other => Err(FetchError::ExecutionPathNotFound {
+                        reason: "expected an array".to_string(),
+                    }),
+                };
+            }
+
+            &PathElement::Index(index) => {
+                if current_node.is_null() {
+                    *current_node = Value::Array(Vec::new());
+                }
+                match current_node {
+                    Value::Array(a) => {
+                        // add more elements if the index is after the end
+                        extend_to_index(a, index)?;
+                        current_node = &mut a[index];
+                    }
+                    _other => {
+                        return Err(FetchError::ExecutionPathNotFound {
+                            reason: "expected an array".to_string(),
+                        })
+                    }
+                }
+            }
+            PathElement::Key(k) => {
+                if current_node.is_null() {
+                    *current_node = Value::Object(Map::new());
+                }
+                match current_node {
+                    Value::Object(o) => {
+                        current_node = o.entry(k.as_str()).or_insert(Value::Null);
+                    }
+                    _other => {
+                        return Err(FetchError::ExecutionPathNotFound {
+                            reason: "expected an object".to_string(),
+                        })
+                    }
+                }
+            }
+            PathElement::Fragment(_) => {}
+        }
+    }
+
+    *current_node = value;
+    Ok(())
+}
+
+/// Iterates over the elements of a path, each with the elements after it
+fn elements_with_rest(
+    path: &[PathElement],
+) -> impl Iterator<Item = (&PathElement, &[PathElement])> {
+    path.iter()
+        .enumerate()
+        .map(move |(i, element)| (element, &path[i + 1..]))
+}
+
+/// Folds the values into a new one with [`ValueExt::deep_merge`], the later values taking
+/// precedence over the earlier ones. Returns `null` when there are no values.
+///
+/// The execution of query plans does not use it: it merges the results as they arrive,
+/// with the configured reporting of merge conflicts.
+#[cfg(test)]
+pub(crate) fn merge_all(values: impl IntoIterator<Item = Value>) -> Value {
+    values.into_iter().fold(Value::Null, |mut merged, value| {
+        merged.deep_merge(value);
+        merged
+    })
+}
+
+fn report_merge_conflict(conflicts: MergeConflicts, message: &str) {
+    match conflicts {
+        MergeConflicts::Ignore => {}
+        MergeConflicts::Debug => {
+            failfast_debug!("{}", message);
+        }
+        MergeConflicts::Warn => {
+            tracing::warn!(
+                monotonic_counter.apollo_router_merge_conflicts_total = 1u64,
+                "{}",
+                message
+            );
+        }
+    }
+}
+
 /// A GraphQL path element that is composes of strings or numbers.
 /// e.g `/book/3/name`
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Hash)]
@@ -685,6 +1557,23 @@ impl Path {
         )
     }
 
+    /// Renders each element of the path as a string, the inverse of [`Path::from_slice`].
+    ///
+    /// Unlike splitting the [`Display`](fmt::Display) output of the path, this keeps the keys
+    /// containing a `/`. Keys made of digits only are read back as indexes by
+    /// [`Path::from_slice`].
+    pub fn to_vec_string(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|element| match element {
+                PathElement::Index(index) => index.to_string(),
+                PathElement::Key(key) => key.clone(),
+                PathElement::Flatten => "@".to_string(),
+                PathElement::Fragment(name) => format!("{FRAGMENT_PREFIX}{name}"),
+            })
+            .collect()
+    }
+
     pub fn from_response_slice(s: &[ResponsePathElement]) -> Self {
         Self(
             s.iter()
@@ -712,6 +1601,13 @@ impl Path {
         Path(Default::default())
     }
 
+    /// Starts building a path element by element.
+    ///
+    /// Unlike parsing a string, this keeps keys that look like numbers, like `"2"`, as keys.
+    pub fn builder() -> PathBuilder {
+        PathBuilder::default()
+    }
+
     pub fn parent(&self) -> Option<Path> {
         if self.is_empty() {
             None
@@ -728,6 +1624,40 @@ impl Path {
         Path(new)
     }
 
+    /// Returns the canonical form of the path, so that paths selecting the same values
+    /// compare and hash equal.
+    ///
+    /// The type conditions of consecutive fragments all apply to the same value, whatever
+    /// their order: every run of consecutive fragments is sorted by type name, and repeated
+    /// type conditions are removed. The other elements are kept as they are, since they
+    /// select different values even when they look redundant: a `Flatten` only goes through
+    /// one level of arrays, while keys and fragments go through all of them.
+    pub fn canonical(&self) -> Path {
+        let mut elements = Vec::with_capacity(self.len());
+        let mut fragments = BTreeSet::new();
+        for element in self.iter() {
+            match element {
+                PathElement::Fragment(name) => {
+                    fragments.insert(name);
+                }
+                element => {
+                    elements.extend(
+                        std::mem::take(&mut fragments)
+                            .into_iter()
+                            .map(|name| PathElement::Fragment(name.clone())),
+                    );
+                    elements.push(element.clone());
+                }
+            }
+        }
+        elements.extend(
+            fragments
+                .into_iter()
+                .map(|name| PathElement::Fragment(name.clone())),
+        );
+        Path(elements)
+    }
+
     pub fn push(&mut self, element: PathElement) {
         self.0.push(element)
     }
@@ -750,6 +1680,70 @@ impl Path {
     pub fn starts_with(&self, other: &Path) -> bool {
         self.0.starts_with(&other.0[..])
     }
+
+    /// Returns the elements of the path after `prefix`, or `None` if the path does not
+    /// start with it
+    pub fn strip_prefix(&self, prefix: &Path) -> Option<&[PathElement]> {
+        self.0.strip_prefix(&prefix.0[..])
+    }
+
+    /// Whether `other` is a path below this one: unlike [`Path::starts_with`], a path is not
+    /// an ancestor of itself
+    pub fn is_ancestor_of(&self, other: &Path) -> bool {
+        other
+            .strip_prefix(self)
+            .map_or(false, |rest| !rest.is_empty())
+    }
+
+    /// Splits the path into its first element and the rest of the path,
+    /// or `None` if the path is empty
+    pub fn split_first(&self) -> Option<(&PathElement, Path)> {
+        self.0
+            .split_first()
+            .map(|(first, rest)| (first, Path(rest.to_vec())))
+    }
+
+    /// Iterates over the elements of the path, each with the rest of the path after it,
+    /// without allocating a new path for every element like [`Path::split_first`] does
+    pub fn iter_with_rest(&self) -> impl Iterator<Item = (&PathElement, &[PathElement])> {
+        elements_with_rest(&self.0)
+    }
+}
+
+/// Builds a [`Path`] from typed elements, see [`Path::builder`]
+#[derive(Clone, Debug, Default)]
+pub struct PathBuilder {
+    elements: Vec<PathElement>,
+}
+
+impl PathBuilder {
+    /// Appends an object key
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.elements.push(PathElement::Key(key.into()));
+        self
+    }
+
+    /// Appends an array index
+    pub fn index(mut self, index: usize) -> Self {
+        self.elements.push(PathElement::Index(index));
+        self
+    }
+
+    /// Appends an element matching every item of an array
+    pub fn flatten(mut self) -> Self {
+        self.elements.push(PathElement::Flatten);
+        self
+    }
+
+    /// Appends a type condition
+    pub fn fragment(mut self, type_name: impl Into<String>) -> Self {
+        self.elements.push(PathElement::Fragment(type_name.into()));
+        self
+    }
+
+    pub fn build(self) -> Path {
+        Path(self.elements)
+    }
 }
 
 impl FromIterator<PathElement> for Path {
@@ -804,6 +1798,42 @@ impl fmt::Display for Path {
     }
 }
 
+/// Interns the [`Path`]s parsed from string literals.
+///
+/// Building a [`Path`] from a string parses it and allocates each of its elements.
+/// Code selecting the same paths for every request can instead get them from a shared
+/// cache, where each path is only parsed once and is then handed out as an `Arc<Path>`.
+///
+/// Paths built at runtime can be reused the same way by building them once and keeping
+/// them around, as a [`Path`] is owned and does not borrow from the string it was parsed from.
+#[derive(Clone, Debug, Default)]
+pub struct PathCache {
+    paths: Arc<Mutex<HashMap<&'static str, Arc<Path>>>>,
+}
+
+impl PathCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the path parsed from `path`, parsing it on first use only
+    pub fn get(&self, path: &'static str) -> Arc<Path> {
+        self.paths
+            .lock()
+            .entry(path)
+            .or_insert_with(|| Arc::new(Path::from(path)))
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.lock().is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json_bytes::json;
@@ -927,47 +1957,356 @@ mod tests {
     }
 
     #[test]
-    fn test_deep_merge() {
-        let mut json = json!({"obj":{"arr":[{"prop1":1},{"prop2":2}]}});
-        json.deep_merge(json!({"obj":{"arr":[{"prop1":2,"prop3":3},{"prop4":4}]}}));
+    fn test_select_many_attributes_the_values_to_their_paths() {
+        let schema = test_schema();
+        let json = json!({"obj":{"arr":[{"prop1":1,"prop2":"a"},{"prop1":2}]}});
+        let paths = [
+            Path::from("obj/arr/@/prop1"),
+            Path::from("obj/arr"),
+            Path::from("obj/arr/0"),
+            Path::from("obj/arr/@/prop1"),
+            Path::from("obj/missing"),
+            Path::from("obj/prop2"),
+        ];
+
+        let selected: Vec<_> = json
+            .select_many(&schema, &paths)
+            .into_iter()
+            .map(|(index, path, value)| (index, path.to_string(), value.clone()))
+            .collect();
         assert_eq!(
-            json,
-            json!({"obj":{"arr":[{"prop1":2, "prop3":3},{"prop2":2, "prop4":4}]}})
+            selected,
+            vec![
+                (
+                    1,
+                    "/obj/arr".to_string(),
+                    json!([{"prop1":1,"prop2":"a"},{"prop1":2}])
+                ),
+                (2, "/obj/arr/0".to_string(), json!({"prop1":1,"prop2":"a"})),
+                (0, "/obj/arr/0/prop1".to_string(), json!(1)),
+                (3, "/obj/arr/0/prop1".to_string(), json!(1)),
+                (0, "/obj/arr/1/prop1".to_string(), json!(2)),
+                (3, "/obj/arr/1/prop1".to_string(), json!(2)),
+            ]
         );
+
+        // each path selects the same values as when walking the value for it alone
+        for (index, path) in paths.iter().enumerate() {
+            let mut expected = Vec::new();
+            json.select_values_and_paths(&schema, path, |path, value| {
+                expected.push((path.clone(), value));
+            });
+            let selected: Vec<_> = json
+                .select_many(&schema, &paths)
+                .into_iter()
+                .filter(|(i, _, _)| *i == index)
+                .map(|(_, path, value)| (path, value))
+                .collect();
+            assert_eq!(selected, expected, "{path}");
+        }
     }
 
     #[test]
-    fn test_is_subset_eq() {
-        assert_is_subset!(
-            json!({"obj":{"arr":[{"prop1":1},{"prop4":4}]}}),
-            json!({"obj":{"arr":[{"prop1":1},{"prop4":4}]}}),
+    fn test_select_values_and_paths_bounded_stops_early() {
+        let schema = test_schema();
+        let items: Vec<_> = (0..100_000).map(|i| json!({ "id": i })).collect();
+        let json = json!({ "items": items });
+        let path = Path::from("items/@/id");
+
+        let mut selected = Vec::new();
+        let truncated = json.select_values_and_paths_bounded(&schema, &path, 3, |path, value| {
+            selected.push((path.to_string(), value.clone()));
+        });
+        assert!(truncated);
+        assert_eq!(
+            selected,
+            vec![
+                ("/items/0/id".to_string(), json!(0)),
+                ("/items/1/id".to_string(), json!(1)),
+                ("/items/2/id".to_string(), json!(2)),
+            ]
         );
-    }
 
-    #[test]
-    fn test_is_subset_missing_pop() {
-        assert_is_subset!(
-            json!({"obj":{"arr":[{"prop1":1},{"prop4":4}]}}),
-            json!({"obj":{"arr":[{"prop1":1,"prop3":3},{"prop4":4}]}}),
+        // reaching the limit without going over it is not a truncation
+        let mut count = 0;
+        assert!(
+            !json.select_values_and_paths_bounded(&schema, &path, 100_000, |_, _| { count += 1 })
         );
+        assert_eq!(count, 100_000);
     }
 
     #[test]
-    fn test_is_subset_array_lengths_differ() {
-        assert_is_not_subset!(
-            json!({"obj":{"arr":[{"prop1":1}]}}),
-            json!({"obj":{"arr":[{"prop1":1,"prop3":3},{"prop4":4}]}}),
+    fn test_iter_values_and_paths_is_lazy() {
+        let schema = test_schema();
+        let items: Vec<_> = (0..100_000)
+            .map(|i| json!({ "id": i, "tags": [i, -i] }))
+            .collect();
+        let json = json!({ "items": items });
+
+        let path = Path::from("items/@/id");
+        let mut values = json.iter_values_and_paths(&schema, &path);
+        let selected: Vec<_> = values
+            .by_ref()
+            .take(2)
+            .map(|(path, value)| (path.to_string(), value.clone()))
+            .collect();
+        assert_eq!(
+            selected,
+            vec![
+                ("/items/0/id".to_string(), json!(0)),
+                ("/items/1/id".to_string(), json!(1)),
+            ]
+        );
+        // only the first two items were visited
+        let unvisited: usize = values.arrays.iter().map(|array| array.elements.len()).sum();
+        assert_eq!(unvisited, 100_000 - 2);
+
+        // the values come in the same order as with the closure
+        let path = Path::from("items/@/tags/@");
+        let mut expected = Vec::new();
+        json.select_values_and_paths(&schema, &path, |path, value| {
+            expected.push((path.clone(), value));
+        });
+        assert_eq!(
+            json.iter_values_and_paths(&schema, &path)
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(
+            json.iter_values_and_paths(&schema, &Path::from("items/3/tags/1"))
+                .collect::<Vec<_>>(),
+            vec![(Path::from("items/3/tags/1"), &json!(-3))]
         );
     }
 
-    #[test]
-    fn test_is_subset_extra_prop() {
+    /// The recursive walk that `select_values_and_paths` used before it was built on
+    /// [`ValuesAndPaths`], kept to check that both select the same values in the same order
+    fn recursive_walk<'a>(
+        schema: &Schema,
+        parent: &mut Path,
+        path: &'a [PathElement],
+        data: &'a Value,
+        selected: &mut Vec<(Path, &'a Value)>,
+    ) {
+        match path.split_first() {
+            None => selected.push((parent.clone(), data)),
+            Some((PathElement::Flatten, rest)) => {
+                if let Some(array) = data.as_array() {
+                    for (i, value) in array.iter().enumerate() {
+                        parent.push(PathElement::Index(i));
+                        recursive_walk(schema, parent, rest, value, selected);
+                        parent.pop();
+                    }
+                }
+            }
+            Some((PathElement::Index(i), rest)) => {
+                if let Some(value) = data.as_array().and_then(|a| a.get(*i)) {
+                    parent.push(PathElement::Index(*i));
+                    recursive_walk(schema, parent, rest, value, selected);
+                    parent.pop();
+                }
+            }
+            Some((PathElement::Key(k), rest)) => {
+                if let Value::Object(o) = data {
+                    if let Some(value) = o.get(k.as_str()) {
+                        parent.push(PathElement::Key(k.to_string()));
+                        recursive_walk(schema, parent, rest, value, selected);
+                        parent.pop();
+                    }
+                } else if let Value::Array(array) = data {
+                    for (i, value) in array.iter().enumerate() {
+                        parent.push(PathElement::Index(i));
+                        recursive_walk(schema, parent, path, value, selected);
+                        parent.pop();
+                    }
+                }
+            }
+            Some((PathElement::Fragment(name), rest)) => {
+                if data.is_object_of_type(schema, name) {
+                    recursive_walk(schema, parent, rest, data, selected);
+                } else if let Value::Array(array) = data {
+                    for (i, value) in array.iter().enumerate() {
+                        parent.push(PathElement::Index(i));
+                        recursive_walk(schema, parent, path, value, selected);
+                        parent.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_values_and_paths_matches_the_recursive_walk() {
+        let schema = test_schema();
+        let json = json!({
+            "i": [
+                { "__typename": "A", "x": 1, "list": [[{ "y": 1 }, { "y": 2 }], [{ "y": 3 }]] },
+                { "__typename": "B", "y": 2 },
+                [{ "__typename": "A", "x": 3 }, { "x": 4 }],
+                null
+            ],
+            "obj": { "arr": [{ "k": 1 }, { "other": 2 }, [{ "k": 3 }], "k"] }
+        });
+        let walked = |path: &str| {
+            let path = Path::from(path);
+            let mut expected = Vec::new();
+            recursive_walk(&schema, &mut Path::default(), &path.0, &json, &mut expected);
+
+            let mut selected = Vec::new();
+            json.select_values_and_paths(&schema, &path, |path, value| {
+                selected.push((path.clone(), value));
+            });
+            assert_eq!(selected, expected, "{path}");
+            assert_eq!(
+                json.iter_values_and_paths(&schema, &path)
+                    .collect::<Vec<_>>(),
+                expected,
+                "{path}"
+            );
+            assert_eq!(
+                json.get_path(&schema, &path).ok(),
+                expected.last().map(|(_, value)| *value),
+                "{path}"
+            );
+            expected
+                .into_iter()
+                .map(|(path, _)| path.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // fragments, on objects and on the elements of arrays
+        // without a __typename, an object is considered to be of the type of the fragment
+        assert_eq!(walked("i/... on A/x"), ["/i/0/x", "/i/2/0/x", "/i/2/1/x"]);
+        assert_eq!(walked("i/@/... on B/y"), ["/i/1/y"]);
+        assert_eq!(
+            walked("i/... on A/... on A/x"),
+            ["/i/0/x", "/i/2/0/x", "/i/2/1/x"]
+        );
+        // keys applying to the elements of arrays, nested or not
+        assert_eq!(walked("i/x"), ["/i/0/x", "/i/2/0/x", "/i/2/1/x"]);
+        assert_eq!(walked("obj/arr/k"), ["/obj/arr/0/k", "/obj/arr/2/0/k"]);
+        // nested flatten
+        assert_eq!(
+            walked("i/@/list/@/@/y"),
+            ["/i/0/list/0/0/y", "/i/0/list/0/1/y", "/i/0/list/1/0/y"]
+        );
+        assert_eq!(walked("i/0/list/@/1/y"), ["/i/0/list/0/1/y"]);
+        assert_eq!(walked("i/@/@/x"), ["/i/2/0/x", "/i/2/1/x"]);
+        // missing keys and indexes
+        assert!(walked("missing/x").is_empty());
+        assert!(walked("i/7/x").is_empty());
+        assert!(walked("obj/arr/@/missing").is_empty());
+        assert!(walked("obj/arr/k/@").is_empty());
+    }
+
+    #[test]
+    fn test_select_values_and_paths_emits_index_elements() {
+        let schema = test_schema();
+        let json = json!({"obj":{"arr":[{"prop1":1},{"prop1":2}], "0": {"prop1":3}}});
+        let mut paths = Vec::new();
+        json.select_values_and_paths(&schema, &Path::from("obj/arr/@/prop1"), |path, _| {
+            paths.push(path.clone());
+        });
+        let key = |k: &str| PathElement::Key(k.to_string());
+        assert_eq!(
+            paths,
+            vec![
+                Path(vec![
+                    key("obj"),
+                    key("arr"),
+                    PathElement::Index(0),
+                    key("prop1")
+                ]),
+                Path(vec![
+                    key("obj"),
+                    key("arr"),
+                    PathElement::Index(1),
+                    key("prop1")
+                ]),
+            ]
+        );
+
+        // a key that looks like an index stays a key
+        let path = Path(vec![key("obj"), key("0"), key("prop1")]);
+        let mut paths = Vec::new();
+        json.select_values_and_paths(&schema, &path, |path, _| {
+            paths.push(path.clone());
+        });
+        assert_eq!(paths, vec![path]);
+    }
+
+    #[test]
+    fn test_deep_merge() {
+        let mut json = json!({"obj":{"arr":[{"prop1":1},{"prop2":2}]}});
+        json.deep_merge(json!({"obj":{"arr":[{"prop1":2,"prop3":3},{"prop4":4}]}}));
+        assert_eq!(
+            json,
+            json!({"obj":{"arr":[{"prop1":2, "prop3":3},{"prop2":2, "prop4":4}]}})
+        );
+    }
+
+    #[test]
+    fn test_is_subset_eq() {
+        assert_is_subset!(
+            json!({"obj":{"arr":[{"prop1":1},{"prop4":4}]}}),
+            json!({"obj":{"arr":[{"prop1":1},{"prop4":4}]}}),
+        );
+    }
+
+    #[test]
+    fn test_is_subset_missing_pop() {
+        assert_is_subset!(
+            json!({"obj":{"arr":[{"prop1":1},{"prop4":4}]}}),
+            json!({"obj":{"arr":[{"prop1":1,"prop3":3},{"prop4":4}]}}),
+        );
+    }
+
+    #[test]
+    fn test_is_subset_array_lengths_differ() {
+        assert_is_not_subset!(
+            json!({"obj":{"arr":[{"prop1":1}]}}),
+            json!({"obj":{"arr":[{"prop1":1,"prop3":3},{"prop4":4}]}}),
+        );
+    }
+
+    #[test]
+    fn test_is_subset_extra_prop() {
         assert_is_not_subset!(
             json!({"obj":{"arr":[{"prop1":1,"prop3":3},{"prop4":4}]}}),
             json!({"obj":{"arr":[{"prop1":1},{"prop4":4}]}}),
         );
     }
 
+    #[test]
+    fn eq_numeric() {
+        assert!(json!(1).eq_numeric(&json!(1.0)));
+        assert!(json!(1.0).eq_numeric(&json!(1)));
+        assert!(!json!(1.5).eq_numeric(&json!(1)));
+        assert!(!json!(1).eq_numeric(&json!("1")));
+        assert!(json!(u64::MAX).eq_numeric(&json!(u64::MAX)));
+        assert!(!json!(-1).eq_numeric(&json!(u64::MAX)));
+        // the object order does not matter, like with `==`
+        assert!(json!({"a": [1, {"b": 2.0}], "c": 3})
+            .eq_numeric(&json!({"c": 3.0, "a": [1.0, {"b": 2}]})));
+        assert!(!json!({"a": 1}).eq_numeric(&json!({"a": 1, "b": 2})));
+        assert!(!json!([1, 2]).eq_numeric(&json!([1])));
+        // precision is lost above 2^53
+        assert!(json!(9_007_199_254_740_993u64).eq_numeric(&json!(9_007_199_254_740_992.0)));
+        assert!(!json!(9_007_199_254_740_993u64).eq_numeric(&json!(9_007_199_254_740_992u64)));
+    }
+
+    #[test]
+    fn is_subset_numeric() {
+        let superset = json!({"obj": {"arr": [{"prop1": 1.0, "prop3": 3}, {"prop4": 4}]}});
+        assert!(
+            json!({"obj": {"arr": [{"prop1": 1}, {"prop4": 4.0}]}}).is_subset_numeric(&superset)
+        );
+        assert!(!json!({"obj": {"arr": [{"prop1": 1}, {"prop4": 4.0}]}}).is_subset(&superset));
+        assert!(
+            !json!({"obj": {"arr": [{"prop1": 1.5}, {"prop4": 4}]}}).is_subset_numeric(&superset)
+        );
+    }
+
     #[test]
     fn eq_and_ordered() {
         // test not objects
@@ -991,7 +2330,7 @@ mod tests {
     fn test_from_path() {
         let json = json!([{"prop1":1},{"prop1":2}]);
         let path = Path::from("obj/arr");
-        let result = Value::from_path(&path, json);
+        let result = Value::from_path(&path, json).unwrap();
         assert_eq!(result, json!({"obj":{"arr":[{"prop1":1},{"prop1":2}]}}));
     }
 
@@ -999,15 +2338,62 @@ mod tests {
     fn test_from_path_index() {
         let json = json!({"prop1":1});
         let path = Path::from("obj/arr/1");
-        let result = Value::from_path(&path, json);
+        let result = Value::from_path(&path, json).unwrap();
         assert_eq!(result, json!({"obj":{"arr":[null, {"prop1":1}]}}));
     }
 
+    #[test]
+    fn test_from_path_never_panics() {
+        let json = json!({"prop1":1});
+        for (path, expected) in [
+            ("0/0/0", json!([[[{"prop1":1}]]])),
+            (
+                "obj/1/... on A/2/obj2",
+                json!({"obj":[null, [null, null, {"obj2":{"prop1":1}}]]}),
+            ),
+            ("... on A/... on B", json!({"prop1":1})),
+            ("@", Value::Null),
+            ("obj/@/@/0", json!({ "obj": null })),
+            ("0/obj/0/obj/0", json!([{"obj":[{"obj":[{"prop1":1}]}]}])),
+        ] {
+            assert_eq!(
+                Value::from_path(&Path::from(path), json.clone()).unwrap(),
+                expected,
+                "{path}"
+            );
+        }
+
+        // the array holding these indexes cannot be allocated
+        for index in [usize::MAX, usize::MAX / 2] {
+            let path = Path(vec![
+                PathElement::Key("obj".to_string()),
+                PathElement::Index(index),
+            ]);
+            match Value::from_path(&path, json.clone()) {
+                Err(FetchError::ExecutionPathNotFound { reason }) => {
+                    assert_eq!(
+                        reason,
+                        format!("cannot create index {index} of {path}: too large")
+                    )
+                }
+                other => panic!("unexpected result for index {index}: {other:?}"),
+            }
+        }
+        assert_eq!(
+            Value::from_path(&Path::from("0/0/... on A/key"), json.clone()).unwrap(),
+            json!([[{"key":{"prop1":1}}]])
+        );
+        assert_eq!(
+            Value::from_path(&Path::default(), json.clone()).unwrap(),
+            json
+        );
+    }
+
     #[test]
     fn test_from_path_flatten() {
         let json = json!({"prop1":1});
         let path = Path::from("obj/arr/@/obj2");
-        let result = Value::from_path(&path, json);
+        let result = Value::from_path(&path, json).unwrap();
         assert_eq!(result, json!({"obj":{"arr":null}}));
     }
 
@@ -1102,4 +2488,733 @@ mod tests {
             "[\"k\",\"... on T\",\"@\",\"arr\",3]",
         );
     }
+
+    #[test]
+    fn path_cache_parses_once() {
+        let cache = PathCache::new();
+        assert!(cache.is_empty());
+
+        let path = cache.get("topProducts/@/reviews/0/author");
+        assert_eq!(*path, Path::from("topProducts/@/reviews/0/author"));
+        assert!(Arc::ptr_eq(
+            &path,
+            &cache.get("topProducts/@/reviews/0/author")
+        ));
+        assert!(Arc::ptr_eq(
+            &path,
+            &cache.clone().get("topProducts/@/reviews/0/author")
+        ));
+
+        cache.get("topProducts/@/name");
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn take_at_path_moves_a_subtree() {
+        let mut value = json!({
+            "data": {
+                "user": { "name": "Ada", "profile": { "bio": "mathematician" } },
+                "friends": ["Bob", "Charles"],
+            },
+        });
+        let profile = value
+            .take_at_path(&Path::from("data/user/profile"))
+            .unwrap();
+        value.insert(&Path::from("profile"), profile).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "data": { "user": { "name": "Ada" }, "friends": ["Bob", "Charles"] },
+                "profile": { "bio": "mathematician" },
+            })
+        );
+
+        assert_eq!(
+            value.take_at_path(&Path::from("data/friends/0")),
+            Some(json!("Bob"))
+        );
+        assert_eq!(
+            value.get_array(&Path::from("data/friends")).unwrap().len(),
+            1
+        );
+        assert_eq!(value.take_at_path(&Path::from("data/friends/1")), None);
+        assert_eq!(value.take_at_path(&Path::from("data/user/profile")), None);
+        assert_eq!(value.take_at_path(&Path::from("data/friends/@")), None);
+    }
+
+    #[test]
+    fn retain_removes_object_keys() {
+        let mut value = json!({
+            "user": { "name": "Ada", "ssn": "123", "friends": [{ "name": "Bob", "ssn": "456" }] },
+            "ssn": { "area": "789", "serial": "0123" },
+        });
+        let mut visited = Vec::new();
+        value.retain(|path, _| {
+            visited.push(path.to_string());
+            path.last() != Some(&PathElement::Key("ssn".to_string()))
+        });
+        assert_eq!(
+            value,
+            json!({ "user": { "name": "Ada", "friends": [{ "name": "Bob" }] } })
+        );
+        // `f` is called on the removed values, but not on their children
+        assert_eq!(
+            visited,
+            [
+                "/user",
+                "/user/name",
+                "/user/ssn",
+                "/user/friends",
+                "/user/friends/0",
+                "/user/friends/0/name",
+                "/user/friends/0/ssn",
+                "/ssn",
+            ]
+        );
+    }
+
+    #[test]
+    fn retain_removes_array_elements() {
+        let mut value = json!({
+            "products": [
+                { "upc": "1", "public": true, "reviews": [1, 2, 3] },
+                { "upc": "2", "public": false, "reviews": [4] },
+                { "upc": "3", "public": true, "reviews": [5, 6] },
+            ],
+        });
+        let mut paths = Vec::new();
+        value.retain(|path, value| {
+            paths.push(path.to_string());
+            value
+                .get("public")
+                .and_then(|public| public.as_bool())
+                .unwrap_or(true)
+                && value.as_i64() != Some(2)
+        });
+        assert_eq!(
+            value,
+            json!({
+                "products": [
+                    { "upc": "1", "public": true, "reviews": [1, 3] },
+                    { "upc": "3", "public": true, "reviews": [5, 6] },
+                ],
+            })
+        );
+        // the paths are the ones of the document before the removals
+        assert!(paths.contains(&"/products/2/reviews/1".to_string()));
+        assert!(!paths.iter().any(|path| path.starts_with("/products/1/")));
+    }
+
+    #[test]
+    fn pointer() {
+        // the examples of RFC 6901, also used by the documentation of serde_json
+        let mut value = json!({
+            "foo": ["bar", "baz"],
+            "": 0,
+            "a/b": 1,
+            "c%d": 2,
+            "e^f": 3,
+            "g|h": 4,
+            "i\\j": 5,
+            "k\"l": 6,
+            " ": 7,
+            "m~n": 8,
+        });
+
+        assert_eq!(value.pointer(""), Some(&value.clone()));
+        assert_eq!(value.pointer("/foo"), Some(&json!(["bar", "baz"])));
+        assert_eq!(value.pointer("/foo/0"), Some(&json!("bar")));
+        assert_eq!(value.pointer("/"), Some(&json!(0)));
+        assert_eq!(value.pointer("/a~1b"), Some(&json!(1)));
+        assert_eq!(value.pointer("/c%d"), Some(&json!(2)));
+        assert_eq!(value.pointer("/e^f"), Some(&json!(3)));
+        assert_eq!(value.pointer("/g|h"), Some(&json!(4)));
+        assert_eq!(value.pointer("/i\\j"), Some(&json!(5)));
+        assert_eq!(value.pointer("/k\"l"), Some(&json!(6)));
+        assert_eq!(value.pointer("/ "), Some(&json!(7)));
+        assert_eq!(value.pointer("/m~0n"), Some(&json!(8)));
+
+        assert_eq!(value.pointer("foo"), None);
+        assert_eq!(value.pointer("/foo/2"), None);
+        assert_eq!(value.pointer("/foo/01"), None);
+        assert_eq!(value.pointer("/foo/0/bar"), None);
+        assert_eq!(value.pointer("/missing"), None);
+
+        *value.pointer_mut("/foo/1").unwrap() = json!("qux");
+        assert_eq!(value.pointer("/foo"), Some(&json!(["bar", "qux"])));
+        assert!(value.pointer_mut("/foo/2").is_none());
+    }
+
+    #[test]
+    fn len_at() {
+        let value = json!({
+            "products": [
+                { "reviews": [{ "id": 1 }, { "id": 2 }] },
+                { "reviews": [] },
+                { "reviews": null },
+                { "reviews": [{ "id": 3 }] },
+            ],
+            "me": { "name": "a", "id": 1 },
+        });
+
+        assert_eq!(value.len_at(&Path::empty()), Some(2));
+        assert_eq!(value.len_at(&Path::from("products")), Some(4));
+        assert_eq!(value.len_at(&Path::from("products/0/reviews")), Some(2));
+        assert_eq!(value.len_at(&Path::from("products/1/reviews")), Some(0));
+        assert_eq!(value.len_at(&Path::from("products/@/reviews")), Some(3));
+        assert_eq!(value.len_at(&Path::from("me")), Some(2));
+        assert_eq!(
+            value.len_at(&Path::from_slice(&["me", "... on User"])),
+            Some(2)
+        );
+
+        // scalars and missing values have no length
+        assert_eq!(value.len_at(&Path::from("me/name")), None);
+        assert_eq!(value.len_at(&Path::from("products/2/reviews")), None);
+        assert_eq!(value.len_at(&Path::from("products/4/reviews")), None);
+        assert_eq!(value.len_at(&Path::from("me/@")), None);
+
+        // the type conditions are not checked, unlike when selecting the values
+        let schema = test_schema();
+        let value = json!({
+            "i": [{ "__typename": "A", "x": 1 }, { "__typename": "B", "y": 2 }],
+        });
+        let path = Path::from_slice(&["i", "@", "... on B"]);
+        assert_eq!(value.len_at(&path), Some(4));
+        assert_eq!(select_values(&schema, &path, &value).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn typed_getters() {
+        let value = json!({
+            "me": {
+                "name": "a",
+                "id": 1,
+                "admin": false,
+                "reviews": [{ "body": "b" }],
+            },
+        });
+
+        assert_eq!(value.get_str(&Path::from("me/name")), Some("a"));
+        assert_eq!(value.get_i64(&Path::from("me/id")), Some(1));
+        assert_eq!(value.get_bool(&Path::from("me/admin")), Some(false));
+        assert_eq!(
+            value.get_array(&Path::from("me/reviews")),
+            Some(&vec![json!({ "body": "b" })])
+        );
+        assert_eq!(
+            value.get_object(&Path::from("me")).map(|me| me.len()),
+            Some(4)
+        );
+        assert_eq!(value.get_str(&Path::from("me/reviews/0/body")), Some("b"));
+        assert_eq!(
+            value.get_str(&Path::from_slice(&["me", "... on User", "name"])),
+            Some("a")
+        );
+
+        // type mismatches
+        assert_eq!(value.get_str(&Path::from("me/id")), None);
+        assert_eq!(value.get_i64(&Path::from("me/name")), None);
+        assert_eq!(value.get_bool(&Path::from("me/id")), None);
+        assert_eq!(value.get_array(&Path::from("me")), None);
+        assert_eq!(value.get_object(&Path::from("me/reviews")), None);
+
+        // missing paths
+        assert_eq!(value.get_str(&Path::from("me/email")), None);
+        assert_eq!(value.get_str(&Path::from("me/reviews/1/body")), None);
+        assert_eq!(value.get_str(&Path::from("me/name/first")), None);
+        assert_eq!(value.get_str(&Path::from("me/reviews/@/body")), None);
+    }
+
+    #[test]
+    fn leaf_paths() {
+        let value = json!({
+            "obj": {
+                "arr": [{ "prop1": 1 }, { "prop1": null, "prop2": [true, false] }],
+                "empty_arr": [],
+                "empty_obj": {},
+            },
+            "str": "a",
+        });
+
+        assert_eq!(
+            value.leaf_paths(),
+            vec![
+                Path::from("obj/arr/0/prop1"),
+                Path::from("obj/arr/1/prop1"),
+                Path::from("obj/arr/1/prop2/0"),
+                Path::from("obj/arr/1/prop2/1"),
+                Path::from("obj/empty_arr"),
+                Path::from("obj/empty_obj"),
+                Path::from("str"),
+            ]
+        );
+        assert_eq!(json!(1).leaf_paths(), vec![Path::empty()]);
+    }
+
+    #[test]
+    fn path_split_first() {
+        assert_eq!(Path::empty().split_first(), None);
+        assert_eq!(Path::empty().iter_with_rest().next(), None);
+
+        let path = Path::from("a");
+        assert_eq!(
+            path.split_first(),
+            Some((&PathElement::Key("a".to_string()), Path::empty()))
+        );
+        assert_eq!(
+            path.iter_with_rest().collect::<Vec<_>>(),
+            vec![(&PathElement::Key("a".to_string()), &[][..])]
+        );
+
+        let path = Path::from("a/@/b");
+        assert_eq!(
+            path.split_first(),
+            Some((&PathElement::Key("a".to_string()), Path::from("@/b")))
+        );
+        assert_eq!(
+            path.iter_with_rest()
+                .map(|(element, rest)| (element.clone(), rest.len()))
+                .collect::<Vec<_>>(),
+            vec![
+                (PathElement::Key("a".to_string()), 2),
+                (PathElement::Flatten, 1),
+                (PathElement::Key("b".to_string()), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn path_is_ancestor_of() {
+        let path = Path::from("a/@/b");
+
+        // a path is not its own ancestor, but starts with itself
+        assert!(!path.is_ancestor_of(&path));
+        assert!(path.starts_with(&path));
+        assert_eq!(path.strip_prefix(&path), Some(&[][..]));
+
+        assert!(Path::from("a/@").is_ancestor_of(&path));
+        assert!(Path::empty().is_ancestor_of(&path));
+        assert!(!path.is_ancestor_of(&Path::from("a/@")));
+        assert_eq!(
+            path.strip_prefix(&Path::from("a")),
+            Some(&[PathElement::Flatten, PathElement::Key("b".to_string())][..])
+        );
+
+        // divergent paths
+        assert!(!Path::from("a/c").is_ancestor_of(&path));
+        assert!(!Path::from("a/0").is_ancestor_of(&path));
+        assert_eq!(path.strip_prefix(&Path::from("a/c")), None);
+    }
+
+    #[test]
+    fn path_canonical() {
+        let joined = Path::from("i/... on I")
+            .join(Path::from_slice(&["... on A", "... on I", "x"]))
+            .join(Path::from("@"));
+        let built = Path::builder()
+            .key("i")
+            .fragment("A")
+            .fragment("I")
+            .key("x")
+            .flatten()
+            .build();
+        assert_ne!(joined, built);
+        assert_eq!(joined.canonical(), built.canonical());
+        assert_eq!(built.canonical(), built);
+
+        // both select the same values
+        let schema = test_schema();
+        let value = json!({
+            "i": [{ "__typename": "A", "x": [1] }, { "__typename": "B", "x": [2] }],
+        });
+        let select = |path: &Path| {
+            let mut values = Vec::new();
+            value.select_values_and_paths(&schema, path, |path, value| {
+                values.push((path.clone(), value.clone()))
+            });
+            values
+        };
+        assert_eq!(select(&joined), select(&joined.canonical()));
+        assert_eq!(select(&joined).len(), 1);
+
+        // fragments separated by other elements, and flattens, are kept
+        for path in [
+            Path::from("... on A/i/... on A"),
+            Path::from("i/@/@"),
+            Path::from("i/@/... on A"),
+            Path::empty(),
+        ] {
+            assert_eq!(path.canonical(), path);
+        }
+        assert_eq!(
+            Path::from("i/... on B/... on A/... on B").canonical(),
+            Path::from("i/... on A/... on B")
+        );
+    }
+
+    #[test]
+    fn path_to_vec_string_round_trips_through_from_slice() {
+        let elements = ["a/b", "0", "@", "... on User", "name"];
+        let path = Path::from_slice(&elements);
+        assert_eq!(
+            path,
+            Path::builder()
+                .key("a/b")
+                .index(0)
+                .flatten()
+                .fragment("User")
+                .key("name")
+                .build()
+        );
+        assert_eq!(path.to_vec_string(), elements);
+        assert_eq!(Path::from_slice(&path.to_vec_string()), path);
+        assert!(Path::empty().to_vec_string().is_empty());
+    }
+
+    #[test]
+    fn path_builder_keeps_numeric_keys() {
+        let path = Path::builder()
+            .key("2")
+            .index(0)
+            .flatten()
+            .fragment("User")
+            .key("name")
+            .build();
+
+        assert_eq!(
+            path,
+            Path(vec![
+                PathElement::Key("2".to_string()),
+                PathElement::Index(0),
+                PathElement::Flatten,
+                PathElement::Fragment("User".to_string()),
+                PathElement::Key("name".to_string()),
+            ])
+        );
+        // parsing the same path from a string turns the key into an index
+        assert_eq!(
+            Path::from("2/0").iter().next(),
+            Some(&PathElement::Index(2))
+        );
+
+        let schema = test_schema();
+        let json = json!({ "2": [{ "name": "Alice" }] });
+        let path = Path::builder().key("2").index(0).key("name").build();
+        let result = select_values(&schema, &path, &json).unwrap();
+        assert_eq!(result, vec![&json!("Alice")]);
+        assert!(select_values(&schema, &Path::from("2/0/name"), &json)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn deep_merge_counts_conflicts() {
+        let mut value = json!({ "obj": { "a": 1 }, "arr": [1, 2], "list": [{ "b": [] }] });
+        let conflicts = value.deep_merge_with(
+            json!({ "obj": [1], "arr": { "a": 1 }, "list": [{ "b": {}, "c": 3 }] }),
+            MergeConflicts::Warn,
+        );
+
+        assert_eq!(conflicts, 3);
+        // conflicting values are not replaced
+        assert_eq!(
+            value,
+            json!({ "obj": { "a": 1 }, "arr": [1, 2], "list": [{ "b": [], "c": 3 }] })
+        );
+
+        let mut value = json!({ "obj": { "a": 1 } });
+        assert_eq!(
+            value.deep_merge_with(json!({ "obj": { "a": 2 } }), MergeConflicts::Ignore),
+            0
+        );
+        assert_eq!(value, json!({ "obj": { "a": 2 } }));
+    }
+
+    #[test]
+    fn merge_all_folds_the_values_in_order() {
+        let merged = merge_all([
+            json!({ "user": { "id": 1, "name": "Ada" }, "list": [1, 2] }),
+            json!({ "user": { "name": "Ada Lovelace", "born": 1815 }, "list": [3] }),
+            json!({ "user": { "born": null, "dead": 1852 }, "extra": true }),
+        ]);
+        assert_eq!(
+            merged,
+            json!({
+                "user": { "id": 1, "name": "Ada Lovelace", "born": 1815, "dead": 1852 },
+                "list": [3, 2],
+                "extra": true
+            })
+        );
+
+        assert_eq!(merge_all([]), Value::Null);
+        assert_eq!(merge_all([json!(1), json!({ "a": 1 })]), json!({ "a": 1 }));
+    }
+
+    #[test]
+    fn get_or_insert_with_creates_missing_nodes() {
+        let mut value = json!({ "obj": { "a": 1 } });
+
+        let node = value
+            .get_or_insert_with(&Path::from("obj/list/1/b"), || json!(2))
+            .unwrap();
+        assert_eq!(node, &json!(2));
+        *node = json!(3);
+
+        assert_eq!(
+            value,
+            json!({ "obj": { "a": 1, "list": [null, { "b": 3 }] } })
+        );
+
+        let mut value = Value::Null;
+        value
+            .get_or_insert_with(&Path::from("a/b"), || json!([]))
+            .unwrap()
+            .as_array_mut()
+            .unwrap()
+            .push(json!(1));
+        assert_eq!(value, json!({ "a": { "b": [1] } }));
+    }
+
+    #[test]
+    fn get_or_insert_with_keeps_existing_nodes() {
+        let mut value = json!({ "obj": { "a": { "b": 1 }, "list": [1, 2] } });
+
+        let node = value
+            .get_or_insert_with(&Path::from("obj/a"), || unreachable!())
+            .unwrap();
+        assert_eq!(node, &json!({ "b": 1 }));
+        node.as_object_mut().unwrap().insert("c", json!(2));
+
+        assert_eq!(
+            value
+                .get_or_insert_with(&Path::from("obj/list/0"), || unreachable!())
+                .unwrap(),
+            &json!(1)
+        );
+        assert_eq!(
+            value,
+            json!({ "obj": { "a": { "b": 1, "c": 2 }, "list": [1, 2] } })
+        );
+
+        // intermediate nodes of the wrong type are an error
+        assert!(value
+            .get_or_insert_with(&Path::from("obj/list/a"), || json!(1))
+            .is_err());
+        assert!(value
+            .get_or_insert_with(&Path::from("obj/a/0"), || json!(1))
+            .is_err());
+    }
+
+    #[test]
+    fn insert_if_absent_does_not_overwrite() {
+        let mut value = json!({ "obj": { "a": 1, "b": null } });
+
+        assert!(value
+            .insert_if_absent(&Path::from("obj/c/0"), json!(2))
+            .unwrap());
+        assert!(value
+            .insert_if_absent(&Path::from("obj/b"), json!(3))
+            .unwrap());
+        assert!(!value
+            .insert_if_absent(&Path::from("obj/a"), json!(4))
+            .unwrap());
+        assert!(!value
+            .insert_if_absent(&Path::from("obj/c"), json!("other"))
+            .unwrap());
+        assert_eq!(value, json!({ "obj": { "a": 1, "b": 3, "c": [2] } }));
+
+        assert!(value
+            .insert_if_absent(&Path::from("obj/a/b"), json!(5))
+            .is_err());
+    }
+
+    #[test]
+    fn fill_absent_does_not_overwrite() {
+        let mut value = json!({ "obj": { "a": 1, "b": null, "list": [1] }, "c": "c" });
+        value.fill_absent(&json!({
+            "obj": { "a": 2, "b": 3, "d": { "e": 4 }, "list": [5, 6] },
+            "c": { "f": 7 },
+            "g": true
+        }));
+        assert_eq!(
+            value,
+            json!({
+                "obj": { "a": 1, "b": null, "list": [1], "d": { "e": 4 } },
+                "c": "c",
+                "g": true
+            })
+        );
+
+        let mut value = json!([1]);
+        value.fill_absent(&json!({ "a": 1 }));
+        assert_eq!(value, json!([1]));
+    }
+
+    #[test]
+    fn deep_merge_at_matches_merging_from_path() {
+        let documents = [
+            json!(null),
+            json!({}),
+            json!({ "obj": { "a": 1, "list": [{ "b": 2 }] } }),
+            json!({ "obj": { "a": { "c": 3 }, "list": null } }),
+            json!({ "obj": [1, 2] }),
+            json!({ "obj": { "a": [1], "list": { "b": 2 } } }),
+        ];
+        let paths = [
+            "obj",
+            "obj/a",
+            "obj/list/0",
+            "obj/list/2/b",
+            "obj/a/d",
+            "obj/list/@/b",
+            "obj/... on Obj/a",
+        ];
+        let value = json!({ "b": 4, "d": [5] });
+
+        for document in &documents {
+            for path in paths {
+                let path = Path::from(path);
+                let mut expected = document.clone();
+                expected.deep_merge(Value::from_path(&path, value.clone()).unwrap());
+
+                let mut merged = document.clone();
+                merged.deep_merge_at(&path, value.clone());
+                assert_eq!(merged, expected, "merging at {path} into {document}");
+            }
+        }
+    }
+
+    #[test]
+    fn deep_merge_at_ignores_indexes_too_large() {
+        let mut value = json!({ "obj": [1] });
+        let path = Path(vec![
+            PathElement::Key("obj".to_string()),
+            PathElement::Index(usize::MAX),
+        ]);
+        value.deep_merge_at(&path, json!(2));
+        assert_eq!(value, json!({ "obj": [1] }));
+    }
+
+    #[test]
+    fn stable_hash_ignores_the_order_of_object_keys() {
+        let value = json!({ "a": 1, "b": { "c": [1, "2", null], "d": true } });
+        let reordered = json!({ "b": { "d": true, "c": [1, "2", null] }, "a": 1 });
+        assert_eq!(value, reordered);
+        assert_eq!(value.stable_hash(), reordered.stable_hash());
+
+        for different in [
+            json!({ "a": 1, "b": { "c": [1, "2", null], "d": false } }),
+            json!({ "a": 1, "b": { "c": [1, 2, null], "d": true } }),
+            json!({ "a": 1, "b": { "c": ["2", 1, null], "d": true } }),
+            json!({ "a": 1, "b": { "c": [1, "2"], "d": true } }),
+            json!({ "a": 1, "b": { "e": [1, "2", null], "d": true } }),
+            json!({ "a": 1 }),
+        ] {
+            assert_ne!(value.stable_hash(), different.stable_hash(), "{different}");
+        }
+    }
+
+    #[test]
+    fn insert_broadcasts_through_flatten() {
+        let mut value = json!({ "users": [{ "id": 1 }, { "id": 2, "verified": false }, null] });
+        value
+            .insert(&Path::from("users/@/verified"), json!(true))
+            .unwrap();
+        assert_eq!(
+            value,
+            json!({ "users": [
+                { "id": 1, "verified": true },
+                { "id": 2, "verified": true },
+                { "verified": true }
+            ] })
+        );
+
+        let mut value = json!({ "a": [[{ "b": 1 }], [{ "b": 2 }, { "b": 3 }], []] });
+        value.insert(&Path::from("a/@/@/c/0"), json!(4)).unwrap();
+        assert_eq!(
+            value,
+            json!({ "a": [
+                [{ "b": 1, "c": [4] }],
+                [{ "b": 2, "c": [4] }, { "b": 3, "c": [4] }],
+                []
+            ] })
+        );
+
+        // nothing to insert in an empty array
+        let mut value = json!({ "users": [] });
+        value
+            .insert(&Path::from("users/@/verified"), json!(true))
+            .unwrap();
+        assert_eq!(value, json!({ "users": [] }));
+    }
+
+    #[test]
+    fn insert_through_flatten_handles_mismatching_elements() {
+        let mut value = json!({ "users": [{ "id": 1 }, 2, { "id": 3 }] });
+        assert!(value
+            .insert(&Path::from("users/@/verified"), json!(true))
+            .is_err());
+
+        let mut value = json!({ "users": [{ "id": 1 }, 2, { "id": 3 }] });
+        value
+            .insert_with(
+                &Path::from("users/@/verified"),
+                json!(true),
+                FlattenMismatch::Skip,
+            )
+            .unwrap();
+        assert_eq!(
+            value,
+            json!({ "users": [{ "id": 1, "verified": true }, 2, { "id": 3, "verified": true }] })
+        );
+
+        // the flattened node itself must be an array
+        let mut value = json!({ "users": { "id": 1 } });
+        assert!(value
+            .insert_with(
+                &Path::from("users/@/verified"),
+                json!(true),
+                FlattenMismatch::Skip,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn insert_rejects_indexes_too_large() {
+        for index in [usize::MAX, usize::MAX / 2] {
+            let mut value = json!({ "users": [{ "tags": [] }] });
+            let path = Path(vec![
+                PathElement::Key("users".to_string()),
+                PathElement::Flatten,
+                PathElement::Key("tags".to_string()),
+                PathElement::Index(index),
+            ]);
+            match value.insert(&path, json!(true)) {
+                Err(FetchError::ExecutionPathNotFound { reason }) => {
+                    assert_eq!(reason, format!("cannot create index {index}: too large"))
+                }
+                other => panic!("unexpected result for index {index}: {other:?}"),
+            }
+            assert_eq!(value, json!({ "users": [{ "tags": [] }] }));
+        }
+    }
+
+    #[test]
+    fn get_or_insert_with_rejects_indexes_too_large() {
+        for index in [usize::MAX, usize::MAX / 2] {
+            let mut value = json!({ "obj": [1] });
+            let path = Path(vec![
+                PathElement::Key("obj".to_string()),
+                PathElement::Index(index),
+            ]);
+            match value.get_or_insert_with(&path, || json!(2)) {
+                Err(FetchError::ExecutionPathNotFound { reason }) => {
+                    assert_eq!(reason, format!("cannot create index {index}: too large"))
+                }
+                other => panic!("unexpected result for index {index}: {other:?}"),
+            }
+            assert_eq!(value, json!({ "obj": [1] }));
+        }
+    }
 }
@@ -21,6 +21,7 @@ pub mod test;
 
 use std::any::TypeId;
 use std::fmt;
+use std::future::Future;
 use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
@@ -29,6 +30,7 @@ use ::serde::de::DeserializeOwned;
 use ::serde::Deserialize;
 use async_trait::async_trait;
 use futures::future::BoxFuture;
+use futures::FutureExt;
 use multimap::MultiMap;
 use once_cell::sync::Lazy;
 use schemars::gen::SchemaGenerator;
@@ -232,6 +234,52 @@ pub trait Plugin: Send + Sync + 'static {
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         MultiMap::new()
     }
+
+    /// Return a probe run by the health check endpoint: the router is reported as down when
+    /// it fails. The default probe always passes.
+    ///
+    /// This method is experimental and subject to change post 1.0
+    fn health_probe(&self) -> HealthProbe {
+        HealthProbe::default()
+    }
+}
+
+/// An asynchronous check of the readiness of the router, run by each request to the health
+/// check endpoint.
+///
+/// The probe fails when it returns `false`, or when it does not complete within the
+/// `health_check.probe_timeout` configuration option.
+#[derive(Clone)]
+pub struct HealthProbe(Arc<dyn Fn() -> BoxFuture<'static, bool> + Send + Sync>);
+
+impl HealthProbe {
+    /// Create a probe from a function returning a future that resolves to `true` when the
+    /// router is ready
+    pub fn new<F, Fut>(probe: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        Self(Arc::new(move || probe().boxed()))
+    }
+
+    /// Run the probe
+    pub async fn check(&self) -> bool {
+        (self.0)().await
+    }
+}
+
+impl Default for HealthProbe {
+    /// A probe that always passes
+    fn default() -> Self {
+        Self::new(|| async { true })
+    }
+}
+
+impl fmt::Debug for HealthProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HealthProbe").finish()
+    }
 }
 
 fn get_type_of<T>(_: &T) -> &'static str {
@@ -275,6 +323,9 @@ pub(crate) trait DynPlugin: Send + Sync + 'static {
     /// Return one or several `Endpoint`s and `ListenAddr` and the router will serve your custom web Endpoint(s).
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint>;
 
+    /// Return a probe run by the health check endpoint.
+    fn health_probe(&self) -> HealthProbe;
+
     /// Support downcasting
     fn as_any(&self) -> &dyn std::any::Any;
 
@@ -313,6 +364,10 @@ where
         self.web_endpoints()
     }
 
+    fn health_probe(&self) -> HealthProbe {
+        self.health_probe()
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
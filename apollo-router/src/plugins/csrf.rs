@@ -13,6 +13,7 @@ use tower::ServiceExt;
 use crate::layers::ServiceBuilderExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::telemetry::metrics::count_rejected_request;
 use crate::register_plugin;
 use crate::services::supergraph;
 use crate::services::SupergraphResponse;
@@ -109,6 +110,7 @@ impl Plugin for Csrf {
                         Ok(ControlFlow::Continue(req))
                     } else {
                         tracing::trace!("request is not preflighted");
+                        count_rejected_request("csrf_prevention");
                         let error = crate::error::Error::builder().message(
                             format!(
                                 "This operation has been blocked as a potential Cross-Site Request Forgery (CSRF). \
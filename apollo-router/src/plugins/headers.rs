@@ -26,6 +26,7 @@ use tower::ServiceBuilder;
 use tower::ServiceExt;
 use tower_service::Service;
 
+use crate::http_ext::copy_headers;
 use crate::plugin::serde::deserialize_header_name;
 use crate::plugin::serde::deserialize_header_value;
 use crate::plugin::serde::deserialize_json_query;
@@ -365,16 +366,20 @@ where
                     }
                 }
                 Operation::Propagate(Propagate::Matching { matching }) => {
-                    let headers = req.subgraph_request.headers_mut();
-                    req.supergraph_request
+                    let allowlist: Vec<HeaderName> = req
+                        .supergraph_request
                         .headers()
-                        .iter()
-                        .filter(|(name, _)| {
+                        .keys()
+                        .filter(|name| {
                             !RESERVED_HEADERS.contains(name) && matching.is_match(name.as_str())
                         })
-                        .for_each(|(name, value)| {
-                            headers.insert(name, value.clone());
-                        });
+                        .cloned()
+                        .collect();
+                    copy_headers(
+                        req.supergraph_request.headers(),
+                        req.subgraph_request.headers_mut(),
+                        &allowlist,
+                    );
                 }
             }
         }
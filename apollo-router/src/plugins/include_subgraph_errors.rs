@@ -213,6 +213,7 @@ mod test {
             &Configuration::default(),
         )
         .await
+        .unwrap()
         .make()
         .boxed()
     }
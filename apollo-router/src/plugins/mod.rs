@@ -31,6 +31,7 @@ mod forbid_mutations;
 mod headers;
 mod include_subgraph_errors;
 pub(crate) mod override_url;
+mod response_transform;
 pub(crate) mod rhai;
 pub(crate) mod telemetry;
 pub(crate) mod traffic_shaping;
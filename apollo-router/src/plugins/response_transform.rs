@@ -0,0 +1,267 @@
+//! Transformations applied to the GraphQL responses before they are serialized
+
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::graphql;
+use crate::json_ext::Path;
+use crate::json_ext::PathElement;
+use crate::json_ext::Value;
+use crate::json_ext::ValueExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::supergraph;
+use crate::spec::Schema;
+use crate::Configuration;
+
+register_plugin!(
+    "apollo",
+    "experimental_response_transform",
+    ResponseTransformPlugin
+);
+
+/// Configuration for the transformations applied to responses before they are sent to the client
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Remove the fields found at these paths from the response data.
+    /// Path elements are separated by `/` and `@` matches every element of a list,
+    /// for example `topProducts/@/_debug`
+    strip_paths: Vec<String>,
+}
+
+/// A transformation applied to every GraphQL response, before it is serialized
+pub(crate) trait ResponseTransform: Send + Sync {
+    fn transform(&self, schema: &Schema, response: &mut graphql::Response);
+}
+
+/// Removes the fields found at a list of paths, in the primary response as well as
+/// in the deferred responses
+pub(crate) struct StripPaths {
+    /// path to the objects containing the field, and name of the field
+    paths: Vec<(Path, String)>,
+}
+
+impl StripPaths {
+    pub(crate) fn new(paths: impl IntoIterator<Item = Path>) -> Result<Self, BoxError> {
+        let paths = paths
+            .into_iter()
+            .map(|path| match (path.parent(), path.last()) {
+                (Some(parent), Some(PathElement::Key(key))) => Ok((parent, key.clone())),
+                _ => Err(format!(
+                    "cannot strip '{path}': the path must end with a field name"
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(StripPaths { paths })
+    }
+}
+
+impl ResponseTransform for StripPaths {
+    fn transform(&self, schema: &Schema, response: &mut graphql::Response) {
+        for (parent, key) in &self.paths {
+            if let Some(data) = response.data.as_mut() {
+                strip(schema, data, parent, key);
+            }
+
+            for incremental in response.incremental.iter_mut() {
+                if let (Some(path), Some(data)) =
+                    (incremental.path.as_ref(), incremental.data.as_mut())
+                {
+                    if let Some(relative_parent) = relative_path(parent, path) {
+                        strip(schema, data, &relative_parent, key);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn strip(schema: &Schema, data: &mut Value, parent: &Path, key: &str) {
+    data.select_values_and_paths_mut(schema, parent, |_, value| remove_key(value, key));
+}
+
+fn remove_key(value: &mut Value, key: &str) {
+    match value {
+        Value::Object(object) => {
+            object.remove(key);
+        }
+        // like in selections, a field name applies to every element of a list
+        Value::Array(array) => array.iter_mut().for_each(|value| remove_key(value, key)),
+        _ => {}
+    }
+}
+
+/// Returns what remains of `path` once the response path of a deferred response is
+/// removed from its start, if `path` goes through that deferred response.
+fn relative_path(path: &Path, response_path: &Path) -> Option<Path> {
+    if response_path.len() > path.len() {
+        return None;
+    }
+
+    path.iter()
+        .zip(response_path.iter())
+        .all(
+            |(element, response_element)| match (element, response_element) {
+                (PathElement::Flatten, PathElement::Index(_)) => true,
+                (element, response_element) => element == response_element,
+            },
+        )
+        .then(|| path.iter().skip(response_path.len()).cloned().collect())
+}
+
+struct ResponseTransformPlugin {
+    schema: Arc<Schema>,
+    transforms: Arc<Vec<Box<dyn ResponseTransform>>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ResponseTransformPlugin {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let schema = Schema::parse(&init.supergraph_sdl, &Configuration::default(), None)?;
+
+        let mut transforms: Vec<Box<dyn ResponseTransform>> = Vec::new();
+        if !init.config.strip_paths.is_empty() {
+            transforms.push(Box::new(StripPaths::new(
+                init.config.strip_paths.iter().map(Path::from),
+            )?));
+        }
+
+        Ok(ResponseTransformPlugin {
+            schema: Arc::new(schema),
+            transforms: Arc::new(transforms),
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if self.transforms.is_empty() {
+            return service;
+        }
+
+        let schema = self.schema.clone();
+        let transforms = self.transforms.clone();
+        service
+            .map_response(move |response: supergraph::Response| {
+                let schema = schema.clone();
+                let transforms = transforms.clone();
+                response.map_stream(move |mut response| {
+                    for transform in transforms.iter() {
+                        transform.transform(&schema, &mut response);
+                    }
+                    response
+                })
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::graphql::IncrementalResponse;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::services::SupergraphRequest;
+    use crate::services::SupergraphResponse;
+
+    const SCHEMA: &str = include_str!("../../testing_schema.graphql");
+
+    async fn transformed(strip_paths: &[&str], response: graphql::Response) -> graphql::Response {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(move |req| {
+            Ok(SupergraphResponse::new_from_graphql_response(
+                response.clone(),
+                req.context,
+            ))
+        });
+
+        let config = Config {
+            strip_paths: strip_paths.iter().map(|path| path.to_string()).collect(),
+        };
+        ResponseTransformPlugin::new(PluginInit::new(config, Arc::new(SCHEMA.to_string())))
+            .await
+            .unwrap()
+            .supergraph_service(mock_service.boxed())
+            .oneshot(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_strips_configured_paths() {
+        let response = graphql::Response::builder()
+            .data(json!({
+                "_debug": "top level",
+                "topProducts": [
+                    { "upc": "1", "name": "Table", "_debug": { "cost": 1 } },
+                    { "upc": "2", "name": "Couch" },
+                    { "upc": "3", "name": "Chair", "_debug": null },
+                ],
+            }))
+            .build();
+
+        let response = transformed(&["_debug", "topProducts/@/_debug"], response).await;
+
+        assert_eq!(
+            response.data.unwrap(),
+            json!({
+                "topProducts": [
+                    { "upc": "1", "name": "Table" },
+                    { "upc": "2", "name": "Couch" },
+                    { "upc": "3", "name": "Chair" },
+                ],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_leaves_unrelated_fields_alone() {
+        let data = json!({
+            "topProducts": [
+                { "upc": "1", "name": "Table", "reviews": [{ "id": "1", "_debug": "nested" }] },
+            ],
+            "me": { "_debug": "not configured" },
+        });
+        let response = graphql::Response::builder().data(data.clone()).build();
+
+        let response = transformed(&["topProducts/@/_debug", "unknown/field"], response).await;
+
+        assert_eq!(response.data.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn it_strips_paths_in_deferred_responses() {
+        let response = graphql::Response::builder()
+            .incremental(vec![IncrementalResponse::builder()
+                .data(json!({ "reviews": [{ "id": "1", "_debug": "deferred" }] }))
+                .path(Path::from("topProducts/1"))
+                .build()])
+            .build();
+
+        let response = transformed(&["topProducts/@/reviews/@/_debug"], response).await;
+
+        assert_eq!(
+            response.incremental[0].data.as_ref().unwrap(),
+            &json!({ "reviews": [{ "id": "1" }] })
+        );
+    }
+
+    #[test]
+    fn it_rejects_paths_not_ending_with_a_field() {
+        assert!(StripPaths::new([Path::from("topProducts/@")]).is_err());
+        assert!(StripPaths::new([Path::from("topProducts/0")]).is_err());
+        assert!(StripPaths::new([Path::from("topProducts/@/name")]).is_ok());
+    }
+}
@@ -74,7 +74,7 @@ pub(crate) struct Metrics {
     /// Common metrics configuration across all exporters
     pub(crate) common: Option<MetricsCommon>,
     /// Open Telemetry native exporter configuration
-    pub(crate) otlp: Option<otlp::Config>,
+    pub(crate) otlp: Option<metrics::otlp::Config>,
     /// Prometheus exporter configuration
     pub(crate) prometheus: Option<metrics::prometheus::Config>,
 }
@@ -84,6 +84,9 @@ pub(crate) struct Metrics {
 pub(crate) struct MetricsCommon {
     /// Configuration to add custom labels/attributes to metrics
     pub(crate) attributes: Option<MetricsAttributesConf>,
+    /// Label the metrics of GraphQL requests with their operation name
+    #[serde(default)]
+    pub(crate) operation_name_label: OperationNameLabel,
     /// Set a service.name resource in your metrics
     pub(crate) service_name: Option<String>,
     /// Set a service.namespace attribute in your metrics
@@ -93,6 +96,28 @@ pub(crate) struct MetricsCommon {
     pub(crate) resources: HashMap<String, String>,
 }
 
+/// Label the request count and duration metrics of GraphQL requests with their operation name
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct OperationNameLabel {
+    /// Add the `operation_name` label, for the requests with an operation name
+    /// (default: false)
+    pub(crate) enabled: bool,
+    /// The maximum number of distinct operation names used as label values: the requests
+    /// for other operations are labeled `__other__`, bounding the number of time series
+    /// (default: 100)
+    pub(crate) max_distinct: usize,
+}
+
+impl Default for OperationNameLabel {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_distinct: 100,
+        }
+    }
+}
+
 /// Tracing configuration
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
@@ -1,5 +1,6 @@
 use std::any::Any;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use ::serde::Deserialize;
@@ -11,6 +12,7 @@ use multimap::MultiMap;
 use opentelemetry::metrics::Counter;
 use opentelemetry::metrics::Histogram;
 use opentelemetry::metrics::MeterProvider;
+use parking_lot::Mutex;
 use regex::Regex;
 use schemars::JsonSchema;
 use serde::Serialize;
@@ -26,6 +28,7 @@ use crate::plugins::telemetry::apollo_exporter::Sender;
 use crate::plugins::telemetry::config::AttributeValue;
 use crate::plugins::telemetry::config::MetricsCommon;
 use crate::plugins::telemetry::metrics::aggregation::AggregateMeterProvider;
+use crate::plugins::telemetry::metrics::prometheus::PushgatewayHandle;
 use crate::router_factory::Endpoint;
 use crate::Context;
 use crate::ListenAddr;
@@ -38,6 +41,8 @@ pub(crate) mod prometheus;
 pub(crate) mod span_metrics_exporter;
 
 pub(crate) const METRIC_PREFIX_MONOTONIC_COUNTER: &str = "monotonic_counter.";
+/// The value of the attributes bounded by [`BoundedValues`] once their limit is reached
+pub(crate) const OTHER_VALUE: &str = "__other__";
 pub(crate) const METRIC_PREFIX_COUNTER: &str = "counter.";
 pub(crate) const METRIC_PREFIX_HISTOGRAM: &str = "histogram.";
 pub(crate) const METRIC_PREFIX_VALUE: &str = "value.";
@@ -489,6 +494,7 @@ impl AttributesForwardConf {
 #[derive(Default)]
 pub(crate) struct MetricsBuilder {
     exporters: Vec<MetricsExporterHandle>,
+    pushgateway: Option<PushgatewayHandle>,
     meter_providers: Vec<Arc<dyn MeterProvider + Send + Sync + 'static>>,
     custom_endpoints: MultiMap<ListenAddr, Endpoint>,
     apollo_metrics: Sender,
@@ -498,6 +504,9 @@ impl MetricsBuilder {
     pub(crate) fn exporters(&mut self) -> Vec<MetricsExporterHandle> {
         std::mem::take(&mut self.exporters)
     }
+    pub(crate) fn pushgateway(&mut self) -> Option<PushgatewayHandle> {
+        self.pushgateway.take()
+    }
     pub(crate) fn meter_provider(&mut self) -> AggregateMeterProvider {
         AggregateMeterProvider::new(std::mem::take(&mut self.meter_providers))
     }
@@ -516,6 +525,11 @@ impl MetricsBuilder {
         self
     }
 
+    fn with_pushgateway(mut self, pushgateway: PushgatewayHandle) -> Self {
+        self.pushgateway = Some(pushgateway);
+        self
+    }
+
     fn with_meter_provider<T: MeterProvider + Send + Sync + 'static>(
         mut self,
         meter_provider: T,
@@ -549,6 +563,46 @@ pub(crate) struct BasicMetrics {
     pub(crate) http_requests_duration: Histogram<f64>,
 }
 
+/// Counts a request rejected before its execution, by reason, to see which kind of invalid
+/// requests clients send
+pub(crate) fn count_rejected_request(reason: &'static str) {
+    ::tracing::info!(
+        monotonic_counter.apollo_router_rejected_requests_total = 1u64,
+        reason = reason
+    );
+}
+
+/// Bounds the number of distinct values of a metric attribute, and so the number of time
+/// series: once `max_distinct` values were seen, new values are replaced by [`OTHER_VALUE`].
+/// The values seen first keep their own time series.
+#[derive(Debug)]
+pub(crate) struct BoundedValues {
+    max_distinct: usize,
+    seen: Mutex<HashSet<String>>,
+}
+
+impl BoundedValues {
+    pub(crate) fn new(max_distinct: usize) -> Self {
+        Self {
+            max_distinct,
+            seen: Default::default(),
+        }
+    }
+
+    /// Returns `value`, or [`OTHER_VALUE`] if it is new and the limit is reached
+    pub(crate) fn bound(&self, value: &str) -> String {
+        let mut seen = self.seen.lock();
+        if seen.contains(value) {
+            return value.to_string();
+        }
+        if seen.len() < self.max_distinct {
+            seen.insert(value.to_string());
+            return value.to_string();
+        }
+        OTHER_VALUE.to_string()
+    }
+}
+
 impl BasicMetrics {
     pub(crate) fn new(meter_provider: &impl MeterProvider) -> BasicMetrics {
         let meter = meter_provider.meter("apollo/router");
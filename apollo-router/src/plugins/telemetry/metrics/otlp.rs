@@ -1,14 +1,97 @@
 use opentelemetry::sdk::export::metrics::aggregation;
+use opentelemetry::sdk::export::metrics::aggregation::TemporalitySelector;
+use opentelemetry::sdk::metrics::controllers::BasicController;
 use opentelemetry::sdk::metrics::selectors;
 use opentelemetry::sdk::Resource;
 use opentelemetry::KeyValue;
 use opentelemetry_otlp::HttpExporterBuilder;
 use opentelemetry_otlp::TonicExporterBuilder;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
 use tower::BoxError;
 
 use crate::plugins::telemetry::config::MetricsCommon;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
+use crate::plugins::telemetry::otlp;
+use crate::plugins::telemetry::otlp::deser_endpoint;
+use crate::plugins::telemetry::otlp::Endpoint;
+use crate::plugins::telemetry::otlp::GrpcExporter;
+use crate::plugins::telemetry::otlp::HttpExporter;
+use crate::plugins::telemetry::otlp::Protocol;
+use crate::plugins::telemetry::tracing::BatchProcessorConfig;
+
+/// The settings of the OTLP exporter shared with tracing, and the ones specific to metrics
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// The endpoint to send data to
+    #[serde(deserialize_with = "deser_endpoint")]
+    #[schemars(with = "String")]
+    pub(crate) endpoint: Endpoint,
+
+    /// The protocol to use when sending data
+    #[serde(default)]
+    pub(crate) protocol: Protocol,
+
+    /// gRPC configuration settings
+    #[serde(default)]
+    pub(crate) grpc: GrpcExporter,
+
+    /// HTTP configuration settings
+    #[serde(default)]
+    pub(crate) http: HttpExporter,
+
+    /// Batch processor settings
+    #[serde(default)]
+    pub(crate) batch_processor: BatchProcessorConfig,
+
+    /// The temporality of all the exported metrics
+    /// Defaults to cumulative
+    #[serde(default)]
+    pub(crate) temporality: Temporality,
+}
+
+impl Config {
+    fn exporter_config(&self) -> otlp::Config {
+        otlp::Config {
+            endpoint: self.endpoint.clone(),
+            protocol: self.protocol.clone(),
+            grpc: self.grpc.clone(),
+            http: self.http.clone(),
+            batch_processor: self.batch_processor.clone(),
+        }
+    }
+}
+
+/// How the values of metrics are aggregated over time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum Temporality {
+    /// The values accumulated since the router started
+    #[default]
+    Cumulative,
+    /// The values accumulated since the previous export
+    Delta,
+}
+
+impl From<Temporality> for aggregation::Temporality {
+    fn from(temporality: Temporality) -> Self {
+        match temporality {
+            Temporality::Cumulative => aggregation::Temporality::Cumulative,
+            Temporality::Delta => aggregation::Temporality::Delta,
+        }
+    }
+}
+
+impl Temporality {
+    /// The selector given to the pipeline by [`build_pipeline`], the same temporality for
+    /// every instrument
+    fn selector(self) -> impl TemporalitySelector + Clone + Send + Sync + 'static {
+        aggregation::constant_temporality_selector(self.into())
+    }
+}
 
 // TODO Remove MetricExporterBuilder once upstream issue is fixed
 // This has to exist because Http is not currently supported for metrics export
@@ -31,32 +114,16 @@ impl From<HttpExporterBuilder> for MetricExporterBuilder {
     }
 }
 
-impl MetricsConfigurator for super::super::otlp::Config {
+impl MetricsConfigurator for Config {
     fn apply(
         &self,
         mut builder: MetricsBuilder,
         metrics_config: &MetricsCommon,
     ) -> Result<MetricsBuilder, BoxError> {
-        let exporter: MetricExporterBuilder = self.exporter()?;
+        let exporter: MetricExporterBuilder = self.exporter_config().exporter()?;
         match exporter.exporter {
             Some(exporter) => {
-                let exporter = opentelemetry_otlp::new_pipeline()
-                    .metrics(
-                        selectors::simple::histogram([
-                            0.001, 0.005, 0.015, 0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 1.0, 5.0, 10.0,
-                        ]),
-                        aggregation::stateless_temporality_selector(),
-                        opentelemetry::runtime::Tokio,
-                    )
-                    .with_exporter(exporter)
-                    .with_resource(Resource::new(
-                        metrics_config
-                            .resources
-                            .clone()
-                            .into_iter()
-                            .map(|(k, v)| KeyValue::new(k, v)),
-                    ))
-                    .build()?;
+                let exporter = build_pipeline(exporter, self.temporality, metrics_config)?;
                 builder = builder.with_meter_provider(exporter.clone());
                 builder = builder.with_exporter(exporter);
                 Ok(builder)
@@ -65,3 +132,87 @@ impl MetricsConfigurator for super::super::otlp::Config {
         }
     }
 }
+
+fn build_pipeline(
+    exporter: TonicExporterBuilder,
+    temporality: Temporality,
+    metrics_config: &MetricsCommon,
+) -> Result<BasicController, BoxError> {
+    Ok(opentelemetry_otlp::new_pipeline()
+        .metrics(
+            selectors::simple::histogram([
+                0.001, 0.005, 0.015, 0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 1.0, 5.0, 10.0,
+            ]),
+            temporality.selector(),
+            opentelemetry::runtime::Tokio,
+        )
+        .with_exporter(exporter)
+        .with_resource(Resource::new(
+            metrics_config
+                .resources
+                .clone()
+                .into_iter()
+                .map(|(k, v)| KeyValue::new(k, v)),
+        ))
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::sdk::export::metrics::aggregation::AggregationKind;
+    use opentelemetry::sdk::metrics::sdk_api::Descriptor;
+    use opentelemetry::sdk::metrics::sdk_api::InstrumentKind;
+    use opentelemetry::sdk::metrics::sdk_api::NumberKind;
+
+    use super::*;
+
+    fn temporality_of(
+        config: &Config,
+        instrument_kind: InstrumentKind,
+    ) -> aggregation::Temporality {
+        let descriptor = Descriptor::new(
+            "apollo_router_http_requests_total".to_string(),
+            instrument_kind,
+            NumberKind::U64,
+            None,
+            None,
+        );
+        config
+            .temporality
+            .selector()
+            .temporality_for(&descriptor, &AggregationKind::SUM)
+    }
+
+    #[test]
+    fn temporality_configuration() {
+        let config: Config = serde_yaml::from_str("endpoint: default").unwrap();
+        assert_eq!(config.temporality, Temporality::Cumulative);
+        assert_eq!(
+            temporality_of(&config, InstrumentKind::Counter),
+            aggregation::Temporality::Cumulative
+        );
+
+        let config: Config = serde_yaml::from_str("endpoint: default\ntemporality: delta").unwrap();
+        assert_eq!(
+            temporality_of(&config, InstrumentKind::Counter),
+            aggregation::Temporality::Delta
+        );
+        assert_eq!(
+            temporality_of(&config, InstrumentKind::Histogram),
+            aggregation::Temporality::Delta
+        );
+
+        let config: Config =
+            serde_yaml::from_str("endpoint: default\ntemporality: cumulative").unwrap();
+        assert_eq!(
+            temporality_of(&config, InstrumentKind::Counter),
+            aggregation::Temporality::Cumulative
+        );
+
+        assert!(serde_yaml::from_str::<Config>("endpoint: default\ntemporality: gauge").is_err());
+        // tracing has no temporality
+        assert!(
+            serde_yaml::from_str::<otlp::Config>("endpoint: default\ntemporality: delta").is_err()
+        );
+    }
+}
@@ -1,8 +1,14 @@
+use std::sync::Arc;
 use std::sync::Mutex;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Duration;
 
+use bytes::Bytes;
 use futures::future::BoxFuture;
+use futures::stream;
+use futures::Stream;
+use http::Method;
 use http::StatusCode;
 use once_cell::sync::Lazy;
 use opentelemetry::sdk::export::metrics::aggregation;
@@ -12,15 +18,21 @@ use opentelemetry::sdk::metrics::processors;
 use opentelemetry::sdk::metrics::selectors;
 use opentelemetry::sdk::Resource;
 use opentelemetry::KeyValue;
+use prometheus::proto::MetricFamily;
 use prometheus::Encoder;
 use prometheus::Registry;
 use prometheus::TextEncoder;
 use schemars::JsonSchema;
 use serde::Deserialize;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 use tower::BoxError;
 use tower::ServiceExt;
 use tower_service::Service;
+use url::Url;
 
+use crate::configuration::RouteGroup;
+use crate::http_ext::is_authorized;
 use crate::plugins::telemetry::config::MetricsCommon;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
@@ -40,6 +52,35 @@ pub(crate) struct Config {
     /// The path where prometheus will be exposed
     #[serde(default = "prometheus_default_path")]
     pub(crate) path: String,
+    /// When set, scrapers must send an `Authorization: Bearer <bearer_token>` header
+    pub(crate) bearer_token: Option<String>,
+    /// Push the metrics to a Prometheus Pushgateway at an interval and on shutdown, for
+    /// routers that do not run long enough to be scraped
+    pub(crate) pushgateway: Option<Pushgateway>,
+}
+
+/// Prometheus Pushgateway configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Pushgateway {
+    /// The URL of the Pushgateway, like `http://pushgateway:9091`
+    #[schemars(with = "String")]
+    pub(crate) url: Url,
+    /// The job the pushed metrics are grouped by
+    #[serde(default = "pushgateway_default_job")]
+    pub(crate) job: String,
+    /// How often the metrics are pushed
+    #[serde(with = "humantime_serde", default = "pushgateway_default_interval")]
+    #[schemars(with = "String")]
+    pub(crate) interval: Duration,
+}
+
+fn pushgateway_default_job() -> String {
+    "apollo-router".to_string()
+}
+
+fn pushgateway_default_interval() -> Duration {
+    Duration::from_secs(15)
 }
 
 fn prometheus_default_listen_addr() -> ListenAddr {
@@ -56,6 +97,8 @@ impl Default for Config {
             enabled: true,
             listen: prometheus_default_listen_addr(),
             path: prometheus_default_path(),
+            bearer_token: None,
+            pushgateway: None,
         }
     }
 }
@@ -124,10 +167,20 @@ impl MetricsConfigurator for Config {
                     self.path.clone(),
                     PrometheusService {
                         registry: exporter.registry().clone(),
+                        bearer_token: self.bearer_token.clone().map(Arc::from),
                     }
                     .boxed(),
-                ),
+                )
+                .in_group(RouteGroup::Metrics),
             );
+            if let Some(pushgateway) = &self.pushgateway {
+                builder = builder.with_pushgateway(pushgateway.spawn(exporter.registry().clone())?);
+                tracing::info!(
+                    "Prometheus metrics pushed to {} every {:?}",
+                    pushgateway.url,
+                    pushgateway.interval
+                );
+            }
             builder = builder.with_meter_provider(exporter.meter_provider()?);
             builder = builder.with_exporter(exporter);
             tracing::info!(
@@ -140,9 +193,13 @@ impl MetricsConfigurator for Config {
     }
 }
 
+/// The content type of the Prometheus text exposition format
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
 #[derive(Clone)]
 pub(crate) struct PrometheusService {
     registry: Registry,
+    bearer_token: Option<Arc<str>>,
 }
 
 impl Service<router::Request> for PrometheusService {
@@ -155,19 +212,448 @@ impl Service<router::Request> for PrometheusService {
     }
 
     fn call(&mut self, req: router::Request) -> Self::Future {
-        let metric_families = self.registry.gather();
+        let method = req.router_request.method().clone();
+        if method != Method::GET && method != Method::HEAD {
+            return Box::pin(async move {
+                Ok(router::Response {
+                    response: http::Response::builder()
+                        .status(StatusCode::METHOD_NOT_ALLOWED)
+                        .header(http::header::ALLOW, "GET, HEAD")
+                        .body::<hyper::Body>(hyper::Body::empty())
+                        .map_err(BoxError::from)?,
+                    context: req.context,
+                })
+            });
+        }
+        if !is_authorized(req.router_request.headers(), self.bearer_token.as_deref()) {
+            return Box::pin(async move {
+                Ok(router::Response {
+                    response: http::Response::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .header(http::header::WWW_AUTHENTICATE, "Bearer")
+                        .body::<hyper::Body>(hyper::Body::empty())
+                        .map_err(BoxError::from)?,
+                    context: req.context,
+                })
+            });
+        }
+        // HEAD requests get the headers of a scrape, without gathering the metrics
+        if method == Method::HEAD {
+            return Box::pin(async move {
+                Ok(router::Response {
+                    response: http::Response::builder()
+                        .status(StatusCode::OK)
+                        .header(http::header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)
+                        .body::<hyper::Body>(hyper::Body::empty())
+                        .map_err(BoxError::from)?,
+                    context: req.context,
+                })
+            });
+        }
+        let metric_families = gather(&self.registry);
         Box::pin(async move {
-            let encoder = TextEncoder::new();
-            let mut result = Vec::new();
-            encoder.encode(&metric_families, &mut result)?;
             Ok(router::Response {
                 response: http::Response::builder()
                     .status(StatusCode::OK)
-                    .header(http::header::CONTENT_TYPE, "text/plain; version=0.0.4")
-                    .body::<hyper::Body>(result.into())
+                    .header(http::header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)
+                    .body::<hyper::Body>(hyper::Body::wrap_stream(encode_chunks(metric_families)))
                     .map_err(BoxError::from)?,
                 context: req.context,
             })
         })
     }
 }
+
+/// Gathers the metrics of the registry, with their labels sanitized
+///
+/// The number of sanitized labels is recorded as a gauge, set on every gather: the same
+/// labels are sanitized again on each scrape, so a counter would grow with the scrapes.
+fn gather(registry: &Registry) -> Vec<MetricFamily> {
+    let mut metric_families = registry.gather();
+    let sanitized = sanitize_labels(&mut metric_families);
+    tracing::info!(value.apollo_router_prometheus_sanitized_labels = sanitized);
+    metric_families
+}
+
+/// Keeps pushing the metrics to the Pushgateway until it is shut down or dropped, which
+/// triggers a last push
+pub(crate) struct PushgatewayHandle {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl PushgatewayHandle {
+    /// Pushes the metrics one last time, and waits for the push to complete
+    pub(crate) async fn shutdown(self) {
+        drop(self.shutdown);
+        if let Err(err) = self.task.await {
+            tracing::error!("failed to push the metrics to the Pushgateway on shutdown: {err}");
+        }
+    }
+}
+
+impl Pushgateway {
+    /// The URL the metrics of the job are pushed to, in the format documented at
+    /// <https://github.com/prometheus/pushgateway#url>
+    fn push_url(&self) -> Result<Url, BoxError> {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .map_err(|_| format!("invalid Pushgateway URL: {}", self.url))?
+            .pop_if_empty()
+            .extend(["metrics", "job", self.job.as_str()]);
+        Ok(url)
+    }
+
+    fn spawn(&self, registry: Registry) -> Result<PushgatewayHandle, BoxError> {
+        let url = self.push_url()?;
+        let period = self.interval;
+        let (shutdown, mut shutdown_received) = oneshot::channel::<()>();
+        let task = tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => push(&client, &url, &registry).await,
+                    // the handle was shut down or dropped: the router is shutting down or reloading
+                    _ = &mut shutdown_received => {
+                        push(&client, &url, &registry).await;
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(PushgatewayHandle { shutdown, task })
+    }
+}
+
+async fn push(client: &reqwest::Client, url: &Url, registry: &Registry) {
+    let mut body = Vec::new();
+    if let Err(err) = TextEncoder::new().encode(&gather(registry), &mut body) {
+        tracing::error!("failed to encode the metrics pushed to the Pushgateway: {err}");
+        return;
+    }
+    let result = client
+        .post(url.clone())
+        .header(http::header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)
+        .body(body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(err) = result {
+        tracing::warn!("failed to push the metrics to the Pushgateway: {err}");
+    }
+}
+
+/// Label values longer than this, in bytes, are truncated
+const MAX_LABEL_VALUE_LENGTH: usize = 1024;
+
+/// Replaces the characters that are not allowed in label names by `_`, and the control
+/// characters, like newlines, in label values by `_`, truncating the long label values.
+/// Label values can come from request headers: this keeps them from making the output
+/// unreadable by scrapers.
+///
+/// Returns the number of labels that were changed.
+fn sanitize_labels(metric_families: &mut [MetricFamily]) -> u64 {
+    let mut sanitized = 0;
+    for metric_family in metric_families {
+        for metric in metric_family.mut_metric().iter_mut() {
+            for label in metric.mut_label().iter_mut() {
+                let name = sanitize_label_name(label.get_name());
+                let value = sanitize_label_value(label.get_value());
+                if name.is_none() && value.is_none() {
+                    continue;
+                }
+                sanitized += 1;
+                if let Some(name) = name {
+                    label.set_name(name);
+                }
+                if let Some(value) = value {
+                    label.set_value(value);
+                }
+            }
+        }
+    }
+    sanitized
+}
+
+/// Label names must match `[a-zA-Z_][a-zA-Z0-9_]*`
+fn sanitize_label_name(name: &str) -> Option<String> {
+    let is_valid = |(i, c): (usize, char)| {
+        c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())
+    };
+    if !name.is_empty() && name.chars().enumerate().all(is_valid) {
+        return None;
+    }
+    let name: String = name
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if is_valid((i, c)) { c } else { '_' })
+        .collect();
+    Some(if name.is_empty() {
+        "_".to_string()
+    } else {
+        name
+    })
+}
+
+fn sanitize_label_value(value: &str) -> Option<String> {
+    if value.len() <= MAX_LABEL_VALUE_LENGTH && !value.contains(char::is_control) {
+        return None;
+    }
+    let mut end = value.len().min(MAX_LABEL_VALUE_LENGTH);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(value[..end].replace(char::is_control, "_"))
+}
+
+/// Size above which the encoded metrics are sent as a body chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Encodes the metric families as they are sent, so that large registries are not
+/// held in memory both as gathered metrics and as a fully encoded body
+///
+/// The gathered metric families are still all in memory until they are encoded:
+/// `Registry::gather` builds every `MetricFamily` at once, and the `prometheus` crate has no
+/// way to collect them one by one. What is avoided is the copy of the whole encoded text, and
+/// its copy into a `String`: the text is only held a chunk of about [`CHUNK_SIZE`] at a time.
+fn encode_chunks(
+    metric_families: Vec<MetricFamily>,
+) -> impl Stream<Item = Result<Bytes, BoxError>> + Send {
+    let encoder = TextEncoder::new();
+    let mut metric_families = metric_families.into_iter().peekable();
+    stream::iter(std::iter::from_fn(move || {
+        metric_families.peek()?;
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        while chunk.len() < CHUNK_SIZE {
+            match metric_families.next() {
+                Some(metric_family) => {
+                    if let Err(err) = encoder.encode(&[metric_family], &mut chunk) {
+                        return Some(Err(err.into()));
+                    }
+                }
+                None => break,
+            }
+        }
+        Some(Ok(Bytes::from(chunk)))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use prometheus::IntCounterVec;
+    use prometheus::Opts;
+
+    use super::*;
+
+    async fn scrape(bearer_token: Option<&str>, authorization: Option<&str>) -> StatusCode {
+        let mut service = PrometheusService {
+            registry: Registry::new(),
+            bearer_token: bearer_token.map(Arc::from),
+        };
+        let mut request = http::Request::get("http://localhost:9090/metrics");
+        if let Some(authorization) = authorization {
+            request = request.header(http::header::AUTHORIZATION, authorization);
+        }
+        let request = router::Request::from(request.body(hyper::Body::empty()).unwrap());
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        response.response.status()
+    }
+
+    #[tokio::test]
+    async fn it_checks_the_bearer_token() {
+        assert_eq!(scrape(None, None).await, StatusCode::OK);
+        assert_eq!(
+            scrape(Some("scraper-token"), Some("Bearer scraper-token")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            scrape(Some("scraper-token"), Some("Bearer other-token")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            scrape(Some("scraper-token"), Some("Basic c2NyYXBlcjp0b2tlbg==")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            scrape(Some("scraper-token"), None).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn it_only_answers_get_and_head_requests() {
+        let registry = Registry::new();
+        let counter =
+            IntCounterVec::new(Opts::new("requests", "a test counter"), &["client"]).unwrap();
+        counter.with_label_values(&["test"]).inc();
+        registry.register(Box::new(counter)).unwrap();
+        let mut service = PrometheusService {
+            registry,
+            bearer_token: None,
+        };
+
+        let request = router::Request::from(
+            http::Request::head("http://localhost:9090/metrics")
+                .body(hyper::Body::empty())
+                .unwrap(),
+        );
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+        assert_eq!(
+            response.response.headers()[http::header::CONTENT_TYPE],
+            PROMETHEUS_CONTENT_TYPE
+        );
+        let body = hyper::body::to_bytes(response.response.into_body())
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+
+        let request = router::Request::from(
+            http::Request::post("http://localhost:9090/metrics")
+                .body(hyper::Body::empty())
+                .unwrap(),
+        );
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            response.response.headers()[http::header::ALLOW],
+            "GET, HEAD"
+        );
+    }
+
+    #[test]
+    fn it_sanitizes_label_names_and_values() {
+        let registry = Registry::new();
+        let counter =
+            IntCounterVec::new(Opts::new("requests", "a test counter"), &["client"]).unwrap();
+        counter.with_label_values(&["my\nclient\"} 1"]).inc();
+        counter.with_label_values(&["valid"]).inc();
+        counter.with_label_values(&[&"a".repeat(2000)]).inc();
+        registry.register(Box::new(counter)).unwrap();
+
+        let mut metric_families = registry.gather();
+        // the registry validates the names, but the exporter can add other labels
+        for metric in metric_families[0].mut_metric().iter_mut() {
+            let label = &mut metric.mut_label()[0];
+            if label.get_value().starts_with("my") {
+                label.set_name("client-name".to_string());
+            }
+        }
+        assert_eq!(sanitize_labels(&mut metric_families), 2);
+
+        let mut output = Vec::new();
+        TextEncoder::new()
+            .encode(&metric_families, &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(lines.len(), 3, "{output}");
+        assert!(output.contains(&format!("requests{{client=\"{}\"}} 1", "a".repeat(1024))));
+        assert!(output.contains("requests{client_name=\"my_client\\\"} 1\"} 1"));
+        assert!(output.contains("requests{client=\"valid\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn it_streams_the_same_output_as_a_single_encoding() {
+        let registry = Registry::new();
+        for i in 0..100 {
+            let counter = IntCounterVec::new(
+                Opts::new(format!("counter_{i}"), "a test counter"),
+                &["label"],
+            )
+            .unwrap();
+            for j in 0..500 {
+                counter.with_label_values(&[&format!("value_{j}")]).inc();
+            }
+            registry.register(Box::new(counter)).unwrap();
+        }
+
+        let mut expected = Vec::new();
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut expected)
+            .unwrap();
+
+        let chunks = encode_chunks(registry.gather())
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), expected);
+    }
+
+    #[test]
+    fn it_builds_the_push_url_of_the_job() {
+        let pushgateway = |url: &str, job: &str| Pushgateway {
+            url: Url::parse(url).unwrap(),
+            job: job.to_string(),
+            interval: pushgateway_default_interval(),
+        };
+        assert_eq!(
+            pushgateway("http://pushgateway:9091", "apollo-router")
+                .push_url()
+                .unwrap()
+                .as_str(),
+            "http://pushgateway:9091/metrics/job/apollo-router"
+        );
+        assert_eq!(
+            pushgateway("http://gateway/prometheus/", "nightly batch")
+                .push_url()
+                .unwrap()
+                .as_str(),
+            "http://gateway/prometheus/metrics/job/nightly%20batch"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_pushes_the_metrics_at_the_configured_interval() {
+        let (pushes_sender, mut pushes) = tokio::sync::mpsc::unbounded_channel();
+        let gateway = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(
+            axum::Router::new()
+                .route(
+                    "/metrics/job/:job",
+                    axum::routing::post(
+                        move |axum::extract::Path(job): axum::extract::Path<String>,
+                              body: String| {
+                            let pushes_sender = pushes_sender.clone();
+                            async move {
+                                pushes_sender.send((job, body)).unwrap();
+                            }
+                        },
+                    ),
+                )
+                .into_make_service(),
+        );
+        let url = Url::parse(&format!("http://{}/", gateway.local_addr())).unwrap();
+        tokio::spawn(gateway);
+
+        let registry = Registry::new();
+        let counter =
+            IntCounterVec::new(Opts::new("requests", "a test counter"), &["client"]).unwrap();
+        counter.with_label_values(&["test"]).inc();
+        registry.register(Box::new(counter)).unwrap();
+
+        let start = tokio::time::Instant::now();
+        let handle = Pushgateway {
+            url,
+            job: "batch".to_string(),
+            interval: Duration::from_millis(100),
+        }
+        .spawn(registry)
+        .unwrap();
+        for _ in 0..2 {
+            let (job, body) = pushes.recv().await.unwrap();
+            assert_eq!(job, "batch");
+            assert!(body.contains(r#"requests{client="test"} 1"#));
+        }
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // shutting the handle down pushes the metrics one last time, before returning
+        handle.shutdown().await;
+        let (job, _) = pushes.try_recv().unwrap();
+        assert_eq!(job, "batch");
+    }
+}
@@ -37,6 +37,7 @@ use opentelemetry::trace::TraceFlags;
 use opentelemetry::trace::TraceState;
 use opentelemetry::trace::TracerProvider;
 use opentelemetry::KeyValue;
+use parking_lot::Mutex;
 use rand::Rng;
 use router_bridge::planner::UsageReporting;
 use serde_json_bytes::json;
@@ -68,6 +69,7 @@ use self::reload::reload_metrics;
 use self::reload::NullFieldFormatter;
 use self::reload::OPENTELEMETRY_TRACER_HANDLE;
 use self::tracing::reload::ReloadTracer;
+use crate::axum_factory::utils::UntrustedPeer;
 use crate::layers::ServiceBuilderExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
@@ -86,7 +88,9 @@ use crate::plugins::telemetry::metrics::apollo::studio::SingleQueryLatencyStats;
 use crate::plugins::telemetry::metrics::apollo::studio::SingleStats;
 use crate::plugins::telemetry::metrics::apollo::studio::SingleStatsReport;
 use crate::plugins::telemetry::metrics::layer::MetricsLayer;
+use crate::plugins::telemetry::metrics::prometheus::PushgatewayHandle;
 use crate::plugins::telemetry::metrics::BasicMetrics;
+use crate::plugins::telemetry::metrics::BoundedValues;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
 use crate::plugins::telemetry::metrics::MetricsExporterHandle;
@@ -134,6 +138,8 @@ pub(crate) const LOGGING_DISPLAY_BODY: &str = "apollo_telemetry::logging::displa
 const DEFAULT_SERVICE_NAME: &str = "apollo-router";
 const GLOBAL_TRACER_NAME: &str = "apollo-router";
 const DEFAULT_EXPOSE_TRACE_ID_HEADER: &str = "apollo-trace-id";
+/// How long the router waits for the last push of the metrics when it shuts down
+const METRICS_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[doc(hidden)] // Only public for integration tests
 pub(crate) struct Telemetry {
@@ -143,9 +149,13 @@ pub(crate) struct Telemetry {
     // Typically the handles are a PushController but may be something else. Dropping the handle will
     // shutdown exporter.
     _metrics_exporters: Vec<MetricsExporterHandle>,
+    /// Taken on shutdown, to push the metrics one last time
+    pushgateway: Mutex<Option<PushgatewayHandle>>,
     custom_endpoints: MultiMap<ListenAddr, Endpoint>,
     apollo_metrics_sender: apollo_exporter::Sender,
     field_level_instrumentation_ratio: f64,
+    /// the operation names used as metric labels, when enabled
+    operation_names: Option<Arc<BoundedValues>>,
 
     tracer_provider: Option<opentelemetry::sdk::trace::TracerProvider>,
     meter_provider: AggregateMeterProvider,
@@ -219,9 +229,17 @@ impl Plugin for Telemetry {
         Ok(Telemetry {
             custom_endpoints: metrics_builder.custom_endpoints(),
             _metrics_exporters: metrics_builder.exporters(),
+            pushgateway: Mutex::new(metrics_builder.pushgateway()),
             metrics: BasicMetrics::new(&meter_provider),
             apollo_metrics_sender: metrics_builder.apollo_metrics_provider(),
             field_level_instrumentation_ratio,
+            operation_names: config
+                .metrics
+                .as_ref()
+                .and_then(|m| m.common.as_ref())
+                .map(|c| &c.operation_name_label)
+                .filter(|label| label.enabled)
+                .map(|label| Arc::new(BoundedValues::new(label.max_distinct))),
             tracer_provider: Some(Self::create_tracer_provider(&config)?),
             meter_provider,
             config: Arc::new(config),
@@ -239,15 +257,8 @@ impl Plugin for Telemetry {
                     .map(|t| t.to_string())
                     .unwrap_or_default();
                 let router_request = &request.router_request;
-                let headers = router_request.headers();
-                let client_name = headers
-                    .get(&apollo.client_name_header)
-                    .cloned()
-                    .unwrap_or_else(|| HeaderValue::from_static(""));
-                let client_version = headers
-                    .get(&apollo.client_version_header)
-                    .cloned()
-                    .unwrap_or_else(|| HeaderValue::from_static(""));
+                let client_name = client_header(router_request, &apollo.client_name_header);
+                let client_version = client_header(router_request, &apollo.client_version_header);
                 let span = ::tracing::info_span!(ROUTER_SPAN_NAME,
                     "http.method" = %router_request.method(),
                     "http.route" = %router_request.uri(),
@@ -310,6 +321,7 @@ impl Plugin for Telemetry {
         let config_map_res_first = config.clone();
         let config_map_res = config.clone();
         let field_level_instrumentation_ratio = self.field_level_instrumentation_ratio;
+        let operation_names = self.operation_names.clone();
         ServiceBuilder::new()
             .instrument(Self::supergraph_service_span(
                 self.field_level_instrumentation_ratio,
@@ -355,7 +367,12 @@ impl Plugin for Telemetry {
             })
             .map_future_with_request_data(
                 move |req: &SupergraphRequest| {
-                    Self::populate_context(config.clone(), field_level_instrumentation_ratio, req);
+                    Self::populate_context(
+                        config.clone(),
+                        field_level_instrumentation_ratio,
+                        operation_names.as_deref(),
+                        req,
+                    );
                     req.context.clone()
                 },
                 move |ctx: Context, fut| {
@@ -484,6 +501,22 @@ impl Plugin for Telemetry {
 }
 
 impl Telemetry {
+    /// Pushes the metrics to the Pushgateway one last time, waiting for the push for at most
+    /// [`METRICS_SHUTDOWN_TIMEOUT`]
+    pub(crate) async fn shutdown(&self) {
+        let pushgateway = self.pushgateway.lock().take();
+        if let Some(pushgateway) = pushgateway {
+            if tokio::time::timeout(METRICS_SHUTDOWN_TIMEOUT, pushgateway.shutdown())
+                .await
+                .is_err()
+            {
+                ::tracing::warn!(
+                    "the last push of the metrics did not complete in {METRICS_SHUTDOWN_TIMEOUT:?}"
+                );
+            }
+        }
+    }
+
     pub(crate) fn activate(&mut self) {
         // Only apply things if we were executing in the context of a vanilla the Apollo executable.
         // Users that are rolling their own routers will need to set up telemetry themselves.
@@ -818,30 +851,22 @@ impl Telemetry {
     fn populate_context(
         config: Arc<Conf>,
         field_level_instrumentation_ratio: f64,
+        operation_names: Option<&BoundedValues>,
         req: &SupergraphRequest,
     ) {
         let apollo_config = config.apollo.clone().unwrap_or_default();
         let context = &req.context;
         let http_request = &req.supergraph_request;
-        let headers = http_request.headers();
-        let client_name_header = &apollo_config.client_name_header;
-        let client_version_header = &apollo_config.client_version_header;
         let _ = context.insert(
             CLIENT_NAME,
-            headers
-                .get(client_name_header)
-                .cloned()
-                .unwrap_or_else(|| HeaderValue::from_static(""))
+            client_header(http_request, &apollo_config.client_name_header)
                 .to_str()
                 .unwrap_or_default()
                 .to_string(),
         );
         let _ = context.insert(
             CLIENT_VERSION,
-            headers
-                .get(client_version_header)
-                .cloned()
-                .unwrap_or_else(|| HeaderValue::from_static(""))
+            client_header(http_request, &apollo_config.client_version_header)
                 .to_str()
                 .unwrap_or_default()
                 .to_string(),
@@ -861,10 +886,13 @@ impl Telemetry {
         if let Some(metrics_conf) = &config.metrics {
             // List of custom attributes for metrics
             let mut attributes: HashMap<String, AttributeValue> = HashMap::new();
-            if let Some(operation_name) = &req.supergraph_request.body().operation_name {
+            if let (Some(operation_names), Some(operation_name)) = (
+                operation_names,
+                &req.supergraph_request.body().operation_name,
+            ) {
                 attributes.insert(
                     "operation_name".to_string(),
-                    AttributeValue::String(operation_name.clone()),
+                    AttributeValue::String(operation_names.bound(operation_name)),
                 );
             }
 
@@ -1382,6 +1410,19 @@ impl Telemetry {
     }
 }
 
+/// The value of a client identification header, empty if the request comes from
+/// a client outside of the trusted proxies
+fn client_header<B>(request: &http::Request<B>, name: &HeaderName) -> HeaderValue {
+    if request.extensions().get::<UntrustedPeer>().is_some() {
+        return HeaderValue::from_static("");
+    }
+    request
+        .headers()
+        .get(name)
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_static(""))
+}
+
 fn filter_headers(headers: &HeaderMap, forward_rules: &ForwardHeaders) -> String {
     let headers_map = headers
         .iter()
@@ -1539,6 +1580,15 @@ fn store_ftv1(subgraph_name: &ByteString, resp: SubgraphResponse) -> SubgraphRes
 
 /// CustomTraceIdPropagator to set custom trace_id for our tracing system
 /// coming from headers
+/// Injects the trace context in the headers of a request sent by the router, with the
+/// propagators configured in telemetry. With the W3C trace context propagator, the
+/// `tracestate` received from the client is sent along with the `traceparent`.
+pub(crate) fn inject_trace_context(context: &opentelemetry::Context, headers: &mut HeaderMap) {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(context, &mut opentelemetry_http::HeaderInjector(headers));
+    });
+}
+
 #[derive(Debug)]
 struct CustomTraceIdPropagator {
     header_name: String,
@@ -1553,7 +1603,13 @@ impl CustomTraceIdPropagator {
         }
     }
 
-    fn extract_span_context(&self, extractor: &dyn Extractor) -> Option<SpanContext> {
+    /// The trace state extracted by the previous propagators, like the `tracestate` header
+    /// of the trace context propagator, is kept.
+    fn extract_span_context(
+        &self,
+        extractor: &dyn Extractor,
+        trace_state: TraceState,
+    ) -> Option<SpanContext> {
         let trace_id = extractor.get(&self.header_name)?;
 
         // extract trace id
@@ -1570,7 +1626,7 @@ impl CustomTraceIdPropagator {
             SpanId::INVALID,
             TraceFlags::default().with_sampled(true),
             true,
-            TraceState::default(),
+            trace_state,
         )
         .into()
     }
@@ -1591,10 +1647,12 @@ impl TextMapPropagator for CustomTraceIdPropagator {
         cx: &opentelemetry::Context,
         extractor: &dyn Extractor,
     ) -> opentelemetry::Context {
-        cx.with_remote_span_context(
-            self.extract_span_context(extractor)
-                .unwrap_or_else(SpanContext::empty_context),
-        )
+        // without the header, the context extracted by the previous propagators is kept
+        let trace_state = cx.span().span_context().trace_state().clone();
+        match self.extract_span_context(extractor, trace_state) {
+            Some(span_context) => cx.with_remote_span_context(span_context),
+            None => cx.clone(),
+        }
     }
 
     fn fields(&self) -> FieldIter<'_> {
@@ -2098,6 +2156,89 @@ mod tests {
         assert_snapshot!(prom_metrics);
     }
 
+    #[tokio::test]
+    async fn it_bounds_the_operation_name_labels() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(5)
+            .returning(move |req: SupergraphRequest| {
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+
+        let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+            .find(|factory| factory.name == "apollo.telemetry")
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({
+                    "metrics": {
+                        "common": {
+                            "operation_name_label": { "enabled": true, "max_distinct": 2 }
+                        },
+                        "prometheus": { "enabled": true }
+                    }
+                }),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        let mut supergraph_service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+        for operation_name in ["A", "B", "C", "A", "D"] {
+            let request = SupergraphRequest::fake_builder()
+                .operation_name(operation_name)
+                .build()
+                .unwrap();
+            supergraph_service
+                .ready()
+                .await
+                .unwrap()
+                .call(request)
+                .await
+                .unwrap()
+                .next_response()
+                .await
+                .unwrap();
+        }
+
+        let web_endpoint = dyn_plugin
+            .web_endpoints()
+            .into_iter()
+            .next()
+            .unwrap()
+            .1
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_router();
+        let http_req_prom = http::Request::get("http://localhost:9090/metrics")
+            .body(Default::default())
+            .unwrap();
+        let mut resp = web_endpoint.oneshot(http_req_prom).await.unwrap();
+        let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+        let prom_metrics = String::from_utf8_lossy(&body).to_string();
+        let count = |operation_name: &str| {
+            let label = format!("operation_name=\"{operation_name}\"");
+            prom_metrics
+                .lines()
+                .find(|line| {
+                    line.starts_with("apollo_router_http_request_duration_seconds_count{")
+                        && line.contains(&label)
+                })
+                .and_then(|line| line.rsplit(' ').next())
+                .map(|count| count.to_string())
+        };
+
+        assert_eq!(count("A").as_deref(), Some("2"));
+        assert_eq!(count("B").as_deref(), Some("1"));
+        // the names seen once the limit was reached share a time series
+        assert_eq!(count("__other__").as_deref(), Some("2"));
+        assert_eq!(count("C"), None);
+        assert_eq!(count("D"), None);
+    }
+
     #[test]
     fn it_test_send_headers_to_studio() {
         let fw_headers = ForwardHeaders::Only(vec![
@@ -2201,4 +2342,111 @@ mod tests {
         .await;
         test_layer.assert_log_entry_count("other error", 2);
     }
+
+    #[test]
+    fn it_extracts_trace_context_regardless_of_header_case() {
+        use opentelemetry::propagation::Extractor;
+        use opentelemetry::propagation::TextMapPropagator;
+        use opentelemetry::trace::TraceContextExt;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_bytes(b"TraceParent").unwrap(),
+            HeaderValue::from_static("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+        );
+        headers.insert(
+            HeaderName::from_bytes(b"X-Custom-Trace-Id").unwrap(),
+            HeaderValue::from_static("0af7651916cd43dd8448eb211c80319c"),
+        );
+        let extractor = opentelemetry_http::HeaderExtractor(&headers);
+
+        assert_eq!(
+            extractor.keys().into_iter().sorted().collect::<Vec<_>>(),
+            vec!["traceparent", "x-custom-trace-id"]
+        );
+        assert!(extractor.get("TRACEPARENT").is_some());
+
+        let context =
+            opentelemetry::sdk::propagation::TraceContextPropagator::new().extract(&extractor);
+        assert!(context.span().span_context().is_valid());
+        assert_eq!(
+            context.span().span_context().trace_id().to_string(),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+
+        let context = super::CustomTraceIdPropagator::new("x-CUSTOM-trace-id".to_string())
+            .extract(&extractor);
+        assert_eq!(
+            context.span().span_context().trace_id().to_string(),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+    }
+
+    #[test]
+    fn it_forwards_the_trace_state() {
+        use opentelemetry::propagation::TextMapPropagator;
+        use opentelemetry::trace::TraceContextExt;
+
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let tracestate = "vendor1=opaque1,vendor2=opaque2";
+        let config: super::config::Conf = serde_json::from_value(serde_json::json!({
+            "tracing": {
+                "propagation": {
+                    "trace_context": true,
+                    "request": { "header_name": "x-custom-trace-id" }
+                }
+            }
+        }))
+        .unwrap();
+        let propagator = super::Telemetry::create_propagator(&config);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", HeaderValue::from_static(traceparent));
+        headers.insert("tracestate", HeaderValue::from_static(tracestate));
+        // the custom trace id propagator, missing its header, must keep the extracted context
+        let context = propagator.extract(&opentelemetry_http::HeaderExtractor(&headers));
+        assert_eq!(
+            context.span().span_context().trace_state().header(),
+            tracestate
+        );
+
+        let mut forwarded = HeaderMap::new();
+        propagator.inject_context(
+            &context,
+            &mut opentelemetry_http::HeaderInjector(&mut forwarded),
+        );
+        assert_eq!(forwarded.get("traceparent").unwrap(), traceparent);
+        assert_eq!(forwarded.get("tracestate").unwrap(), tracestate);
+
+        // the trace id of the custom header replaces the one of the trace parent, and the
+        // trace state is kept
+        headers.insert(
+            "x-custom-trace-id",
+            HeaderValue::from_static("4bf92f3577b34da6a3ce929d0e0e4736"),
+        );
+        let context = propagator.extract(&opentelemetry_http::HeaderExtractor(&headers));
+        assert_eq!(
+            context.span().span_context().trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(
+            context.span().span_context().trace_state().header(),
+            tracestate
+        );
+    }
+
+    #[test]
+    fn client_headers_of_untrusted_peers_are_ignored() {
+        let name = HeaderName::from_static("apollographql-client-name");
+        let mut request = http::Request::builder()
+            .header(&name, "web")
+            .body(())
+            .unwrap();
+        assert_eq!(super::client_header(&request, &name), "web");
+
+        request
+            .extensions_mut()
+            .insert(crate::axum_factory::utils::UntrustedPeer);
+        assert_eq!(super::client_header(&request, &name), "");
+    }
 }
@@ -103,7 +103,7 @@ pub(crate) enum Endpoint {
     Url(Url),
 }
 
-fn deser_endpoint<'de, D>(deserializer: D) -> Result<Endpoint, D::Error>
+pub(crate) fn deser_endpoint<'de, D>(deserializer: D) -> Result<Endpoint, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -599,6 +599,7 @@ mod test {
             &Configuration::default(),
         )
         .await
+        .unwrap()
         .make()
         .boxed()
     }
@@ -6,6 +6,8 @@ use std::fmt;
 use axum::response::IntoResponse;
 use http::StatusCode;
 
+use crate::axum_factory::utils::graphql_error_response;
+
 /// The rate limit error.
 #[derive(Debug, Default)]
 pub(crate) struct RateLimited;
@@ -25,7 +27,11 @@ impl fmt::Display for RateLimited {
 
 impl IntoResponse for RateLimited {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::TOO_MANY_REQUESTS, self.to_string()).into_response()
+        graphql_error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            self.to_string(),
+            "REQUEST_RATE_LIMITED",
+        )
     }
 }
 
@@ -6,6 +6,8 @@ use std::fmt;
 use axum::response::IntoResponse;
 use http::StatusCode;
 
+use crate::axum_factory::utils::graphql_error_response;
+
 /// The timeout elapsed.
 #[derive(Debug, Default)]
 pub(crate) struct Elapsed;
@@ -25,7 +27,11 @@ impl fmt::Display for Elapsed {
 
 impl IntoResponse for Elapsed {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::GATEWAY_TIMEOUT, self.to_string()).into_response()
+        graphql_error_response(
+            StatusCode::GATEWAY_TIMEOUT,
+            self.to_string(),
+            "REQUEST_TIMEOUT",
+        )
     }
 }
 
@@ -14,6 +14,7 @@ use super::QueryPlan;
 use crate::error::Error;
 use crate::graphql::Request;
 use crate::graphql::Response;
+use crate::json_ext::MergeConflicts;
 use crate::json_ext::Path;
 use crate::json_ext::Value;
 use crate::json_ext::ValueExt;
@@ -42,6 +43,7 @@ impl QueryPlan {
         service_factory: &'a Arc<SubgraphServiceFactory>,
         supergraph_request: &'a Arc<http::Request<Request>>,
         schema: &'a Arc<Schema>,
+        merge_conflicts: MergeConflicts,
         sender: futures::channel::mpsc::Sender<Response>,
     ) -> Response {
         let root = Path::empty();
@@ -58,6 +60,7 @@ impl QueryPlan {
                     supergraph_request,
                     deferred_fetches: &deferred_fetches,
                     query: &self.query,
+                    merge_conflicts,
                 },
                 &root,
                 &Value::default(),
@@ -85,6 +88,7 @@ pub(crate) struct ExecutionParameters<'a> {
     pub(crate) supergraph_request: &'a Arc<http::Request<Request>>,
     pub(crate) deferred_fetches: &'a HashMap<String, Sender<(Value, Vec<Error>)>>,
     pub(crate) query: &'a Arc<Query>,
+    pub(crate) merge_conflicts: MergeConflicts,
 }
 
 impl PlanNode {
@@ -116,7 +120,7 @@ impl PlanNode {
                                 )
                                 .in_current_span()
                                 .await;
-                            value.deep_merge(v);
+                            value.deep_merge_with(v, parameters.merge_conflicts);
                             errors.extend(err.into_iter());
                             subselection = subselect;
                         }
@@ -146,7 +150,7 @@ impl PlanNode {
 
                         while let Some((v, _subselect, err)) = stream.next().in_current_span().await
                         {
-                            value.deep_merge(v);
+                            value.deep_merge_with(v, parameters.merge_conflicts);
                             errors.extend(err.into_iter());
                         }
                     }
@@ -249,6 +253,7 @@ impl PlanNode {
                                         supergraph_request: parameters.supergraph_request,
                                         deferred_fetches: &deferred_fetches,
                                         query: parameters.query,
+                                        merge_conflicts: parameters.merge_conflicts,
                                     },
                                     current_dir,
                                     &value,
@@ -259,7 +264,7 @@ impl PlanNode {
                                     "otel.kind" = "INTERNAL"
                                 ))
                                 .await;
-                            value.deep_merge(v);
+                            value.deep_merge_with(v, parameters.merge_conflicts);
                             errors.extend(err.into_iter());
                             subselection = primary_subselection.clone();
 
@@ -312,7 +317,7 @@ impl PlanNode {
                                         "otel.kind" = "INTERNAL"
                                     ))
                                     .await;
-                                value.deep_merge(v);
+                                value.deep_merge_with(v, parameters.merge_conflicts);
                                 errors.extend(err.into_iter());
                                 subselection = subselect;
                             }
@@ -329,7 +334,7 @@ impl PlanNode {
                                     "otel.kind" = "INTERNAL"
                                 ))
                                 .await;
-                            value.deep_merge(v);
+                            value.deep_merge_with(v, parameters.merge_conflicts);
                             errors.extend(err.into_iter());
                             subselection = subselect;
                         }
@@ -392,6 +397,7 @@ impl DeferredNode {
         let sf = parameters.service_factory.clone();
         let ctx = parameters.context.clone();
         let query = parameters.query.clone();
+        let merge_conflicts = parameters.merge_conflicts;
         let mut primary_receiver = primary_sender.subscribe();
         let mut value = parent_value.clone();
         let depends_json = serde_json::to_string(&self.depends).unwrap_or_default();
@@ -401,7 +407,7 @@ impl DeferredNode {
             if is_depends_empty {
                 let (primary_value, primary_errors) =
                     primary_receiver.recv().await.unwrap_or_default();
-                value.deep_merge(primary_value);
+                value.deep_merge_with(primary_value, merge_conflicts);
                 errors.extend(primary_errors.into_iter())
             } else {
                 while let Some((v, _remaining)) = stream.next().await {
@@ -410,7 +416,7 @@ impl DeferredNode {
                     // or because it is lagging, but here we only send one message so it
                     // will not happen
                     if let Some(Ok((deferred_value, err))) = v {
-                        value.deep_merge(deferred_value);
+                        value.deep_merge_with(deferred_value, merge_conflicts);
                         errors.extend(err.into_iter())
                     }
                 }
@@ -428,6 +434,7 @@ impl DeferredNode {
                             supergraph_request: &orig,
                             deferred_fetches: &deferred_fetches,
                             query: &query,
+                            merge_conflicts,
                         },
                         &Path::default(),
                         &value,
@@ -445,7 +452,7 @@ impl DeferredNode {
                 if !is_depends_empty {
                     let (primary_value, primary_errors) =
                         primary_receiver.recv().await.unwrap_or_default();
-                    v.deep_merge(primary_value);
+                    v.deep_merge_with(primary_value, merge_conflicts);
                     errors.extend(primary_errors.into_iter())
                 }
 
@@ -471,7 +478,7 @@ impl DeferredNode {
             } else {
                 let (primary_value, primary_errors) =
                     primary_receiver.recv().await.unwrap_or_default();
-                value.deep_merge(primary_value);
+                value.deep_merge_with(primary_value, merge_conflicts);
                 errors.extend(primary_errors.into_iter());
 
                 if let Err(e) = tx
@@ -3,6 +3,7 @@ use std::fmt::Display;
 use std::sync::Arc;
 
 use indexmap::IndexSet;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 use tower::ServiceExt;
@@ -27,7 +28,7 @@ use crate::services::SubgraphRequest;
 use crate::spec::Schema;
 
 /// GraphQL operation type.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 #[non_exhaustive]
 pub enum OperationKind {
@@ -403,7 +404,7 @@ impl FetchNode {
                 &current_dir.0[..]
             };
 
-            let errors: Vec<Error> = response
+            let mut errors: Vec<Error> = response
                 .errors
                 .into_iter()
                 .map(|error| {
@@ -421,7 +422,13 @@ impl FetchNode {
                 .collect();
             let mut data = response.data.unwrap_or_default();
             rewrites::apply_rewrites(schema, &mut data, &self.output_rewrites);
-            (Value::from_path(current_dir, data), errors)
+            match Value::from_path(current_dir, data) {
+                Ok(value) => (value, errors),
+                Err(err) => {
+                    errors.push(err.to_graphql_error(Some(current_dir.clone())));
+                    (Value::Null, errors)
+                }
+            }
         }
     }
 
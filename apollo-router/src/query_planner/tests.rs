@@ -107,6 +107,7 @@ async fn mock_subgraph_service_withf_panics_should_be_reported_as_service_closed
             &sf,
             &Default::default(),
             &Arc::new(Schema::parse_test(test_schema!(), &Default::default()).unwrap()),
+            Default::default(),
             sender,
         )
         .await;
@@ -164,6 +165,7 @@ async fn fetch_includes_operation_name() {
             &sf,
             &Default::default(),
             &Arc::new(Schema::parse_test(test_schema!(), &Default::default()).unwrap()),
+            Default::default(),
             sender,
         )
         .await;
@@ -218,6 +220,7 @@ async fn fetch_makes_post_requests() {
             &sf,
             &Default::default(),
             &Arc::new(Schema::parse_test(test_schema!(), &Default::default()).unwrap()),
+            Default::default(),
             sender,
         )
         .await;
@@ -452,6 +455,7 @@ async fn defer_if_condition() {
                     .unwrap(),
             ),
             &schema,
+            Default::default(),
             sender,
         )
         .await;
@@ -470,6 +474,7 @@ async fn defer_if_condition() {
             &service_factory,
             &Default::default(),
             &schema,
+            Default::default(),
             default_sender,
         )
         .await;
@@ -494,6 +499,7 @@ async fn defer_if_condition() {
                     .unwrap(),
             ),
             &schema,
+            Default::default(),
             sender,
         )
         .await;
@@ -611,6 +617,7 @@ async fn dependent_mutations() {
             &sf,
             &Default::default(),
             &Arc::new(Schema::parse_test(schema, &Default::default()).unwrap()),
+            Default::default(),
             sender,
         )
         .await;
@@ -1,11 +1,24 @@
+use std::cell::Cell;
+use std::fmt;
+
 use derivative::Derivative;
+use displaydoc::Display;
+use serde::de;
+use serde::de::DeserializeSeed;
+use serde::de::Deserializer;
+use serde::de::EnumAccess;
 use serde::de::Error;
+use serde::de::MapAccess;
+use serde::de::SeqAccess;
+use serde::de::VariantAccess;
+use serde::de::Visitor;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json_bytes::ByteString;
 use serde_json_bytes::Map as JsonMap;
 use serde_json_bytes::Value;
 
+use crate::configuration::Supergraph;
 use crate::json_ext::Object;
 
 /// A GraphQL `Request` used to represent both supergraph and subgraph requests.
@@ -171,12 +184,491 @@ impl Request {
     }
 }
 
+/// Same fields as [`Request`], used to reject unknown fields when parsing strictly
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct StrictRequest {
+    #[serde(default)]
+    query: Option<String>,
+    #[serde(default)]
+    operation_name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    variables: Object,
+    #[serde(default)]
+    extensions: Object,
+}
+
+impl From<StrictRequest> for Request {
+    fn from(request: StrictRequest) -> Self {
+        let StrictRequest {
+            query,
+            operation_name,
+            variables,
+            extensions,
+        } = request;
+
+        Request {
+            query,
+            operation_name,
+            variables,
+            extensions,
+        }
+    }
+}
+
+impl Request {
+    /// Deserialize a GraphQL [`Request`] from a JSON body, with an error naming
+    /// the first top-level field that is not part of a GraphQL request, if any.
+    pub(crate) fn from_slice_strict(
+        body: &[u8],
+        limits: JsonLimits,
+    ) -> Result<Request, JsonBodyError> {
+        limits.parse::<StrictRequest>(body).map(Request::from)
+    }
+
+    /// Deserialize a batch of GraphQL [`Request`]s from a JSON array, rejecting unknown
+    /// fields like [`Request::from_slice_strict`] does if `strict` is set.
+    pub(crate) fn batch_from_slice(
+        body: &[u8],
+        strict: bool,
+        limits: JsonLimits,
+    ) -> Result<Vec<Request>, JsonBodyError> {
+        let requests: Vec<Request> = if strict {
+            limits
+                .parse::<Vec<StrictRequest>>(body)?
+                .into_iter()
+                .map(Request::from)
+                .collect()
+        } else {
+            limits.parse(body)?
+        };
+        if requests.is_empty() {
+            return Err(JsonBodyError::Invalid(serde_json::Error::custom(
+                "the batch is empty",
+            )));
+        }
+        Ok(requests)
+    }
+}
+
+/// Bounds on the structure of the JSON bodies of requests, enforced while they are
+/// deserialized, so that adversarial bodies are rejected as soon as a limit is reached
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct JsonLimits {
+    /// see `Supergraph::max_request_depth`
+    pub(crate) max_depth: Option<usize>,
+    /// see `Supergraph::max_request_tokens`
+    pub(crate) max_tokens: Option<usize>,
+}
+
+impl From<&Supergraph> for JsonLimits {
+    fn from(supergraph: &Supergraph) -> Self {
+        Self {
+            max_depth: supergraph.max_request_depth,
+            max_tokens: supergraph.max_request_tokens,
+        }
+    }
+}
+
+/// Why a JSON request body could not be deserialized
+#[derive(Debug, Display)]
+pub(crate) enum JsonBodyError {
+    /// {0}
+    OverLimits(String),
+    /// {0}
+    Invalid(serde_json::Error),
+}
+
+impl JsonLimits {
+    /// Deserializes `body`, returning an error if it nests arrays and objects deeper than
+    /// `max_depth`, or has more than `max_tokens` object keys and values.
+    ///
+    /// The values are counted as the parser reads them: every occurrence of a duplicate key
+    /// counts, and so do the values of the fields that are ignored.
+    pub(crate) fn parse<'a, T: Deserialize<'a>>(&self, body: &'a [u8]) -> Result<T, JsonBodyError> {
+        let mut deserializer = serde_json::Deserializer::from_slice(body);
+        if self.max_depth.is_none() && self.max_tokens.is_none() {
+            return T::deserialize(&mut deserializer)
+                .and_then(|value| deserializer.end().map(|()| value))
+                .map_err(JsonBodyError::Invalid);
+        }
+
+        let budget = Budget {
+            limits: *self,
+            depth: Cell::new(0),
+            tokens: Cell::new(0),
+            exceeded: Cell::new(None),
+        };
+        T::deserialize(Limited {
+            inner: &mut deserializer,
+            budget: &budget,
+            counted: false,
+        })
+        .and_then(|value| deserializer.end().map(|()| value))
+        .map_err(|err| match budget.exceeded.take() {
+            Some(message) => JsonBodyError::OverLimits(message),
+            None => JsonBodyError::Invalid(err),
+        })
+    }
+}
+
+/// What is left of the [`JsonLimits`] of a body being deserialized
+struct Budget {
+    limits: JsonLimits,
+    depth: Cell<usize>,
+    tokens: Cell<usize>,
+    /// the limit that was reached, to tell it apart from a syntax error
+    exceeded: Cell<Option<String>>,
+}
+
+impl Budget {
+    fn token<E: de::Error>(&self) -> Result<(), E> {
+        let tokens = self.tokens.get() + 1;
+        self.tokens.set(tokens);
+        match self.limits.max_tokens {
+            Some(max_tokens) if tokens > max_tokens => Err(self.exceeded(format!(
+                "the request body has more than {max_tokens} JSON keys and values"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn enter<E: de::Error>(&self) -> Result<(), E> {
+        let depth = self.depth.get() + 1;
+        self.depth.set(depth);
+        match self.limits.max_depth {
+            Some(max_depth) if depth > max_depth => Err(self.exceeded(format!(
+                "the request body is nested deeper than {max_depth} levels"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn leave(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    fn exceeded<E: de::Error>(&self, message: String) -> E {
+        let error = E::custom(&message);
+        self.exceeded.set(Some(message));
+        error
+    }
+}
+
+/// A deserializer counting the values it reads against a [`Budget`]
+struct Limited<'b, D> {
+    inner: D,
+    budget: &'b Budget,
+    /// the value was already counted, like the content of an `Option`
+    counted: bool,
+}
+
+/// Wraps `visitor`, so that the nested arrays and objects are counted too
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($arg: $ty,)*
+                visitor: V,
+            ) -> Result<V::Value, D::Error> {
+                if !self.counted {
+                    self.budget.token::<D::Error>()?;
+                }
+                self.inner.$method($($arg,)* LimitedVisitor {
+                    inner: visitor,
+                    budget: self.budget,
+                })
+            }
+        )*
+    };
+}
+
+impl<'de, 'b, D: Deserializer<'de>> Deserializer<'de> for Limited<'b, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any()
+        deserialize_bool()
+        deserialize_i8()
+        deserialize_i16()
+        deserialize_i32()
+        deserialize_i64()
+        deserialize_i128()
+        deserialize_u8()
+        deserialize_u16()
+        deserialize_u32()
+        deserialize_u64()
+        deserialize_u128()
+        deserialize_f32()
+        deserialize_f64()
+        deserialize_char()
+        deserialize_str()
+        deserialize_string()
+        deserialize_bytes()
+        deserialize_byte_buf()
+        deserialize_option()
+        deserialize_unit()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_seq()
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_map()
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier()
+    }
+
+    // the JSON parser skips ignored values without visiting them: they are read
+    // like any other value so that they are counted
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+struct LimitedVisitor<'b, V> {
+    inner: V,
+    budget: &'b Budget,
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty))*) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> Result<Self::Value, E> {
+                self.inner.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, 'b, V: Visitor<'de>> Visitor<'de> for LimitedVisitor<'b, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(formatter)
+    }
+
+    forward_visit! {
+        visit_bool(bool)
+        visit_i8(i8)
+        visit_i16(i16)
+        visit_i32(i32)
+        visit_i64(i64)
+        visit_i128(i128)
+        visit_u8(u8)
+        visit_u16(u16)
+        visit_u32(u32)
+        visit_u64(u64)
+        visit_u128(u128)
+        visit_f32(f32)
+        visit_f64(f64)
+        visit_char(char)
+        visit_str(&str)
+        visit_borrowed_str(&'de str)
+        visit_string(String)
+        visit_bytes(&[u8])
+        visit_borrowed_bytes(&'de [u8])
+        visit_byte_buf(Vec<u8>)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.inner.visit_some(Limited {
+            inner: deserializer,
+            budget: self.budget,
+            counted: true,
+        })
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        self.inner.visit_newtype_struct(Limited {
+            inner: deserializer,
+            budget: self.budget,
+            counted: true,
+        })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.budget.enter::<A::Error>()?;
+        let value = self.inner.visit_seq(LimitedAccess {
+            inner: seq,
+            budget: self.budget,
+        })?;
+        self.budget.leave();
+        Ok(value)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.budget.enter::<A::Error>()?;
+        let value = self.inner.visit_map(LimitedAccess {
+            inner: map,
+            budget: self.budget,
+        })?;
+        self.budget.leave();
+        Ok(value)
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        self.inner.visit_enum(LimitedAccess {
+            inner: data,
+            budget: self.budget,
+        })
+    }
+}
+
+/// Wraps the elements, keys, values and variants read from an array, object or enum
+struct LimitedAccess<'b, A> {
+    inner: A,
+    budget: &'b Budget,
+}
+
+impl<'b, A> LimitedAccess<'b, A> {
+    fn seed<T>(&self, seed: T) -> LimitedSeed<'b, T> {
+        LimitedSeed {
+            inner: seed,
+            budget: self.budget,
+        }
+    }
+}
+
+impl<'de, 'b, A: SeqAccess<'de>> SeqAccess<'de> for LimitedAccess<'b, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, A::Error> {
+        let seed = self.seed(seed);
+        self.inner.next_element_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, 'b, A: MapAccess<'de>> MapAccess<'de> for LimitedAccess<'b, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        let seed = self.seed(seed);
+        self.inner.next_key_seed(seed)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, A::Error> {
+        let seed = self.seed(seed);
+        self.inner.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, 'b, A: EnumAccess<'de>> EnumAccess<'de> for LimitedAccess<'b, A> {
+    type Error = A::Error;
+    type Variant = LimitedAccess<'b, A::Variant>;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<(T::Value, Self::Variant), A::Error> {
+        let budget = self.budget;
+        let seed = self.seed(seed);
+        let (value, variant) = self.inner.variant_seed(seed)?;
+        Ok((
+            value,
+            LimitedAccess {
+                inner: variant,
+                budget,
+            },
+        ))
+    }
+}
+
+impl<'de, 'b, A: VariantAccess<'de>> VariantAccess<'de> for LimitedAccess<'b, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        let seed = self.seed(seed);
+        self.inner.newtype_variant_seed(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        self.inner.tuple_variant(
+            len,
+            LimitedVisitor {
+                inner: visitor,
+                budget: self.budget,
+            },
+        )
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        self.inner.struct_variant(
+            fields,
+            LimitedVisitor {
+                inner: visitor,
+                budget: self.budget,
+            },
+        )
+    }
+}
+
+struct LimitedSeed<'b, T> {
+    inner: T,
+    budget: &'b Budget,
+}
+
+impl<'de, 'b, T: DeserializeSeed<'de>> DeserializeSeed<'de> for LimitedSeed<'b, T> {
+    type Value = T::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T::Value, D::Error> {
+        self.inner.deserialize(Limited {
+            inner: deserializer,
+            budget: self.budget,
+            counted: false,
+        })
+    }
+}
+
 fn get_from_urldecoded<'a, T: Deserialize<'a>>(
     object: &'a serde_json::Value,
     key: &str,
 ) -> Result<Option<T>, serde_json::Error> {
     if let Some(serde_json::Value::String(byte_string)) = object.get(key) {
-        Some(serde_json::from_str(byte_string.as_str())).transpose()
+        Some(
+            serde_json::from_str(byte_string.as_str()).map_err(|err| {
+                serde_json::Error::custom(format!("invalid '{key}' parameter: {err}"))
+            }),
+        )
+        .transpose()
     } else {
         Ok(None)
     }
@@ -309,4 +801,124 @@ mod tests {
 
         assert_eq!(expected_result, req);
     }
+
+    #[test]
+    fn from_urlencoded_query_with_invalid_variables() {
+        let query_string = "query=query%20aTest%28%24arg1%3A%20String%21%29%20%7B%20test%28who%3A%20%24arg1%29%20%7D&variables=%7B%22arg1%22%3A";
+
+        let error = Request::from_urlencoded_query(query_string.to_string()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid 'variables' parameter: EOF while parsing a value at line 1 column 8"
+        );
+    }
+
+    #[test]
+    fn test_strict_parsing_rejects_unknown_fields() {
+        let data = json!(
+        {
+          "quiery": "{ me }",
+          "variables": null
+        })
+        .to_string();
+
+        // lenient parsing ignores the typo
+        assert_eq!(
+            serde_json::from_str::<Request>(&data).unwrap(),
+            Request::builder().build()
+        );
+
+        let error = Request::from_slice_strict(data.as_bytes(), JsonLimits::default()).unwrap_err();
+        assert!(
+            error.to_string().starts_with("unknown field `quiery`"),
+            "{error}"
+        );
+
+        let data = json!(
+        {
+          "query": "{ me }",
+          "operationName": null,
+          "variables": null,
+          "extensions": {"extension": 1}
+        })
+        .to_string();
+        assert_eq!(
+            Request::from_slice_strict(data.as_bytes(), JsonLimits::default()).unwrap(),
+            Request::builder()
+                .query("{ me }")
+                .extensions(bjson!({"extension": 1}).as_object().cloned().unwrap())
+                .build()
+        );
+    }
+
+    fn over_limits(
+        max_depth: Option<usize>,
+        max_tokens: Option<usize>,
+        body: &[u8],
+    ) -> Result<Request, String> {
+        match (JsonLimits {
+            max_depth,
+            max_tokens,
+        })
+        .parse::<Request>(body)
+        {
+            Ok(request) => Ok(request),
+            Err(JsonBodyError::OverLimits(error)) => Err(error),
+            Err(JsonBodyError::Invalid(error)) => panic!("unexpected error: {error}"),
+        }
+    }
+
+    #[test]
+    fn json_limits_bound_the_structure_of_the_body() {
+        let body = br#"{"query":"{ me }","variables":{"a":[1,true,null],"b":"[{\"]"}}"#;
+        // the request, `query`, its value, `variables`, its object, `a`, its array and its
+        // three elements, `b` and its string
+        assert!(over_limits(Some(3), Some(12), body).is_ok());
+        assert_eq!(
+            over_limits(Some(2), None, body).unwrap_err(),
+            "the request body is nested deeper than 2 levels"
+        );
+        assert_eq!(
+            over_limits(None, Some(11), body).unwrap_err(),
+            "the request body has more than 11 JSON keys and values"
+        );
+
+        // every occurrence of a duplicate key counts, even if only the last one is kept
+        let body = br#"{"query":"{ me }","variables":{"a":1,"a":2,"a":3}}"#;
+        assert!(over_limits(None, Some(11), body).is_ok());
+        assert!(over_limits(None, Some(10), body).is_err());
+
+        // a number is a single value, however large it is
+        let body = br#"{"query":"{ me }","variables":{"a":123456789012345678901234567890}}"#;
+        assert!(over_limits(None, Some(7), body).is_ok());
+
+        // the fields that are ignored are counted too
+        let body = br#"{"query":"{ me }","unknown":[[[[1]]]]}"#;
+        assert_eq!(
+            over_limits(Some(3), None, body).unwrap_err(),
+            "the request body is nested deeper than 3 levels"
+        );
+
+        let nested = format!(
+            r#"{{"query":"{{ me }}","variables":{{"a":{}{}}}}}"#,
+            "[".repeat(100_000),
+            "]".repeat(100_000)
+        );
+        assert!(over_limits(Some(32), None, nested.as_bytes()).is_err());
+        // without limits, the parser still rejects it
+        assert!(matches!(
+            JsonLimits::default().parse::<Request>(nested.as_bytes()),
+            Err(JsonBodyError::Invalid(_))
+        ));
+
+        // syntax errors are not reported as limits
+        assert!(matches!(
+            JsonLimits {
+                max_depth: Some(32),
+                max_tokens: Some(100),
+            }
+            .parse::<Request>(br#"{"query":"#),
+            Err(JsonBodyError::Invalid(_))
+        ));
+    }
 }
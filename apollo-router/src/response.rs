@@ -88,6 +88,7 @@ impl Response {
     /// Create a [`Response`] from the supplied [`Bytes`].
     ///
     /// This will return an error (identifying the faulty service) if the input is invalid.
+    /// Integers fitting in 64 bits are kept exactly, other numbers are parsed as `f64`.
     pub(crate) fn from_bytes(service_name: &str, b: Bytes) -> Result<Response, FetchError> {
         let value =
             Value::from_bytes(b).map_err(|error| FetchError::SubrequestMalformedResponse {
@@ -436,4 +437,12 @@ mod tests {
                 .build()
         );
     }
+
+    #[test]
+    fn it_preserves_64_bit_integers() {
+        // over 2^53, the integers that a f64 cannot represent exactly
+        let body = r#"{"data":{"id":9007199254740993,"max":18446744073709551615,"min":-9223372036854775808}}"#;
+        let response = Response::from_bytes("test", Bytes::from_static(body.as_bytes())).unwrap();
+        assert_eq!(serde_json::to_string(&response).unwrap(), body);
+    }
 }
@@ -1,9 +1,13 @@
+use std::collections::HashMap;
 use std::io;
 // With regards to ELv2 licensing, this entire file is license key functionality
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::response::IntoResponse;
+use futures::future::BoxFuture;
 use http::StatusCode;
+use http::Uri;
 use multimap::MultiMap;
 use once_cell::sync::Lazy;
 use rustls::RootCertStore;
@@ -18,10 +22,13 @@ use tower_service::Service;
 
 use crate::configuration::Configuration;
 use crate::configuration::ConfigurationError;
+use crate::configuration::RouteGroup;
 use crate::configuration::TlsSubgraph;
 use crate::plugin::DynPlugin;
 use crate::plugin::Handler;
+use crate::plugin::HealthProbe;
 use crate::plugin::PluginFactory;
+use crate::plugins::traffic_shaping::Elapsed;
 use crate::plugins::traffic_shaping::TrafficShaping;
 use crate::plugins::traffic_shaping::APOLLO_TRAFFIC_SHAPING;
 use crate::query_planner::BridgeQueryPlanner;
@@ -39,6 +46,10 @@ use crate::ListenAddr;
 /// A path and a handler to be exposed as a web_endpoint for plugins
 pub struct Endpoint {
     pub(crate) path: String,
+    /// The group of routes served by the router itself this endpoint is part of
+    pub(crate) group: Option<RouteGroup>,
+    /// Set from the timeout of the group when the server is created
+    pub(crate) timeout: Option<Duration>,
     // Plugins need to be Send + Sync
     // BoxCloneService isn't enough
     handler: Handler,
@@ -64,6 +75,8 @@ impl Endpoint {
             .boxed();
         Self {
             path,
+            group: None,
+            timeout: None,
             handler: Handler::new(router_service),
         }
     }
@@ -72,16 +85,32 @@ impl Endpoint {
     pub fn from_router_service(path: String, handler: router::BoxService) -> Self {
         Self {
             path,
+            group: None,
+            timeout: None,
             handler: Handler::new(handler),
         }
     }
+
+    /// Marks an endpoint served by the router itself, to apply the timeout of its group
+    pub(crate) fn in_group(mut self, group: RouteGroup) -> Self {
+        self.group = Some(group);
+        self
+    }
+
     pub(crate) fn into_router(self) -> axum::Router {
+        let timeout = self.timeout;
         let handler = move |req: http::Request<hyper::Body>| {
             let endpoint = self.handler.clone();
             async move {
-                Ok(endpoint
-                    .oneshot(req.into())
-                    .await
+                let response = endpoint.oneshot(req.into());
+                let response = match timeout {
+                    Some(timeout) => match tokio::time::timeout(timeout, response).await {
+                        Ok(response) => response,
+                        Err(_) => return Ok(Elapsed::new().into_response()),
+                    },
+                    None => response.await,
+                };
+                Ok(response
                     .map(|res| res.response)
                     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
                     .into_response())
@@ -106,6 +135,26 @@ pub(crate) trait RouterFactory:
     type Future: Send;
 
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint>;
+
+    /// Hash of the supergraph schema served by the routers this factory creates
+    fn schema_id(&self) -> Option<String> {
+        None
+    }
+
+    /// URLs of the subgraphs declared in the supergraph schema, by subgraph name
+    fn subgraph_urls(&self) -> HashMap<String, Uri> {
+        HashMap::new()
+    }
+
+    /// Probes of the plugins, run by the health check endpoint
+    fn health_probes(&self) -> Vec<HealthProbe> {
+        Vec::new()
+    }
+
+    /// Flushes the telemetry of the plugins, when the router shuts down
+    fn shutdown(&self) -> BoxFuture<'static, ()> {
+        Box::pin(async {})
+    }
 }
 
 /// Factory for creating a RouterFactory
@@ -226,7 +275,7 @@ impl RouterSuperServiceFactory for YamlRouterFactory {
             }
         }
 
-        Ok(Self::RouterFactory::new(Arc::new(supergraph_creator), &configuration).await)
+        Self::RouterFactory::new(Arc::new(supergraph_creator), &configuration).await
     }
 }
 
@@ -21,11 +21,13 @@ use tower_service::Service;
 use tracing::Instrument;
 
 use super::layers::allow_only_http_post_mutations::AllowOnlyHttpPostMutationsLayer;
+use super::layers::single_flight::SingleFlightLayer;
 use super::new_service::ServiceFactory;
 use super::Plugins;
 use super::SubgraphServiceFactory;
 use crate::graphql::IncrementalResponse;
 use crate::graphql::Response;
+use crate::json_ext::MergeConflicts;
 use crate::json_ext::Object;
 use crate::json_ext::Path;
 use crate::json_ext::PathElement;
@@ -40,6 +42,7 @@ use crate::spec::Schema;
 pub(crate) struct ExecutionService {
     pub(crate) schema: Arc<Schema>,
     pub(crate) subgraph_service_factory: Arc<SubgraphServiceFactory>,
+    pub(crate) merge_conflicts: MergeConflicts,
 }
 
 impl Service<ExecutionRequest> for ExecutionService {
@@ -81,6 +84,7 @@ impl Service<ExecutionRequest> for ExecutionService {
                     &this.subgraph_service_factory,
                     &Arc::new(req.supergraph_request),
                     &this.schema,
+                    this.merge_conflicts,
                     sender,
                 )
                 .await;
@@ -342,6 +346,8 @@ pub(crate) struct ExecutionServiceFactory {
     pub(crate) schema: Arc<Schema>,
     pub(crate) plugins: Arc<Plugins>,
     pub(crate) subgraph_service_factory: Arc<SubgraphServiceFactory>,
+    pub(crate) merge_conflicts: MergeConflicts,
+    pub(crate) single_flight: Option<SingleFlightLayer>,
 }
 
 impl ServiceFactory<ExecutionRequest> for ExecutionServiceFactory {
@@ -352,11 +358,15 @@ impl ServiceFactory<ExecutionRequest> for ExecutionServiceFactory {
             .layer(AllowOnlyHttpPostMutationsLayer::default())
             .service(
                 self.plugins.iter().rev().fold(
-                    crate::services::execution_service::ExecutionService {
-                        schema: self.schema.clone(),
-                        subgraph_service_factory: self.subgraph_service_factory.clone(),
-                    }
-                    .boxed(),
+                    // plugins run for every request, even when its execution is shared
+                    ServiceBuilder::new()
+                        .option_layer(self.single_flight.clone())
+                        .service(crate::services::execution_service::ExecutionService {
+                            schema: self.schema.clone(),
+                            subgraph_service_factory: self.subgraph_service_factory.clone(),
+                            merge_conflicts: self.merge_conflicts,
+                        })
+                        .boxed(),
                     |acc, (_, e)| e.execution_service(acc),
                 ),
             )
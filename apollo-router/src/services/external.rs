@@ -10,7 +10,6 @@ use http::header::CONTENT_TYPE;
 use http::Method;
 use http::StatusCode;
 use hyper::Body;
-use opentelemetry::global::get_text_map_propagator;
 use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
@@ -20,6 +19,7 @@ use tower::BoxError;
 use tower::Service;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use crate::plugins::telemetry::inject_trace_context;
 use crate::Context;
 
 pub(crate) const DEFAULT_EXTERNALIZATION_TIMEOUT: Duration = Duration::from_secs(1);
@@ -191,12 +191,10 @@ where
             .header(CONTENT_TYPE, "application/json")
             .body(serde_json::to_vec(&self)?.into())?;
 
-        get_text_map_propagator(|propagator| {
-            propagator.inject_context(
-                &tracing::span::Span::current().context(),
-                &mut opentelemetry_http::HeaderInjector(request.headers_mut()),
-            );
-        });
+        inject_trace_context(
+            &tracing::span::Span::current().context(),
+            request.headers_mut(),
+        );
 
         let response = client.call(request).await?;
         hyper::body::to_bytes(response.into_body())
@@ -15,6 +15,7 @@ use tower::Service;
 use crate::graphql::Error;
 use crate::json_ext::Object;
 use crate::layers::sync_checkpoint::CheckpointService;
+use crate::plugins::telemetry::metrics::count_rejected_request;
 use crate::services::ExecutionRequest;
 use crate::services::ExecutionResponse;
 
@@ -34,6 +35,7 @@ where
                 if req.supergraph_request.method() != Method::POST
                     && req.query_plan.contains_mutations()
                 {
+                    count_rejected_request("mutation_over_get");
                     let errors = vec![Error::builder()
                         .message("Mutations can only be sent over HTTP POST".to_string())
                         .extension_code("MUTATION_FORBIDDEN")
@@ -0,0 +1,220 @@
+//! Reject the operations whose type is not allowed by the configuration.
+//!
+//! The type is read from the parsed document, before the query planner, so that the
+//! rejected operations are not planned. Documents that cannot be parsed, or without the
+//! selected operation, are left to the query planner, which reports their errors.
+//!
+//! See [`Layer`] and [`Service`] for more details.
+
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use apollo_parser::ast;
+use apollo_parser::Parser;
+use http::StatusCode;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::graphql::Error;
+use crate::layers::sync_checkpoint::CheckpointService;
+use crate::plugins::telemetry::metrics::count_rejected_request;
+use crate::query_planner::OperationKind;
+use crate::services::SupergraphRequest;
+use crate::services::SupergraphResponse;
+
+#[derive(Clone)]
+pub(crate) struct AllowedOperationTypesLayer {
+    allowed: Arc<Vec<OperationKind>>,
+}
+
+impl AllowedOperationTypesLayer {
+    /// Returns `None` when all the operation types are allowed
+    pub(crate) fn new(allowed: &[OperationKind]) -> Option<Self> {
+        let all_allowed = [
+            OperationKind::Query,
+            OperationKind::Mutation,
+            OperationKind::Subscription,
+        ]
+        .iter()
+        .all(|kind| allowed.contains(kind));
+        (!all_allowed).then(|| Self {
+            allowed: Arc::new(allowed.to_vec()),
+        })
+    }
+}
+
+impl<S> Layer<S> for AllowedOperationTypesLayer
+where
+    S: Service<SupergraphRequest, Response = SupergraphResponse, Error = BoxError> + Send + 'static,
+    <S as Service<SupergraphRequest>>::Future: Send + 'static,
+{
+    type Service = CheckpointService<S, SupergraphRequest>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        let allowed = self.allowed.clone();
+        CheckpointService::new(
+            move |req: SupergraphRequest| {
+                let body = req.supergraph_request.body();
+                let kind = match &body.query {
+                    Some(query) => operation_kind(query, body.operation_name.as_deref()),
+                    None => None,
+                };
+                let kind = match kind {
+                    Some(kind) if !allowed.contains(&kind) => kind,
+                    _ => return Ok(ControlFlow::Continue(req)),
+                };
+
+                count_rejected_request("operation_type_not_allowed");
+                let errors = vec![Error::builder()
+                    .message(format!("{kind} operations are not allowed"))
+                    .extension_code("OPERATION_TYPE_NOT_ALLOWED")
+                    .build()];
+                let res = SupergraphResponse::builder()
+                    .errors(errors)
+                    .status_code(StatusCode::BAD_REQUEST)
+                    .context(req.context)
+                    .build()?;
+                Ok(ControlFlow::Break(res))
+            },
+            service,
+        )
+    }
+}
+
+/// The type of the operation selected by `operation_name`, or of the only operation of the
+/// document. Shorthand operations, without a type, are queries.
+fn operation_kind(query: &str, operation_name: Option<&str>) -> Option<OperationKind> {
+    let tree = Parser::new(query).parse();
+    if tree.errors().next().is_some() {
+        return None;
+    }
+    let mut operations = tree
+        .document()
+        .definitions()
+        .filter_map(|definition| match definition {
+            ast::Definition::OperationDefinition(operation) => Some(operation),
+            _ => None,
+        });
+    let operation = match operation_name {
+        Some(operation_name) => operations.find(|operation| {
+            operation
+                .name()
+                .map(|name| name.text() == operation_name)
+                .unwrap_or_default()
+        })?,
+        None => {
+            let operation = operations.next()?;
+            if operations.next().is_some() {
+                return None;
+            }
+            operation
+        }
+    };
+    Some(match operation.operation_type() {
+        Some(operation_type) if operation_type.mutation_token().is_some() => {
+            OperationKind::Mutation
+        }
+        Some(operation_type) if operation_type.subscription_token().is_some() => {
+            OperationKind::Subscription
+        }
+        _ => OperationKind::Query,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+
+    const DOCUMENT: &str = r#"
+        query GetMe { me { name } }
+        mutation CreateProduct { createProduct(upc: "1") { name } }
+    "#;
+
+    fn create_request(document: &str, operation_name: Option<&str>) -> SupergraphRequest {
+        SupergraphRequest::fake_builder()
+            .query(document)
+            .and_operation_name(operation_name.map(ToString::to_string))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn it_is_not_needed_when_all_types_are_allowed() {
+        assert!(AllowedOperationTypesLayer::new(&[
+            OperationKind::Subscription,
+            OperationKind::Query,
+            OperationKind::Mutation,
+        ])
+        .is_none());
+        assert!(AllowedOperationTypesLayer::new(&[OperationKind::Query]).is_some());
+    }
+
+    #[test]
+    fn it_reads_the_type_of_the_selected_operation() {
+        assert_eq!(
+            operation_kind("{ me { name } }", None),
+            Some(OperationKind::Query)
+        );
+        assert_eq!(
+            operation_kind(DOCUMENT, Some("CreateProduct")),
+            Some(OperationKind::Mutation)
+        );
+        assert_eq!(
+            operation_kind("subscription { reviewAdded { body } }", None),
+            Some(OperationKind::Subscription)
+        );
+        // the query planner reports the errors of these documents
+        assert_eq!(operation_kind(DOCUMENT, None), None);
+        assert_eq!(operation_kind(DOCUMENT, Some("Unknown")), None);
+        assert_eq!(operation_kind("mutation {", None), None);
+    }
+
+    #[tokio::test]
+    async fn it_lets_allowed_operation_types_pass_through() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(3)
+            .returning(move |_| Ok(SupergraphResponse::fake_builder().build().unwrap()));
+        let mut service = AllowedOperationTypesLayer::new(&[OperationKind::Query])
+            .unwrap()
+            .layer(mock_service);
+
+        for request in [
+            create_request("{ me { name } }", None),
+            create_request(DOCUMENT, Some("GetMe")),
+            // left to the query planner
+            create_request(DOCUMENT, None),
+        ] {
+            let response = service.ready().await.unwrap().call(request).await.unwrap();
+            assert_eq!(response.response.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_rejects_operation_types_that_are_not_allowed() {
+        // the operation is rejected before the inner service, which plans it
+        let mut service = AllowedOperationTypesLayer::new(&[OperationKind::Query])
+            .unwrap()
+            .layer(MockSupergraphService::new());
+
+        let mut response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(create_request(DOCUMENT, Some("CreateProduct")))
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+        let error = &response.next_response().await.unwrap().errors[0];
+        assert_eq!(error.message, "Mutation operations are not allowed");
+        assert_eq!(
+            error.extensions.get("code").and_then(|code| code.as_str()),
+            Some("OPERATION_TYPE_NOT_ALLOWED")
+        );
+    }
+}
@@ -22,6 +22,7 @@ use tower::ServiceExt;
 use crate::graphql;
 use crate::layers::sync_checkpoint::CheckpointService;
 use crate::layers::ServiceExt as _;
+use crate::plugins::telemetry::metrics::count_rejected_request;
 use crate::services::router;
 use crate::services::router::ClientRequestAccepts;
 use crate::services::supergraph;
@@ -48,6 +49,7 @@ where
                 if req.router_request.method() != Method::GET
                     && !content_type_is_json(req.router_request.headers())
                 {
+                    count_rejected_request("invalid_content_type");
                     let response: http::Response<hyper::Body> = http::Response::builder()
                         .status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
                         .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
@@ -77,6 +79,7 @@ where
 
                     Ok(ControlFlow::Continue(req))
                 } else {
+                    count_rejected_request("invalid_accept");
                     let response: http::Response<hyper::Body> = http::Response::builder().status(StatusCode::NOT_ACCEPTABLE).header(CONTENT_TYPE, APPLICATION_JSON.essence_str()).body(
                             hyper::Body::from(
                                 serde_json::json!({
@@ -1,5 +1,9 @@
 //! Layers that are internal to the execution pipeline.
 pub(crate) mod allow_only_http_post_mutations;
+pub(crate) mod allowed_operation_types;
 pub(crate) mod apq;
 pub(crate) mod content_negociation;
+pub(crate) mod response_overlay;
+pub(crate) mod safelist;
+pub(crate) mod single_flight;
 pub(crate) mod static_page;
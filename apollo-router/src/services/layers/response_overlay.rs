@@ -0,0 +1,134 @@
+//! Add the values of the configured overlay to the extensions of GraphQL responses.
+//!
+//! The overlay only fills the keys missing from the extensions, see [`ValueExt::fill_absent`].
+//! It is added to every part of the response, including the incremental parts of `@defer`.
+
+use std::sync::Arc;
+
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tower::ServiceExt;
+
+use crate::configuration::ResponseOverlay;
+use crate::graphql;
+use crate::json_ext::Value;
+use crate::json_ext::ValueExt;
+use crate::services::supergraph;
+use crate::services::SupergraphRequest;
+use crate::services::SupergraphResponse;
+
+#[derive(Clone)]
+pub(crate) struct ResponseOverlayLayer {
+    extensions: Arc<Value>,
+}
+
+impl ResponseOverlayLayer {
+    /// Returns `None` when the overlay is empty
+    pub(crate) fn new(overlay: &ResponseOverlay) -> Option<Self> {
+        (!overlay.extensions.is_empty()).then(|| Self {
+            extensions: Arc::new(Value::Object(overlay.extensions.clone())),
+        })
+    }
+
+    fn apply(&self, response: &mut graphql::Response) {
+        let mut extensions = Value::Object(std::mem::take(&mut response.extensions));
+        extensions.fill_absent(&self.extensions);
+        if let Value::Object(extensions) = extensions {
+            response.extensions = extensions;
+        }
+    }
+}
+
+impl<S> Layer<S> for ResponseOverlayLayer
+where
+    S: Service<SupergraphRequest, Response = SupergraphResponse, Error = BoxError> + Send + 'static,
+    <S as Service<SupergraphRequest>>::Future: Send + 'static,
+{
+    type Service = supergraph::BoxService;
+
+    fn layer(&self, service: S) -> Self::Service {
+        let overlay = self.clone();
+        service
+            .map_response(move |response: SupergraphResponse| {
+                response.map_stream(move |mut response| {
+                    overlay.apply(&mut response);
+                    response
+                })
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use futures::StreamExt;
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+
+    #[tokio::test]
+    async fn it_fills_the_missing_extensions_of_every_part() {
+        let overlay: ResponseOverlay = serde_json::from_value(serde_json::json!({
+            "extensions": { "notice": "deprecated endpoint", "meta": { "region": "eu" } }
+        }))
+        .unwrap();
+        assert!(ResponseOverlayLayer::new(&ResponseOverlay::default()).is_none());
+        assert!(
+            serde_json::from_value::<ResponseOverlay>(serde_json::json!({
+                "data": { "version": 1 }
+            }))
+            .is_err()
+        );
+
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(|request| {
+            let parts = vec![
+                graphql::Response::builder()
+                    .data(json!({ "me": { "name": "Ada", "id": null } }))
+                    .extension("notice", json!("from a plugin"))
+                    .extension("meta", json!({ "version": null }))
+                    .has_next(true)
+                    .build(),
+                graphql::Response::builder().has_next(false).build(),
+            ];
+            Ok(SupergraphResponse::new_from_response(
+                http::Response::new(stream::iter(parts).boxed()),
+                request.context,
+            ))
+        });
+        let mut service = ResponseOverlayLayer::new(&overlay)
+            .unwrap()
+            .layer(mock_service);
+
+        let mut response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        let first = response.next_response().await.unwrap();
+        // the data is left as it is
+        assert_eq!(
+            first.data,
+            Some(json!({ "me": { "name": "Ada", "id": null } }))
+        );
+        // the values set by plugins are kept, including nulls
+        assert_eq!(
+            Value::Object(first.extensions),
+            json!({
+                "notice": "from a plugin",
+                "meta": { "version": null, "region": "eu" }
+            })
+        );
+
+        let second = response.next_response().await.unwrap();
+        assert_eq!(
+            Value::Object(second.extensions),
+            json!({ "notice": "deprecated endpoint", "meta": { "region": "eu" } })
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Only execute the operations registered in a safelist.
+//!
+//! Unlike APQ, which caches any query sent by clients, the safelist is a fixed set of
+//! operations, identified by the SHA-256 hash of their query, and the other operations
+//! are rejected.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+use http::StatusCode;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::graphql::Error;
+use crate::plugins::telemetry::metrics::count_rejected_request;
+use crate::services::SupergraphRequest;
+use crate::services::SupergraphResponse;
+
+/// The hashes of the queries allowed to run
+#[derive(Clone, Debug)]
+pub(crate) struct Safelist {
+    hashes: Arc<HashSet<String>>,
+}
+
+impl Safelist {
+    /// Loads a manifest file, see [`Safelist::from_manifest`]
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let manifest = std::fs::read_to_string(path).map_err(|e| {
+            format!(
+                "could not read the safelist manifest '{}': {e}",
+                path.display()
+            )
+        })?;
+        Self::from_manifest(&manifest)
+    }
+
+    /// Parses a manifest: a JSON array of the hexadecimal SHA-256 hashes of the queries
+    pub(crate) fn from_manifest(manifest: &str) -> Result<Self, String> {
+        let hashes: Vec<String> = serde_json::from_str(manifest)
+            .map_err(|e| format!("invalid safelist manifest: {e}"))?;
+        let hashes = hashes
+            .into_iter()
+            .map(|hash| {
+                if hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    Ok(hash.to_ascii_lowercase())
+                } else {
+                    Err(format!(
+                        "invalid safelist manifest: '{hash}' is not a SHA-256 hash"
+                    ))
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            hashes: Arc::new(hashes),
+        })
+    }
+
+    /// Returns `true` if the hash of `query` is in the safelist
+    pub(crate) fn allows(&self, query: &str) -> bool {
+        let hash = hex::encode(Sha256::digest(query.as_bytes()));
+        self.hashes.contains(&hash)
+    }
+
+    /// Lets the request through if its query is in the safelist, otherwise answers it with
+    /// a HTTP 403 error. The query of persisted queries must be resolved first.
+    pub(crate) fn check(
+        &self,
+        request: SupergraphRequest,
+    ) -> Result<SupergraphRequest, SupergraphResponse> {
+        let query = request.supergraph_request.body().query.as_deref();
+        if query.map(|query| self.allows(query)).unwrap_or_default() {
+            return Ok(request);
+        }
+
+        count_rejected_request("query_not_in_safelist");
+        Err(SupergraphResponse::builder()
+            .error(
+                Error::builder()
+                    .message("the query is not in the safelist")
+                    .extension_code("QUERY_NOT_IN_SAFELIST")
+                    .build(),
+            )
+            .status_code(StatusCode::FORBIDDEN)
+            .context(request.context)
+            .build()
+            .expect("response is valid"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql;
+
+    const QUERY: &str = "{ me { name } }";
+
+    fn manifest() -> String {
+        serde_json::json!([hex::encode(Sha256::digest(QUERY.as_bytes()))]).to_string()
+    }
+
+    fn request(query: &str) -> SupergraphRequest {
+        SupergraphRequest::fake_builder()
+            .query(query)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn it_parses_manifests() {
+        let safelist = Safelist::from_manifest(&manifest().to_ascii_uppercase()).unwrap();
+        assert!(safelist.allows(QUERY));
+        assert!(!safelist.allows("{ me { id } }"));
+
+        assert!(Safelist::from_manifest("{}").is_err());
+        assert_eq!(
+            Safelist::from_manifest(r#"["abc"]"#).unwrap_err(),
+            "invalid safelist manifest: 'abc' is not a SHA-256 hash"
+        );
+    }
+
+    #[test]
+    fn it_loads_manifest_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("safelist.json");
+        std::fs::write(&path, manifest()).unwrap();
+        assert!(Safelist::load(&path).unwrap().allows(QUERY));
+
+        assert!(Safelist::load(&dir.path().join("missing.json"))
+            .unwrap_err()
+            .starts_with("could not read the safelist manifest"));
+    }
+
+    #[tokio::test]
+    async fn it_rejects_queries_not_in_the_safelist() {
+        let safelist = Safelist::from_manifest(&manifest()).unwrap();
+        assert!(safelist.check(request(QUERY)).is_ok());
+
+        let mut response = safelist.check(request("{ me { id } }")).unwrap_err();
+        assert_eq!(response.response.status(), StatusCode::FORBIDDEN);
+        let response: graphql::Response = response.next_response().await.unwrap();
+        assert_eq!(
+            response.errors[0].message,
+            "the query is not in the safelist"
+        );
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .get("code")
+                .and_then(|code| code.as_str()),
+            Some("QUERY_NOT_IN_SAFELIST")
+        );
+    }
+}
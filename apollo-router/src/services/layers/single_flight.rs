@@ -0,0 +1,331 @@
+//! Coalesce identical execution requests running at the same time.
+//!
+//! The first request starts the execution, and the identical requests received before it
+//! completes wait for its responses instead of fetching the same data from the subgraphs.
+//! The execution is spawned, so that it completes, and leaves the in-flight requests, even
+//! when all the requests waiting for it are dropped.
+//!
+//! See [`Layer`] and [`Service`] for more details.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::sync::Arc;
+use std::task::Poll;
+
+use futures::future::BoxFuture;
+use futures::future::Shared;
+use futures::stream;
+use futures::FutureExt;
+use futures::StreamExt;
+use http::header::AUTHORIZATION;
+use http::header::COOKIE;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::StatusCode;
+use parking_lot::Mutex;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tracing::Instrument;
+
+use crate::graphql;
+use crate::json_ext::Value;
+use crate::json_ext::ValueExt;
+use crate::services::ExecutionRequest;
+use crate::services::ExecutionResponse;
+
+/// The headers that identify the client to the subgraphs: requests with different values
+/// can get different responses
+const IDENTITY_HEADERS: [http::header::HeaderName; 2] = [AUTHORIZATION, COOKIE];
+
+type SharedResult = Result<SharedResponse, SharedError>;
+type InFlight = Arc<Mutex<HashMap<Arc<RequestKey>, Shared<BoxFuture<'static, SharedResult>>>>>;
+
+#[derive(Clone)]
+struct SharedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    responses: Vec<graphql::Response>,
+}
+
+/// The error of an execution, returned to all the requests waiting for it
+#[derive(Clone, Debug)]
+struct SharedError(Arc<BoxError>);
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for SharedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&**self.0)
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct SingleFlightLayer {
+    in_flight: InFlight,
+}
+
+impl<S> Layer<S> for SingleFlightLayer {
+    type Service = SingleFlightService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SingleFlightService {
+            inner,
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+pub(crate) struct SingleFlightService<S> {
+    inner: S,
+    in_flight: InFlight,
+}
+
+impl<S> Service<ExecutionRequest> for SingleFlightService<S>
+where
+    S: Service<ExecutionRequest, Response = ExecutionResponse, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = ExecutionResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ExecutionRequest) -> Self::Future {
+        // mutations must run once per request, and deferred responses must not wait
+        // for the whole execution
+        let body = req.supergraph_request.body();
+        if req.query_plan.contains_mutations()
+            || req
+                .query_plan
+                .is_deferred(body.operation_name.as_deref(), &body.variables)
+        {
+            return self.inner.call(req).boxed();
+        }
+
+        let key = Arc::new(RequestKey::new(&req.supergraph_request));
+        let context = req.context.clone();
+        let shared = {
+            let mut in_flight = self.in_flight.lock();
+            match in_flight.get(&*key) {
+                Some(shared) => {
+                    tracing::info!(monotonic_counter.apollo_router_coalesced_requests_total = 1u64);
+                    shared.clone()
+                }
+                None => {
+                    let execution = self.inner.call(req);
+                    let in_flight_on_completion = self.in_flight.clone();
+                    let key_on_completion = key.clone();
+                    let execution = tokio::spawn(
+                        async move {
+                            let result = collect(execution)
+                                .await
+                                .map_err(|e| SharedError(Arc::new(e)));
+                            in_flight_on_completion.lock().remove(&*key_on_completion);
+                            result
+                        }
+                        .in_current_span(),
+                    );
+                    let shared = async move {
+                        execution
+                            .await
+                            .unwrap_or_else(|e| Err(SharedError(Arc::new(e.into()))))
+                    }
+                    .boxed()
+                    .shared();
+                    in_flight.insert(key, shared.clone());
+                    shared
+                }
+            }
+        };
+
+        async move {
+            let SharedResponse {
+                status,
+                headers,
+                responses,
+            } = shared.await?;
+            let mut response = http::Response::new(stream::iter(responses).boxed());
+            *response.status_mut() = status;
+            *response.headers_mut() = headers;
+            Ok(ExecutionResponse::new_from_response(response, context))
+        }
+        .boxed()
+    }
+}
+
+async fn collect<F>(execution: F) -> Result<SharedResponse, BoxError>
+where
+    F: std::future::Future<Output = Result<ExecutionResponse, BoxError>>,
+{
+    let (parts, body) = execution.await?.response.into_parts();
+    Ok(SharedResponse {
+        status: parts.status,
+        headers: parts.headers,
+        responses: body.collect().await,
+    })
+}
+
+/// Requests with the same key get the same response: the key covers the GraphQL request,
+/// independently of the order of the variables, and the headers identifying the client
+#[derive(PartialEq, Eq)]
+struct RequestKey {
+    query: Option<String>,
+    operation_name: Option<String>,
+    variables: Value,
+    extensions: Value,
+    identity: Vec<Vec<HeaderValue>>,
+}
+
+impl RequestKey {
+    fn new(request: &http::Request<graphql::Request>) -> Self {
+        let body = request.body();
+        Self {
+            query: body.query.clone(),
+            operation_name: body.operation_name.clone(),
+            variables: Value::Object(body.variables.clone()),
+            extensions: Value::Object(body.extensions.clone()),
+            identity: IDENTITY_HEADERS
+                .iter()
+                .map(|name| request.headers().get_all(name).iter().cloned().collect())
+                .collect(),
+        }
+    }
+}
+
+impl Hash for RequestKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.query.hash(state);
+        self.operation_name.hash(state);
+        // objects are equal whatever the order of their keys, so they are hashed the same way
+        self.variables.stable_hash_into(state);
+        self.extensions.stable_hash_into(state);
+        self.identity.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use futures::future::join_all;
+    use serde_json_bytes::json;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn request(authorization: &str) -> ExecutionRequest {
+        ExecutionRequest::fake_builder()
+            .supergraph_request(
+                http::Request::builder()
+                    .header(AUTHORIZATION, authorization)
+                    .body(
+                        graphql::Request::fake_builder()
+                            .query("{ me { name } }")
+                            .build(),
+                    )
+                    .unwrap(),
+            )
+            .build()
+    }
+
+    /// Counts the executions, which complete after a while so that requests overlap
+    fn execution_service(
+        calls: Arc<AtomicUsize>,
+        result: Result<(), &'static str>,
+    ) -> tower::util::BoxService<ExecutionRequest, ExecutionResponse, BoxError> {
+        tower::service_fn(move |req: ExecutionRequest| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                result?;
+                ExecutionResponse::fake_builder()
+                    .data(json!({ "call": call }))
+                    .context(req.context)
+                    .build()
+            }
+        })
+        .boxed()
+    }
+
+    #[tokio::test]
+    async fn it_executes_identical_concurrent_requests_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            SingleFlightLayer::default().layer(execution_service(calls.clone(), Ok(())));
+
+        let mut executions = Vec::new();
+        for _ in 0..10 {
+            executions.push(service.ready().await.unwrap().call(request("Bearer a")));
+        }
+        for response in join_all(executions).await {
+            let data = response.unwrap().next_response().await.unwrap().data;
+            assert_eq!(data, Some(json!({ "call": 0 })));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // once completed, the execution is not shared anymore
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(request("Bearer a"))
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_completes_the_executions_of_dropped_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let layer = SingleFlightLayer::default();
+        let mut service = layer.layer(execution_service(calls.clone(), Ok(())));
+
+        drop(service.ready().await.unwrap().call(request("Bearer a")));
+        assert_eq!(layer.in_flight.lock().len(), 1);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(layer.in_flight.lock().is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn it_does_not_coalesce_requests_of_different_clients() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            SingleFlightLayer::default().layer(execution_service(calls.clone(), Ok(())));
+
+        let first = service.ready().await.unwrap().call(request("Bearer a"));
+        let second = service.ready().await.unwrap().call(request("Bearer b"));
+        let (first, second) = futures::join!(first, second);
+        assert_ne!(
+            first.unwrap().next_response().await.unwrap().data,
+            second.unwrap().next_response().await.unwrap().data
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_shares_execution_errors() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut service = SingleFlightLayer::default()
+            .layer(execution_service(calls.clone(), Err("subgraph down")));
+
+        let first = service.ready().await.unwrap().call(request("Bearer a"));
+        let second = service.ready().await.unwrap().call(request("Bearer a"));
+        let (first, second) = futures::join!(first, second);
+        assert_eq!(first.err().unwrap().to_string(), "subgraph down");
+        assert_eq!(second.err().unwrap().to_string(), "subgraph down");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
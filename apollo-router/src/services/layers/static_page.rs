@@ -5,14 +5,18 @@
 
 // This entire file is license key functionality
 
-use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ops::ControlFlow;
+use std::sync::Arc;
 
 use askama::Template;
+use bytes::Bytes;
 use http::header::CONTENT_TYPE;
+use http::header::LOCATION;
 use http::HeaderMap;
 use http::HeaderValue;
 use http::Method;
+use http::StatusCode;
 use hyper::Body;
 use mediatype::names::HTML;
 use mediatype::names::TEXT;
@@ -23,14 +27,25 @@ use tower::Layer;
 use tower::Service;
 
 use crate::configuration::Homepage;
+use crate::configuration::HtmlBehavior;
+use crate::graphql;
 use crate::layers::sync_checkpoint::CheckpointService;
 use crate::services::router;
 use crate::Configuration;
 
 /// [`Layer`] That serves Static pages such as Homepage and Sandbox.
+///
+/// When both are disabled, requests preferring HTML are handled like any other request:
+/// the router never answers them with a page or a redirection, unless their path has
+/// its own behavior in `supergraph.html_by_path`.
 #[derive(Clone)]
 pub(crate) struct StaticPageLayer {
-    static_page: Option<String>,
+    static_page: Option<Bytes>,
+    studio_redirect: Option<(StatusCode, HeaderValue)>,
+    /// The behavior of the paths missing from `html_by_path`, `None` to handle
+    /// requests preferring HTML like any other request
+    default_behavior: Option<HtmlBehavior>,
+    html_by_path: Arc<HashMap<String, HtmlBehavior>>,
 }
 
 impl StaticPageLayer {
@@ -43,11 +58,58 @@ impl StaticPageLayer {
         } else {
             None
         };
+        // also used by the paths redirecting to Studio in `html_by_path`
+        let studio_redirect = configuration
+            .homepage
+            .graph_ref
+            .as_deref()
+            .and_then(|graph_ref| {
+                Some((
+                    StatusCode::from_u16(configuration.homepage.redirect_status).ok()?,
+                    HeaderValue::from_str(&studio_explorer_url(graph_ref)).ok()?,
+                ))
+            });
+        let default_behavior = if configuration.sandbox.enabled {
+            Some(HtmlBehavior::LandingPage)
+        } else if configuration.homepage.enabled {
+            if configuration.homepage.redirect_to_studio && studio_redirect.is_some() {
+                Some(HtmlBehavior::Redirect)
+            } else {
+                Some(HtmlBehavior::LandingPage)
+            }
+        } else {
+            None
+        };
+
+        Self {
+            static_page: static_page.map(Bytes::from),
+            studio_redirect,
+            default_behavior,
+            html_by_path: Arc::new(configuration.supergraph.html_by_path.clone()),
+        }
+    }
 
-        Self { static_page }
+    /// The behavior for a request path, if it can be served
+    fn behavior(&self, path: &str) -> Option<HtmlBehavior> {
+        match self
+            .html_by_path
+            .get(path)
+            .copied()
+            .or(self.default_behavior)?
+        {
+            HtmlBehavior::Redirect if self.studio_redirect.is_none() => None,
+            HtmlBehavior::LandingPage if self.static_page.is_none() => None,
+            behavior => Some(behavior),
+        }
     }
 }
 
+/// The Apollo Studio Explorer URL for a graph reference, like `my-graph@current`
+fn studio_explorer_url(graph_ref: &str) -> String {
+    let (graph, variant) = graph_ref.split_once('@').unwrap_or((graph_ref, "current"));
+    format!("https://studio.apollographql.com/graph/{graph}/explorer?variant={variant}")
+}
+
 impl<S> Layer<S> for StaticPageLayer
 where
     S: Service<router::Request, Response = router::Response, Error = BoxError> + Send + 'static,
@@ -56,37 +118,73 @@ where
     type Service = CheckpointService<S, router::Request>;
 
     fn layer(&self, service: S) -> Self::Service {
-        if let Some(page) = self.static_page.as_ref() {
-            let page = page.clone();
-            let cow = Cow::from(page);
-
-            CheckpointService::new(
-                move |req| {
-                    let res = if req.router_request.method() == Method::GET
-                        && prefers_html(req.router_request.headers())
-                    {
-                        let response = http::Response::builder()
-                            .header(
-                                CONTENT_TYPE,
-                                HeaderValue::from_static(mime::TEXT_HTML_UTF_8.as_ref()),
-                            )
-                            .body(Body::from(cow.clone()))
-                            .unwrap();
-                        ControlFlow::Break(router::Response {
-                            response,
-                            context: req.context,
-                        })
-                    } else {
-                        ControlFlow::Continue(req)
-                    };
-
-                    Ok(res)
-                },
-                service,
-            )
-        } else {
-            CheckpointService::new(move |req| Ok(ControlFlow::Continue(req)), service)
+        if self.default_behavior.is_none() && self.html_by_path.is_empty() {
+            return CheckpointService::new(move |req| Ok(ControlFlow::Continue(req)), service);
         }
+
+        let static_pages = self.clone();
+        CheckpointService::new(
+            move |req| {
+                if req.router_request.method() != Method::GET
+                    || !prefers_html(req.router_request.headers())
+                {
+                    return Ok(ControlFlow::Continue(req));
+                }
+
+                let response = match static_pages.behavior(req.router_request.uri().path()) {
+                    None => return Ok(ControlFlow::Continue(req)),
+                    Some(HtmlBehavior::Error) => {
+                        return Ok(ControlFlow::Break(
+                            router::Response::error_builder()
+                                .error(
+                                    graphql::Error::builder()
+                                        .message(
+                                            "this path only serves GraphQL requests, not HTML pages",
+                                        )
+                                        .extension_code("HTML_NOT_SERVED")
+                                        .build(),
+                                )
+                                .status_code(StatusCode::NOT_ACCEPTABLE)
+                                .header(CONTENT_TYPE, mime::APPLICATION_JSON.essence_str())
+                                .context(req.context)
+                                .build()?,
+                        ));
+                    }
+                    Some(HtmlBehavior::Redirect) => {
+                        let (status, location) = static_pages
+                            .studio_redirect
+                            .clone()
+                            .expect("checked by StaticPageLayer::behavior");
+                        http::Response::builder()
+                            .status(status)
+                            .header(LOCATION, location)
+                            .body(Body::empty())
+                            .unwrap()
+                    }
+                    Some(HtmlBehavior::LandingPage) => http::Response::builder()
+                        .header(
+                            CONTENT_TYPE,
+                            HeaderValue::from_static(mime::TEXT_HTML_UTF_8.as_ref()),
+                        )
+                        .body(Body::from(
+                            static_pages
+                                .static_page
+                                .clone()
+                                .expect("checked by StaticPageLayer::behavior"),
+                        ))
+                        .unwrap(),
+                };
+                ::tracing::info!(
+                    monotonic_counter.apollo_router_http_route_requests_total = 1u64,
+                    route = "landing_page"
+                );
+                Ok(ControlFlow::Break(router::Response {
+                    response,
+                    context: req.context,
+                }))
+            },
+            service,
+        )
     }
 }
 
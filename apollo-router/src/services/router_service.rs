@@ -2,12 +2,16 @@
 
 //! Implements the router phase of the request lifecycle.
 
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::task::Poll;
+use std::time::Instant;
 
 use axum::body::StreamBody;
+use axum::extract::ConnectInfo;
 use axum::response::*;
-use bytes::Buf;
 use bytes::Bytes;
 use futures::future::ready;
 use futures::future::BoxFuture;
@@ -15,16 +19,25 @@ use futures::stream;
 use futures::stream::once;
 use futures::stream::StreamExt;
 use http::header::CONTENT_TYPE;
+use http::header::ETAG;
+use http::header::IF_NONE_MATCH;
 use http::header::VARY;
 use http::HeaderMap;
 use http::HeaderValue;
 use http::Method;
 use http::StatusCode;
+use http::Uri;
 use http_body::Body as _;
 use hyper::Body;
+use lru::LruCache;
 use mime::APPLICATION_JSON;
 use multimap::MultiMap;
+use parking_lot::Mutex;
 use router_bridge::planner::Planner;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio_stream::wrappers::ReceiverStream;
 use tower::BoxError;
 use tower::Layer;
 use tower::ServiceBuilder;
@@ -34,20 +47,33 @@ use tracing::Instrument;
 
 use super::layers::apq::APQLayer;
 use super::layers::content_negociation;
+use super::layers::safelist::Safelist;
 use super::layers::static_page::StaticPageLayer;
 use super::new_service::ServiceFactory;
 use super::router;
 use super::router::ClientRequestAccepts;
 use super::supergraph;
 use super::HasPlugins;
+use super::HasSchema;
 #[cfg(test)]
 use super::SupergraphCreator;
 use super::MULTIPART_DEFER_CONTENT_TYPE;
 use crate::cache::DeduplicatingCache;
+use crate::configuration::BatchExecution;
+use crate::configuration::BatchMode;
+use crate::configuration::QueryStringParameters;
 use crate::graphql;
+use crate::http_ext::remove_hop_by_hop_headers;
+use crate::json_ext::Object;
+use crate::json_ext::Value;
 #[cfg(test)]
 use crate::plugin::test::MockSupergraphService;
+use crate::plugin::HealthProbe;
+use crate::plugins::telemetry::metrics::count_rejected_request;
+use crate::plugins::telemetry::Telemetry;
 use crate::query_planner::QueryPlanResult;
+use crate::request::JsonBodyError;
+use crate::request::JsonLimits;
 use crate::router_factory::RouterFactory;
 use crate::services::layers::content_negociation::GRAPHQL_JSON_RESPONSE_HEADER_VALUE;
 use crate::services::RouterRequest;
@@ -55,9 +81,16 @@ use crate::services::RouterResponse;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
 use crate::Configuration;
+use crate::Context;
 use crate::Endpoint;
 use crate::ListenAddr;
 
+/// The context key of the operation name of the GraphQL request
+pub(crate) const OPERATION_NAME: &str = "apollo_router::operation_name";
+
+/// GraphQL requests decoded from GET requests, by the query string of their URL
+type GetQueryCache = Arc<Mutex<LruCache<String, graphql::Request>>>;
+
 /// Containing [`Service`] in the request lifecyle.
 #[derive(Clone)]
 pub(crate) struct RouterService<SF>
@@ -66,16 +99,53 @@ where
 {
     supergraph_creator: Arc<SF>,
     apq_layer: APQLayer,
+    max_response_bytes: Option<usize>,
+    etag: bool,
+    strict_request_parsing: bool,
+    json_limits: JsonLimits,
+    get_plus_as_space: bool,
+    stream_json_responses: bool,
+    batch_execution: BatchExecution,
+    default_variables: Arc<Object>,
+    get_query_cache: Option<GetQueryCache>,
+    query_string_parameters: QueryStringParameters,
+    safelist: Option<Safelist>,
 }
 
 impl<SF> RouterService<SF>
 where
     SF: ServiceFactory<supergraph::Request> + Clone + Send + Sync + 'static,
 {
-    pub(crate) fn new(supergraph_creator: Arc<SF>, apq_layer: APQLayer) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        supergraph_creator: Arc<SF>,
+        apq_layer: APQLayer,
+        max_response_bytes: Option<usize>,
+        etag: bool,
+        strict_request_parsing: bool,
+        json_limits: JsonLimits,
+        get_plus_as_space: bool,
+        stream_json_responses: bool,
+        batch_execution: BatchExecution,
+        default_variables: Arc<Object>,
+        get_query_cache: Option<GetQueryCache>,
+        query_string_parameters: QueryStringParameters,
+        safelist: Option<Safelist>,
+    ) -> Self {
         RouterService {
             supergraph_creator,
             apq_layer,
+            max_response_bytes,
+            etag,
+            strict_request_parsing,
+            json_limits,
+            get_plus_as_space,
+            stream_json_responses,
+            batch_execution,
+            default_variables,
+            get_query_cache,
+            query_string_parameters,
+            safelist,
         }
     }
 }
@@ -108,6 +178,7 @@ pub(crate) async fn from_supergraph_mock_callback_and_configuration(
         &configuration,
     )
     .await
+    .unwrap()
     .make()
 }
 
@@ -148,6 +219,7 @@ pub(crate) async fn empty() -> impl Service<
         &Configuration::default(),
     )
     .await
+    .unwrap()
     .make()
 }
 
@@ -173,28 +245,61 @@ where
             context,
         } = req;
 
-        let (parts, body) = router_request.into_parts();
+        let (mut parts, body) = router_request.into_parts();
+        // the supergraph requests are not sent over the client connection
+        remove_hop_by_hop_headers(&mut parts.headers);
 
         let supergraph_creator = self.supergraph_creator.clone();
         let apq = self.apq_layer.clone();
+        let max_response_bytes = self.max_response_bytes;
+        let etag = self.etag;
+        let strict_request_parsing = self.strict_request_parsing;
+        let json_limits = self.json_limits;
+        let get_plus_as_space = self.get_plus_as_space;
+        let stream_json_responses = self.stream_json_responses;
+        let batch_execution = self.batch_execution.clone();
+        let default_variables = self.default_variables.clone();
+        let get_query_cache = self.get_query_cache.clone();
+        let query_string_parameters = self.query_string_parameters;
+        let safelist = self.safelist.clone();
+
+        ::tracing::info!(
+            monotonic_counter.apollo_router_http_route_requests_total = 1u64,
+            route = match parts.method {
+                Method::GET => "graphql_get",
+                Method::POST => "graphql_post",
+                _ => "other",
+            }
+        );
 
         let fut = async move {
-            let graphql_request: Result<graphql::Request, (&str, String)> = if parts.method
+            // conditional requests only make sense for safe methods
+            let if_none_match = if parts.method == Method::GET || parts.method == Method::HEAD {
+                parts.headers.get(IF_NONE_MATCH).cloned()
+            } else {
+                None
+            };
+
+            let graphql_request: Result<ParsedRequest, (&str, String)> = if parts.method
                 == Method::GET
             {
-                parts
-                    .uri
-                    .query()
-                    .map(|q| {
-                        graphql::Request::from_urlencoded_query(q.to_string()).map_err(|e| {
-                            (
-                                "failed to decode a valid GraphQL request from path",
-                                format!("failed to decode a valid GraphQL request from path {e}"),
-                            )
-                        })
+                // A GET request without any query string is handled like one with an empty
+                // query string: it will be rejected below with a `MISSING_QUERY_STRING` error
+                // instead of a generic decoding failure
+                tracing::debug_span!("decode_query_string")
+                    .in_scope(|| {
+                        decode_get_query(
+                            parts.uri.query().unwrap_or_default(),
+                            get_plus_as_space,
+                            get_query_cache.as_ref(),
+                        )
                     })
-                    .unwrap_or_else(|| {
-                        Err(("There was no GraphQL operation to execute. Use the `query` parameter to send an operation, using either GET or POST.", "There was no GraphQL operation to execute. Use the `query` parameter to send an operation, using either GET or POST.".to_string()))
+                    .map(ParsedRequest::Single)
+                    .map_err(|e| {
+                        (
+                            "failed to decode a valid GraphQL request from path",
+                            format!("failed to decode a valid GraphQL request from path: {e}"),
+                        )
                     })
             } else {
                 hyper::body::to_bytes(body)
@@ -207,53 +312,109 @@ where
                         )
                     })
                     .and_then(|bytes| {
-                        serde_json::from_reader(bytes.reader()).map_err(|err| {
-                            (
-                                "failed to deserialize the request body into JSON",
-                                format!("failed to deserialize the request body into JSON: {err}"),
-                            )
-                        })
+                        tracing::debug_span!("parse_body", "http.request.body.size" = bytes.len())
+                            .in_scope(|| {
+                                if batch_execution.enabled && is_batch(&bytes) {
+                                    graphql::Request::batch_from_slice(
+                                        &bytes,
+                                        strict_request_parsing,
+                                        json_limits,
+                                    )
+                                    .map(ParsedRequest::Batch)
+                                } else if strict_request_parsing {
+                                    graphql::Request::from_slice_strict(&bytes, json_limits)
+                                        .map(ParsedRequest::Single)
+                                } else {
+                                    json_limits.parse(&bytes).map(ParsedRequest::Single)
+                                }
+                            })
+                            .map_err(|err| match err {
+                                JsonBodyError::OverLimits(err) => {
+                                    ("the request body exceeds the JSON limits", err)
+                                }
+                                JsonBodyError::Invalid(err) => (
+                                    "failed to deserialize the request body into JSON",
+                                    format!(
+                                        "failed to deserialize the request body into JSON: {err}"
+                                    ),
+                                ),
+                            })
                     })
             };
 
+            let graphql_request = match graphql_request {
+                Ok(ParsedRequest::Single(mut request)) if parts.method != Method::GET => {
+                    apply_query_string_parameters(
+                        &mut request,
+                        parts.uri.query(),
+                        query_string_parameters,
+                    )
+                    .map(|()| ParsedRequest::Single(request))
+                    .map_err(|e| ("invalid query string parameters", e))
+                }
+                graphql_request => graphql_request,
+            };
+
+            let graphql_request = match graphql_request {
+                Ok(ParsedRequest::Batch(requests))
+                    if requests.len() > batch_execution.max_batch_size.get() =>
+                {
+                    Err((
+                        "the batch is too large",
+                        format!(
+                            "the batch has {} operations, the limit is {}",
+                            requests.len(),
+                            batch_execution.max_batch_size
+                        ),
+                    ))
+                }
+                graphql_request => graphql_request,
+            };
+
+            let graphql_request = graphql_request
+                .map(|parsed_request| parsed_request.with_default_variables(&default_variables));
+
             match graphql_request {
-                Ok(graphql_request) => {
+                Ok(ParsedRequest::Batch(requests)) => {
+                    let responses = execute_batch(
+                        &*supergraph_creator,
+                        &apq,
+                        safelist.as_ref(),
+                        &batch_execution,
+                        &parts,
+                        &context,
+                        requests,
+                    )
+                    .await;
+                    let (mut parts, ()) = http::Response::new(()).into_parts();
+                    parts.headers.insert(
+                        CONTENT_TYPE,
+                        HeaderValue::from_static(APPLICATION_JSON.essence_str()),
+                    );
+                    // every operation gets the error of a batch over the size limit, so that
+                    // clients can still match the responses with their requests
+                    let batch_size = responses.len();
+                    let response = json_response(
+                        parts,
+                        &responses,
+                        |too_large| vec![too_large; batch_size],
+                        max_response_bytes,
+                        etag,
+                        if_none_match.as_ref(),
+                    )?;
+                    Ok(router::Response { response, context })
+                }
+                Ok(ParsedRequest::Single(graphql_request)) => {
+                    if let Some(operation_name) = &graphql_request.operation_name {
+                        let _ = context.insert(OPERATION_NAME, operation_name.clone());
+                    }
                     let request = SupergraphRequest {
                         supergraph_request: http::Request::from_parts(parts, graphql_request),
                         context,
                     };
 
-                    let request_res = apq.supergraph_request(request).await;
-
                     let SupergraphResponse { response, context } =
-                        match request_res.and_then(|request| {
-                            let query = request.supergraph_request.body().query.as_ref();
-
-                            if query.is_none() || query.unwrap().trim().is_empty() {
-                                let errors = vec![crate::error::Error::builder()
-                                    .message("Must provide query string.".to_string())
-                                    .extension_code("MISSING_QUERY_STRING")
-                                    .build()];
-                                tracing::error!(
-                                    monotonic_counter.apollo_router_http_requests_total = 1u64,
-                                    status = %StatusCode::BAD_REQUEST.as_u16(),
-                                    error = "Must provide query string",
-                                    "Must provide query string"
-                                );
-
-                                Err(SupergraphResponse::builder()
-                                    .errors(errors)
-                                    .status_code(StatusCode::BAD_REQUEST)
-                                    .context(request.context)
-                                    .build()
-                                    .expect("response is valid"))
-                            } else {
-                                Ok(request)
-                            }
-                        }) {
-                            Err(response) => response,
-                            Ok(request) => supergraph_creator.create().oneshot(request).await?,
-                        };
+                        execute(&*supergraph_creator, &apq, safelist.as_ref(), request).await?;
 
                     let ClientRequestAccepts {
                         wildcard: accepts_wildcard,
@@ -290,16 +451,25 @@ where
                                     CONTENT_TYPE,
                                     HeaderValue::from_static(APPLICATION_JSON.essence_str()),
                                 );
-                                tracing::trace_span!("serialize_response").in_scope(|| {
-                                    let body = serde_json::to_string(&response)?;
-                                    Ok(router::Response {
+                                // the size limit and the ETag need the whole serialized body
+                                if stream_json_responses && max_response_bytes.is_none() && !etag {
+                                    return Ok(router::Response {
                                         response: http::Response::from_parts(
                                             parts,
-                                            Body::from(body),
+                                            serialize_in_chunks(response),
                                         ),
                                         context,
-                                    })
-                                })
+                                    });
+                                }
+                                let response = json_response(
+                                    parts,
+                                    &response,
+                                    |too_large| too_large,
+                                    max_response_bytes,
+                                    etag,
+                                    if_none_match.as_ref(),
+                                )?;
+                                Ok(router::Response { response, context })
                             } else if accepts_multipart {
                                 parts.headers.insert(
                                     CONTENT_TYPE,
@@ -386,6 +556,7 @@ where
                         error = %error,
                         %error
                     );
+                    count_rejected_request("invalid_graphql_request");
 
                     router::Response::error_builder()
                         .error(
@@ -406,6 +577,463 @@ where
     }
 }
 
+/// The body of a GraphQL request
+enum ParsedRequest {
+    Single(graphql::Request),
+    Batch(Vec<graphql::Request>),
+}
+
+impl ParsedRequest {
+    /// Adds `default_variables` to the variables of the requests, so that the variables
+    /// sent by the client are kept and the missing ones are added
+    fn with_default_variables(mut self, default_variables: &Object) -> Self {
+        if default_variables.is_empty() {
+            return self;
+        }
+        let requests = match &mut self {
+            ParsedRequest::Single(request) => std::slice::from_mut(request),
+            ParsedRequest::Batch(requests) => requests.as_mut_slice(),
+        };
+        for request in requests {
+            add_default_variables(&mut request.variables, default_variables);
+        }
+        self
+    }
+}
+
+/// Adds the keys of `defaults` that are absent from `variables`. The values of `variables`,
+/// including `null` and lists, replace the default ones: only objects are merged, key by key.
+fn add_default_variables(variables: &mut Object, defaults: &Object) {
+    for (key, default) in defaults {
+        match (variables.get_mut(key), default) {
+            (None, _) => {
+                variables.insert(key.clone(), default.clone());
+            }
+            (Some(Value::Object(value)), Value::Object(default)) => {
+                add_default_variables(value, default)
+            }
+            (Some(_), _) => {}
+        }
+    }
+}
+
+/// A batch is a JSON array, instead of an object
+fn is_batch(body: &[u8]) -> bool {
+    body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'[')
+}
+
+/// Checks the query of a GraphQL request, after resolving its persisted query,
+/// then runs it through the supergraph service
+async fn execute<SF>(
+    supergraph_creator: &SF,
+    apq: &APQLayer,
+    safelist: Option<&Safelist>,
+    request: SupergraphRequest,
+) -> Result<SupergraphResponse, BoxError>
+where
+    SF: ServiceFactory<supergraph::Request>,
+    <SF as ServiceFactory<supergraph::Request>>::Service:
+        Service<supergraph::Request, Response = supergraph::Response, Error = BoxError>,
+{
+    let request_res = apq.supergraph_request(request).await;
+
+    match request_res.and_then(|request| {
+        let query = request.supergraph_request.body().query.as_ref();
+
+        if query.is_none() || query.unwrap().trim().is_empty() {
+            let errors = vec![crate::error::Error::builder()
+                .message("Must provide query string.".to_string())
+                .extension_code("MISSING_QUERY_STRING")
+                .build()];
+            tracing::error!(
+                monotonic_counter.apollo_router_http_requests_total = 1u64,
+                status = %StatusCode::BAD_REQUEST.as_u16(),
+                error = "Must provide query string",
+                "Must provide query string"
+            );
+            count_rejected_request("missing_query");
+
+            Err(SupergraphResponse::builder()
+                .errors(errors)
+                .status_code(StatusCode::BAD_REQUEST)
+                .context(request.context)
+                .build()
+                .expect("response is valid"))
+        } else {
+            match safelist {
+                Some(safelist) => safelist.check(request),
+                None => Ok(request),
+            }
+        }
+    }) {
+        Err(response) => Ok(response),
+        Ok(request) => supergraph_creator.create().oneshot(request).await,
+    }
+}
+
+/// Executes the requests of a batch, and returns their responses in the order of the
+/// requests whatever the execution mode.
+///
+/// Every request gets its own context, with a copy of the entries of the batch's context,
+/// and the peer address of the connection when it has one.
+/// Responses are not streamed, so deferred requests are answered with an error.
+/// A request that fails is answered with an error, without failing the rest of the batch.
+async fn execute_batch<SF>(
+    supergraph_creator: &SF,
+    apq: &APQLayer,
+    safelist: Option<&Safelist>,
+    batch_execution: &BatchExecution,
+    parts: &http::request::Parts,
+    context: &Context,
+    requests: Vec<graphql::Request>,
+) -> Vec<graphql::Response>
+where
+    SF: ServiceFactory<supergraph::Request>,
+    <SF as ServiceFactory<supergraph::Request>>::Service:
+        Service<supergraph::Request, Response = supergraph::Response, Error = BoxError>,
+{
+    let accepts: ClientRequestAccepts = context
+        .private_entries
+        .lock()
+        .get()
+        .cloned()
+        .unwrap_or_default();
+
+    let execute_one = |graphql_request: graphql::Request| {
+        let request_context = Context::new();
+        for entry in context.iter() {
+            request_context.insert_json_value(entry.key().clone(), entry.value().clone());
+        }
+        if let Some(operation_name) = &graphql_request.operation_name {
+            let _ = request_context.insert(OPERATION_NAME, operation_name.clone());
+        }
+        request_context
+            .private_entries
+            .lock()
+            .insert(ClientRequestAccepts {
+                multipart: false,
+                ..accepts.clone()
+            });
+
+        let mut supergraph_request = http::Request::new(graphql_request);
+        *supergraph_request.method_mut() = parts.method.clone();
+        *supergraph_request.uri_mut() = parts.uri.clone();
+        *supergraph_request.version_mut() = parts.version;
+        *supergraph_request.headers_mut() = parts.headers.clone();
+        if let Some(peer_addr) = parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+            supergraph_request.extensions_mut().insert(*peer_addr);
+        }
+        let request = SupergraphRequest {
+            supergraph_request,
+            context: request_context,
+        };
+
+        async move {
+            let response = match execute(supergraph_creator, apq, safelist, request).await {
+                Ok(response) => response.response.into_body().next().await,
+                Err(err) => {
+                    tracing::error!("router service call failed: {err}");
+                    Some(
+                        graphql::Response::builder()
+                            .error(
+                                graphql::Error::builder()
+                                    .message("router service call failed")
+                                    .extension_code("INTERNAL_SERVER_ERROR")
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                }
+            };
+            response.unwrap_or_else(|| {
+                graphql::Response::builder()
+                    .error(
+                        graphql::Error::builder()
+                            .message("router service is not available to process request")
+                            .extension_code("SERVICE_UNAVAILABLE")
+                            .build(),
+                    )
+                    .build()
+            })
+        }
+    };
+
+    match batch_execution.mode {
+        BatchMode::Sequential => {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.push(execute_one(request).await);
+            }
+            responses
+        }
+        BatchMode::Concurrent => {
+            // `buffered` yields the responses in the order of the requests
+            stream::iter(requests)
+                .map(execute_one)
+                .buffered(batch_execution.max_concurrency.get())
+                .collect()
+                .await
+        }
+    }
+}
+
+/// Decodes the GraphQL request of a GET request from its query string, or reuses the one
+/// decoded for a previous request with the same query string
+fn decode_get_query(
+    query: &str,
+    plus_as_space: bool,
+    cache: Option<&GetQueryCache>,
+) -> Result<graphql::Request, serde_json::Error> {
+    if let Some(request) = cache.and_then(|cache| cache.lock().get(query).cloned()) {
+        return Ok(request);
+    }
+
+    let request = graphql::Request::from_urlencoded_query(
+        prepare_get_query(query, plus_as_space).into_owned(),
+    )?;
+    if let Some(cache) = cache {
+        cache.lock().put(query.to_string(), request.clone());
+    }
+    Ok(request)
+}
+
+/// Sets the `operationName` and `variables` parameters of the query string of a POST request
+/// on the GraphQL request of its body, as configured by `supergraph.query_string_parameters`
+fn apply_query_string_parameters(
+    request: &mut graphql::Request,
+    query_string: Option<&str>,
+    handling: QueryStringParameters,
+) -> Result<(), String> {
+    let query_string = match (handling, query_string) {
+        (QueryStringParameters::Ignore, _) | (_, None) => return Ok(()),
+        (_, Some(query_string)) => query_string,
+    };
+    let parameters = graphql::Request::from_urlencoded_query(query_string.to_string())
+        .map_err(|e| format!("failed to decode the parameters of the query string: {e}"))?;
+    let reject_conflicts = handling == QueryStringParameters::RejectConflicts;
+
+    if let Some(operation_name) = parameters.operation_name {
+        if let Some(body_operation_name) = &request.operation_name {
+            if reject_conflicts && *body_operation_name != operation_name {
+                return Err(format!(
+                    "the operationName of the query string, {operation_name:?}, is different from the one of the body, {body_operation_name:?}"
+                ));
+            }
+        }
+        request.operation_name = Some(operation_name);
+    }
+    if !parameters.variables.is_empty() {
+        if reject_conflicts
+            && !request.variables.is_empty()
+            && request.variables != parameters.variables
+        {
+            return Err(
+                "the variables of the query string are different from the ones of the body"
+                    .to_string(),
+            );
+        }
+        request.variables = parameters.variables;
+    }
+    Ok(())
+}
+
+/// Form encoding, used by GET requests, decodes `+` as a space, which changes queries sent
+/// by clients that do not percent-encode a literal `+`. Those queries are counted, and their
+/// `+` are kept as is when `plus_as_space` is false.
+fn prepare_get_query(query: &str, plus_as_space: bool) -> Cow<'_, str> {
+    if !query.contains('+') {
+        return Cow::Borrowed(query);
+    }
+
+    tracing::debug!(plus_as_space, "GET request query string contains a '+'");
+    tracing::info!(
+        monotonic_counter.apollo_router_get_plus_decoded_total = 1u64,
+        plus_as_space
+    );
+    if plus_as_space {
+        Cow::Borrowed(query)
+    } else {
+        Cow::Owned(query.replace('+', "%2B"))
+    }
+}
+
+/// Strong entity tag of a response body
+fn entity_tag(body: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(body.as_bytes());
+    format!("\"{}\"", hex::encode(hasher.finalize()))
+}
+
+/// Whether a `If-None-Match` header value matches an entity tag.
+/// Like described in RFC 9110, the comparison is weak: `W/` prefixes are ignored
+fn etag_matches(if_none_match: &HeaderValue, etag: &str) -> bool {
+    let if_none_match = match if_none_match.to_str() {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Size of the chunks of the streamed JSON responses
+const JSON_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Serializes the response in a blocking task, sending each chunk to the client as soon
+/// as it is written, instead of waiting for the whole response to be serialized
+fn serialize_in_chunks(response: graphql::Response) -> Body {
+    let (sender, receiver) = tokio::sync::mpsc::channel(2);
+    tokio::task::spawn_blocking(move || {
+        let mut writer = ChunkWriter {
+            chunk: Vec::with_capacity(JSON_CHUNK_SIZE),
+            sender,
+        };
+        let result = tracing::trace_span!("serialize_response")
+            .in_scope(|| serde_json::to_writer(&mut writer, &response));
+        match result {
+            Ok(()) => {
+                let _ = writer.send_chunk();
+            }
+            // the client went away, or the response cannot be serialized: aborts the body
+            Err(err) => {
+                let _ = writer.sender.blocking_send(Err(err.into()));
+            }
+        }
+    });
+    Body::wrap_stream(ReceiverStream::new(receiver))
+}
+
+struct ChunkWriter {
+    chunk: Vec<u8>,
+    sender: tokio::sync::mpsc::Sender<Result<Bytes, std::io::Error>>,
+}
+
+impl ChunkWriter {
+    fn send_chunk(&mut self) -> std::io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(JSON_CHUNK_SIZE));
+        self.sender.blocking_send(Ok(chunk.into())).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "the response body was dropped",
+            )
+        })
+    }
+}
+
+impl std::io::Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+        if self.chunk.len() >= JSON_CHUNK_SIZE {
+            self.send_chunk()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_chunk()
+    }
+}
+
+/// Serializes the JSON body of a response, with an ETag when `etag` is enabled
+fn json_response<T: Serialize, E: Serialize>(
+    mut parts: http::response::Parts,
+    response: &T,
+    too_large: impl FnOnce(graphql::Response) -> E,
+    max_response_bytes: Option<usize>,
+    etag: bool,
+    if_none_match: Option<&HeaderValue>,
+) -> Result<http::Response<Body>, BoxError> {
+    tracing::trace_span!("serialize_response").in_scope(|| {
+        let start = Instant::now();
+        let mut body = serialize_response(response, max_response_bytes, too_large)?;
+        tracing::info!(
+            histogram.apollo_router_response_serialization_seconds = start.elapsed().as_secs_f64()
+        );
+        if etag && parts.status == StatusCode::OK {
+            let etag = entity_tag(&body);
+            let not_modified = if_none_match
+                .map(|value| etag_matches(value, &etag))
+                .unwrap_or_default();
+            parts.headers.insert(ETAG, HeaderValue::from_str(&etag)?);
+            if not_modified {
+                parts.status = StatusCode::NOT_MODIFIED;
+                body.clear();
+            }
+        }
+        Ok(http::Response::from_parts(parts, Body::from(body)))
+    })
+}
+
+/// Serializes a response, replacing it with `too_large` of a `RESPONSE_TOO_LARGE` error as
+/// soon as it goes over `max_response_bytes`: the rest of a large response is never serialized.
+fn serialize_response<T: Serialize, E: Serialize>(
+    response: &T,
+    max_response_bytes: Option<usize>,
+    too_large: impl FnOnce(graphql::Response) -> E,
+) -> Result<String, serde_json::Error> {
+    let max_response_bytes = match max_response_bytes {
+        Some(max_response_bytes) => max_response_bytes,
+        None => return serde_json::to_string(response),
+    };
+    let mut writer = LimitedWriter {
+        buf: Vec::new(),
+        limit: max_response_bytes,
+    };
+    match serde_json::to_writer(&mut writer, response) {
+        // serde_json only writes valid UTF-8
+        Ok(()) => Ok(String::from_utf8(writer.buf).expect("serialized JSON is valid UTF-8")),
+        // the only IO errors come from the writer going over the limit
+        Err(err) if err.is_io() => {
+            serde_json::to_string(&too_large(response_too_large(max_response_bytes)))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// A buffer refusing writes beyond its limit
+struct LimitedWriter {
+    buf: Vec<u8>,
+    limit: usize,
+}
+
+impl std::io::Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.buf.len() + buf.len() > self.limit {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "the response is over the size limit",
+            ));
+        }
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The response sent instead of a response over the `max_response_bytes` limit
+fn response_too_large(max_response_bytes: usize) -> graphql::Response {
+    let message = format!("response is too large: the limit is {max_response_bytes} bytes");
+    tracing::error!(%message, "response is over the configured size limit");
+
+    graphql::Response::builder()
+        .error(
+            graphql::Error::builder()
+                .message(message)
+                .extension_code("RESPONSE_TOO_LARGE")
+                .build(),
+        )
+        .build()
+}
+
 // Process the headers to make sure that `VARY` is set correctly
 fn process_vary_header(headers: &mut HeaderMap<HeaderValue>) {
     if headers.get(VARY).is_none() {
@@ -423,6 +1051,18 @@ where
     supergraph_creator: Arc<SF>,
     static_page: StaticPageLayer,
     apq_layer: APQLayer,
+    max_response_bytes: Option<usize>,
+    etag: bool,
+    strict_request_parsing: bool,
+    json_limits: JsonLimits,
+    get_plus_as_space: bool,
+    stream_json_responses: bool,
+    batch_execution: BatchExecution,
+    default_variables: Arc<Object>,
+    get_query_cache: Option<GetQueryCache>,
+    query_string_parameters: QueryStringParameters,
+    response_headers: Arc<HeaderMap>,
+    safelist: Option<Safelist>,
 }
 
 impl<SF> ServiceFactory<router::Request> for RouterCreator<SF>
@@ -441,7 +1081,13 @@ where
 
 impl<SF> RouterFactory for RouterCreator<SF>
 where
-    SF: HasPlugins + ServiceFactory<supergraph::Request> + Clone + Send + Sync + 'static,
+    SF: HasPlugins
+        + HasSchema
+        + ServiceFactory<supergraph::Request>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     <SF as ServiceFactory<supergraph::Request>>::Service:
         Service<supergraph::Request, Response = supergraph::Response, Error = BoxError> + Send,
     <<SF as ServiceFactory<supergraph::Request>>::Service as Service<supergraph::Request>>::Future:
@@ -461,6 +1107,37 @@ where
             .for_each(|p| mm.extend(p.web_endpoints()));
         mm
     }
+
+    fn schema_id(&self) -> Option<String> {
+        self.supergraph_creator.schema().schema_id.clone()
+    }
+
+    fn subgraph_urls(&self) -> HashMap<String, Uri> {
+        self.supergraph_creator
+            .schema()
+            .subgraphs()
+            .map(|(name, url)| (name.clone(), url.clone()))
+            .collect()
+    }
+
+    fn health_probes(&self) -> Vec<HealthProbe> {
+        self.supergraph_creator
+            .plugins()
+            .values()
+            .map(|p| p.health_probe())
+            .collect()
+    }
+
+    fn shutdown(&self) -> BoxFuture<'static, ()> {
+        let plugins = self.supergraph_creator.plugins();
+        Box::pin(async move {
+            for plugin in plugins.values() {
+                if let Some(telemetry) = plugin.as_any().downcast_ref::<Telemetry>() {
+                    telemetry.shutdown().await;
+                }
+            }
+        })
+    }
 }
 
 impl<SF> RouterCreator<SF>
@@ -471,7 +1148,16 @@ where
     <<SF as ServiceFactory<supergraph::Request>>::Service as Service<supergraph::Request>>::Future:
         Send,
 {
-    pub(crate) async fn new(supergraph_creator: Arc<SF>, configuration: &Configuration) -> Self {
+    pub(crate) async fn new(
+        supergraph_creator: Arc<SF>,
+        configuration: &Configuration,
+    ) -> Result<Self, BoxError> {
+        let safelist = configuration
+            .supergraph
+            .safelist
+            .as_deref()
+            .map(Safelist::load)
+            .transpose()?;
         let static_page = StaticPageLayer::new(configuration);
         let apq_layer = if configuration.apq.enabled {
             APQLayer::with_cache(
@@ -482,11 +1168,26 @@ where
             APQLayer::disabled()
         };
 
-        Self {
+        Ok(Self {
             supergraph_creator,
             static_page,
             apq_layer,
-        }
+            max_response_bytes: configuration.supergraph.max_response_bytes,
+            etag: configuration.supergraph.etag,
+            strict_request_parsing: configuration.supergraph.strict_request_parsing,
+            json_limits: JsonLimits::from(&configuration.supergraph),
+            get_plus_as_space: configuration.supergraph.get_plus_as_space,
+            stream_json_responses: configuration.supergraph.stream_json_responses,
+            batch_execution: configuration.supergraph.batch_execution.clone(),
+            default_variables: Arc::new(configuration.supergraph.default_variables.clone()),
+            get_query_cache: configuration
+                .supergraph
+                .get_query_cache_size
+                .map(|size| Arc::new(Mutex::new(LruCache::new(size)))),
+            query_string_parameters: configuration.supergraph.query_string_parameters,
+            response_headers: Arc::new(configuration.supergraph.response_headers()),
+            safelist,
+        })
     }
 
     pub(crate) fn make(
@@ -500,17 +1201,45 @@ where
         let router_service = content_negociation::RouterLayer::default().layer(RouterService::new(
             self.supergraph_creator.clone(),
             self.apq_layer.clone(),
+            self.max_response_bytes,
+            self.etag,
+            self.strict_request_parsing,
+            self.json_limits,
+            self.get_plus_as_space,
+            self.stream_json_responses,
+            self.batch_execution.clone(),
+            self.default_variables.clone(),
+            self.get_query_cache.clone(),
+            self.query_string_parameters,
+            self.safelist.clone(),
         ));
 
+        let router_service = self
+            .supergraph_creator
+            .plugins()
+            .iter()
+            .rev()
+            .fold(router_service.boxed(), |acc, (_, e)| e.router_service(acc));
+        let router_service = if self.response_headers.is_empty() {
+            router_service
+        } else {
+            let response_headers = self.response_headers.clone();
+            router_service
+                .map_response(move |mut response: router::Response| {
+                    let headers = response.response.headers_mut();
+                    for (name, value) in response_headers.iter() {
+                        if !headers.contains_key(name) {
+                            headers.insert(name, value.clone());
+                        }
+                    }
+                    response
+                })
+                .boxed()
+        };
+
         ServiceBuilder::new()
             .layer(self.static_page.clone())
-            .service(
-                self.supergraph_creator
-                    .plugins()
-                    .iter()
-                    .rev()
-                    .fold(router_service.boxed(), |acc, (_, e)| e.router_service(acc)),
-            )
+            .service(router_service)
     }
 }
 
@@ -526,6 +1255,10 @@ impl RouterCreator<crate::services::supergraph_service::SupergraphCreator> {
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    use http::header::ACCEPT;
     use http::Uri;
     use mime::APPLICATION_JSON;
     use serde_json_bytes::json;
@@ -691,4 +1424,1146 @@ mod tests {
         assert_eq!(expected_error, actual_error);
         assert!(response.errors[0].extensions.contains_key("code"));
     }
+
+    #[tokio::test]
+    async fn it_replaces_responses_over_the_size_limit() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .max_response_bytes(100)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder()
+                        .data(json!({ "large": "a".repeat(200) }))
+                        .build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let request = SupergraphRequest::fake_builder()
+            .query("{ large }")
+            .build()
+            .expect("expecting valid request")
+            .try_into()
+            .unwrap();
+
+        let response = router_service
+            .oneshot(request)
+            .await
+            .unwrap()
+            .into_graphql_response_stream()
+            .await
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(response.data, None);
+        assert_eq!(
+            response.errors[0].message,
+            "response is too large: the limit is 100 bytes"
+        );
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .get("code")
+                .and_then(|code| code.as_str()),
+            Some("RESPONSE_TOO_LARGE")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_names_invalid_get_variables() {
+        let router_service = from_supergraph_mock_callback(move |_req| unreachable!()).await;
+
+        let request = http::Request::builder()
+            .method(Method::GET)
+            .uri("/?query=%7B%20me%20%7D&variables=%7Bbroken")
+            .header(ACCEPT, APPLICATION_JSON.essence_str())
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router_service.oneshot(request.into()).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+
+        let response = response
+            .into_graphql_response_stream()
+            .await
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.errors[0].message, "Invalid GraphQL request");
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .get("details")
+                .and_then(|details| details.as_str()),
+            Some(
+                "failed to decode a valid GraphQL request from path: invalid 'variables' parameter: key must be a string at line 1 column 2"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn it_answers_not_modified_for_a_matching_etag() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .etag(true)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let mut router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder()
+                        .data(json!({ "__typename": "Query" }))
+                        .build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let request = |if_none_match: Option<&str>| {
+            let mut request = http::Request::builder()
+                .method(Method::GET)
+                .uri("/?query=%7B%20__typename%20%7D")
+                .header(ACCEPT, APPLICATION_JSON.essence_str())
+                .header("apollo-require-preflight", "true");
+            if let Some(if_none_match) = if_none_match {
+                request = request.header(IF_NONE_MATCH, if_none_match);
+            }
+            request.body(Body::empty()).unwrap().into()
+        };
+
+        let response = router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(None))
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .expect("the response should have an etag")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(etag, entity_tag(std::str::from_utf8(&body).unwrap()));
+
+        let response = router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Some(&format!("\"other\", W/{etag}"))))
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(ETAG).unwrap(), etag.as_str());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        let response = router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Some("\"other\"")))
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn it_rejects_unknown_fields_with_strict_request_parsing() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .strict_request_parsing(true)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |_req| unreachable!(),
+            Arc::new(configuration),
+        )
+        .await;
+
+        let request = http::Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(ACCEPT, APPLICATION_JSON.essence_str())
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .body(Body::from(r#"{"quiery":"{ me }"}"#))
+            .unwrap();
+
+        let response = router_service.oneshot(request.into()).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+
+        let response = response
+            .into_graphql_response_stream()
+            .await
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.errors[0].message, "Invalid GraphQL request");
+        let details = response.errors[0]
+            .extensions
+            .get("details")
+            .and_then(|details| details.as_str())
+            .unwrap();
+        assert!(
+            details.starts_with(
+                "failed to deserialize the request body into JSON: unknown field `quiery`"
+            ),
+            "{details}"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_request_bodies_over_the_json_limits() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .max_request_depth(16)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |_req| unreachable!(),
+            Arc::new(configuration),
+        )
+        .await;
+
+        let body = format!(
+            r#"{{"query":"{{ me }}","variables":{{"a":{}{}}}}}"#,
+            "[".repeat(10_000),
+            "]".repeat(10_000)
+        );
+        let request = http::Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(ACCEPT, APPLICATION_JSON.essence_str())
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = router_service.oneshot(request.into()).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+
+        let response = response
+            .into_graphql_response_stream()
+            .await
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .get("details")
+                .and_then(|details| details.as_str()),
+            Some("the request body is nested deeper than 16 levels")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_adds_the_configured_response_headers() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .response_headers(
+                        [
+                            ("cache-control", "no-store"),
+                            ("x-router-region", "eu-west-1"),
+                            ("content-type", "text/plain"),
+                        ]
+                        .into_iter()
+                        .map(|(name, value)| (name.to_string(), value.to_string()))
+                        .collect(),
+                    )
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder().build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let request = http::Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(ACCEPT, APPLICATION_JSON.essence_str())
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .body(Body::from(r#"{ "query": "{ me { name } }" }"#))
+            .unwrap();
+
+        let response = router_service
+            .oneshot(request.into())
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(headers.get("cache-control").unwrap(), "no-store");
+        assert_eq!(headers.get("x-router-region").unwrap(), "eu-west-1");
+        // the headers set by the router are kept
+        assert_eq!(
+            headers.get(CONTENT_TYPE).unwrap(),
+            APPLICATION_JSON.essence_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_only_executes_the_queries_in_the_safelist() {
+        let allowed = "{ me { name } }";
+        let manifest = serde_json::json!([hex::encode(Sha256::digest(allowed.as_bytes()))]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("safelist.json");
+        std::fs::write(&path, manifest.to_string()).unwrap();
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .safelist(path)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let mut router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder().build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let request = |query: &str| {
+            http::Request::builder()
+                .method(Method::POST)
+                .uri("/")
+                .header(ACCEPT, APPLICATION_JSON.essence_str())
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .body(Body::from(
+                    serde_json::json!({ "query": query }).to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(allowed).into())
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(request("{ me { id } }").into())
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response: graphql::Response = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .get("code")
+                .and_then(|code| code.as_str()),
+            Some("QUERY_NOT_IN_SAFELIST")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fails_to_create_the_router_service_without_the_safelist_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .safelist(dir.path().join("missing.json"))
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let supergraph_creator = SupergraphCreator::for_tests(MockSupergraphService::new()).await;
+
+        let error = RouterCreator::new(Arc::new(supergraph_creator), &configuration)
+            .await
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("could not read the safelist manifest"));
+    }
+
+    #[tokio::test]
+    async fn it_answers_batches_in_the_order_of_the_requests() {
+        for mode in [BatchMode::Sequential, BatchMode::Concurrent] {
+            let configuration = Configuration::fake_builder()
+                .supergraph(
+                    crate::configuration::Supergraph::fake_builder()
+                        .batch_execution(BatchExecution {
+                            enabled: true,
+                            mode,
+                            max_concurrency: NonZeroUsize::new(5).unwrap(),
+                            max_batch_size: NonZeroUsize::new(5).unwrap(),
+                        })
+                        .build(),
+                )
+                .build()
+                .unwrap();
+            let completed = Arc::new(parking_lot::Mutex::new(Vec::new()));
+            let completed_in_callback = completed.clone();
+            let router_service = from_supergraph_mock_callback_and_configuration(
+                move |req| {
+                    // the first operations of the batch are the slowest ones
+                    let name = req
+                        .supergraph_request
+                        .body()
+                        .operation_name
+                        .clone()
+                        .unwrap();
+                    let index: u64 = name.trim_start_matches("op").parse().unwrap();
+                    let completed = completed_in_callback.clone();
+                    let response = async move {
+                        tokio::time::sleep(Duration::from_millis((5 - index) * 50)).await;
+                        completed.lock().push(name.clone());
+                        graphql::Response::builder()
+                            .data(json!({ "name": name }))
+                            .build()
+                    };
+                    Ok(SupergraphResponse {
+                        response: http::Response::new(Box::pin(stream::once(response))),
+                        context: req.context,
+                    })
+                },
+                Arc::new(configuration),
+            )
+            .await;
+
+            let batch: Vec<_> = (0..5)
+                .map(|i| serde_json::json!({ "query": "{ name }", "operationName": format!("op{i}") }))
+                .collect();
+            let request = http::Request::builder()
+                .method(Method::POST)
+                .uri("/")
+                .header(ACCEPT, APPLICATION_JSON.essence_str())
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .body(Body::from(serde_json::to_vec(&batch).unwrap()))
+                .unwrap();
+
+            let response = router_service
+                .oneshot(request.into())
+                .await
+                .unwrap()
+                .response;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let responses: Vec<graphql::Response> = serde_json::from_slice(&body).unwrap();
+            let names: Vec<_> = responses
+                .iter()
+                .map(|response| response.data.as_ref().unwrap()["name"].as_str().unwrap())
+                .collect();
+            assert_eq!(names, ["op0", "op1", "op2", "op3", "op4"]);
+
+            let mut expected_completion = vec!["op0", "op1", "op2", "op3", "op4"];
+            if mode == BatchMode::Concurrent {
+                expected_completion.reverse();
+            }
+            assert_eq!(*completed.lock(), expected_completion);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_passes_the_peer_address_to_the_operations_of_batches() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .batch_execution(BatchExecution {
+                        enabled: true,
+                        mode: BatchMode::Sequential,
+                        ..Default::default()
+                    })
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let peer_addr = SocketAddr::from(([10, 0, 0, 1], 4000));
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                assert_eq!(
+                    req.supergraph_request
+                        .extensions()
+                        .get::<ConnectInfo<SocketAddr>>()
+                        .map(|info| info.0),
+                    Some(peer_addr)
+                );
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder().build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let batch = serde_json::json!([{ "query": "{ name }" }, { "query": "{ name }" }]);
+        let mut request = http::Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(ACCEPT, APPLICATION_JSON.essence_str())
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .body(Body::from(serde_json::to_vec(&batch).unwrap()))
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(peer_addr));
+
+        let response = router_service
+            .oneshot(request.into())
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let responses: Vec<graphql::Response> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(responses.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_does_not_forward_hop_by_hop_headers() {
+        let router_service = from_supergraph_mock_callback(move |req| {
+            let headers = req.supergraph_request.headers();
+            assert!(!headers.contains_key(http::header::CONNECTION));
+            assert!(!headers.contains_key("keep-alive"));
+            assert!(!headers.contains_key("x-per-hop"));
+            assert_eq!(headers.get("x-tenant").unwrap(), "a");
+            Ok(SupergraphResponse::new_from_graphql_response(
+                graphql::Response::builder().build(),
+                req.context,
+            ))
+        })
+        .await;
+
+        let request = http::Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(ACCEPT, APPLICATION_JSON.essence_str())
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .header(http::header::CONNECTION, "keep-alive, x-per-hop")
+            .header("keep-alive", "timeout=5")
+            .header("x-per-hop", "1")
+            .header("x-tenant", "a")
+            .body(Body::from(r#"{ "query": "{ me { name } }" }"#))
+            .unwrap();
+
+        let response = router_service
+            .oneshot(request.into())
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn it_combines_the_query_string_parameters_with_the_body_as_configured() {
+        let body = serde_json::json!({
+            "query": "query A { me { name } } query B($id: ID) { user(id: $id) { name } }",
+            "operationName": "A",
+            "variables": { "id": "1" },
+        });
+        let send = |handling: QueryStringParameters, query_string: &'static str| {
+            let body = body.clone();
+            async move {
+                let configuration = Configuration::fake_builder()
+                    .supergraph(
+                        crate::configuration::Supergraph::fake_builder()
+                            .query_string_parameters(handling)
+                            .build(),
+                    )
+                    .build()
+                    .unwrap();
+                let received = Arc::new(parking_lot::Mutex::new(None));
+                let received_in_callback = received.clone();
+                let router_service = from_supergraph_mock_callback_and_configuration(
+                    move |req| {
+                        let request = req.supergraph_request.body();
+                        *received_in_callback.lock() = Some((
+                            request.operation_name.clone(),
+                            Value::Object(request.variables.clone()),
+                        ));
+                        Ok(SupergraphResponse::new_from_graphql_response(
+                            graphql::Response::builder().data(json!({})).build(),
+                            req.context,
+                        ))
+                    },
+                    Arc::new(configuration),
+                )
+                .await;
+                let request = http::Request::builder()
+                    .method(Method::POST)
+                    .uri(format!("/?{query_string}"))
+                    .header(ACCEPT, APPLICATION_JSON.essence_str())
+                    .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                    .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                    .unwrap();
+                let response = router_service
+                    .oneshot(request.into())
+                    .await
+                    .unwrap()
+                    .response;
+                let received = received.lock().take();
+                (response.status(), received)
+            }
+        };
+        let query_string = "operationName=B&variables=%7B%22id%22%3A%222%22%7D";
+
+        // by default, the query string is ignored
+        assert_eq!(
+            send(QueryStringParameters::Ignore, query_string).await,
+            (
+                StatusCode::OK,
+                Some((Some("A".to_string()), json!({ "id": "1" })))
+            )
+        );
+        assert_eq!(
+            send(QueryStringParameters::Override, query_string).await,
+            (
+                StatusCode::OK,
+                Some((Some("B".to_string()), json!({ "id": "2" })))
+            )
+        );
+        assert_eq!(
+            send(QueryStringParameters::RejectConflicts, query_string).await,
+            (StatusCode::BAD_REQUEST, None)
+        );
+        assert_eq!(
+            send(
+                QueryStringParameters::RejectConflicts,
+                "variables=%7B%22id%22%3A%222%22%7D"
+            )
+            .await,
+            (StatusCode::BAD_REQUEST, None)
+        );
+        // the same values in both are not a conflict
+        assert_eq!(
+            send(
+                QueryStringParameters::RejectConflicts,
+                "operationName=A&variables=%7B%22id%22%3A%221%22%7D"
+            )
+            .await,
+            (
+                StatusCode::OK,
+                Some((Some("A".to_string()), json!({ "id": "1" })))
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn it_merges_the_request_variables_over_the_default_ones() {
+        let default_variables = json!({ "locale": "en", "page": { "limit": 10, "offset": 0 } });
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .default_variables(default_variables.as_object().unwrap().clone())
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            |req| {
+                assert_eq!(
+                    Value::Object(req.supergraph_request.body().variables.clone()),
+                    json!({ "locale": "en", "page": { "limit": 5, "offset": 0 }, "id": 1 })
+                );
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder().data(json!({})).build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let request = SupergraphRequest::fake_builder()
+            .query("query($id: Int) { me(id: $id) { name } }")
+            .variable("id", json!(1))
+            .variable("page", json!({ "limit": 5 }))
+            .build()
+            .unwrap();
+        let response = router_service
+            .oneshot(request.try_into().unwrap())
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn it_reuses_the_requests_decoded_from_the_same_query_string() {
+        let cache: GetQueryCache =
+            Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(1).unwrap())));
+        let query = "query=%7B%20me%20%7D&variables=%7B%22id%22%3A1%7D";
+
+        let request = decode_get_query(query, true, Some(&cache)).unwrap();
+        assert_eq!(request.query.as_deref(), Some("{ me }"));
+        assert_eq!(cache.lock().peek(query), Some(&request));
+        assert_eq!(
+            decode_get_query(query, true, Some(&cache)).unwrap(),
+            request
+        );
+
+        // failures are not cached
+        assert!(decode_get_query("variables=%7Bbroken", true, Some(&cache)).is_err());
+        assert_eq!(cache.lock().len(), 1);
+
+        // the least recently used request is evicted
+        decode_get_query("query=%7B%20__typename%20%7D", true, Some(&cache)).unwrap();
+        assert!(cache.lock().peek(query).is_none());
+    }
+
+    #[tokio::test]
+    async fn it_streams_json_responses_in_chunks() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .stream_json_responses(true)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let items: Vec<String> = (0..10_000).map(|i| format!("item {i}")).collect();
+        let data = json!({ "items": items });
+        let expected = graphql::Response::builder().data(data.clone()).build();
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder().data(data.clone()).build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let request = SupergraphRequest::fake_builder()
+            .query("{ items }")
+            .build()
+            .expect("expecting valid request")
+            .try_into()
+            .unwrap();
+
+        let response = router_service.oneshot(request).await.unwrap().response;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .is_none());
+
+        let mut body = response.into_body();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = hyper::body::HttpBody::data(&mut body).await {
+            chunks.push(chunk.unwrap());
+        }
+        assert!(chunks.len() > 1);
+        assert!(chunks[0].len() >= JSON_CHUNK_SIZE);
+        assert_eq!(chunks.concat(), serde_json::to_vec(&expected).unwrap());
+    }
+
+    fn batch_request(size: usize) -> router::Request {
+        let batch: Vec<_> = (0..size)
+            .map(|i| serde_json::json!({ "query": "{ name }", "operationName": format!("op{i}") }))
+            .collect();
+        http::Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(ACCEPT, APPLICATION_JSON.essence_str())
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .body(Body::from(serde_json::to_vec(&batch).unwrap()))
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn it_rejects_batches_over_the_size_limit() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .batch_execution(BatchExecution {
+                        enabled: true,
+                        max_batch_size: NonZeroUsize::new(2).unwrap(),
+                        ..Default::default()
+                    })
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let mut router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder().build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let response = router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(batch_request(2))
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(batch_request(3))
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response: graphql::Response = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            response.errors[0].extensions["details"],
+            "the batch has 3 operations, the limit is 2"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_answers_the_failed_operations_of_a_batch_with_an_error() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .batch_execution(BatchExecution {
+                        enabled: true,
+                        ..Default::default()
+                    })
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                if req.supergraph_request.body().operation_name.as_deref() == Some("op1") {
+                    return Err("the operation failed".into());
+                }
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder()
+                        .data(json!({ "name": "test" }))
+                        .build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let response = router_service
+            .oneshot(batch_request(3))
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let responses: Vec<graphql::Response> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(responses.len(), 3);
+        assert!(responses[0].errors.is_empty());
+        assert_eq!(
+            responses[1].errors[0].extensions["code"],
+            "INTERNAL_SERVER_ERROR"
+        );
+        assert!(responses[2].errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_replaces_batches_over_the_response_size_limit() {
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .max_response_bytes(300)
+                    .etag(true)
+                    .batch_execution(BatchExecution {
+                        enabled: true,
+                        ..Default::default()
+                    })
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder()
+                        .data(json!({ "large": "a".repeat(100) }))
+                        .build(),
+                    req.context,
+                ))
+            },
+            Arc::new(configuration),
+        )
+        .await;
+
+        let response = router_service
+            .oneshot(batch_request(3))
+            .await
+            .unwrap()
+            .response;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(ETAG));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let responses: Vec<graphql::Response> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(responses.len(), 3);
+        for response in responses {
+            assert!(response.data.is_none());
+            assert_eq!(response.errors[0].extensions["code"], "RESPONSE_TOO_LARGE");
+        }
+    }
+
+    #[tokio::test]
+    fn it_does_not_merge_the_lists_and_nulls_of_the_request_variables() {
+        let default_variables = json!({
+            "tags": ["a", "b"],
+            "filter": { "status": "active" },
+            "page": { "limit": 10, "sort": ["name"] },
+        });
+        let request = graphql::Request::fake_builder()
+            .variables(
+                json!({
+                    "tags": ["c"],
+                    "filter": null,
+                    "page": { "sort": [] },
+                })
+                .as_object()
+                .unwrap()
+                .clone(),
+            )
+            .build();
+        let request = match ParsedRequest::Single(request)
+            .with_default_variables(default_variables.as_object().unwrap())
+        {
+            ParsedRequest::Single(request) => request,
+            ParsedRequest::Batch(_) => panic!("expected a single request"),
+        };
+        assert_eq!(
+            Value::Object(request.variables),
+            json!({
+                "tags": ["c"],
+                "filter": null,
+                "page": { "limit": 10, "sort": [] },
+            })
+        );
+    }
+
+    #[test]
+    async fn it_records_the_response_serialization_time() {
+        use crate::axum_factory::tests::prometheus_recorder;
+        use crate::axum_factory::tests::scraped_value;
+
+        let (exporter, _guard) = prometheus_recorder();
+
+        let router_service = from_supergraph_mock_callback(move |req| {
+            Ok(SupergraphResponse::new_from_graphql_response(
+                graphql::Response::builder()
+                    .data(json!({ "__typename": "Query" }))
+                    .build(),
+                req.context,
+            ))
+        })
+        .await;
+        let request = SupergraphRequest::fake_builder()
+            .query("{ __typename }")
+            .build()
+            .expect("expecting valid request")
+            .try_into()
+            .unwrap();
+        let response = router_service.oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+
+        assert_eq!(
+            scraped_value(
+                &exporter,
+                "apollo_router_response_serialization_seconds_count"
+            ),
+            Some(1.0)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_documents_with_too_many_operations() {
+        use crate::axum_factory::tests::prometheus_recorder;
+        use crate::axum_factory::tests::scraped_value;
+
+        let (exporter, _guard) = prometheus_recorder();
+        let router_service = crate::TestHarness::builder()
+            .configuration_json(serde_json::json!({
+                "preview_operation_limits": { "max_operations_per_document": 2 }
+            }))
+            .unwrap()
+            .build_router()
+            .await
+            .unwrap();
+
+        let request = SupergraphRequest::fake_builder()
+            .query("query A { me { name } } query B { me { name } } query C { me { name } }")
+            .operation_name("A")
+            .build()
+            .expect("expecting valid request")
+            .try_into()
+            .unwrap();
+        let response = router_service.oneshot(request).await.unwrap().response;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response: graphql::Response = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .get("code")
+                .and_then(|code| code.as_str()),
+            Some("MAX_OPERATIONS_LIMIT")
+        );
+        assert_eq!(
+            scraped_value(
+                &exporter,
+                r#"apollo_router_rejected_requests_total{reason="max_operations_limit"}"#
+            ),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn it_counts_get_queries_with_a_plus() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::atomic::Ordering;
+
+        use tracing_subscriber::layer::Context;
+        use tracing_subscriber::layer::SubscriberExt;
+        use tracing_subscriber::Layer;
+
+        #[derive(Clone, Default)]
+        struct CountPlusDecoded(Arc<AtomicUsize>);
+
+        impl<S: tracing::Subscriber> Layer<S> for CountPlusDecoded {
+            fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+                if event
+                    .metadata()
+                    .fields()
+                    .field("monotonic_counter.apollo_router_get_plus_decoded_total")
+                    .is_some()
+                {
+                    self.0.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+
+        let counter = CountPlusDecoded::default();
+        tracing::subscriber::with_default(
+            tracing_subscriber::registry().with(counter.clone()),
+            || {
+                let query = "query=%7B%20me%20%7D&variables=%7B%7D";
+                assert!(matches!(
+                    prepare_get_query(query, true),
+                    Cow::Borrowed(q) if q == query
+                ));
+                assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+
+                let query = "query=query+%7B+me+%7D";
+                assert_eq!(prepare_get_query(query, true), query);
+                assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+
+                assert_eq!(
+                    prepare_get_query(query, false),
+                    "query=query%2B%7B%2Bme%2B%7D"
+                );
+                assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn it_counts_the_requests_by_method() {
+        use crate::axum_factory::tests::prometheus_recorder;
+        use crate::axum_factory::tests::scraped_value;
+
+        let (exporter, _guard) = prometheus_recorder();
+        let mut router_service = from_supergraph_mock_callback(move |req| {
+            Ok(SupergraphResponse::new_from_graphql_response(
+                graphql::Response::builder().build(),
+                req.context,
+            ))
+        })
+        .await;
+
+        // the requests are counted whether they are answered or rejected
+        for method in [
+            Method::GET,
+            Method::POST,
+            Method::POST,
+            Method::HEAD,
+            Method::PUT,
+        ] {
+            let request = http::Request::builder()
+                .method(method)
+                .uri("/")
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .body(Body::from(r#"{"query":"{ me { name } }"}"#))
+                .unwrap();
+            router_service
+                .ready()
+                .await
+                .unwrap()
+                .call(request.into())
+                .await
+                .unwrap();
+        }
+
+        let requests = |route: &str| {
+            scraped_value(
+                &exporter,
+                &format!("apollo_router_http_route_requests_total{{route=\"{route}\"}}"),
+            )
+        };
+        assert_eq!(requests("graphql_get"), Some(1.0));
+        assert_eq!(requests("graphql_post"), Some(2.0));
+        assert_eq!(requests("other"), Some(2.0));
+    }
 }
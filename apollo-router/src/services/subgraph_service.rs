@@ -14,7 +14,6 @@ use async_compression::tokio::write::BrotliEncoder;
 use async_compression::tokio::write::GzipEncoder;
 use async_compression::tokio::write::ZlibEncoder;
 use futures::future::BoxFuture;
-use global::get_text_map_propagator;
 use http::header::ACCEPT;
 use http::header::ACCEPT_ENCODING;
 use http::header::CONTENT_ENCODING;
@@ -27,7 +26,6 @@ use hyper::Client;
 use hyper_rustls::ConfigBuilderExt;
 use hyper_rustls::HttpsConnector;
 use mime::APPLICATION_JSON;
-use opentelemetry::global;
 use rustls::RootCertStore;
 use schemars::JsonSchema;
 use tokio::io::AsyncWriteExt;
@@ -45,6 +43,7 @@ use super::layers::content_negociation::GRAPHQL_JSON_RESPONSE_HEADER_VALUE;
 use super::Plugins;
 use crate::error::FetchError;
 use crate::graphql;
+use crate::plugins::telemetry::inject_trace_context;
 use crate::plugins::telemetry::LOGGING_DISPLAY_BODY;
 use crate::plugins::telemetry::LOGGING_DISPLAY_HEADERS;
 use crate::services::layers::apq;
@@ -320,12 +319,7 @@ async fn call_http(
         "apollo.subgraph.name" = %service_name,
         "graphql.operation.name" = %operation_name,
     );
-    get_text_map_propagator(|propagator| {
-        propagator.inject_context(
-            &subgraph_req_span.context(),
-            &mut opentelemetry_http::HeaderInjector(request.headers_mut()),
-        );
-    });
+    inject_trace_context(&subgraph_req_span.context(), request.headers_mut());
     let cloned_service_name = service_name.clone();
     let cloned_context = context.clone();
     let (parts, body) = async move {
@@ -19,7 +19,10 @@ use tower::ServiceExt;
 use tower_service::Service;
 use tracing_futures::Instrument;
 
+use super::layers::allowed_operation_types::AllowedOperationTypesLayer;
 use super::layers::content_negociation;
+use super::layers::response_overlay::ResponseOverlayLayer;
+use super::layers::single_flight::SingleFlightLayer;
 use super::new_service::ServiceFactory;
 use super::router::ClientRequestAccepts;
 use super::subgraph_service::MakeSubgraphService;
@@ -29,9 +32,11 @@ use super::QueryPlannerContent;
 use crate::error::CacheResolverError;
 use crate::graphql;
 use crate::graphql::IntoGraphQLErrors;
+use crate::json_ext::MergeConflicts;
 #[cfg(test)]
 use crate::plugin::test::MockSupergraphService;
 use crate::plugin::DynPlugin;
+use crate::plugins::telemetry::metrics::count_rejected_request;
 use crate::plugins::telemetry::Telemetry;
 use crate::plugins::traffic_shaping::TrafficShaping;
 use crate::plugins::traffic_shaping::APOLLO_TRAFFIC_SHAPING;
@@ -126,6 +131,25 @@ impl Service<SupergraphRequest> for SupergraphService {
     }
 }
 
+/// Operations over the configured limits are counted apart from the other invalid operations
+fn query_rejection_reason(errors: &[graphql::Error]) -> &'static str {
+    let codes = || {
+        errors
+            .iter()
+            .filter_map(|error| error.extensions.get("code").and_then(|code| code.as_str()))
+    };
+    let exceeds_limits = codes().any(|code| {
+        (code.starts_with("MAX_") && code.ends_with("_LIMIT")) || code == "RECURSION_LIMIT_EXCEEDED"
+    });
+    if codes().any(|code| code == "MAX_OPERATIONS_LIMIT") {
+        "max_operations_limit"
+    } else if exceeds_limits {
+        "operation_limits_exceeded"
+    } else {
+        "invalid_query"
+    }
+}
+
 async fn service_call<ExecutionService>(
     planning: CachingQueryPlanner<BridgeQueryPlanner>,
     execution: ExecutionService,
@@ -147,6 +171,7 @@ where
         Ok(resp) => resp,
         Err(err) => match err.into_graphql_errors() {
             Ok(gql_errors) => {
+                count_rejected_request(query_rejection_reason(&gql_errors));
                 return Ok(SupergraphResponse::builder()
                     .context(context)
                     .errors(gql_errors)
@@ -159,6 +184,7 @@ where
     };
 
     if !errors.is_empty() {
+        count_rejected_request(query_rejection_reason(&errors));
         return Ok(SupergraphResponse::builder()
             .context(context)
             .errors(errors)
@@ -172,6 +198,7 @@ where
             SupergraphResponse::new_from_graphql_response(*response, context),
         ),
         Some(QueryPlannerContent::IntrospectionDisabled) => {
+            count_rejected_request("introspection_disabled");
             let mut response = SupergraphResponse::new_from_graphql_response(
                 graphql::Response::builder()
                     .errors(vec![crate::error::Error::builder()
@@ -200,6 +227,7 @@ where
                 .unwrap_or_default();
 
             if is_deferred && !accepts_multipart {
+                count_rejected_request("defer_not_accepted");
                 let mut response = SupergraphResponse::new_from_graphql_response(graphql::Response::builder()
                     .errors(vec![crate::error::Error::builder()
                         .message(String::from("the router received a query with the @defer directive but the client does not accept multipart/mixed HTTP responses. To enable @defer support, add the HTTP header 'Accept: multipart/mixed; deferSpec=20220824'"))
@@ -209,6 +237,7 @@ where
                 *response.response.status_mut() = StatusCode::NOT_ACCEPTABLE;
                 Ok(response)
             } else if let Some(err) = plan.query.validate_variables(body, &schema).err() {
+                count_rejected_request("invalid_variables");
                 let mut res = SupergraphResponse::new_from_graphql_response(err, context);
                 *res.response.status_mut() = StatusCode::BAD_REQUEST;
                 Ok(res)
@@ -349,6 +378,15 @@ impl PluggableSupergraphServiceBuilder {
             subgraph_service_factory,
             schema,
             plugins,
+            merge_conflicts: configuration.supergraph.merge_conflicts,
+            single_flight: configuration
+                .supergraph
+                .coalesce_requests
+                .then(SingleFlightLayer::default),
+            allowed_operation_types: AllowedOperationTypesLayer::new(
+                &configuration.supergraph.allowed_operation_types,
+            ),
+            response_overlay: ResponseOverlayLayer::new(&configuration.supergraph.response_overlay),
         })
     }
 }
@@ -382,6 +420,10 @@ pub(crate) struct SupergraphCreator {
     subgraph_service_factory: Arc<SubgraphServiceFactory>,
     schema: Arc<Schema>,
     plugins: Arc<Plugins>,
+    merge_conflicts: MergeConflicts,
+    single_flight: Option<SingleFlightLayer>,
+    allowed_operation_types: Option<AllowedOperationTypesLayer>,
+    response_overlay: Option<ResponseOverlayLayer>,
 }
 
 pub(crate) trait HasPlugins {
@@ -394,6 +436,16 @@ impl HasPlugins for SupergraphCreator {
     }
 }
 
+pub(crate) trait HasSchema {
+    fn schema(&self) -> Arc<Schema>;
+}
+
+impl HasSchema for SupergraphCreator {
+    fn schema(&self) -> Arc<Schema> {
+        self.schema.clone()
+    }
+}
+
 impl ServiceFactory<supergraph::Request> for SupergraphCreator {
     type Service = supergraph::BoxService;
     fn create(&self) -> Self::Service {
@@ -416,6 +468,8 @@ impl SupergraphCreator {
                 schema: self.schema.clone(),
                 plugins: self.plugins.clone(),
                 subgraph_service_factory: self.subgraph_service_factory.clone(),
+                merge_conflicts: self.merge_conflicts,
+                single_flight: self.single_flight.clone(),
             })
             .schema(self.schema.clone())
             .build();
@@ -429,9 +483,14 @@ impl SupergraphCreator {
             Some(shaping) => Either::A(shaping.supergraph_service_internal(supergraph_service)),
             None => Either::B(supergraph_service),
         };
+        // rejects the operations before planning them
+        let supergraph_service = ServiceBuilder::new()
+            .option_layer(self.allowed_operation_types.clone())
+            .service(supergraph_service);
 
         ServiceBuilder::new()
             .layer(content_negociation::SupergraphLayer::default())
+            .option_layer(self.response_overlay.clone())
             .service(
                 self.plugins
                     .iter()
@@ -471,6 +530,7 @@ impl SupergraphCreator {
 pub(crate) struct MockSupergraphCreator {
     supergraph_service: MockSupergraphService,
     plugins: Arc<Plugins>,
+    schema: Arc<Schema>,
 }
 
 #[cfg(test)]
@@ -479,20 +539,19 @@ impl MockSupergraphCreator {
         let canned_schema = include_str!("../../testing_schema.graphql");
         let configuration = Configuration::builder().build().unwrap();
 
+        let schema = Schema::parse_test(canned_schema, &configuration).unwrap();
+
         use crate::router_factory::create_plugins;
-        let plugins = create_plugins(
-            &configuration,
-            &Schema::parse_test(canned_schema, &configuration).unwrap(),
-            None,
-        )
-        .await
-        .unwrap()
-        .into_iter()
-        .collect();
+        let plugins = create_plugins(&configuration, &schema, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
 
         Self {
             supergraph_service,
             plugins: Arc::new(plugins),
+            schema: Arc::new(schema),
         }
     }
 }
@@ -504,6 +563,13 @@ impl HasPlugins for MockSupergraphCreator {
     }
 }
 
+#[cfg(test)]
+impl HasSchema for MockSupergraphCreator {
+    fn schema(&self) -> Arc<Schema> {
+        self.schema.clone()
+    }
+}
+
 #[cfg(test)]
 impl ServiceFactory<supergraph::Request> for MockSupergraphCreator {
     type Service = supergraph::BoxService;
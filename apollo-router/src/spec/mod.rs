@@ -37,6 +37,8 @@ pub(crate) enum SpecError {
     ParsingError(String),
     /// subscription operation is not supported
     SubscriptionNotSupported,
+    /// the document contains {0} operations, the limit is {1}
+    TooManyOperations(usize, usize),
 }
 
 impl SpecError {
@@ -56,6 +58,7 @@ impl ErrorExtension for SpecError {
             SpecError::InvalidField(_, _) => "INVALID_FIELD",
             SpecError::ParsingError(_) => "PARSING_ERROR",
             SpecError::SubscriptionNotSupported => "SUBSCRIPTION_NOT_SUPPORTED",
+            SpecError::TooManyOperations(_, _) => "MAX_OPERATIONS_LIMIT",
         }
         .to_string()
     }
@@ -288,11 +288,19 @@ impl Query {
             return Err(SpecError::ParsingError(errors));
         }
 
+        let all_operations = compiler.db.all_operations();
+        if let Some(max) = configuration
+            .preview_operation_limits
+            .max_operations_per_document
+        {
+            if all_operations.len() > max {
+                return Err(SpecError::TooManyOperations(all_operations.len(), max));
+            }
+        }
+
         let fragments = Fragments::from_hir(&compiler, schema)?;
 
-        let operations = compiler
-            .db
-            .all_operations()
+        let operations = all_operations
             .iter()
             .map(|operation| Operation::from_hir(operation, schema))
             .collect::<Result<Vec<_>, SpecError>>()?;
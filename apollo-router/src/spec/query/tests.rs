@@ -2,6 +2,7 @@ use serde_json_bytes::json;
 use test_log::test;
 
 use super::*;
+use crate::graphql::ErrorExtension;
 use crate::json_ext::ValueExt;
 
 macro_rules! assert_eq_and_ordered {
@@ -5429,3 +5430,31 @@ fn test_query_not_named_query() {
         "unexpected selection {selection:?}"
     );
 }
+
+#[test]
+fn it_limits_the_operations_per_document() {
+    let schema = with_supergraph_boilerplate("type Query { foo: String }");
+    let schema = Schema::parse_test(&schema, &Default::default()).unwrap();
+    let config: Configuration = serde_json::from_value(serde_json::json!({
+        "preview_operation_limits": { "max_operations_per_document": 2 }
+    }))
+    .unwrap();
+
+    let query = "query A { foo } query B { foo }";
+    assert_eq!(
+        Query::parse(query, &schema, &config)
+            .unwrap()
+            .operations
+            .len(),
+        2
+    );
+
+    let query = "query A { foo } query B { foo } query C { foo }";
+    let error = Query::parse(query, &schema, &config).unwrap_err();
+    assert!(matches!(error, SpecError::TooManyOperations(3, 2)));
+    assert_eq!(
+        error.to_string(),
+        "the document contains 3 operations, the limit is 2"
+    );
+    assert_eq!(error.extension_code(), "MAX_OPERATIONS_LIMIT");
+}
@@ -230,18 +230,37 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
     async fn shutdown(self) -> Self {
         match self {
             Running {
+                configuration,
                 server_handle: Some(server_handle),
+                router_service_factory,
                 mut all_connections_stopped_signal,
                 ..
             } => {
                 tracing::info!("shutting down");
-                let state = server_handle
-                    .shutdown()
+                let deadline = configuration.supergraph.shutdown_deadline;
+                let state = HttpServerHandle::shutdown_all([server_handle], deadline)
                     .map_ok_or_else(Errored, |_| Stopped)
                     .await;
-                //FIXME: we might want to set a timeout here
-                let _ = all_connections_stopped_signal.recv().await;
-                tracing::info!("all connections shut down");
+                let connections_stopped = all_connections_stopped_signal.recv();
+                match deadline {
+                    Some(deadline) => {
+                        if tokio::time::timeout(deadline, connections_stopped)
+                            .await
+                            .is_err()
+                        {
+                            tracing::warn!(
+                                "connections still open at the {deadline:?} shutdown deadline"
+                            );
+                        } else {
+                            tracing::info!("all connections shut down");
+                        }
+                    }
+                    None => {
+                        let _ = connections_stopped.await;
+                        tracing::info!("all connections shut down");
+                    }
+                }
+                router_service_factory.shutdown().await;
                 state
             }
             _ => Stopped,
@@ -255,7 +255,7 @@ impl<'a> TestHarness<'a> {
     /// Builds the router service
     pub async fn build_router(self) -> Result<router::BoxCloneService, BoxError> {
         let (config, supergraph_creator) = self.build_common().await?;
-        let router_creator = RouterCreator::new(Arc::new(supergraph_creator), &config).await;
+        let router_creator = RouterCreator::new(Arc::new(supergraph_creator), &config).await?;
 
         Ok(tower::service_fn(move |request: router::Request| {
             let router = ServiceBuilder::new().service(router_creator.make()).boxed();
@@ -273,7 +273,7 @@ impl<'a> TestHarness<'a> {
         use crate::uplink::entitlement::EntitlementState;
 
         let (config, supergraph_creator) = self.build_common().await?;
-        let router_creator = RouterCreator::new(Arc::new(supergraph_creator), &config).await;
+        let router_creator = RouterCreator::new(Arc::new(supergraph_creator), &config).await?;
         let web_endpoints = router_creator.web_endpoints();
 
         let routers = make_axum_router(